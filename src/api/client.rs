@@ -1,21 +1,31 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
-use reqwest::Certificate;
 use serde_json::json;
 use uuid::Uuid;
 
 use super::cloud_type::CloudType;
 use super::errors::*;
+use super::http;
 use super::response::ApiResponse;
+use super::retry;
 use super::signing::get_signing_headers;
+use super::transport::{self, HttpTransport, PostRequest};
 use crate::error::AppError;
+use crate::timing;
 
 const PATH_ACCOUNT_STATUS: &str = "/api/v2/account/getAccountStatusAndUrl";
 const PATH_LOGIN: &str = "/api/v2/account/login";
 const PATH_REFRESH_TOKEN: &str = "/api/v2/account/refreshToken";
 const PATH_MFA_LOGIN: &str = "/api/v2/account/checkMFACodeAndLogin";
+const PATH_MESSAGE_LIST: &str = "/api/v2/message/getMessageList";
+const PATH_DEVICE_ACTIVITY: &str = "/api/v2/device/getDeviceActivityLog";
 
-const CA_CERT_PEM: &[u8] = include_bytes!("../../certs/tplink-ca-chain.pem");
+/// Items requested per page of a paged list (messages, activity).
+const PAGE_SIZE: usize = 50;
+
+/// Stop paging after this many pages so a huge backlog cannot loop for long.
+const MAX_PAGES: usize = 20;
 
 pub struct LoginResult {
     pub token: String,
@@ -24,7 +34,7 @@ pub struct LoginResult {
 }
 
 pub struct TPLinkApi {
-    client: reqwest::Client,
+    transport: Arc<dyn HttpTransport>,
     pub host: String,
     term_id: String,
     cloud_type: CloudType,
@@ -32,15 +42,6 @@ pub struct TPLinkApi {
     verbose: bool,
 }
 
-fn build_http_client() -> Result<reqwest::Client, AppError> {
-    let cert = Certificate::from_pem(CA_CERT_PEM)?;
-    Ok(reqwest::Client::builder()
-        .add_root_certificate(cert)
-        .user_agent("Dalvik/2.1.0 (Linux; U; Android 14; Pixel Build/UP1A)")
-        .timeout(std::time::Duration::from_secs(15))
-        .build()?)
-}
-
 fn build_query_params(cloud_type: CloudType, term_id: &str) -> HashMap<String, String> {
     let mut params = HashMap::new();
     params.insert("appName".into(), cloud_type.app_type().into());
@@ -65,10 +66,10 @@ impl TPLinkApi {
     ) -> Result<Self, AppError> {
         let term_id = term_id.unwrap_or_else(|| Uuid::new_v4().to_string());
         let query_params = build_query_params(cloud_type, &term_id);
-        let client = build_http_client()?;
+        let transport = transport::default_transport()?;
 
         Ok(Self {
-            client,
+            transport,
             host: host.unwrap_or_else(|| cloud_type.host().to_string()),
             term_id,
             cloud_type,
@@ -77,6 +78,12 @@ impl TPLinkApi {
         })
     }
 
+    /// Send requests through `transport` instead of the default one.
+    pub fn with_transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
     pub fn term_id(&self) -> &str {
         &self.term_id
     }
@@ -85,13 +92,27 @@ impl TPLinkApi {
         self.cloud_type
     }
 
-    /// Make a signed V2 API request.
+    /// Make a signed V2 API request, retrying transient failures.
     async fn request_post_v2(
         &self,
         base_url: &str,
         url_path: &str,
         body: &serde_json::Value,
         token: Option<&str>,
+    ) -> Result<ApiResponse, AppError> {
+        let label = format!("[{}] POST {}", self.cloud_type, url_path);
+        retry::with_retries(&label, self.verbose, || {
+            self.try_post_v2(base_url, url_path, body, token)
+        })
+        .await
+    }
+
+    async fn try_post_v2(
+        &self,
+        base_url: &str,
+        url_path: &str,
+        body: &serde_json::Value,
+        token: Option<&str>,
     ) -> Result<ApiResponse, AppError> {
         let url = format!("{}{}", base_url, url_path);
         let body_json = serde_json::to_string(body)?;
@@ -108,19 +129,24 @@ impl TPLinkApi {
             eprintln!("Body: {}", body_json);
         }
 
+        let _span = timing::span(format!("http [{}] POST {}", self.cloud_type, url_path));
         let response = self
-            .client
-            .post(&url)
-            .query(&params)
-            .header("Content-Type", "application/json;charset=UTF-8")
-            .header("Content-MD5", &signing.content_md5)
-            .header("X-Authorization", &signing.x_authorization)
-            .body(body_json)
-            .send()
+            .transport
+            .post(PostRequest {
+                url: &url,
+                url_path,
+                query: &params,
+                signing: &signing,
+                body: &body_json,
+                timeout: http::timeouts().api,
+            })
             .await?;
 
-        if response.status().is_success() {
-            let api_response: ApiResponse = response.json().await?;
+        if response.is_success() {
+            let api_response: ApiResponse = {
+                let _span = timing::span("json parse");
+                response.json()?
+            };
             if self.verbose {
                 eprintln!(
                     "Response: {}",
@@ -130,14 +156,10 @@ impl TPLinkApi {
                     }))?
                 );
             }
+            retry::check_error_code(&api_response)?;
             Ok(api_response)
         } else {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            Err(AppError::Api {
-                message: format!("{}: {}", status, body),
-                error_code: None,
-            })
+            Err(response.error())
         }
     }
 
@@ -147,6 +169,15 @@ impl TPLinkApi {
         &self,
         body: &serde_json::Value,
         token: Option<&str>,
+    ) -> Result<ApiResponse, AppError> {
+        let label = format!("[{}] POST /", self.cloud_type);
+        retry::with_retries(&label, self.verbose, || self.try_post_v1(body, token)).await
+    }
+
+    async fn try_post_v1(
+        &self,
+        body: &serde_json::Value,
+        token: Option<&str>,
     ) -> Result<ApiResponse, AppError> {
         let url_path = "/";
         let body_json = serde_json::to_string(body)?;
@@ -163,19 +194,24 @@ impl TPLinkApi {
             eprintln!("Body: {}", body_json);
         }
 
+        let _span = timing::span(format!("http [{}] POST /", self.cloud_type));
         let response = self
-            .client
-            .post(&self.host)
-            .query(&params)
-            .header("Content-Type", "application/json;charset=UTF-8")
-            .header("Content-MD5", &signing.content_md5)
-            .header("X-Authorization", &signing.x_authorization)
-            .body(body_json)
-            .send()
+            .transport
+            .post(PostRequest {
+                url: &self.host,
+                url_path,
+                query: &params,
+                signing: &signing,
+                body: &body_json,
+                timeout: http::timeouts().api,
+            })
             .await?;
 
-        if response.status().is_success() {
-            let api_response: ApiResponse = response.json().await?;
+        if response.is_success() {
+            let api_response: ApiResponse = {
+                let _span = timing::span("json parse");
+                response.json()?
+            };
             if self.verbose {
                 eprintln!(
                     "Response: {}",
@@ -185,14 +221,10 @@ impl TPLinkApi {
                     }))?
                 );
             }
+            retry::check_error_code(&api_response)?;
             Ok(api_response)
         } else {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            Err(AppError::Api {
-                message: format!("{}: {}", status, body),
-                error_code: None,
-            })
+            Err(response.error())
         }
     }
 
@@ -236,7 +268,8 @@ impl TPLinkApi {
             "cloudUserName": username,
             "platform": "Android",
             "refreshTokenNeeded": true,
-            "supportBindAccount": false,
+            // Lets the cloud remember this terminal once an MFA code is accepted
+            "supportBindAccount": true,
             "terminalUUID": self.term_id,
             "terminalName": "Pixel",
             "terminalMeta": "Pixel",
@@ -261,34 +294,22 @@ impl TPLinkApi {
                 })
                 .unwrap_or(0);
 
+            if inner_error == ERR_MFA_REQUIRED {
+                return Err(AppError::MfaRequired {
+                    mfa_type: result
+                        .get("mfaType")
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.to_string()),
+                    email: Some(username.to_string()),
+                });
+            }
+
             if inner_error != 0 {
                 let inner_msg = result
                     .get("errorMsg")
                     .and_then(|v| v.as_str())
-                    .unwrap_or("Login failed")
-                    .to_string();
-
-                if inner_error == ERR_MFA_REQUIRED {
-                    return Err(AppError::MfaRequired {
-                        mfa_type: result
-                            .get("mfaType")
-                            .and_then(|v| v.as_str())
-                            .map(|s| s.to_string()),
-                        email: Some(username.to_string()),
-                    });
-                }
-
-                if inner_error == ERR_WRONG_CREDENTIALS || inner_error == ERR_ACCOUNT_LOCKED {
-                    return Err(AppError::Auth {
-                        message: inner_msg,
-                        error_code: Some(inner_error),
-                    });
-                }
-
-                return Err(AppError::Api {
-                    message: inner_msg,
-                    error_code: Some(inner_error),
-                });
+                    .map(|s| s.to_string());
+                return Err(error_for_code(inner_error, inner_msg));
             }
 
             return Ok(LoginResult {
@@ -311,21 +332,7 @@ impl TPLinkApi {
             });
         }
 
-        if error_code == ERR_WRONG_CREDENTIALS || error_code == ERR_ACCOUNT_LOCKED {
-            return Err(AppError::Auth {
-                message: response
-                    .msg
-                    .unwrap_or_else(|| "Authentication failed".into()),
-                error_code: Some(error_code),
-            });
-        }
-
-        Err(AppError::Api {
-            message: response
-                .msg
-                .unwrap_or_else(|| format!("Login failed with error code {}", error_code)),
-            error_code: Some(error_code),
-        })
+        Err(error_for_code(error_code, response.msg))
     }
 
     /// Complete MFA verification.
@@ -340,6 +347,7 @@ impl TPLinkApi {
             "cloudPassword": password,
             "cloudUserName": username,
             "code": mfa_code,
+            "terminalBindEnabled": true,
             "terminalUUID": self.term_id,
         });
 
@@ -385,22 +393,7 @@ impl TPLinkApi {
             });
         }
 
-        if response.error_code == ERR_REFRESH_TOKEN_EXPIRED {
-            return Err(AppError::TokenExpired {
-                message: "Refresh token has expired. Run 'tplc login' to re-authenticate.".into(),
-                error_code: Some(response.error_code),
-            });
-        }
-
-        Err(AppError::Api {
-            message: response.msg.unwrap_or_else(|| {
-                format!(
-                    "Token refresh failed with error code {}",
-                    response.error_code
-                )
-            }),
-            error_code: Some(response.error_code),
-        })
+        Err(error_for_code(response.error_code, response.msg))
     }
 
     /// Get the list of devices registered to the account.
@@ -422,13 +415,139 @@ impl TPLinkApi {
             return Ok(vec![]);
         }
 
-        if response.error_code == ERR_TOKEN_EXPIRED {
-            return Err(AppError::TokenExpired {
-                message: "Auth token expired".into(),
-                error_code: Some(response.error_code),
-            });
+        Err(error_for_code(response.error_code, response.msg))
+    }
+
+    /// Get message-center notices (device offline, overload and the like)
+    /// sent at or after `since_ms` (Unix milliseconds).
+    pub async fn get_message_list(
+        &self,
+        token: &str,
+        since_ms: i64,
+    ) -> Result<Vec<serde_json::Value>, AppError> {
+        let body = json!({"startTime": since_ms});
+        self.get_pages(PATH_MESSAGE_LIST, body, &["messageList", "msgList"], token)
+            .await
+    }
+
+    /// Get the activity (on/off, online/offline) the cloud logged for a
+    /// device at or after `since_ms`. Only some models keep such a log.
+    pub async fn get_device_activity(
+        &self,
+        token: &str,
+        device_id: &str,
+        since_ms: i64,
+    ) -> Result<Vec<serde_json::Value>, AppError> {
+        let body = json!({"deviceId": device_id, "startTime": since_ms});
+        self.get_pages(
+            PATH_DEVICE_ACTIVITY,
+            body,
+            &["logList", "activityList"],
+            token,
+        )
+        .await
+    }
+
+    /// Collect a paged list: `body` plus `index`/`limit`, repeated until a
+    /// short page. The items are under the first of `keys` present.
+    async fn get_pages(
+        &self,
+        url_path: &str,
+        mut body: serde_json::Value,
+        keys: &[&str],
+        token: &str,
+    ) -> Result<Vec<serde_json::Value>, AppError> {
+        let mut items = Vec::new();
+        for page in 0..MAX_PAGES {
+            body["index"] = json!(page * PAGE_SIZE);
+            body["limit"] = json!(PAGE_SIZE);
+            let response = self
+                .request_post_v2(&self.host, url_path, &body, Some(token))
+                .await?;
+            if !response.successful() {
+                return Err(error_for_code(response.error_code, response.msg));
+            }
+            let result = response.result.unwrap_or_default();
+            let batch = keys
+                .iter()
+                .find_map(|k| result.get(*k))
+                .and_then(|v| v.as_array())
+                .cloned()
+                .unwrap_or_default();
+            let done = batch.len() < PAGE_SIZE;
+            items.extend(batch);
+            if done {
+                break;
+            }
         }
+        Ok(items)
+    }
+}
 
-        Ok(vec![])
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::transport::MockTransport;
+
+    #[tokio::test]
+    async fn test_login_uses_regional_url() {
+        let transport = MockTransport::new(&[
+            (
+                PATH_ACCOUNT_STATUS,
+                r#"{"error_code":0,"result":{"appServerUrl":"https://eu-wap.tplinkcloud.com"}}"#,
+            ),
+            (
+                PATH_LOGIN,
+                r#"{"error_code":0,"result":{"token":"tok","refreshToken":"ref"}}"#,
+            ),
+        ]);
+        let mut api = TPLinkApi::new(None, false, Some("term".into()), CloudType::Kasa)
+            .unwrap()
+            .with_transport(transport.clone());
+        let result = api.login("user@example.com", "hunter2").await.unwrap();
+        assert_eq!(result.token, "tok");
+        assert_eq!(result.refresh_token.as_deref(), Some("ref"));
+        assert_eq!(result.regional_url, "https://eu-wap.tplinkcloud.com");
+        assert_eq!(api.host, "https://eu-wap.tplinkcloud.com");
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].1["cloudUserName"], "user@example.com");
+        assert_eq!(requests[1].1["terminalUUID"], "term");
+    }
+
+    #[tokio::test]
+    async fn test_login_inner_error() {
+        let transport = MockTransport::new(&[(
+            PATH_LOGIN,
+            r#"{"error_code":0,"result":{"errorCode":"-20601","errorMsg":"Incorrect password"}}"#,
+        )]);
+        let mut api = TPLinkApi::new(None, false, None, CloudType::Kasa)
+            .unwrap()
+            .with_transport(transport);
+        // Without an account status answer the 404 stops the login early
+        assert!(matches!(
+            api.login("user@example.com", "wrong").await,
+            Err(AppError::Api { .. })
+        ));
+
+        let transport = MockTransport::new(&[
+            (PATH_ACCOUNT_STATUS, r#"{"error_code":0,"result":{}}"#),
+            (
+                PATH_LOGIN,
+                r#"{"error_code":0,"result":{"errorCode":"-20601","errorMsg":"Incorrect password"}}"#,
+            ),
+        ]);
+        let mut api = api.with_transport(transport);
+        match api.login("user@example.com", "wrong").await {
+            Err(AppError::Auth {
+                message,
+                error_code,
+            }) => {
+                assert_eq!(message, "Incorrect password");
+                assert_eq!(error_code, Some(ERR_WRONG_CREDENTIALS));
+            }
+            _ => panic!("expected an auth error"),
+        }
     }
 }
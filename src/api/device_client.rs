@@ -1,18 +1,21 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
-use reqwest::Certificate;
 use serde_json::json;
 
 use super::cloud_type::CloudType;
 use super::errors::*;
+use super::http;
 use super::response::ApiResponse;
+use super::retry;
 use super::signing::get_signing_headers;
+use super::transport::{self, HttpTransport, PostRequest};
 use crate::error::AppError;
+use crate::timing;
 
-const CA_CERT_PEM: &[u8] = include_bytes!("../../certs/tplink-ca-chain.pem");
-
+#[derive(Clone)]
 pub struct DeviceClient {
-    client: reqwest::Client,
+    transport: Arc<dyn HttpTransport>,
     host: String,
     cloud_type: CloudType,
     query_params: HashMap<String, String>,
@@ -27,12 +30,7 @@ impl DeviceClient {
         verbose: bool,
         cloud_type: CloudType,
     ) -> Result<Self, AppError> {
-        let cert = Certificate::from_pem(CA_CERT_PEM)?;
-        let client = reqwest::Client::builder()
-            .add_root_certificate(cert)
-            .user_agent("Dalvik/2.1.0 (Linux; U; Android 14; Pixel Build/UP1A)")
-            .timeout(std::time::Duration::from_secs(600))
-            .build()?;
+        let transport = transport::default_transport()?;
 
         let mut query_params = HashMap::new();
         query_params.insert("appName".into(), cloud_type.app_type().into());
@@ -48,7 +46,7 @@ impl DeviceClient {
         query_params.insert("token".into(), token.into());
 
         Ok(Self {
-            client,
+            transport,
             host: host.to_string(),
             cloud_type,
             query_params,
@@ -56,6 +54,17 @@ impl DeviceClient {
         })
     }
 
+    /// Cloud endpoint the requests go to.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Send requests through `transport` instead of the default one.
+    pub fn with_transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
     /// Send a passthrough command to a device and return the parsed response data.
     pub async fn passthrough(
         &self,
@@ -87,68 +96,29 @@ impl DeviceClient {
         };
 
         let body_json = serde_json::to_string(&body)?;
-        let signing = get_signing_headers(&body_json, url_path, self.cloud_type);
-
         let url = if url_path == "/" {
             self.host.clone()
         } else {
             format!("{}{}", self.host, url_path)
         };
 
-        if self.verbose {
-            eprintln!("[{}] POST {}", self.cloud_type, url);
-            eprintln!("Body: {}", body_json);
-        }
-
-        let response = self
-            .client
-            .post(&url)
-            .query(&self.query_params)
-            .header("Content-Type", "application/json;charset=UTF-8")
-            .header("Content-MD5", &signing.content_md5)
-            .header("X-Authorization", &signing.x_authorization)
-            .body(body_json)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            return Err(AppError::Api {
-                message: format!("{}: {}", status, body),
-                error_code: None,
-            });
-        }
-
-        let api_response: ApiResponse = response.json().await?;
-
-        if self.verbose {
-            eprintln!(
-                "Response: error_code={}, msg={:?}",
-                api_response.error_code, api_response.msg
-            );
-        }
-
-        if api_response.error_code == ERR_TOKEN_EXPIRED {
-            return Err(AppError::TokenExpired {
-                message: "Auth token expired".into(),
-                error_code: Some(api_response.error_code),
-            });
-        }
+        let label = format!("[{}] passthrough {}", self.cloud_type, device_id);
+        let api_response = retry::with_retries(&label, self.verbose, || {
+            self.send(&url, url_path, &body_json, device_id)
+        })
+        .await?;
 
         if !api_response.successful() {
-            return Err(AppError::Api {
-                message: api_response
-                    .msg
-                    .unwrap_or_else(|| format!("Device error code {}", api_response.error_code)),
-                error_code: Some(api_response.error_code),
-            });
+            return Err(error_for_code(api_response.error_code, api_response.msg));
         }
 
         // Parse the double-encoded responseData
         if let Some(result) = api_response.result {
             if let Some(response_data_str) = result.get("responseData").and_then(|v| v.as_str()) {
-                let parsed: serde_json::Value = serde_json::from_str(response_data_str)?;
+                let parsed: serde_json::Value = {
+                    let _span = timing::span("json parse responseData");
+                    serde_json::from_str(response_data_str)?
+                };
                 if self.verbose {
                     eprintln!(
                         "Passthrough response: {}",
@@ -161,4 +131,55 @@ impl DeviceClient {
 
         Ok(None)
     }
+
+    /// Post a signed passthrough body once. Signing headers are made fresh
+    /// for every attempt.
+    async fn send(
+        &self,
+        url: &str,
+        url_path: &str,
+        body_json: &str,
+        device_id: &str,
+    ) -> Result<ApiResponse, AppError> {
+        let signing = get_signing_headers(body_json, url_path, self.cloud_type);
+
+        if self.verbose {
+            eprintln!("[{}] POST {}", self.cloud_type, url);
+            eprintln!("Body: {}", body_json);
+        }
+
+        let _span = timing::span(format!(
+            "http [{}] passthrough {}",
+            self.cloud_type, device_id
+        ));
+        let response = self
+            .transport
+            .post(PostRequest {
+                url,
+                url_path,
+                query: &self.query_params,
+                signing: &signing,
+                body: body_json,
+                timeout: http::timeouts().device,
+            })
+            .await?;
+
+        if !response.is_success() {
+            return Err(response.error());
+        }
+
+        let api_response: ApiResponse = {
+            let _span = timing::span("json parse");
+            response.json()?
+        };
+
+        if self.verbose {
+            eprintln!(
+                "Response: error_code={}, msg={:?}",
+                api_response.error_code, api_response.msg
+            );
+        }
+        retry::check_error_code(&api_response)?;
+        Ok(api_response)
+    }
 }
@@ -1,6 +1,220 @@
+use crate::error::AppError;
+
+pub const ERR_REQUEST_TIMEOUT: i32 = -20002;
+pub const ERR_RATE_LIMITED: i32 = -20004;
 pub const ERR_MFA_REQUIRED: i32 = -20677;
 pub const ERR_TOKEN_EXPIRED: i32 = -20651;
 pub const ERR_REFRESH_TOKEN_EXPIRED: i32 = -20655;
 pub const ERR_WRONG_CREDENTIALS: i32 = -20601;
 pub const ERR_ACCOUNT_LOCKED: i32 = -20675;
 pub const ERR_MALFORMED_REQUEST: i32 = -20104;
+pub const ERR_DEVICE_OFFLINE: i32 = -20571;
+pub const ERR_DEVICE_NOT_BOUND: i32 = -20580;
+
+/// The `AppError` variant a cloud error code is reported as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Api,
+    Auth,
+    TokenExpired,
+    DeviceOffline,
+    RateLimited,
+    PermissionDenied,
+}
+
+/// Description of a known TP-Link cloud error code.
+pub struct ErrorCodeInfo {
+    pub code: i32,
+    pub name: &'static str,
+    pub kind: ErrorKind,
+    pub explanation: &'static str,
+    pub hint: &'static str,
+}
+
+/// Known TP-Link cloud error codes with explanations and suggested next steps.
+pub const ERROR_CODES: &[ErrorCodeInfo] = &[
+    ErrorCodeInfo {
+        code: ERR_REQUEST_TIMEOUT,
+        name: "request_timeout",
+        kind: ErrorKind::Api,
+        explanation: "The cloud did not get a response from the device in time.",
+        hint: "Retry the command. If it keeps failing, check the device's WiFi connection.",
+    },
+    ErrorCodeInfo {
+        code: ERR_RATE_LIMITED,
+        name: "rate_limited",
+        kind: ErrorKind::RateLimited,
+        explanation: "Too many requests were sent to the TP-Link cloud in a short period.",
+        hint: "Wait a minute before retrying and reduce how often scripts call tplc.",
+    },
+    ErrorCodeInfo {
+        code: ERR_MALFORMED_REQUEST,
+        name: "malformed_request",
+        kind: ErrorKind::Api,
+        explanation: "The cloud rejected the request parameters.",
+        hint: "The device may not support this operation. Re-run with --verbose and report the request body.",
+    },
+    ErrorCodeInfo {
+        code: ERR_DEVICE_OFFLINE,
+        name: "device_offline",
+        kind: ErrorKind::DeviceOffline,
+        explanation: "The device is not connected to the TP-Link cloud.",
+        hint: "Check that the device is powered and on WiFi, then run 'tplc devices list' to see its status.",
+    },
+    ErrorCodeInfo {
+        code: ERR_DEVICE_NOT_BOUND,
+        name: "device_not_bound",
+        kind: ErrorKind::PermissionDenied,
+        explanation: "The device is not bound to this account.",
+        hint: "Make sure the device is registered to the account you logged in with.",
+    },
+    ErrorCodeInfo {
+        code: ERR_WRONG_CREDENTIALS,
+        name: "wrong_credentials",
+        kind: ErrorKind::Auth,
+        explanation: "The email or password is incorrect.",
+        hint: "Run 'tplc login' again and check your TP-Link account email and password.",
+    },
+    ErrorCodeInfo {
+        code: ERR_TOKEN_EXPIRED,
+        name: "token_expired",
+        kind: ErrorKind::TokenExpired,
+        explanation: "The stored auth token has expired.",
+        hint: "tplc refreshes tokens automatically. If this persists, run 'tplc login'.",
+    },
+    ErrorCodeInfo {
+        code: ERR_REFRESH_TOKEN_EXPIRED,
+        name: "refresh_token_expired",
+        kind: ErrorKind::TokenExpired,
+        explanation: "The stored refresh token has expired, so the session cannot be renewed.",
+        hint: "Run 'tplc login' to re-authenticate. With TPLC_USERNAME/TPLC_PASSWORD set, or after 'tplc login --save-password', tplc logs in again by itself.",
+    },
+    ErrorCodeInfo {
+        code: ERR_ACCOUNT_LOCKED,
+        name: "account_locked",
+        kind: ErrorKind::Auth,
+        explanation: "The account is temporarily locked after too many failed login attempts.",
+        hint: "Wait before trying again, or reset your password in the Kasa or Tapo app.",
+    },
+    ErrorCodeInfo {
+        code: ERR_MFA_REQUIRED,
+        name: "mfa_required",
+        kind: ErrorKind::Auth,
+        explanation: "The account requires multi-factor verification to log in.",
+        hint: "Run 'tplc login' interactively and enter the code sent to your email.",
+    },
+];
+
+/// Look up a known error code.
+pub fn lookup_error_code(code: i32) -> Option<&'static ErrorCodeInfo> {
+    ERROR_CODES.iter().find(|info| info.code == code)
+}
+
+/// The error for a response that failed with `code`, typed by the code's
+/// entry in `ERROR_CODES`. Unknown codes are `AppError::Api`. `msg` is the
+/// cloud's message, if it sent one.
+pub fn error_for_code(code: i32, msg: Option<String>) -> AppError {
+    let info = lookup_error_code(code);
+    let message = msg
+        .filter(|m| !m.is_empty())
+        .or_else(|| info.map(|i| i.explanation.to_string()))
+        .unwrap_or_else(|| format!("Cloud error code {}", code));
+    let error_code = Some(code);
+    match info.map_or(ErrorKind::Api, |i| i.kind) {
+        ErrorKind::Api => AppError::Api {
+            message,
+            error_code,
+        },
+        ErrorKind::Auth => AppError::Auth {
+            message,
+            error_code,
+        },
+        ErrorKind::TokenExpired => AppError::TokenExpired {
+            message,
+            error_code,
+        },
+        ErrorKind::DeviceOffline => AppError::DeviceOffline {
+            message,
+            error_code,
+            device: None,
+        },
+        ErrorKind::RateLimited => AppError::RateLimited {
+            message,
+            error_code,
+        },
+        ErrorKind::PermissionDenied => AppError::PermissionDenied {
+            message,
+            error_code,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCategory;
+
+    #[test]
+    fn test_lookup_known_code() {
+        let info = lookup_error_code(ERR_DEVICE_OFFLINE).unwrap();
+        assert_eq!(info.name, "device_offline");
+    }
+
+    #[test]
+    fn test_error_for_code() {
+        let err = error_for_code(ERR_DEVICE_OFFLINE, Some("Device is offline".into()));
+        assert_eq!(err.error_type(), "device_offline");
+        assert_eq!(err.exit_code(), 4);
+        assert_eq!(err.to_string(), "Device offline: Device is offline");
+
+        let err = error_for_code(ERR_RATE_LIMITED, None);
+        assert_eq!(err.error_type(), "rate_limited");
+        assert!(err.to_string().contains("Too many requests"));
+
+        assert_eq!(
+            error_for_code(ERR_DEVICE_NOT_BOUND, None).error_type(),
+            "permission_denied"
+        );
+        assert_eq!(
+            error_for_code(ERR_TOKEN_EXPIRED, None).error_type(),
+            "token_expired"
+        );
+        assert_eq!(
+            error_for_code(-1, None).to_string(),
+            "API error: Cloud error code -1"
+        );
+    }
+
+    #[test]
+    fn test_error_accessors() {
+        let err = error_for_code(ERR_DEVICE_OFFLINE, None).with_device("Porch");
+        assert_eq!(err.category(), ErrorCategory::DeviceOffline);
+        assert_eq!(err.error_code(), Some(ERR_DEVICE_OFFLINE));
+        assert_eq!(err.device(), Some("Porch"));
+        assert!(!err.is_retryable());
+        assert_eq!(err.to_json()["device"], "Porch");
+        // A name already set is kept
+        assert_eq!(err.with_device("Other").device(), Some("Porch"));
+
+        let err = error_for_code(ERR_RATE_LIMITED, None);
+        assert_eq!(err.category(), ErrorCategory::RateLimited);
+        assert!(err.is_retryable());
+        assert_eq!(err.device(), None);
+        assert_eq!(AppError::NotAuthenticated.category(), ErrorCategory::Auth);
+        assert_eq!(AppError::NotAuthenticated.error_code(), None);
+    }
+
+    #[test]
+    fn test_lookup_unknown_code() {
+        assert!(lookup_error_code(-1).is_none());
+    }
+
+    #[test]
+    fn test_error_codes_unique() {
+        for (i, a) in ERROR_CODES.iter().enumerate() {
+            for b in &ERROR_CODES[i + 1..] {
+                assert_ne!(a.code, b.code);
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use reqwest::{Certificate, ClientBuilder, Proxy};
+
+use crate::error::AppError;
+
+#[cfg(not(any(feature = "rustls", feature = "native-tls")))]
+compile_error!("tplc needs a TLS backend: enable the `rustls` or `native-tls` feature");
+
+const CA_CERT_PEM: &[u8] = include_bytes!("../../certs/tplink-ca-chain.pem");
+
+const USER_AGENT: &str = "Dalvik/2.1.0 (Linux; U; Android 14; Pixel Build/UP1A)";
+
+static CA_CERT: OnceLock<Certificate> = OnceLock::new();
+
+static TIMEOUTS: OnceLock<Timeouts> = OnceLock::new();
+
+/// Explicit proxy setting; `None` inside means connect directly. Unset means
+/// reqwest's default of honoring `HTTPS_PROXY`/`HTTP_PROXY`/`NO_PROXY`.
+static PROXY: OnceLock<Option<Proxy>> = OnceLock::new();
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// `--proxy` value that bypasses any proxy set in the environment.
+const NO_PROXY: &str = "none";
+
+/// Request timeouts for the cloud API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Establishing the TCP/TLS connection
+    pub connect: Duration,
+    /// Whole request for account operations (login, token refresh, device list)
+    pub api: Duration,
+    /// Whole request for a device passthrough command
+    pub device: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(10),
+            api: Duration::from_secs(15),
+            device: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Set the timeouts for the rest of the process. Only the first call counts,
+/// and the connect timeout only if made before the first request.
+pub fn configure_timeouts(timeouts: Timeouts) {
+    let _ = TIMEOUTS.set(timeouts);
+}
+
+pub fn timeouts() -> Timeouts {
+    TIMEOUTS.get().copied().unwrap_or_default()
+}
+
+/// Route all requests through `url` (e.g. `http://proxy.lan:3128`), or
+/// through none for `"none"`. Only the first call counts, and only if made
+/// before the first request.
+pub fn configure_proxy(url: &str) -> Result<(), AppError> {
+    let proxy = if url.eq_ignore_ascii_case(NO_PROXY) {
+        None
+    } else {
+        // The URL is left out of errors since it may carry a password
+        let invalid = |reason: String| AppError::InvalidInput(format!("Invalid proxy: {}", reason));
+        let parsed = reqwest::Url::parse(url).map_err(|e| invalid(e.to_string()))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(invalid(
+                "only http:// and https:// proxies are supported".into(),
+            ));
+        }
+        Some(Proxy::all(parsed).map_err(|e| invalid(e.to_string()))?)
+    };
+    let _ = PROXY.set(proxy);
+    Ok(())
+}
+
+/// The bundled TP-Link CA chain, parsed once per process.
+fn ca_certificate() -> Result<Certificate, AppError> {
+    if let Some(cert) = CA_CERT.get() {
+        return Ok(cert.clone());
+    }
+    let cert = Certificate::from_pem(CA_CERT_PEM)?;
+    Ok(CA_CERT.get_or_init(|| cert).clone())
+}
+
+/// The client shared by every `TPLinkApi` and `DeviceClient`, built on first
+/// use. Sharing it lets multi-device commands reuse pooled TLS connections;
+/// request timeouts are set per request.
+pub fn shared_client() -> Result<reqwest::Client, AppError> {
+    if let Some(client) = CLIENT.get() {
+        return Ok(client.clone());
+    }
+    let client = client_builder()?.build()?;
+    Ok(CLIENT.get_or_init(|| client).clone())
+}
+
+/// Client builder with the settings shared by `TPLinkApi` and `DeviceClient`.
+fn client_builder() -> Result<ClientBuilder, AppError> {
+    // The CA chain is added on top of the backend's own roots (webpki for
+    // rustls, the system store for native-tls)
+    let builder = reqwest::Client::builder()
+        .add_root_certificate(ca_certificate()?)
+        .user_agent(USER_AGENT)
+        .connect_timeout(timeouts().connect);
+    #[cfg(feature = "rustls")]
+    let builder = builder.use_rustls_tls();
+    #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+    let builder = builder.use_native_tls();
+    Ok(match PROXY.get() {
+        Some(Some(proxy)) => builder.proxy(proxy.clone()),
+        Some(None) => builder.no_proxy(),
+        None => builder,
+    })
+}
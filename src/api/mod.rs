@@ -2,5 +2,8 @@ pub mod client;
 pub mod cloud_type;
 pub mod device_client;
 pub mod errors;
+pub mod http;
 pub mod response;
+pub mod retry;
 pub mod signing;
+pub mod transport;
@@ -0,0 +1,165 @@
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use super::errors::{error_for_code, ERR_RATE_LIMITED, ERR_REQUEST_TIMEOUT};
+use super::response::ApiResponse;
+use crate::error::AppError;
+
+/// Cloud error codes that usually succeed when the request is repeated.
+const TRANSIENT_ERROR_CODES: &[i32] = &[ERR_REQUEST_TIMEOUT, ERR_RATE_LIMITED];
+
+/// Upper bound on the wait between attempts, however many retries are allowed.
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+static POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+
+/// How often and how patiently failed requests are repeated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts after the first; 0 disables retrying
+    pub retries: u32,
+    /// Wait before the first retry; doubled for each one after
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 2,
+            delay: Duration::from_millis(500),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before retry number `retry` (0-based).
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(MAX_DELAY)
+    }
+}
+
+/// Set the policy for the rest of the process. Only the first call counts.
+pub fn configure(policy: RetryPolicy) {
+    let _ = POLICY.set(policy);
+}
+
+pub fn policy() -> RetryPolicy {
+    POLICY.get().copied().unwrap_or_default()
+}
+
+/// Whether repeating the request that failed with `err` may succeed:
+/// timeouts, dropped connections, 5xx responses, and the cloud's own
+/// timeout and rate-limit codes.
+pub fn is_transient(err: &AppError) -> bool {
+    match err {
+        AppError::Http(e) => {
+            e.is_timeout() || e.is_connect() || e.status().is_some_and(|s| s.is_server_error())
+        }
+        AppError::RateLimited { .. } => true,
+        AppError::Api {
+            error_code: Some(code),
+            ..
+        } => TRANSIENT_ERROR_CODES.contains(code),
+        _ => false,
+    }
+}
+
+/// Turn a response carrying a transient cloud error code into an error so
+/// it gets retried. Other codes are left for the caller to interpret.
+pub fn check_error_code(response: &ApiResponse) -> Result<(), AppError> {
+    if TRANSIENT_ERROR_CODES.contains(&response.error_code) {
+        return Err(error_for_code(response.error_code, response.msg.clone()));
+    }
+    Ok(())
+}
+
+/// Run `attempt` until it succeeds, fails for good, or the configured
+/// retries run out, backing off exponentially between attempts.
+pub async fn with_retries<T, F, Fut>(
+    label: &str,
+    verbose: bool,
+    mut attempt: F,
+) -> Result<T, AppError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, AppError>>,
+{
+    let policy = policy();
+    let mut retry = 0;
+    loop {
+        match attempt().await {
+            Err(e) if retry < policy.retries && e.is_retryable() => {
+                let delay = policy.backoff(retry);
+                if verbose {
+                    eprintln!(
+                        "{} failed: {} (retry {}/{} in {} ms)",
+                        label,
+                        e,
+                        retry + 1,
+                        policy.retries,
+                        delay.as_millis()
+                    );
+                }
+                tokio::time::sleep(delay).await;
+                retry += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::api::errors::ERR_DEVICE_OFFLINE;
+
+    fn api_error(code: i32) -> AppError {
+        error_for_code(code, None)
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            retries: 10,
+            delay: Duration::from_millis(200),
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(800));
+        assert_eq!(policy.backoff(9), MAX_DELAY);
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(&api_error(ERR_REQUEST_TIMEOUT)));
+        assert!(is_transient(&api_error(ERR_RATE_LIMITED)));
+        assert!(!is_transient(&api_error(ERR_DEVICE_OFFLINE)));
+        assert!(!is_transient(&AppError::NotAuthenticated));
+    }
+
+    #[tokio::test]
+    async fn test_with_retries() {
+        let attempts = AtomicU32::new(0);
+        let result = with_retries("test", false, || async {
+            match attempts.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(api_error(ERR_REQUEST_TIMEOUT)),
+                n => Ok(n),
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 1);
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), AppError> = with_retries("test", false, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(api_error(ERR_DEVICE_OFFLINE))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
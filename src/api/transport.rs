@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use md5::{Digest, Md5};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::http;
+use super::signing::SigningHeaders;
+use crate::error::AppError;
+
+/// JSON keys whose values are secret or differ between runs. They are
+/// blanked in fixture files and left out of the fixture key.
+const REDACTED_KEYS: &[&str] = &[
+    "token",
+    "refreshToken",
+    "password",
+    "cloudPassword",
+    "cloudUserName",
+    "email",
+    "accountId",
+    "terminalUUID",
+];
+
+/// Request keys that change from run to run (e.g. a `--since` window) but
+/// are worth keeping in fixture files. Left out of the fixture key only.
+const VOLATILE_KEYS: &[&str] = &["startTime"];
+
+const REDACTED: &str = "[redacted]";
+
+static FIXTURES: OnceLock<Fixtures> = OnceLock::new();
+
+/// Responses handed out per fixture key so far, so a request repeated in
+/// one run (e.g. polling) gets its recorded responses in order.
+static SEEN: Mutex<Option<HashMap<String, usize>>> = Mutex::new(None);
+
+/// Where cloud HTTP traffic is captured to or served from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fixtures {
+    /// Send requests and write each exchange to the directory
+    Record(PathBuf),
+    /// Answer requests from the directory without touching the network
+    Replay(PathBuf),
+}
+
+/// Record or replay for the rest of the process. Only the first call counts.
+pub fn configure_fixtures(fixtures: Fixtures) {
+    let _ = FIXTURES.set(fixtures);
+}
+
+pub fn is_replaying() -> bool {
+    matches!(FIXTURES.get(), Some(Fixtures::Replay(_)))
+}
+
+/// A signed POST to the cloud.
+pub struct PostRequest<'a> {
+    pub url: &'a str,
+    /// Path the request was signed for; part of the fixture key
+    pub url_path: &'a str,
+    pub query: &'a HashMap<String, String>,
+    pub signing: &'a SigningHeaders,
+    pub body: &'a str,
+    pub timeout: Duration,
+}
+
+/// Status and body of a cloud response, live or replayed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+impl PostResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, AppError> {
+        Ok(serde_json::from_str(&self.body)?)
+    }
+
+    /// Error for a non-success response.
+    pub fn error(&self) -> AppError {
+        AppError::Api {
+            message: match reqwest::StatusCode::from_u16(self.status) {
+                Ok(status) => format!("{}: {}", status, self.body),
+                Err(_) => format!("{}: {}", self.status, self.body),
+            },
+            error_code: None,
+        }
+    }
+}
+
+/// One recorded exchange, a file in the fixture directory.
+#[derive(Debug, Serialize, Deserialize)]
+struct Fixture {
+    path: String,
+    request: Value,
+    response: PostResponse,
+}
+
+/// How `TPLinkApi` and `DeviceClient` send their signed POSTs. The default
+/// (`default_transport`) goes through reqwest; tests and library users can
+/// pass their own with `with_transport` to answer requests without a network.
+pub trait HttpTransport: Send + Sync {
+    fn post<'a>(
+        &'a self,
+        request: PostRequest<'a>,
+    ) -> BoxFuture<'a, Result<PostResponse, AppError>>;
+}
+
+/// The transport for this process: fixtures under `--replay`, the shared
+/// reqwest client otherwise.
+pub fn default_transport() -> Result<Arc<dyn HttpTransport>, AppError> {
+    Ok(match FIXTURES.get() {
+        Some(Fixtures::Replay(dir)) => Arc::new(ReplayTransport::new(dir.clone())),
+        _ => Arc::new(ReqwestTransport::new(http::shared_client()?)),
+    })
+}
+
+/// Sends requests over the network, and writes them to the fixture
+/// directory under `--record`.
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+
+    /// 5xx responses become `AppError::Http` so they are retried; the URL is
+    /// dropped since its query carries the token.
+    async fn send(&self, request: PostRequest<'_>) -> Result<PostResponse, AppError> {
+        let response = self
+            .client
+            .post(request.url)
+            .timeout(request.timeout)
+            .query(request.query)
+            .header("Content-Type", "application/json;charset=UTF-8")
+            .header("Content-MD5", &request.signing.content_md5)
+            .header("X-Authorization", &request.signing.x_authorization)
+            .body(request.body.to_string())
+            .send()
+            .await?;
+        if let Err(e) = response.error_for_status_ref() {
+            if e.status().is_some_and(|s| s.is_server_error()) {
+                return Err(e.without_url().into());
+            }
+        }
+        let response = PostResponse {
+            status: response.status().as_u16(),
+            body: response.text().await?,
+        };
+
+        if let Some(Fixtures::Record(dir)) = FIXTURES.get() {
+            record(dir, request.url_path, request.body, &response)?;
+        }
+        Ok(response)
+    }
+}
+
+impl HttpTransport for ReqwestTransport {
+    fn post<'a>(
+        &'a self,
+        request: PostRequest<'a>,
+    ) -> BoxFuture<'a, Result<PostResponse, AppError>> {
+        Box::pin(self.send(request))
+    }
+}
+
+/// Answers requests from a `--record` directory without touching the
+/// network.
+pub struct ReplayTransport {
+    dir: PathBuf,
+}
+
+impl ReplayTransport {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+impl HttpTransport for ReplayTransport {
+    fn post<'a>(
+        &'a self,
+        request: PostRequest<'a>,
+    ) -> BoxFuture<'a, Result<PostResponse, AppError>> {
+        Box::pin(async move { replay(&self.dir, request.url_path, request.body) })
+    }
+}
+
+fn record(dir: &Path, url_path: &str, body: &str, response: &PostResponse) -> Result<(), AppError> {
+    let index = next_index(&fixture_key(url_path, body));
+    write_fixture(dir, url_path, body, index, response)
+}
+
+/// Write the `index`th exchange for this request, redacted.
+fn write_fixture(
+    dir: &Path,
+    url_path: &str,
+    body: &str,
+    index: usize,
+    response: &PostResponse,
+) -> Result<(), AppError> {
+    let fixture = Fixture {
+        path: url_path.to_string(),
+        request: redact_body(body, REDACTED_KEYS),
+        response: PostResponse {
+            status: response.status,
+            body: match serde_json::from_str::<Value>(&response.body) {
+                Ok(mut value) => {
+                    redact(&mut value, REDACTED_KEYS);
+                    value.to_string()
+                }
+                Err(_) => response.body.clone(),
+            },
+        },
+    };
+    std::fs::create_dir_all(dir)?;
+    let file = dir.join(format!("{}-{}.json", fixture_key(url_path, body), index));
+    std::fs::write(file, serde_json::to_string_pretty(&fixture)?)?;
+    Ok(())
+}
+
+/// The recorded response for this request. A request repeated more often
+/// than it was recorded gets the last recorded response again.
+fn replay(dir: &Path, url_path: &str, body: &str) -> Result<PostResponse, AppError> {
+    let key = fixture_key(url_path, body);
+    let index = next_index(&key);
+    let file = (0..=index)
+        .rev()
+        .map(|i| dir.join(format!("{}-{}.json", key, i)))
+        .find(|f| f.exists())
+        .ok_or_else(|| AppError::Api {
+            message: format!(
+                "No recorded response for POST {} (fixture {}) in {}",
+                url_path,
+                key,
+                dir.display()
+            ),
+            error_code: None,
+        })?;
+    let fixture: Fixture = serde_json::from_str(&std::fs::read_to_string(file)?)?;
+    Ok(fixture.response)
+}
+
+fn next_index(key: &str) -> usize {
+    let mut seen = SEEN.lock().unwrap_or_else(|p| p.into_inner());
+    let count = seen
+        .get_or_insert_with(HashMap::new)
+        .entry(key.to_string())
+        .or_insert(0);
+    *count += 1;
+    *count - 1
+}
+
+/// Identifies a request across runs: its path and body with the secret and
+/// per-run values blanked.
+pub fn fixture_key(url_path: &str, body: &str) -> String {
+    let mut hasher = Md5::new();
+    hasher.update(url_path.as_bytes());
+    hasher.update(b"\n");
+    let mut key = redact_body(body, REDACTED_KEYS);
+    redact(&mut key, VOLATILE_KEYS);
+    hasher.update(key.to_string().as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+fn redact_body(body: &str, keys: &[&str]) -> Value {
+    match serde_json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            redact(&mut value, keys);
+            value
+        }
+        Err(_) => Value::String(body.to_string()),
+    }
+}
+
+/// Blank the values of `keys` anywhere in `value`.
+fn redact(value: &mut Value, keys: &[&str]) {
+    match value {
+        Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                if keys.contains(&key.as_str()) {
+                    *field = Value::String(REDACTED.into());
+                } else {
+                    redact(field, keys);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| redact(item, keys)),
+        _ => {}
+    }
+}
+
+/// Canned responses by URL path, for tests of the API clients.
+#[cfg(test)]
+pub(crate) struct MockTransport {
+    responses: HashMap<&'static str, &'static str>,
+    /// Path and body of every request, in order
+    pub requests: Mutex<Vec<(String, Value)>>,
+}
+
+#[cfg(test)]
+impl MockTransport {
+    pub fn new(responses: &[(&'static str, &'static str)]) -> Arc<Self> {
+        Arc::new(Self {
+            responses: responses.iter().copied().collect(),
+            requests: Mutex::new(Vec::new()),
+        })
+    }
+}
+
+#[cfg(test)]
+impl HttpTransport for MockTransport {
+    fn post<'a>(
+        &'a self,
+        request: PostRequest<'a>,
+    ) -> BoxFuture<'a, Result<PostResponse, AppError>> {
+        self.requests.lock().unwrap().push((
+            request.url_path.to_string(),
+            serde_json::from_str(request.body).unwrap_or_default(),
+        ));
+        let response = match self.responses.get(request.url_path) {
+            Some(body) => PostResponse {
+                status: 200,
+                body: body.to_string(),
+            },
+            None => PostResponse {
+                status: 404,
+                body: String::new(),
+            },
+        };
+        Box::pin(async move { Ok(response) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixture_key_ignores_secrets() {
+        let login = |uuid: &str| {
+            format!(
+                r#"{{"appType":"Kasa_Android_Mix","cloudPassword":"hunter2","terminalUUID":"{}"}}"#,
+                uuid
+            )
+        };
+        assert_eq!(
+            fixture_key("/api/v2/account/login", &login("a")),
+            fixture_key("/api/v2/account/login", &login("b"))
+        );
+        assert_ne!(
+            fixture_key("/", r#"{"method":"getDeviceList"}"#),
+            fixture_key("/", r#"{"method":"passthrough"}"#)
+        );
+        assert_eq!(
+            fixture_key("/messages", r#"{"startTime":1}"#),
+            fixture_key("/messages", r#"{"startTime":2}"#)
+        );
+        let redacted = redact_body(&login("a"), REDACTED_KEYS).to_string();
+        assert!(!redacted.contains("hunter2"));
+    }
+
+    #[test]
+    fn test_record_then_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let body = r#"{"method":"getDeviceList"}"#;
+        let first = PostResponse {
+            status: 200,
+            body: r#"{"error_code":0,"result":{"token":"secret","n":1}}"#.into(),
+        };
+        let second = PostResponse {
+            status: 200,
+            body: r#"{"error_code":0,"result":{"n":2}}"#.into(),
+        };
+        write_fixture(dir.path(), "/list-test", body, 0, &first).unwrap();
+        write_fixture(dir.path(), "/list-test", body, 1, &second).unwrap();
+
+        let replayed: Vec<Value> = (0..3)
+            .map(|_| {
+                replay(dir.path(), "/list-test", body)
+                    .unwrap()
+                    .json()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(replayed[0]["result"]["token"], REDACTED);
+        assert_eq!(replayed[1]["result"]["n"], 2);
+        assert_eq!(replayed[2]["result"]["n"], 2);
+        assert!(replay(dir.path(), "/other", body).is_err());
+    }
+}
@@ -2,10 +2,14 @@ use std::env;
 
 use crate::api::client::TPLinkApi;
 use crate::api::cloud_type::CloudType;
+use crate::api::errors::ERR_REFRESH_TOKEN_EXPIRED;
 use crate::auth::keychain;
+use crate::auth::login::{log_in_to, LoginClouds};
 use crate::auth::token::TokenSet;
 use crate::error::AppError;
+use crate::timing;
 
+#[derive(Clone)]
 pub struct AuthContext {
     pub token: String,
     pub refresh_token: Option<String>,
@@ -15,6 +19,23 @@ pub struct AuthContext {
     pub tapo_token: Option<String>,
     pub tapo_refresh_token: Option<String>,
     pub tapo_regional_url: Option<String>,
+    pub password: Option<String>,
+}
+
+impl From<TokenSet> for AuthContext {
+    fn from(tokens: TokenSet) -> Self {
+        Self {
+            token: tokens.token,
+            refresh_token: tokens.refresh_token,
+            regional_url: tokens.regional_url,
+            term_id: tokens.term_id,
+            username: tokens.username,
+            tapo_token: tokens.tapo_token,
+            tapo_refresh_token: tokens.tapo_refresh_token,
+            tapo_regional_url: tokens.tapo_regional_url,
+            password: tokens.password,
+        }
+    }
 }
 
 impl AuthContext {
@@ -28,35 +49,48 @@ impl AuthContext {
             tapo_token: self.tapo_token.clone(),
             tapo_refresh_token: self.tapo_refresh_token.clone(),
             tapo_regional_url: self.tapo_regional_url.clone(),
+            password: self.password.clone(),
         }
     }
 
+    /// False after `tplc login --cloud tapo`.
+    pub fn has_kasa(&self) -> bool {
+        !self.token.is_empty()
+    }
+
     pub fn has_tapo(&self) -> bool {
         self.tapo_token.as_ref().is_some_and(|t| !t.is_empty())
     }
+
+    /// Token and regional URL for `cloud`, if logged in to it.
+    pub fn session(&self, cloud: CloudType) -> Option<(&str, &str)> {
+        match cloud {
+            CloudType::Kasa if self.has_kasa() => Some((&self.token, &self.regional_url)),
+            CloudType::Kasa => None,
+            CloudType::Tapo => Some((
+                self.tapo_token.as_deref()?,
+                self.tapo_regional_url.as_deref()?,
+            )),
+        }
+    }
 }
 
 /// Get stored authentication context, auto-refreshing if needed.
 pub async fn get_auth_context(_verbose: bool) -> Result<AuthContext, AppError> {
+    let _span = timing::span("auth");
     let tokens = keychain::get_tokens()?.ok_or(AppError::NotAuthenticated)?;
 
-    if tokens.token.is_empty() {
+    let auth = AuthContext::from(tokens);
+    if !auth.has_kasa() && !auth.has_tapo() {
         return Err(AppError::NotAuthenticated);
     }
 
-    Ok(AuthContext {
-        token: tokens.token,
-        refresh_token: tokens.refresh_token,
-        regional_url: tokens.regional_url,
-        term_id: tokens.term_id,
-        username: tokens.username,
-        tapo_token: tokens.tapo_token,
-        tapo_refresh_token: tokens.tapo_refresh_token,
-        tapo_regional_url: tokens.tapo_regional_url,
-    })
+    Ok(auth)
 }
 
-/// Attempt to refresh the Kasa token and update keychain.
+/// Attempt to refresh the Kasa token and update keychain. If the refresh
+/// token itself has expired, log in again when credentials are available
+/// (see `relogin`).
 pub async fn refresh_auth(auth: &mut AuthContext, verbose: bool) -> Result<(), AppError> {
     let refresh_token = auth
         .refresh_token
@@ -70,7 +104,11 @@ pub async fn refresh_auth(auth: &mut AuthContext, verbose: bool) -> Result<(), A
         CloudType::Kasa,
     )?;
 
-    let result = api.refresh_token(refresh_token).await?;
+    let result = match api.refresh_token(refresh_token).await {
+        Ok(result) => result,
+        Err(err) if is_refresh_expired(&err) => return relogin(auth, err, verbose).await,
+        Err(err) => return Err(err),
+    };
 
     auth.token = result.token;
     auth.refresh_token = result.refresh_token;
@@ -81,7 +119,8 @@ pub async fn refresh_auth(auth: &mut AuthContext, verbose: bool) -> Result<(), A
     Ok(())
 }
 
-/// Attempt to refresh the Tapo token and update keychain.
+/// Attempt to refresh the Tapo token and update keychain, logging in again
+/// like `refresh_auth` when the refresh token has expired.
 pub async fn refresh_tapo_auth(auth: &mut AuthContext, verbose: bool) -> Result<(), AppError> {
     let refresh_token = auth
         .tapo_refresh_token
@@ -100,7 +139,11 @@ pub async fn refresh_tapo_auth(auth: &mut AuthContext, verbose: bool) -> Result<
         CloudType::Tapo,
     )?;
 
-    let result = api.refresh_token(refresh_token).await?;
+    let result = match api.refresh_token(refresh_token).await {
+        Ok(result) => result,
+        Err(err) if is_refresh_expired(&err) => return relogin(auth, err, verbose).await,
+        Err(err) => return Err(err),
+    };
 
     auth.tapo_token = Some(result.token);
     auth.tapo_refresh_token = result.refresh_token;
@@ -111,6 +154,43 @@ pub async fn refresh_tapo_auth(auth: &mut AuthContext, verbose: bool) -> Result<
     Ok(())
 }
 
+fn is_refresh_expired(err: &AppError) -> bool {
+    matches!(
+        err,
+        AppError::TokenExpired {
+            error_code: Some(ERR_REFRESH_TOKEN_EXPIRED),
+            ..
+        }
+    )
+}
+
+/// Replace an expired session with a fresh login to the same clouds, using `TPLC_USERNAME`/
+/// `TPLC_PASSWORD` or else the password kept by `tplc login --save-password`.
+/// Without either, `err` is returned unchanged. MFA cannot be answered
+/// here, so an account that asks for it still needs `tplc login`.
+async fn relogin(auth: &mut AuthContext, err: AppError, verbose: bool) -> Result<(), AppError> {
+    let saved = auth
+        .password
+        .clone()
+        .map(|password| (auth.username.clone(), password));
+    let Some((username, password)) = credentials_from_env().or(saved) else {
+        return Err(err);
+    };
+    if verbose {
+        eprintln!("Refresh token expired; logging in again as {}", username);
+    }
+
+    let clouds = match (auth.has_kasa(), auth.has_tapo()) {
+        (false, true) => LoginClouds::Tapo,
+        _ => LoginClouds::Both,
+    };
+    let term_id = Some(auth.term_id.clone());
+    let mut tokens = log_in_to(&username, &password, clouds, term_id, None, verbose).await?;
+    tokens.password = auth.password.take();
+    *auth = AuthContext::from(tokens);
+    keychain::store_tokens(&auth.to_token_set())
+}
+
 /// Get credentials from env vars for login, or None if not set.
 pub fn credentials_from_env() -> Option<(String, String)> {
     let username = env::var("TPLC_USERNAME").ok()?;
@@ -120,3 +200,20 @@ pub fn credentials_from_env() -> Option<(String, String)> {
     }
     Some((username, password))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::errors::ERR_TOKEN_EXPIRED;
+
+    #[test]
+    fn test_is_refresh_expired() {
+        let expired = |code| AppError::TokenExpired {
+            message: "expired".into(),
+            error_code: Some(code),
+        };
+        assert!(is_refresh_expired(&expired(ERR_REFRESH_TOKEN_EXPIRED)));
+        assert!(!is_refresh_expired(&expired(ERR_TOKEN_EXPIRED)));
+        assert!(!is_refresh_expired(&AppError::NotAuthenticated));
+    }
+}
@@ -0,0 +1,168 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::auth::token::TokenSet;
+use crate::error::AppError;
+
+/// PBKDF2 rounds for new exports. Imports use the count stored in the file.
+const PBKDF2_ROUNDS: u32 = 600_000;
+/// Round counts accepted on import: enough to slow guessing, few enough that
+/// a crafted file cannot stall the import.
+const ACCEPTED_ROUNDS: std::ops::RangeInclusive<u32> = 10_000..=10_000_000;
+const KDF: &str = "pbkdf2-sha256";
+
+/// A token set encrypted with a passphrase by `tplc auth export --encrypt`:
+/// AES-256-GCM with a key derived by PBKDF2-HMAC-SHA256. Binary fields are
+/// base64.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SealedTokens {
+    pub kdf: String,
+    pub rounds: u32,
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// Encrypt `tokens` with `passphrase`.
+pub fn seal(tokens: &TokenSet, passphrase: &str) -> Result<SealedTokens, AppError> {
+    seal_with_rounds(tokens, passphrase, PBKDF2_ROUNDS)
+}
+
+fn seal_with_rounds(
+    tokens: &TokenSet,
+    passphrase: &str,
+    rounds: u32,
+) -> Result<SealedTokens, AppError> {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut nonce);
+
+    let cipher = cipher(passphrase, &salt, rounds);
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            serde_json::to_vec(tokens)?.as_slice(),
+        )
+        .map_err(|_| AppError::InvalidInput("Could not encrypt the tokens".into()))?;
+
+    Ok(SealedTokens {
+        kdf: KDF.into(),
+        rounds,
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    })
+}
+
+/// Decrypt an export sealed with `passphrase`.
+pub fn open(sealed: &SealedTokens, passphrase: &str) -> Result<TokenSet, AppError> {
+    if sealed.kdf != KDF {
+        return Err(AppError::InvalidInput(format!(
+            "Unsupported key derivation '{}' in token export",
+            sealed.kdf
+        )));
+    }
+    if !ACCEPTED_ROUNDS.contains(&sealed.rounds) {
+        return Err(AppError::InvalidInput(format!(
+            "Token export uses {} PBKDF2 rounds; expected {} to {}",
+            sealed.rounds,
+            ACCEPTED_ROUNDS.start(),
+            ACCEPTED_ROUNDS.end()
+        )));
+    }
+    let decode = |field: &str| {
+        STANDARD
+            .decode(field)
+            .map_err(|e| AppError::InvalidInput(format!("Damaged token export: {}", e)))
+    };
+    let salt = decode(&sealed.salt)?;
+    let nonce = decode(&sealed.nonce)?;
+    if nonce.len() != 12 {
+        return Err(AppError::InvalidInput(
+            "Damaged token export: bad nonce".into(),
+        ));
+    }
+
+    let plaintext = cipher(passphrase, &salt, sealed.rounds)
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            decode(&sealed.ciphertext)?.as_slice(),
+        )
+        .map_err(|_| {
+            AppError::InvalidInput("Wrong passphrase, or the token export is damaged".into())
+        })?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+/// Read an export: the plain token set JSON (as kept in `tokens.json`), or
+/// `SealedTokens`, in which case `passphrase` is asked for the passphrase.
+pub fn parse(
+    contents: &str,
+    passphrase: impl FnOnce() -> Result<String, AppError>,
+) -> Result<TokenSet, AppError> {
+    let value: serde_json::Value = serde_json::from_str(contents)?;
+    if value.get("ciphertext").is_some() {
+        let sealed: SealedTokens = serde_json::from_value(value)?;
+        open(&sealed, &passphrase()?)
+    } else {
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+fn cipher(passphrase: &str, salt: &[u8], rounds: u32) -> Aes256Gcm {
+    let key = pbkdf2::pbkdf2_hmac_array::<Sha256, 32>(passphrase.as_bytes(), salt, rounds);
+    Aes256Gcm::new(&key.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens() -> TokenSet {
+        TokenSet {
+            token: "t".into(),
+            refresh_token: Some("r".into()),
+            username: "me@example.com".into(),
+            regional_url: "https://n-wap.tplinkcloud.com".into(),
+            term_id: "term".into(),
+            tapo_token: None,
+            tapo_refresh_token: None,
+            tapo_regional_url: None,
+            password: None,
+        }
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let sealed = seal_with_rounds(&tokens(), "hunter2", 10_000).unwrap();
+        let json = serde_json::to_string(&sealed).unwrap();
+        assert!(!json.contains("me@example.com"));
+
+        let opened = parse(&json, || Ok("hunter2".into())).unwrap();
+        assert_eq!(opened.username, "me@example.com");
+        assert!(parse(&json, || Ok("wrong".into())).is_err());
+
+        // A plain export needs no passphrase
+        let plain = serde_json::to_string(&tokens()).unwrap();
+        let opened = parse(&plain, || panic!("not encrypted")).unwrap();
+        assert_eq!(opened.refresh_token.as_deref(), Some("r"));
+    }
+
+    #[test]
+    fn test_open_rejects_out_of_range_rounds() {
+        let mut sealed = seal_with_rounds(&tokens(), "hunter2", 10_000).unwrap();
+        sealed.rounds = 1000;
+        assert!(matches!(
+            open(&sealed, "hunter2"),
+            Err(AppError::InvalidInput(msg)) if msg.contains("PBKDF2 rounds")
+        ));
+        sealed.rounds = u32::MAX;
+        assert!(open(&sealed, "hunter2").is_err());
+    }
+}
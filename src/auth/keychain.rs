@@ -1,95 +1,61 @@
-use keyring::Entry;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
 
 use crate::auth::token::TokenSet;
+#[cfg(feature = "cli")]
+use crate::auth::token_store::KeyringStore;
+use crate::auth::token_store::{MemoryStore, TokenStore};
 use crate::error::AppError;
 
-const SERVICE: &str = "tplc";
+/// The token store for this process. Unset means the default: the OS
+/// keychain, or memory when built without the `cli` feature.
+static STORE: RwLock<Option<Arc<dyn TokenStore>>> = RwLock::new(None);
 
-fn entry(key: &str) -> Result<Entry, AppError> {
-    Entry::new(SERVICE, key).map_err(|e| AppError::Keychain(e.to_string()))
+/// Set by `use_memory`: this process must leave no login state behind.
+static EPHEMERAL: AtomicBool = AtomicBool::new(false);
+
+/// Keep tokens in `store` from now on (`--token-store`, or a library user's
+/// own store). Tokens already in the previous store are not carried over.
+pub fn set_store(store: Arc<dyn TokenStore>) {
+    *STORE.write().unwrap_or_else(|p| p.into_inner()) = Some(store);
 }
 
-fn get_value(key: &str) -> Result<Option<String>, AppError> {
-    let entry = entry(key)?;
-    match entry.get_password() {
-        Ok(val) => Ok(Some(val)),
-        Err(keyring::Error::NoEntry) => Ok(None),
-        Err(e) => Err(AppError::Keychain(e.to_string())),
-    }
+/// With `--ephemeral-auth`, tokens live in memory for the life of the
+/// process and the keychain is never touched.
+pub fn use_memory() {
+    set_store(Arc::new(MemoryStore::default()));
+    EPHEMERAL.store(true, Ordering::Relaxed);
 }
 
-fn set_value(key: &str, value: &str) -> Result<(), AppError> {
-    let entry = entry(key)?;
-    entry
-        .set_password(value)
-        .map_err(|e| AppError::Keychain(e.to_string()))
+/// Tokens are kept in memory only (`use_memory`), so nothing else about the
+/// login, such as trusted terminals, may be written to disk either.
+pub fn is_ephemeral() -> bool {
+    EPHEMERAL.load(Ordering::Relaxed)
 }
 
-fn delete_value(key: &str) -> Result<(), AppError> {
-    let entry = entry(key)?;
-    match entry.delete_credential() {
-        Ok(()) => Ok(()),
-        Err(keyring::Error::NoEntry) => Ok(()),
-        Err(e) => Err(AppError::Keychain(e.to_string())),
-    }
+pub fn store() -> Arc<dyn TokenStore> {
+    let mut store = STORE.write().unwrap_or_else(|p| p.into_inner());
+    store.get_or_insert_with(default_store).clone()
 }
 
-pub fn store_tokens(tokens: &TokenSet) -> Result<(), AppError> {
-    set_value("token", &tokens.token)?;
-    if let Some(ref rt) = tokens.refresh_token {
-        set_value("refresh_token", rt)?;
-    }
-    set_value("username", &tokens.username)?;
-    set_value("regional_url", &tokens.regional_url)?;
-    set_value("term_id", &tokens.term_id)?;
+#[cfg(feature = "cli")]
+fn default_store() -> Arc<dyn TokenStore> {
+    Arc::new(KeyringStore)
+}
 
-    // Tapo tokens
-    if let Some(ref tt) = tokens.tapo_token {
-        set_value("tapo_token", tt)?;
-    }
-    if let Some(ref trt) = tokens.tapo_refresh_token {
-        set_value("tapo_refresh_token", trt)?;
-    }
-    if let Some(ref tru) = tokens.tapo_regional_url {
-        set_value("tapo_regional_url", tru)?;
-    }
+#[cfg(not(feature = "cli"))]
+fn default_store() -> Arc<dyn TokenStore> {
+    Arc::new(MemoryStore::default())
+}
 
-    Ok(())
+pub fn store_tokens(tokens: &TokenSet) -> Result<(), AppError> {
+    store().save(tokens)
 }
 
 pub fn get_tokens() -> Result<Option<TokenSet>, AppError> {
-    let token = match get_value("token")? {
-        Some(t) => t,
-        None => return Ok(None),
-    };
-    let username = get_value("username")?.unwrap_or_default();
-    let regional_url = get_value("regional_url")?.unwrap_or_default();
-    let term_id = get_value("term_id")?.unwrap_or_default();
-    let refresh_token = get_value("refresh_token")?;
-    let tapo_token = get_value("tapo_token")?;
-    let tapo_refresh_token = get_value("tapo_refresh_token")?;
-    let tapo_regional_url = get_value("tapo_regional_url")?;
-
-    Ok(Some(TokenSet {
-        token,
-        refresh_token,
-        username,
-        regional_url,
-        term_id,
-        tapo_token,
-        tapo_refresh_token,
-        tapo_regional_url,
-    }))
+    store().load()
 }
 
 pub fn clear_tokens() -> Result<(), AppError> {
-    delete_value("token")?;
-    delete_value("refresh_token")?;
-    delete_value("username")?;
-    delete_value("regional_url")?;
-    delete_value("term_id")?;
-    delete_value("tapo_token")?;
-    delete_value("tapo_refresh_token")?;
-    delete_value("tapo_regional_url")?;
-    Ok(())
+    store().clear()
 }
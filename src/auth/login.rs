@@ -0,0 +1,106 @@
+use crate::api::client::{LoginResult, TPLinkApi};
+use crate::api::cloud_type::CloudType;
+use crate::auth::token::TokenSet;
+use crate::error::AppError;
+
+/// Asked for the MFA code when a cloud requires one.
+pub type MfaPrompt<'a> = &'a (dyn Fn(CloudType) -> Result<String, AppError> + Sync);
+
+/// Which clouds `log_in_to` signs in to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum LoginClouds {
+    /// Kasa only
+    Kasa,
+    /// Tapo only
+    Tapo,
+    /// Kasa, then Tapo if it accepts the same credentials
+    #[default]
+    Both,
+}
+
+/// Log in to Kasa (required) and Tapo (best-effort). When a cloud asks for
+/// an MFA code, `mfa` supplies it; without one, a Kasa MFA challenge is an
+/// error and a Tapo one skips Tapo.
+pub async fn log_in(
+    username: &str,
+    password: &str,
+    mfa: Option<MfaPrompt<'_>>,
+    verbose: bool,
+) -> Result<TokenSet, AppError> {
+    log_in_to(username, password, LoginClouds::Both, None, mfa, verbose).await
+}
+
+/// Log in to `clouds`. With `Both`, Kasa is required and Tapo best-effort as
+/// in `log_in`; a single cloud is required. `term_id` reuses the terminal ID
+/// of an existing session, e.g. when adding Tapo to a Kasa login. Token
+/// fields of a cloud that was not logged in to are left empty.
+pub async fn log_in_to(
+    username: &str,
+    password: &str,
+    clouds: LoginClouds,
+    term_id: Option<String>,
+    mfa: Option<MfaPrompt<'_>>,
+    verbose: bool,
+) -> Result<TokenSet, AppError> {
+    let mut term_id = term_id;
+
+    let kasa_result = if clouds == LoginClouds::Tapo {
+        None
+    } else {
+        let mut kasa_api = TPLinkApi::new(None, verbose, term_id.clone(), CloudType::Kasa)?;
+        let result = log_in_cloud(&mut kasa_api, username, password, mfa).await?;
+        term_id = Some(kasa_api.term_id().to_string());
+        Some(result)
+    };
+
+    let tapo_result = if clouds == LoginClouds::Kasa {
+        None
+    } else {
+        let mut tapo_api = TPLinkApi::new(None, verbose, term_id.clone(), CloudType::Tapo)?;
+        term_id = Some(tapo_api.term_id().to_string());
+        match log_in_cloud(&mut tapo_api, username, password, mfa).await {
+            Ok(result) => Some(result),
+            Err(e) if clouds == LoginClouds::Tapo => return Err(e),
+            // Best-effort alongside Kasa, don't fail if Tapo login fails
+            Err(e) => {
+                if verbose {
+                    eprintln!("Tapo login failed (non-fatal): {}", e);
+                }
+                None
+            }
+        }
+    };
+
+    let (token, refresh_token, regional_url) = match kasa_result {
+        Some(kasa) => (kasa.token, kasa.refresh_token, kasa.regional_url),
+        None => Default::default(),
+    };
+    Ok(TokenSet {
+        token,
+        refresh_token,
+        username: username.to_string(),
+        regional_url,
+        term_id: term_id.unwrap_or_default(),
+        tapo_token: tapo_result.as_ref().map(|r| r.token.clone()),
+        tapo_refresh_token: tapo_result.as_ref().and_then(|r| r.refresh_token.clone()),
+        tapo_regional_url: tapo_result.map(|r| r.regional_url),
+        password: None,
+    })
+}
+
+/// Log in to one cloud, answering an MFA challenge through `mfa` if given.
+async fn log_in_cloud(
+    api: &mut TPLinkApi,
+    username: &str,
+    password: &str,
+    mfa: Option<MfaPrompt<'_>>,
+) -> Result<LoginResult, AppError> {
+    match (api.login(username, password).await, mfa) {
+        (Err(AppError::MfaRequired { .. }), Some(mfa)) => {
+            let mfa_code = mfa(api.cloud_type())?;
+            api.verify_mfa(username, password, &mfa_code).await
+        }
+        (result, _) => result,
+    }
+}
@@ -1,3 +1,7 @@
 pub mod credentials;
+#[cfg(feature = "cli")]
+pub mod export;
 pub mod keychain;
+pub mod login;
 pub mod token;
+pub mod token_store;
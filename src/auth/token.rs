@@ -10,4 +10,8 @@ pub struct TokenSet {
     pub tapo_token: Option<String>,
     pub tapo_refresh_token: Option<String>,
     pub tapo_regional_url: Option<String>,
+    /// Kept only after `tplc login --save-password`, to log in again once
+    /// the refresh token expires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
 }
@@ -0,0 +1,308 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::Deserialize;
+
+use crate::auth::token::TokenSet;
+use crate::error::AppError;
+use crate::store;
+
+/// Default file for `FileStore`, in the config directory.
+pub const TOKENS_FILE: &str = "tokens.json";
+
+/// Where login tokens are kept between runs. The process-wide store is
+/// chosen with `keychain::set_store`; library users can supply their own
+/// (e.g. a secrets manager) by implementing this.
+pub trait TokenStore: Send + Sync {
+    /// Short name shown by `tplc status`, e.g. "keychain".
+    fn name(&self) -> &'static str;
+
+    /// The stored tokens, or `None` when nobody is logged in.
+    fn load(&self) -> Result<Option<TokenSet>, AppError>;
+
+    fn save(&self, tokens: &TokenSet) -> Result<(), AppError>;
+
+    /// Forget the tokens. Clearing an empty store is not an error.
+    fn clear(&self) -> Result<(), AppError>;
+}
+
+/// The built-in stores, for `--token-store` and `token_store` in config.toml.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum TokenStoreKind {
+    /// The OS keychain (needs the `cli` feature)
+    Keyring,
+    /// tokens.json in the config directory, readable only by its owner
+    File,
+    /// This process only
+    Memory,
+}
+
+impl TokenStoreKind {
+    pub fn open(self) -> Result<Box<dyn TokenStore>, AppError> {
+        match self {
+            #[cfg(feature = "cli")]
+            TokenStoreKind::Keyring => Ok(Box::new(KeyringStore)),
+            #[cfg(not(feature = "cli"))]
+            TokenStoreKind::Keyring => Err(AppError::Keychain(
+                "built without keychain support (the `cli` feature)".into(),
+            )),
+            TokenStoreKind::File => Ok(Box::new(FileStore::new(
+                store::config_dir()?.join(TOKENS_FILE),
+            ))),
+            TokenStoreKind::Memory => Ok(Box::new(MemoryStore::default())),
+        }
+    }
+}
+
+/// Tokens kept for the life of the process (`--ephemeral-auth`, `--replay`,
+/// `Client::from_tokens`).
+#[derive(Default)]
+pub struct MemoryStore {
+    tokens: Mutex<Option<TokenSet>>,
+}
+
+impl MemoryStore {
+    fn tokens(&self) -> std::sync::MutexGuard<'_, Option<TokenSet>> {
+        self.tokens
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl TokenStore for MemoryStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn load(&self) -> Result<Option<TokenSet>, AppError> {
+        Ok(self.tokens().clone())
+    }
+
+    fn save(&self, tokens: &TokenSet) -> Result<(), AppError> {
+        *self.tokens() = Some(tokens.clone());
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<(), AppError> {
+        *self.tokens() = None;
+        Ok(())
+    }
+}
+
+/// Tokens in a JSON file, for headless machines without a keychain. On Unix
+/// the file is created readable by its owner only.
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl TokenStore for FileStore {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn load(&self) -> Result<Option<TokenSet>, AppError> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&self, tokens: &TokenSet) -> Result<(), AppError> {
+        write_private(&self.path, &serde_json::to_string_pretty(tokens)?)
+    }
+
+    fn clear(&self) -> Result<(), AppError> {
+        match std::fs::remove_file(&self.path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Write `contents` to `path`, creating its directory. On Unix a new file is
+/// readable by its owner only.
+pub(crate) fn write_private(path: &Path, contents: &str) -> Result<(), AppError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    // The mode above only applies when the file is created
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    std::io::Write::write_all(&mut file, contents.as_bytes())?;
+    Ok(())
+}
+
+/// The OS keychain: the token set as JSON in one "tokens" entry under the
+/// "tplc" service.
+#[cfg(feature = "cli")]
+pub struct KeyringStore;
+
+#[cfg(feature = "cli")]
+impl KeyringStore {
+    const SERVICE: &'static str = "tplc";
+    const TOKENS: &'static str = "tokens";
+    /// One entry per field, as written by earlier releases.
+    const LEGACY_KEYS: [&'static str; 8] = [
+        "token",
+        "refresh_token",
+        "username",
+        "regional_url",
+        "term_id",
+        "tapo_token",
+        "tapo_refresh_token",
+        "tapo_regional_url",
+    ];
+
+    fn entry(key: &str) -> Result<keyring::Entry, AppError> {
+        keyring::Entry::new(Self::SERVICE, key).map_err(|e| AppError::Keychain(e.to_string()))
+    }
+
+    fn get_value(key: &str) -> Result<Option<String>, AppError> {
+        match Self::entry(key)?.get_password() {
+            Ok(val) => Ok(Some(val)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(AppError::Keychain(e.to_string())),
+        }
+    }
+
+    fn set_value(key: &str, value: &str) -> Result<(), AppError> {
+        Self::entry(key)?
+            .set_password(value)
+            .map_err(|e| AppError::Keychain(e.to_string()))
+    }
+
+    fn delete_value(key: &str) -> Result<(), AppError> {
+        match Self::entry(key)?.delete_credential() {
+            Ok(()) => Ok(()),
+            Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(AppError::Keychain(e.to_string())),
+        }
+    }
+
+    /// Tokens in the per-field layout, if a login from an earlier release
+    /// left them.
+    fn load_legacy() -> Result<Option<TokenSet>, AppError> {
+        let token = match Self::get_value("token")? {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        Ok(Some(TokenSet {
+            token,
+            refresh_token: Self::get_value("refresh_token")?,
+            username: Self::get_value("username")?.unwrap_or_default(),
+            regional_url: Self::get_value("regional_url")?.unwrap_or_default(),
+            term_id: Self::get_value("term_id")?.unwrap_or_default(),
+            tapo_token: Self::get_value("tapo_token")?,
+            tapo_refresh_token: Self::get_value("tapo_refresh_token")?,
+            tapo_regional_url: Self::get_value("tapo_regional_url")?,
+            password: None,
+        }))
+    }
+}
+
+#[cfg(feature = "cli")]
+impl TokenStore for KeyringStore {
+    fn name(&self) -> &'static str {
+        "keychain"
+    }
+
+    fn load(&self) -> Result<Option<TokenSet>, AppError> {
+        if let Some(json) = Self::get_value(Self::TOKENS)? {
+            return Ok(Some(serde_json::from_str(&json)?));
+        }
+        // Move an old per-field login into the single entry on first use
+        let Some(tokens) = Self::load_legacy()? else {
+            return Ok(None);
+        };
+        self.save(&tokens)?;
+        for key in Self::LEGACY_KEYS {
+            Self::delete_value(key)?;
+        }
+        Ok(Some(tokens))
+    }
+
+    fn save(&self, tokens: &TokenSet) -> Result<(), AppError> {
+        Self::set_value(Self::TOKENS, &serde_json::to_string(tokens)?)
+    }
+
+    fn clear(&self) -> Result<(), AppError> {
+        Self::delete_value(Self::TOKENS)?;
+        for key in Self::LEGACY_KEYS {
+            Self::delete_value(key)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens() -> TokenSet {
+        TokenSet {
+            token: "t".into(),
+            refresh_token: Some("r".into()),
+            username: "me@example.com".into(),
+            regional_url: "https://n-wap.tplinkcloud.com".into(),
+            term_id: "term".into(),
+            tapo_token: None,
+            tapo_refresh_token: None,
+            tapo_regional_url: None,
+            password: None,
+        }
+    }
+
+    #[test]
+    fn test_file_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::new(dir.path().join("nested").join(TOKENS_FILE));
+        assert!(store.load().unwrap().is_none());
+        store.save(&tokens()).unwrap();
+        assert_eq!(store.load().unwrap().unwrap().username, "me@example.com");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&store.path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        store.clear().unwrap();
+        store.clear().unwrap();
+        assert!(store.load().unwrap().is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_private_tightens_existing_file() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.json");
+        std::fs::write(&path, "old").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private(&path, "new").unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+    }
+}
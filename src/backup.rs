@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::api::cloud_type::CloudType;
+use crate::resolve::DeviceEntry;
+use crate::store::{DeviceTags, LocalAlias};
+
+/// Bumped when the backup layout changes incompatibly.
+pub const BACKUP_VERSION: u32 = 1;
+
+/// Local state and per-device settings captured by `tplc backup create`.
+/// Tokens are never included.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Backup {
+    pub version: u32,
+    pub created_at: String,
+    /// Contents of config.toml, if one existed
+    pub config_toml: Option<String>,
+    #[serde(default)]
+    pub aliases: BTreeMap<String, LocalAlias>,
+    #[serde(default)]
+    pub tags: BTreeMap<String, DeviceTags>,
+    #[serde(default)]
+    pub notes: BTreeMap<String, String>,
+    /// Devices hidden from listings
+    #[serde(default)]
+    pub hidden: BTreeMap<String, String>,
+    #[serde(default)]
+    pub devices: Vec<DeviceBackup>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceBackup {
+    pub device_id: String,
+    pub alias: String,
+    pub model: String,
+    pub cloud: Option<CloudType>,
+    pub fw_ver: Option<String>,
+    /// Raw schedule rules; `None` when the device could not be read (offline, unsupported)
+    pub rules: Option<Vec<Value>>,
+}
+
+impl DeviceBackup {
+    pub fn from_entry(entry: &DeviceEntry, rules: Option<Vec<Value>>) -> Self {
+        Self {
+            device_id: entry.full_id(),
+            alias: entry.alias().to_string(),
+            model: entry.info.model().to_string(),
+            cloud: entry.info.cloud_type,
+            fw_ver: entry.info.fw_ver.clone(),
+            rules,
+        }
+    }
+}
+
+/// The `rule_list` array of a `get_rules` response.
+pub fn raw_rules(data: &Value) -> Vec<Value> {
+    data.get("rule_list")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// A backed-up rule as an `add_rule` request: the device assigns a new ID.
+pub fn rule_for_restore(rule: &Value) -> Value {
+    let mut rule = rule.clone();
+    if let Some(obj) = rule.as_object_mut() {
+        obj.remove("id");
+    }
+    rule
+}
+
+/// Find the current device for a backed-up one: by ID, or by alias when
+/// restoring onto a different account or a replaced device.
+pub fn match_device<'a>(
+    backup: &DeviceBackup,
+    devices: &'a [DeviceEntry],
+) -> Option<&'a DeviceEntry> {
+    devices
+        .iter()
+        .find(|d| d.full_id() == backup.device_id)
+        .or_else(|| devices.iter().find(|d| d.alias() == backup.alias))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_match_device_by_id_then_alias() {
+        let devices = vec![
+            DeviceEntry::test("AAA", "Porch"),
+            DeviceEntry::test("BBB", "Hall"),
+        ];
+        let mut backup = DeviceBackup::from_entry(&devices[0], None);
+        assert_eq!(match_device(&backup, &devices).unwrap().full_id(), "AAA");
+
+        backup.device_id = "OLD".to_string();
+        backup.alias = "Hall".to_string();
+        assert_eq!(match_device(&backup, &devices).unwrap().full_id(), "BBB");
+
+        backup.alias = "Garage".to_string();
+        assert!(match_device(&backup, &devices).is_none());
+    }
+
+    #[test]
+    fn test_rule_for_restore_drops_id() {
+        let rules = raw_rules(&json!({"rule_list": [{"id": "R1", "sact": 1, "smin": 420}]}));
+        assert_eq!(rule_for_restore(&rules[0]), json!({"sact": 1, "smin": 420}));
+    }
+}
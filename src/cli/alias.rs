@@ -0,0 +1,100 @@
+use clap::Subcommand;
+use serde_json::json;
+use tabled::Tabled;
+
+use crate::cli::output::{print_output, print_table};
+use crate::config::{OutputMode, RuntimeConfig};
+use crate::error::AppError;
+use crate::store::{self, LocalAlias};
+
+use super::super::resolve;
+
+#[derive(Subcommand)]
+pub enum AliasCommand {
+    /// Add a local nickname for a device
+    Add {
+        /// Nickname to use in place of the device name
+        name: String,
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
+    },
+
+    /// Remove a local nickname
+    Remove {
+        /// Nickname to remove
+        name: String,
+    },
+
+    /// List local nicknames
+    List,
+}
+
+#[derive(Tabled)]
+struct AliasRow {
+    #[tabled(rename = "ALIAS")]
+    name: String,
+    #[tabled(rename = "DEVICE")]
+    device_name: String,
+    #[tabled(rename = "DEVICE ID")]
+    device_id: String,
+}
+
+pub async fn handle(cmd: &AliasCommand, config: &RuntimeConfig) -> Result<(), AppError> {
+    match cmd {
+        AliasCommand::Add { name, device } => {
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
+            let alias = LocalAlias {
+                device_id: dev.full_id(),
+                device_name: dev.alias().to_string(),
+            };
+            let mut aliases = store::load_aliases()?;
+            aliases.insert(name.clone(), alias.clone());
+            store::save_aliases(&aliases)?;
+            print_output(
+                &json!({
+                    "alias": name,
+                    "device": alias.device_name,
+                    "device_id": alias.device_id,
+                }),
+                config,
+            );
+            Ok(())
+        }
+        AliasCommand::Remove { name } => {
+            let mut aliases = store::load_aliases()?;
+            if aliases.remove(name).is_none() {
+                return Err(AppError::InvalidInput(format!("No alias named '{}'", name)));
+            }
+            store::save_aliases(&aliases)?;
+            print_output(&json!({"alias": name, "removed": true}), config);
+            Ok(())
+        }
+        AliasCommand::List => {
+            let aliases = store::load_aliases()?;
+            if config.renders_as(OutputMode::Table) {
+                let rows: Vec<AliasRow> = aliases
+                    .into_iter()
+                    .map(|(name, alias)| AliasRow {
+                        name,
+                        device_name: alias.device_name,
+                        device_id: alias.device_id,
+                    })
+                    .collect();
+                print_table(&rows, config);
+            } else {
+                let list: Vec<serde_json::Value> = aliases
+                    .iter()
+                    .map(|(name, alias)| {
+                        json!({
+                            "alias": name,
+                            "device": alias.device_name,
+                            "device_id": alias.device_id,
+                        })
+                    })
+                    .collect();
+                print_output(&json!(list), config);
+            }
+            Ok(())
+        }
+    }
+}
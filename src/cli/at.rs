@@ -0,0 +1,220 @@
+use std::process::Stdio;
+
+use chrono::{DateTime, Local, NaiveTime, TimeZone};
+use clap::{Args, Parser};
+use serde_json::json;
+
+use crate::cli::output::print_output;
+use crate::cli::power::{PowerCommand, ALL_DEVICES};
+use crate::cli::run::{apply_output_flags, apply_step_flags};
+use crate::cli::{Cli, Commands};
+use crate::config::RuntimeConfig;
+use crate::duration::parse_duration;
+use crate::error::AppError;
+use crate::models::schedule::parse_time;
+use crate::resolve;
+use crate::store;
+
+/// Output of `--detach` waiters, in the config directory.
+pub const LOG_FILE: &str = "at.log";
+
+#[derive(Args)]
+pub struct AtArgs {
+    /// When to run: HH:MM (next occurrence, local time), +DURATION (e.g.
+    /// +45m) or an RFC 3339 timestamp
+    when: String,
+
+    /// Wait in a background process and run the command then (any command;
+    /// output goes to at.log)
+    #[arg(long, conflicts_with = "wait")]
+    detach: bool,
+
+    /// Wait in this process and run the command then
+    #[arg(long)]
+    wait: bool,
+
+    /// The tplc command to run, e.g. power off Heater
+    #[arg(
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        required = true,
+        value_name = "COMMAND"
+    )]
+    command: Vec<String>,
+}
+
+pub async fn handle(args: &AtArgs, config: &RuntimeConfig) -> Result<(), AppError> {
+    let now = Local::now();
+    let at = next_time(&args.when, now)?;
+    let delay = (at - now).to_std().unwrap_or_default();
+
+    // Parse now so a typo fails here rather than when nobody is watching
+    let cli = Cli::try_parse_from(std::iter::once("tplc".to_string()).chain(args.command.clone()))
+        .map_err(|e| AppError::InvalidInput(e.render().to_string().trim().to_string()))?;
+    if cli.command.is_interactive() || matches!(cli.command, Commands::At(_)) {
+        return Err(AppError::InvalidInput(
+            "interactive commands and nested `at` cannot be delayed".into(),
+        ));
+    }
+    let command = shell_words::join(&args.command);
+
+    if args.wait {
+        tokio::time::sleep(delay).await;
+        let mut config = config.clone();
+        apply_step_flags(&mut config, &cli);
+        apply_output_flags(&mut config, &cli);
+        return Box::pin(crate::dispatch(cli.command, &config)).await;
+    }
+
+    if args.detach {
+        let pid = spawn_waiter(at, &args.command)?;
+        print_output(
+            &json!({
+                "command": command,
+                "at": at.to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+                "method": "detached",
+                "pid": pid,
+            }),
+            config,
+        );
+        return Ok(());
+    }
+
+    // The device runs power changes itself, so nothing has to stay running.
+    // Guards have to be checked at the time, which the device can't do.
+    let (device, on) = match &cli.command {
+        Commands::Power(PowerCommand::On { device, guard }) if !guard.is_set() => (device, true),
+        Commands::Power(PowerCommand::Off {
+            device,
+            except,
+            guard,
+            ..
+        }) if except.is_empty() && device.as_deref() != Some(ALL_DEVICES) && !guard.is_set() => {
+            (device, false)
+        }
+        _ => {
+            return Err(AppError::InvalidInput(format!(
+                "Only `power on` and `power off` without conditions can be left to the \
+                 device; use --detach or --wait to run `{}`",
+                command
+            )))
+        }
+    };
+    let mut device_config = config.clone();
+    apply_step_flags(&mut device_config, &cli);
+    let dev = resolve::resolve_device_or_default(device.as_deref(), &device_config).await?;
+    // Round up so the action never happens before the requested time
+    let delay_secs = delay.as_secs() + u64::from(delay.subsec_nanos() > 0);
+    dev.set_countdown(delay_secs.max(1), on).await?;
+    print_output(
+        &json!({
+            "device": dev.alias(),
+            "power": if on { "on" } else { "off" },
+            "at": at.to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+            "delay_s": delay_secs,
+            "method": "countdown",
+        }),
+        config,
+    );
+    Ok(())
+}
+
+/// Start `tplc at <at> --wait <command>` in the background, detached from
+/// this terminal. Returns its process ID.
+fn spawn_waiter(at: DateTime<Local>, command: &[String]) -> Result<u32, AppError> {
+    let path = store::config_dir()?.join(LOG_FILE);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+
+    let mut cmd = std::process::Command::new(std::env::current_exe()?);
+    cmd.arg("at")
+        .arg(at.to_rfc3339())
+        .arg("--wait")
+        .arg("--")
+        .args(command)
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+    // Keep Ctrl-C and the closing terminal from taking the waiter with them
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const DETACHED_PROCESS: u32 = 0x0000_0008;
+        cmd.creation_flags(DETACHED_PROCESS);
+    }
+    Ok(cmd.spawn()?.id())
+}
+
+/// The time `when` refers to, seen from `now`: HH:MM is its next
+/// occurrence (tomorrow if already past today), +DURATION is relative to
+/// `now`, and RFC 3339 timestamps must lie in the future.
+pub fn next_time<Tz: TimeZone>(when: &str, now: DateTime<Tz>) -> Result<DateTime<Tz>, AppError> {
+    let tz = now.timezone();
+    if let Some(offset) = when.strip_prefix('+') {
+        let delay =
+            chrono::Duration::from_std(parse_duration(offset).map_err(AppError::InvalidInput)?)
+                .map_err(|_| AppError::InvalidInput(format!("'{}' is too far away", when)))?;
+        return Ok(now + delay);
+    }
+    if when.contains(':') && when.len() <= 5 {
+        let (hour, minute) = parse_time(when)?;
+        let time = NaiveTime::from_hms_opt(hour, minute, 0)
+            .ok_or_else(|| AppError::InvalidInput(format!("Invalid time '{}'", when)))?;
+        let mut date = now.date_naive();
+        loop {
+            // `earliest` picks the first of a repeated hour; a skipped hour
+            // (DST gap) moves on to the next day
+            if let Some(at) = tz.from_local_datetime(&date.and_time(time)).earliest() {
+                if at > now {
+                    return Ok(at);
+                }
+            }
+            date = date
+                .succ_opt()
+                .ok_or_else(|| AppError::InvalidInput(format!("Invalid time '{}'", when)))?;
+        }
+    }
+    let at = DateTime::parse_from_rfc3339(when)
+        .map_err(|_| {
+            AppError::InvalidInput(format!(
+                "Invalid time '{}'. Use HH:MM, +DURATION (e.g. +45m) or an RFC 3339 timestamp",
+                when
+            ))
+        })?
+        .with_timezone(&tz);
+    if at <= now {
+        return Err(AppError::InvalidInput(format!("'{}' is in the past", when)));
+    }
+    Ok(at)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    #[test]
+    fn test_next_time() {
+        let now: DateTime<Utc> = "2026-10-16T21:00:00Z".parse().unwrap();
+        let at = |when| next_time(when, now).unwrap().to_rfc3339();
+        assert_eq!(at("22:30"), "2026-10-16T22:30:00+00:00");
+        assert_eq!(at("07:00"), "2026-10-17T07:00:00+00:00");
+        assert_eq!(at("21:00"), "2026-10-17T21:00:00+00:00");
+        assert_eq!(at("+45m"), "2026-10-16T21:45:00+00:00");
+        assert_eq!(at("2026-10-18T08:00:00Z"), "2026-10-18T08:00:00+00:00");
+        assert!(next_time("2026-10-15T08:00:00Z", now).is_err());
+        assert!(next_time("25:00", now).is_err());
+        assert!(next_time("soon", now).is_err());
+    }
+}
@@ -1,23 +1,56 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use clap::Subcommand;
 use dialoguer::{Input, Password};
 use serde_json::json;
 
 use crate::api::client::TPLinkApi;
 use crate::api::cloud_type::CloudType;
-use crate::auth::credentials::credentials_from_env;
+use crate::auth::credentials::{credentials_from_env, AuthContext};
+use crate::auth::export;
 use crate::auth::keychain;
+use crate::auth::login::{log_in, log_in_to, LoginClouds};
 use crate::auth::token::TokenSet;
-use crate::cli::output::print_json;
+use crate::auth::token_store::write_private;
+use crate::cli::output::{emit, print_output};
 use crate::config::RuntimeConfig;
-use crate::error::AppError;
+use crate::error::{AppError, ErrorCategory};
+use crate::store::{self, TrustedTerminal};
+
+/// `tplc login`. `add_tapo` (`--tapo`) logs in to Tapo only and adds it to
+/// the stored Kasa session, keeping its tokens and terminal ID.
+pub async fn handle_login(
+    clouds: LoginClouds,
+    add_tapo: bool,
+    save_password: bool,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let existing = if add_tapo {
+        let tokens = keychain::get_tokens()?
+            .filter(|t| !t.token.is_empty())
+            .ok_or_else(|| {
+                AppError::InvalidInput(
+                    "--tapo adds Tapo to an existing Kasa login; run 'tplc login' first".into(),
+                )
+            })?;
+        Some(tokens)
+    } else {
+        None
+    };
+    let clouds = if add_tapo { LoginClouds::Tapo } else { clouds };
 
-pub async fn handle_login(config: &RuntimeConfig) -> Result<(), AppError> {
     let (username, password) = match credentials_from_env() {
         Some((u, p)) => (u, p),
         None => {
-            let username: String = Input::new()
-                .with_prompt("TP-Link email")
-                .interact_text()
-                .map_err(|e| AppError::InvalidInput(e.to_string()))?;
+            let username: String = match &existing {
+                Some(tokens) => tokens.username.clone(),
+                None => Input::new()
+                    .with_prompt("TP-Link email")
+                    .interact_text()
+                    .map_err(|e| AppError::InvalidInput(e.to_string()))?,
+            };
             let password: String = Password::new()
                 .with_prompt("Password")
                 .interact()
@@ -26,123 +59,320 @@ pub async fn handle_login(config: &RuntimeConfig) -> Result<(), AppError> {
         }
     };
 
-    // Login to Kasa cloud
-    let mut kasa_api = TPLinkApi::new(None, config.verbose, None, CloudType::Kasa)?;
-
-    let kasa_result = match kasa_api.login(&username, &password).await {
-        Ok(result) => result,
-        Err(AppError::MfaRequired { mfa_type: _, email }) => {
-            eprintln!(
-                "Kasa MFA verification required{}",
-                email
-                    .as_ref()
-                    .map(|e| format!(" for {}", e))
-                    .unwrap_or_default()
-            );
-            let mfa_code: String = Input::new()
-                .with_prompt("Enter Kasa MFA code")
-                .interact_text()
-                .map_err(|e| AppError::InvalidInput(e.to_string()))?;
-
-            kasa_api.verify_mfa(&username, &password, &mfa_code).await?
-        }
-        Err(e) => return Err(e),
-    };
-
-    // Login to Tapo cloud (best-effort, don't fail if Tapo login fails)
-    let mut tapo_api = TPLinkApi::new(
-        None,
-        config.verbose,
-        Some(kasa_api.term_id().to_string()),
-        CloudType::Tapo,
-    )?;
-
-    let tapo_result = match tapo_api.login(&username, &password).await {
-        Ok(result) => Some(result),
-        Err(AppError::MfaRequired { mfa_type: _, email }) => {
-            eprintln!(
-                "Tapo MFA verification required{}",
-                email
-                    .as_ref()
-                    .map(|e| format!(" for {}", e))
-                    .unwrap_or_default()
-            );
-            let mfa_code: String = Input::new()
-                .with_prompt("Enter Tapo MFA code")
-                .interact_text()
-                .map_err(|e| AppError::InvalidInput(e.to_string()))?;
-
-            match tapo_api.verify_mfa(&username, &password, &mfa_code).await {
-                Ok(result) => Some(result),
-                Err(e) => {
-                    if config.verbose {
-                        eprintln!("Tapo MFA failed: {}", e);
-                    }
-                    None
-                }
-            }
-        }
-        Err(e) => {
-            if config.verbose {
-                eprintln!("Tapo login failed (non-fatal): {}", e);
-            }
-            None
-        }
+    // Reuse the terminal ID an earlier MFA login made trusted, so the
+    // cloud does not challenge this machine again. An ephemeral login
+    // neither reads nor records trusted terminals.
+    let ephemeral = keychain::is_ephemeral();
+    let mut trusted = if ephemeral {
+        BTreeMap::new()
+    } else {
+        store::load_trusted_terminals()?
     };
-
-    let tokens = TokenSet {
-        token: kasa_result.token,
-        refresh_token: kasa_result.refresh_token,
-        username: username.clone(),
-        regional_url: kasa_result.regional_url.clone(),
-        term_id: kasa_api.term_id().to_string(),
-        tapo_token: tapo_result.as_ref().map(|r| r.token.clone()),
-        tapo_refresh_token: tapo_result.as_ref().and_then(|r| r.refresh_token.clone()),
-        tapo_regional_url: tapo_result.as_ref().map(|r| r.regional_url.clone()),
+    let account = username.to_lowercase();
+    let term_id = login_term_id(existing.as_ref(), &trusted, &account);
+    let mfa_answered = AtomicBool::new(false);
+    let prompt = |cloud| {
+        mfa_answered.store(true, Ordering::Relaxed);
+        prompt_mfa_code(cloud, &username)
     };
-
+    let mut tokens = log_in_to(
+        &username,
+        &password,
+        clouds,
+        term_id,
+        Some(&prompt),
+        config.verbose,
+    )
+    .await?;
+    if mfa_answered.load(Ordering::Relaxed) && !ephemeral {
+        trusted.insert(
+            account,
+            TrustedTerminal {
+                term_id: tokens.term_id.clone(),
+                trusted_at: chrono::Utc::now(),
+            },
+        );
+        store::save_trusted_terminals(&trusted)?;
+    }
+    if let Some(existing) = existing {
+        tokens = TokenSet {
+            tapo_token: tokens.tapo_token,
+            tapo_refresh_token: tokens.tapo_refresh_token,
+            tapo_regional_url: tokens.tapo_regional_url,
+            ..existing
+        };
+    }
+    if save_password {
+        tokens.password = Some(password);
+    }
     keychain::store_tokens(&tokens)?;
 
     let mut status = json!({
         "status": "authenticated",
-        "username": username,
-        "kasa_regional_url": kasa_result.regional_url,
+        "username": tokens.username,
     });
 
-    if let Some(ref tapo) = tapo_result {
-        status["tapo_regional_url"] = json!(tapo.regional_url);
+    if tokens.token.is_empty() {
+        status["kasa"] = json!("skipped");
+    } else {
+        status["kasa_regional_url"] = json!(tokens.regional_url);
+    }
+
+    if let Some(ref tapo_regional_url) = tokens.tapo_regional_url {
+        status["tapo_regional_url"] = json!(tapo_regional_url);
+    } else if clouds == LoginClouds::Kasa {
+        status["tapo"] = json!("skipped");
     } else {
         status["tapo"] = json!("unavailable");
     }
 
-    print_json(&status);
+    print_output(&status, config);
 
     Ok(())
 }
 
-pub async fn handle_logout(_config: &RuntimeConfig) -> Result<(), AppError> {
+/// Terminal ID to log in with: the existing session's when adding Tapo to
+/// it, otherwise the one an earlier MFA login made trusted for `account`.
+fn login_term_id(
+    existing: Option<&TokenSet>,
+    trusted: &BTreeMap<String, TrustedTerminal>,
+    account: &str,
+) -> Option<String> {
+    match existing {
+        Some(tokens) => Some(tokens.term_id.clone()),
+        None => trusted.get(account).map(|t| t.term_id.clone()),
+    }
+}
+
+/// `--ephemeral-auth`: log in from `TPLC_USERNAME`/`TPLC_PASSWORD` and keep
+/// the tokens in memory for this process only.
+pub async fn ephemeral_login(config: &RuntimeConfig) -> Result<(), AppError> {
+    keychain::use_memory();
+    let (username, password) = credentials_from_env().ok_or_else(|| {
+        AppError::InvalidInput("--ephemeral-auth needs TPLC_USERNAME and TPLC_PASSWORD".into())
+    })?;
+    let tokens = log_in(&username, &password, None, config.verbose).await?;
+    keychain::store_tokens(&tokens)
+}
+
+/// Stand-in tokens for `--replay`, kept in memory. Recorded requests are
+/// matched without their tokens, so any value answers them.
+pub fn replay_login() -> Result<(), AppError> {
+    keychain::use_memory();
+    let placeholder = "[redacted]".to_string();
+    keychain::store_tokens(&TokenSet {
+        token: placeholder.clone(),
+        refresh_token: Some(placeholder.clone()),
+        username: "replay".into(),
+        regional_url: CloudType::Kasa.host().into(),
+        term_id: placeholder.clone(),
+        tapo_token: Some(placeholder.clone()),
+        tapo_refresh_token: Some(placeholder),
+        tapo_regional_url: Some(CloudType::Tapo.host().into()),
+        password: None,
+    })
+}
+
+/// Prompt on the terminal for a cloud's MFA code.
+fn prompt_mfa_code(cloud: CloudType, username: &str) -> Result<String, AppError> {
+    eprintln!("{} MFA verification required for {}", cloud, username);
+    Input::new()
+        .with_prompt(format!("Enter {} MFA code", cloud))
+        .interact_text()
+        .map_err(|e| AppError::InvalidInput(e.to_string()))
+}
+
+pub async fn handle_logout(config: &RuntimeConfig) -> Result<(), AppError> {
     keychain::clear_tokens()?;
-    print_json(&json!({"status": "logged_out"}));
+    print_output(&json!({"status": "logged_out"}), config);
     Ok(())
 }
 
-pub async fn handle_status(_config: &RuntimeConfig) -> Result<(), AppError> {
+pub async fn handle_status(check: bool, config: &RuntimeConfig) -> Result<(), AppError> {
     match keychain::get_tokens()? {
         Some(tokens) => {
-            print_json(&json!({
+            let mut status = json!({
                 "status": "authenticated",
                 "username": tokens.username,
+                "kasa_authenticated": !tokens.token.is_empty(),
                 "kasa_regional_url": tokens.regional_url,
                 "has_kasa_refresh_token": tokens.refresh_token.is_some(),
                 "tapo_authenticated": tokens.tapo_token.is_some(),
                 "has_tapo_refresh_token": tokens.tapo_refresh_token.is_some(),
-            }));
+                "password_saved": tokens.password.is_some(),
+                "storage": keychain::store().name(),
+            });
+            if check {
+                let auth = AuthContext::from(tokens);
+                let (kasa, tapo) = tokio::join!(
+                    check_session(&auth, CloudType::Kasa, config.verbose),
+                    check_session(&auth, CloudType::Tapo, config.verbose),
+                );
+                status["kasa_token"] = json!(kasa?);
+                status["tapo_token"] = json!(tapo?);
+            }
+            print_output(&status, config);
         }
         None => {
-            print_json(&json!({
-                "status": "not_authenticated",
-            }));
+            print_output(
+                &json!({
+                    "status": "not_authenticated",
+                }),
+                config,
+            );
         }
     }
     Ok(())
 }
+
+/// Whether the cloud still accepts `auth`'s token for `cloud`, found by
+/// listing devices with it: "valid", "expired", "invalid" or
+/// "not_logged_in". Tokens are not refreshed. Network and other errors are
+/// returned rather than reported.
+async fn check_session(
+    auth: &AuthContext,
+    cloud: CloudType,
+    verbose: bool,
+) -> Result<&'static str, AppError> {
+    let Some((token, regional_url)) = auth.session(cloud) else {
+        return Ok("not_logged_in");
+    };
+    let api = TPLinkApi::new(
+        Some(regional_url.to_string()),
+        verbose,
+        Some(auth.term_id.clone()),
+        cloud,
+    )?;
+    match api.get_device_info_list(token).await {
+        Ok(_) => Ok("valid"),
+        Err(AppError::TokenExpired { .. }) => Ok("expired"),
+        Err(e) if e.category() == ErrorCategory::Auth => Ok("invalid"),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Subcommand)]
+pub enum AuthCommand {
+    /// Write the stored tokens to a file, to use the login on another
+    /// machine (e.g. a headless server where MFA cannot be answered)
+    Export {
+        /// File to write (default: standard output)
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Encrypt with a passphrase (asked for, or TPLC_PASSPHRASE)
+        #[arg(long)]
+        encrypt: bool,
+    },
+    /// Store tokens written by `tplc auth export`, replacing the current login
+    Import {
+        /// File from `tplc auth export`
+        file: PathBuf,
+    },
+}
+
+pub async fn handle_auth(cmd: &AuthCommand, config: &RuntimeConfig) -> Result<(), AppError> {
+    match cmd {
+        AuthCommand::Export { file, encrypt } => {
+            let mut tokens = keychain::get_tokens()?.ok_or(AppError::NotAuthenticated)?;
+            // A saved password stays on this machine
+            tokens.password = None;
+            let contents = if *encrypt {
+                serde_json::to_string_pretty(&export::seal(&tokens, &passphrase(true)?)?)?
+            } else {
+                serde_json::to_string_pretty(&tokens)?
+            };
+            match file {
+                Some(path) => {
+                    write_private(path, &contents)?;
+                    print_output(
+                        &json!({
+                            "status": "exported",
+                            "file": path,
+                            "username": tokens.username,
+                            "encrypted": encrypt,
+                        }),
+                        config,
+                    );
+                }
+                None => emit(&format!("{}\n", contents)),
+            }
+            Ok(())
+        }
+        AuthCommand::Import { file } => {
+            let contents = std::fs::read_to_string(file)?;
+            let tokens = export::parse(&contents, || passphrase(false))?;
+            keychain::store_tokens(&tokens)?;
+            print_output(
+                &json!({
+                    "status": "imported",
+                    "username": tokens.username,
+                    "kasa_authenticated": !tokens.token.is_empty(),
+                    "tapo_authenticated": tokens.tapo_token.is_some(),
+                    "storage": keychain::store().name(),
+                }),
+                config,
+            );
+            Ok(())
+        }
+    }
+}
+
+/// The export passphrase from `TPLC_PASSPHRASE`, or asked for on the
+/// terminal (twice when encrypting).
+fn passphrase(confirm: bool) -> Result<String, AppError> {
+    if let Some(passphrase) = std::env::var("TPLC_PASSPHRASE")
+        .ok()
+        .filter(|p| !p.is_empty())
+    {
+        return Ok(passphrase);
+    }
+    let mut prompt = Password::new().with_prompt("Passphrase");
+    if confirm {
+        prompt = prompt.with_confirmation("Repeat passphrase", "Passphrases do not match");
+    }
+    prompt
+        .interact()
+        .map_err(|e| AppError::InvalidInput(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(term_id: &str) -> TokenSet {
+        TokenSet {
+            token: "kasa".into(),
+            refresh_token: None,
+            username: "me@example.com".into(),
+            regional_url: CloudType::Kasa.host().into(),
+            term_id: term_id.into(),
+            tapo_token: None,
+            tapo_refresh_token: None,
+            tapo_regional_url: None,
+            password: None,
+        }
+    }
+
+    #[test]
+    fn test_login_term_id() {
+        let mut trusted = BTreeMap::new();
+        trusted.insert(
+            "me@example.com".to_string(),
+            TrustedTerminal {
+                term_id: "trusted".into(),
+                trusted_at: chrono::Utc::now(),
+            },
+        );
+        // No session yet: the trusted terminal is reused
+        assert_eq!(
+            login_term_id(None, &trusted, "me@example.com").as_deref(),
+            Some("trusted")
+        );
+        // Adding Tapo keeps the existing session's terminal
+        assert_eq!(
+            login_term_id(Some(&tokens("session")), &trusted, "me@example.com").as_deref(),
+            Some("session")
+        );
+        assert_eq!(login_term_id(None, &trusted, "other@example.com"), None);
+    }
+}
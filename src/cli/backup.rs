@@ -0,0 +1,182 @@
+use std::path::{Path, PathBuf};
+
+use clap::Subcommand;
+use serde_json::json;
+
+use crate::backup::{self, Backup, DeviceBackup, BACKUP_VERSION};
+use crate::cli::output::print_output;
+use crate::config::{self, RuntimeConfig};
+use crate::error::AppError;
+use crate::store;
+
+use super::super::resolve;
+
+#[derive(Subcommand)]
+pub enum BackupCommand {
+    /// Save config, nicknames, tags, notes, device metadata and schedules to a file (no tokens)
+    Create {
+        /// Backup file to write (JSON)
+        file: PathBuf,
+    },
+
+    /// Restore local state from a backup, and optionally replay schedules.
+    /// An existing config.toml is only overwritten with --force
+    Restore {
+        /// Backup file to read
+        file: PathBuf,
+        /// Replace each device's schedule rules with the backed-up ones
+        #[arg(long)]
+        schedules: bool,
+    },
+}
+
+pub async fn handle(cmd: &BackupCommand, config: &RuntimeConfig) -> Result<(), AppError> {
+    match cmd {
+        BackupCommand::Create { file } => handle_create(file, config).await,
+        BackupCommand::Restore { file, schedules } => {
+            handle_restore(file, *schedules, config).await
+        }
+    }
+}
+
+async fn handle_create(file: &Path, config: &RuntimeConfig) -> Result<(), AppError> {
+    let (devices, auth) = resolve::fetch_all_devices(config).await?;
+    let schedules = resolve::fetch_schedules(&devices, &auth, config.verbose).await;
+
+    let config_toml = match std::fs::read_to_string(config::default_config_path()?) {
+        Ok(contents) => Some(contents),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let backup = Backup {
+        version: BACKUP_VERSION,
+        created_at: chrono::Local::now().to_rfc3339(),
+        config_toml,
+        aliases: store::load_aliases()?,
+        tags: store::load_tags()?,
+        notes: store::load_notes()?,
+        hidden: store::load_hidden()?,
+        devices: devices
+            .iter()
+            .zip(schedules)
+            .map(|(entry, rules)| {
+                DeviceBackup::from_entry(entry, rules.as_ref().map(backup::raw_rules))
+            })
+            .collect(),
+    };
+    std::fs::write(file, serde_json::to_string_pretty(&backup)?)?;
+
+    let with_schedules = backup.devices.iter().filter(|d| d.rules.is_some()).count();
+    print_output(
+        &json!({
+            "file": file.display().to_string(),
+            "aliases": backup.aliases.len(),
+        "tags": backup.tags.len(),
+            "notes": backup.notes.len(),
+            "config": backup.config_toml.is_some(),
+            "devices": backup.devices.len(),
+            "devices_with_schedules": with_schedules,
+        }),
+        config,
+    );
+    Ok(())
+}
+
+async fn handle_restore(
+    file: &Path,
+    schedules: bool,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let contents = std::fs::read_to_string(file)?;
+    let backup: Backup = serde_json::from_str(&contents)?;
+    if backup.version > BACKUP_VERSION {
+        return Err(AppError::InvalidInput(format!(
+            "Backup version {} is newer than this tplc supports ({})",
+            backup.version, BACKUP_VERSION
+        )));
+    }
+
+    let config_status = match &backup.config_toml {
+        None => "none",
+        Some(contents) => {
+            let path = config::default_config_path()?;
+            if path.exists() && !config.force {
+                "skipped (exists; use --force)"
+            } else {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                std::fs::write(&path, contents)?;
+                "restored"
+            }
+        }
+    };
+
+    // Backed-up entries win over current ones with the same key
+    let mut aliases = store::load_aliases()?;
+    aliases.extend(backup.aliases.clone());
+    store::save_aliases(&aliases)?;
+    let mut tags = store::load_tags()?;
+    tags.extend(backup.tags.clone());
+    store::save_tags(&tags)?;
+    let mut notes = store::load_notes()?;
+    notes.extend(backup.notes.clone());
+    store::save_notes(&notes)?;
+    let mut hidden = store::load_hidden()?;
+    hidden.extend(backup.hidden.clone());
+    store::save_hidden(&hidden)?;
+
+    let mut result = json!({
+        "config": config_status,
+        "aliases": backup.aliases.len(),
+        "notes": backup.notes.len(),
+        "hidden": backup.hidden.len(),
+    });
+    if schedules {
+        result["schedules"] = json!(restore_schedules(&backup, config).await?);
+    }
+    print_output(&result, config);
+    Ok(())
+}
+
+/// Replace the rules of each backed-up device that can be matched in the
+/// current account. Per-device failures are reported, not fatal.
+async fn restore_schedules(
+    backup: &Backup,
+    config: &RuntimeConfig,
+) -> Result<Vec<serde_json::Value>, AppError> {
+    let (devices, auth) = resolve::fetch_all_devices(config).await?;
+    let mut report = Vec::new();
+
+    for saved in &backup.devices {
+        let Some(rules) = &saved.rules else {
+            continue;
+        };
+        let Some(entry) = backup::match_device(saved, &devices) else {
+            report.push(json!({"device": saved.alias, "status": "not found"}));
+            continue;
+        };
+        let device = resolve::build_device(entry, &auth, config.verbose)?;
+        let status = async {
+            device.delete_all_schedule_rules().await?;
+            for rule in rules {
+                device
+                    .add_schedule_rule(backup::rule_for_restore(rule))
+                    .await?;
+            }
+            Ok::<_, AppError>(())
+        }
+        .await;
+        report.push(json!({
+            "device": entry.alias(),
+            "device_id": entry.full_id(),
+            "rules": rules.len(),
+            "status": match status {
+                Ok(()) => "restored".to_string(),
+                Err(e) => format!("failed: {}", e),
+            },
+        }));
+    }
+    Ok(report)
+}
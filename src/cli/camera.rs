@@ -0,0 +1,51 @@
+use clap::{Subcommand, ValueEnum};
+use serde_json::json;
+
+use crate::cli::output::print_output;
+use crate::config::RuntimeConfig;
+use crate::error::AppError;
+
+use super::super::resolve;
+
+#[derive(Subcommand)]
+pub enum CameraCommand {
+    /// Turn privacy mode on or off (Kasa KC, Tapo C)
+    Privacy {
+        /// on covers the lens (Tapo) or switches the camera off (Kasa)
+        #[arg(value_enum)]
+        state: PrivacyState,
+        /// Camera name or ID (defaults to `default_device`)
+        device: Option<String>,
+    },
+    /// Privacy mode, recording and motion detection
+    Status {
+        /// Camera name or ID (defaults to `default_device`)
+        device: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PrivacyState {
+    On,
+    Off,
+}
+
+pub async fn handle(cmd: &CameraCommand, config: &RuntimeConfig) -> Result<(), AppError> {
+    match cmd {
+        CameraCommand::Privacy { state, device } => {
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
+            let on = matches!(state, PrivacyState::On);
+            dev.set_privacy_mode(on).await?;
+            print_output(&json!({"device": dev.alias(), "privacy": on}), config);
+            Ok(())
+        }
+        CameraCommand::Status { device } => {
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
+            let status = dev.camera_status().await?;
+            let mut result = serde_json::to_value(&status)?;
+            result["device"] = json!(dev.alias());
+            print_output(&result, config);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use std::ffi::OsStr;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::json;
+
+use crate::cli::output::{emit, print_output};
+use crate::cli::Cli;
+use crate::config::RuntimeConfig;
+use crate::cron::{self, launchd, task_scheduler, CronJob};
+use crate::error::AppError;
+use crate::store;
+
+#[derive(Subcommand)]
+pub enum CronCommand {
+    /// Install the [[cron]] jobs from config.toml with the system scheduler
+    Install {
+        /// Print what would be installed instead of installing it
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Scheduler to use (defaults to launchd on macOS, Task Scheduler on
+        /// Windows and crontab elsewhere)
+        #[arg(long, value_enum)]
+        backend: Option<Backend>,
+    },
+
+    /// Remove the installed tplc jobs
+    Remove {
+        /// Scheduler to remove the jobs from
+        #[arg(long, value_enum)]
+        backend: Option<Backend>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    Crontab,
+    Launchd,
+    TaskScheduler,
+}
+
+impl Backend {
+    fn native() -> Self {
+        if cfg!(target_os = "macos") {
+            Backend::Launchd
+        } else if cfg!(windows) {
+            Backend::TaskScheduler
+        } else {
+            Backend::Crontab
+        }
+    }
+}
+
+pub async fn handle(cmd: &CronCommand, config: &RuntimeConfig) -> Result<(), AppError> {
+    match cmd {
+        CronCommand::Install { dry_run, backend } => {
+            handle_install(*dry_run, backend.unwrap_or_else(Backend::native), config)
+        }
+        CronCommand::Remove { backend } => {
+            let removed = match backend.unwrap_or_else(Backend::native) {
+                Backend::Crontab => {
+                    let crontab = read_crontab()?;
+                    write_crontab(&cron::replace_block(&crontab, None))?;
+                    true
+                }
+                Backend::Launchd => !remove_agents()?.is_empty(),
+                Backend::TaskScheduler => !remove_tasks()?.is_empty(),
+            };
+            print_output(&json!({"removed": removed}), config);
+            Ok(())
+        }
+    }
+}
+
+fn handle_install(dry_run: bool, backend: Backend, config: &RuntimeConfig) -> Result<(), AppError> {
+    if config.cron_jobs.is_empty() {
+        return Err(AppError::InvalidInput(
+            "No [[cron]] jobs in config.toml".into(),
+        ));
+    }
+    for job in &config.cron_jobs {
+        check_command(job)?;
+    }
+
+    let exe = binary_path()?;
+    let env: Vec<(String, String)> = cron::PASSTHROUGH_ENV
+        .iter()
+        .filter_map(|key| Some((key.to_string(), std::env::var(key).ok()?)))
+        .collect();
+    let log = store::config_dir()?.join("cron.log");
+
+    match backend {
+        Backend::Crontab => {
+            let block = cron::render_block(&exe, &env, &log, &config.cron_jobs)?;
+            if dry_run {
+                emit(&block);
+                return Ok(());
+            }
+            std::fs::create_dir_all(store::config_dir()?)?;
+            let crontab = read_crontab()?;
+            write_crontab(&cron::replace_block(&crontab, Some(&block)))?;
+        }
+        Backend::Launchd => {
+            let agents_dir = launch_agents_dir()?;
+            let mut agents = Vec::new();
+            for job in &config.cron_jobs {
+                let path = agents_dir.join(format!("{}.plist", launchd::label(&job.name)));
+                agents.push((path, launchd::render_plist(job, &exe, &env, &log)?));
+            }
+            if dry_run {
+                for (path, plist) in &agents {
+                    emit(&format!("<!-- {} -->\n{}\n", path.display(), plist));
+                }
+                return Ok(());
+            }
+            remove_agents()?;
+            std::fs::create_dir_all(store::config_dir()?)?;
+            std::fs::create_dir_all(&agents_dir)?;
+            for (path, plist) in &agents {
+                std::fs::write(path, plist)?;
+                run(
+                    "launchctl",
+                    &["load".as_ref(), "-w".as_ref(), path.as_os_str()],
+                )?;
+            }
+        }
+        Backend::TaskScheduler => {
+            let tasks_dir = store::config_dir()?.join("tasks");
+            let mut tasks = Vec::new();
+            for job in &config.cron_jobs {
+                let path = tasks_dir.join(format!("{}.xml", job.name));
+                let xml = task_scheduler::render_task(job, &exe, &env, &log)?;
+                tasks.push((task_scheduler::task_name(&job.name), path, xml));
+            }
+            if dry_run {
+                for (name, _, xml) in &tasks {
+                    emit(&format!("<!-- {} -->\n{}\n", name, xml));
+                }
+                return Ok(());
+            }
+            remove_tasks()?;
+            std::fs::create_dir_all(&tasks_dir)?;
+            for (name, path, xml) in &tasks {
+                std::fs::write(path, task_scheduler::encode(xml))?;
+                run(
+                    "schtasks",
+                    &[
+                        "/Create".as_ref(),
+                        "/TN".as_ref(),
+                        name.as_ref(),
+                        "/XML".as_ref(),
+                        path.as_os_str(),
+                        "/F".as_ref(),
+                    ],
+                )?;
+            }
+        }
+    }
+
+    let names: Vec<String> = config.cron_jobs.iter().map(|j| j.name.clone()).collect();
+    if backend != Backend::Crontab {
+        store::save_installed_jobs(&names)?;
+    }
+    print_output(
+        &json!({
+            "installed": names,
+            "backend": backend.to_possible_value().map(|v| v.get_name().to_string()),
+            "binary": exe.display().to_string(),
+            "log": log.display().to_string(),
+        }),
+        config,
+    );
+    Ok(())
+}
+
+fn launch_agents_dir() -> Result<PathBuf, AppError> {
+    dirs::home_dir()
+        .map(|home| home.join("Library").join("LaunchAgents"))
+        .ok_or_else(|| AppError::Io(std::io::Error::other("no home directory found")))
+}
+
+/// Unload and delete the previously installed launchd agents.
+fn remove_agents() -> Result<Vec<String>, AppError> {
+    let names = store::load_installed_jobs()?;
+    let agents_dir = launch_agents_dir()?;
+    for name in &names {
+        let path = agents_dir.join(format!("{}.plist", launchd::label(name)));
+        if path.exists() {
+            // Fails harmlessly when the agent is not loaded
+            let _ = run(
+                "launchctl",
+                &["unload".as_ref(), "-w".as_ref(), path.as_os_str()],
+            );
+            std::fs::remove_file(&path)?;
+        }
+    }
+    store::save_installed_jobs(&[])?;
+    Ok(names)
+}
+
+/// Delete the previously installed scheduled tasks.
+fn remove_tasks() -> Result<Vec<String>, AppError> {
+    let names = store::load_installed_jobs()?;
+    let tasks_dir = store::config_dir()?.join("tasks");
+    for name in &names {
+        // Fails harmlessly when the task was already deleted by hand
+        let task = task_scheduler::task_name(name);
+        let _ = run(
+            "schtasks",
+            &[
+                "/Delete".as_ref(),
+                "/TN".as_ref(),
+                task.as_ref(),
+                "/F".as_ref(),
+            ],
+        );
+        let path = tasks_dir.join(format!("{}.xml", name));
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+    }
+    store::save_installed_jobs(&[])?;
+    Ok(names)
+}
+
+/// Run a scheduler command, turning a failure into an error carrying its
+/// output.
+fn run(program: &str, args: &[&OsStr]) -> Result<(), AppError> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| AppError::Io(std::io::Error::new(e.kind(), format!("{}: {}", program, e))))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let message = String::from_utf8_lossy(if output.stderr.is_empty() {
+        &output.stdout
+    } else {
+        &output.stderr
+    });
+    Err(AppError::Io(std::io::Error::other(format!(
+        "{} failed: {}",
+        program,
+        message.trim()
+    ))))
+}
+
+/// Reject jobs whose command tplc itself would not accept, so mistakes show
+/// up now instead of as silent failures in the cron log.
+fn check_command(job: &CronJob) -> Result<(), AppError> {
+    let args = std::iter::once("tplc".to_string()).chain(job.args()?);
+    Cli::try_parse_from(args).map(|_| ()).map_err(|e| {
+        AppError::InvalidInput(format!(
+            "Cron job '{}': {}",
+            job.name,
+            e.render().to_string().trim()
+        ))
+    })
+}
+
+/// This binary's absolute path. Schedulers have no PATH lookup worth relying
+/// on.
+fn binary_path() -> Result<PathBuf, AppError> {
+    let exe = std::env::current_exe()?.canonicalize()?;
+    let in_build_dir = exe.components().any(|c| c.as_os_str() == "target")
+        && exe
+            .parent()
+            .is_some_and(|p| p.ends_with("debug") || p.ends_with("release"));
+    if in_build_dir {
+        eprintln!(
+            "Warning: {} looks like a cargo build output; jobs break if it is moved or cleaned",
+            exe.display()
+        );
+    }
+    Ok(exe)
+}
+
+fn read_crontab() -> Result<String, AppError> {
+    let output = Command::new("crontab")
+        .arg("-l")
+        .output()
+        .map_err(|e| AppError::Io(std::io::Error::new(e.kind(), format!("crontab: {}", e))))?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        // `crontab -l` fails when the user has no crontab yet
+        Ok(String::new())
+    }
+}
+
+fn write_crontab(contents: &str) -> Result<(), AppError> {
+    let mut child = Command::new("crontab")
+        .arg("-")
+        .stdin(Stdio::piped())
+        .spawn()?;
+    // Dropping stdin closes the pipe so crontab sees end of input
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(contents.as_bytes())?;
+    }
+    if child.wait()?.success() {
+        Ok(())
+    } else {
+        Err(AppError::Io(std::io::Error::other(
+            "crontab rejected the new table",
+        )))
+    }
+}
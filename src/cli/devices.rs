@@ -1,22 +1,46 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
 use clap::Subcommand;
 use serde_json::json;
 use tabled::Tabled;
 
-use crate::cli::output::{print_json, print_table};
+use crate::auth::credentials::AuthContext;
+use crate::cli::led;
+use crate::cli::output::{emit, print_json_stream, print_output, print_table};
+use crate::cli::progress::Spinner;
 use crate::config::{OutputMode, RuntimeConfig};
+use crate::duration::parse_duration;
 use crate::error::AppError;
+use crate::events;
+#[cfg(feature = "history")]
+use crate::history;
+use crate::inventory;
+use crate::models::device_type::DeviceType;
+use crate::models::registry::Capabilities;
+use crate::store;
+use crate::watch;
 
 use super::super::resolve;
+use super::super::resolve::{DeviceEntry, DeviceState};
 
 #[derive(Subcommand)]
 pub enum DevicesCommand {
     /// List all devices
-    List,
+    List {
+        /// Emit JSON array elements as they are produced (for very large accounts)
+        #[arg(long, conflicts_with = "state")]
+        stream: bool,
+        /// Include live power state (and watts for energy-monitoring devices)
+        #[arg(long)]
+        state: bool,
+    },
 
     /// Get device details
     Get {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
     },
 
     /// Search devices by partial name
@@ -24,6 +48,115 @@ pub enum DevicesCommand {
         /// Search query (partial match on alias)
         query: String,
     },
+
+    /// Compare the account's devices against an expected inventory (TOML)
+    Verify {
+        /// Inventory file with [[device]] entries (id, alias, model, room, min_firmware)
+        #[arg(long)]
+        expected: PathBuf,
+    },
+
+    /// Export the inventory (model, MAC, firmware, hardware, region, cloud,
+    /// online status, nicknames, tags, notes) as JSON, or CSV with `-o csv`
+    Export,
+
+    /// Restore nicknames, tags, notes and hidden devices from a `devices export` file
+    Import {
+        /// Export file (JSON or CSV)
+        file: PathBuf,
+    },
+
+    /// Hide a device from listings, watch and the dashboard (it can still be used by name)
+    Hide {
+        /// Device name or ID
+        device: String,
+    },
+
+    /// Show a hidden device in listings again
+    Unhide {
+        /// Device name or ID
+        device: String,
+    },
+
+    /// Show or set a local note for a device (shown in `devices get`)
+    Note {
+        /// Device name or ID
+        device: String,
+        /// Note text (omit to show the current note)
+        text: Option<String>,
+        /// Remove the note
+        #[arg(long, conflicts_with = "text")]
+        clear: bool,
+    },
+
+    /// Poll devices and print an event line when one turns on/off or goes online/offline
+    Watch {
+        /// Devices to watch (default: all)
+        devices: Vec<String>,
+        /// Poll interval
+        #[arg(long, default_value = "10s", value_parser = parse_duration)]
+        interval: Duration,
+        /// Shell command to run for each event (gets TPLC_EVENT, TPLC_DEVICE, TPLC_DEVICE_ID,
+        /// and TPLC_MESSAGE for message-center notices)
+        #[arg(long)]
+        exec: Option<String>,
+        /// Also report new cloud message-center notices (see `events list`)
+        #[arg(long)]
+        messages: bool,
+    },
+
+    /// Wait until a device comes online (or goes offline) in the cloud's
+    /// device list, e.g. to re-apply settings after a power cut
+    Wait {
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
+        /// Wait for the device to be online (the default)
+        #[arg(long, conflicts_with = "offline")]
+        online: bool,
+        /// Wait for the device to be offline instead
+        #[arg(long)]
+        offline: bool,
+        /// Give up after this long (exit code 4 when waiting for online)
+        #[arg(long, default_value = "120s", value_parser = parse_duration)]
+        max_wait: Duration,
+        /// Poll interval
+        #[arg(long, default_value = "5s", value_parser = parse_duration)]
+        interval: Duration,
+    },
+
+    /// Show or change whether the physical button is disabled (child lock)
+    Button {
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
+        /// Disable the onboard button
+        #[arg(long, conflicts_with = "enable_physical")]
+        disable_physical: bool,
+        /// Re-enable the onboard button
+        #[arg(long)]
+        enable_physical: bool,
+    },
+
+    /// Flash a light, or blink a plug's LED, to find which device a name
+    /// refers to
+    Identify {
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
+        /// Number of flashes, one a second
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..=30))]
+        times: u32,
+    },
+
+    /// Show or configure overload protection (Tapo energy-monitoring plugs)
+    Protection {
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
+        /// Enable protection and trip above this many watts
+        #[arg(long, conflicts_with = "disable")]
+        max_watts: Option<u32>,
+        /// Disable overload protection
+        #[arg(long)]
+        disable: bool,
+    },
 }
 
 #[derive(Tabled)]
@@ -44,70 +177,206 @@ struct DeviceRow {
     device_id: String,
 }
 
+#[derive(Tabled)]
+struct DeviceStateRow {
+    #[tabled(inline)]
+    device: DeviceRow,
+    #[tabled(rename = "POWER")]
+    power: String,
+    #[tabled(rename = "WATTS")]
+    watts: String,
+}
+
 pub async fn handle(cmd: &DevicesCommand, config: &RuntimeConfig) -> Result<(), AppError> {
     match cmd {
-        DevicesCommand::List => handle_list(config).await,
-        DevicesCommand::Get { device } => handle_get(device, config).await,
+        DevicesCommand::List { stream, state } => handle_list(*stream, *state, config).await,
+        DevicesCommand::Get { device } => handle_get(device.as_deref(), config).await,
         DevicesCommand::Search { query } => handle_search(query, config).await,
+        DevicesCommand::Verify { expected } => handle_verify(expected, config).await,
+        DevicesCommand::Export => handle_export(config).await,
+        DevicesCommand::Import { file } => handle_import(file, config),
+        DevicesCommand::Hide { device } => handle_hide(device, true, config).await,
+        DevicesCommand::Unhide { device } => handle_hide(device, false, config).await,
+        DevicesCommand::Note {
+            device,
+            text,
+            clear,
+        } => handle_note(device, text.as_deref(), *clear, config).await,
+        DevicesCommand::Watch {
+            devices,
+            interval,
+            exec,
+            messages,
+        } => handle_watch(devices, *interval, exec.as_deref(), *messages, config).await,
+        DevicesCommand::Button {
+            device,
+            disable_physical,
+            enable_physical,
+        } => {
+            handle_button(
+                device.as_deref(),
+                *disable_physical,
+                *enable_physical,
+                config,
+            )
+            .await
+        }
+        DevicesCommand::Wait {
+            device,
+            offline,
+            max_wait,
+            interval,
+            ..
+        } => handle_wait(device.as_deref(), !offline, *max_wait, *interval, config).await,
+        DevicesCommand::Identify { device, times } => {
+            handle_identify(device.as_deref(), *times, config).await
+        }
+        DevicesCommand::Protection {
+            device,
+            max_watts,
+            disable,
+        } => handle_protection(device.as_deref(), *max_watts, *disable, config).await,
+    }
+}
+
+fn cloud_name(entry: &DeviceEntry) -> &'static str {
+    entry
+        .info
+        .cloud_type
+        .map(|c| c.display_name())
+        .unwrap_or("kasa")
+}
+
+fn status_name(entry: &DeviceEntry) -> &'static str {
+    if entry.is_online() {
+        "online"
+    } else {
+        "offline"
     }
 }
 
-async fn handle_list(config: &RuntimeConfig) -> Result<(), AppError> {
-    let (devices, _auth) = resolve::fetch_all_devices(config.verbose).await?;
+fn power_name(is_on: Option<bool>) -> &'static str {
+    match is_on {
+        Some(true) => "on",
+        Some(false) => "off",
+        None => "unknown",
+    }
+}
 
-    if config.output_mode == OutputMode::Table {
-        let rows: Vec<DeviceRow> = devices
+fn device_row(entry: &DeviceEntry) -> DeviceRow {
+    DeviceRow {
+        name: entry.alias().to_string(),
+        model: entry.info.model().to_string(),
+        category: entry.device_type.category().to_string(),
+        cloud: cloud_name(entry).to_string(),
+        status: status_name(entry).to_string(),
+        emeter: if entry.device_type.has_emeter() {
+            "yes"
+        } else {
+            "no"
+        }
+        .to_string(),
+        device_id: entry.info.id().to_string(),
+    }
+}
+
+fn device_json(entry: &DeviceEntry) -> serde_json::Value {
+    json!({
+        "alias": entry.alias(),
+        "model": entry.info.model(),
+        "device_type": format!("{:?}", entry.device_type),
+        "category": entry.device_type.category(),
+        "cloud": cloud_name(entry),
+        "device_id": entry.info.id(),
+        "status": status_name(entry),
+        "energy_monitoring": entry.device_type.has_emeter(),
+    })
+}
+
+/// The shape `devices search` has always returned.
+fn search_json(entry: &DeviceEntry) -> serde_json::Value {
+    json!({
+        "alias": entry.alias(),
+        "model": entry.info.model(),
+        "device_type": format!("{:?}", entry.device_type),
+        "cloud": cloud_name(entry),
+        "device_id": entry.info.id(),
+        "status": status_name(entry),
+    })
+}
+
+async fn handle_list(stream: bool, state: bool, config: &RuntimeConfig) -> Result<(), AppError> {
+    if stream && (config.envelope || !config.renders_as(OutputMode::Json)) {
+        return Err(AppError::InvalidInput(
+            "--stream only applies to plain JSON output".into(),
+        ));
+    }
+
+    let (devices, auth) = resolve::fetch_listed_devices(config).await?;
+
+    if state {
+        let spinner = Spinner::start("Reading device state", Some(devices.len()), config);
+        let states =
+            resolve::fetch_device_states_with(&devices, &auth, config.verbose, || spinner.tick())
+                .await;
+        drop(spinner);
+        return print_devices_with_state(&devices, &states, config);
+    }
+
+    if config.renders_as(OutputMode::Table) {
+        let rows: Vec<DeviceRow> = devices.iter().map(device_row).collect();
+        print_table(&rows, config);
+    } else if stream {
+        if !config.quiet {
+            print_json_stream(devices.iter().map(device_json));
+        }
+    } else {
+        let json_devices: Vec<serde_json::Value> = devices.iter().map(device_json).collect();
+        print_output(&json!(json_devices), config);
+    }
+
+    Ok(())
+}
+
+fn print_devices_with_state(
+    devices: &[DeviceEntry],
+    states: &[DeviceState],
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    if config.renders_as(OutputMode::Table) {
+        let rows: Vec<DeviceStateRow> = devices
             .iter()
-            .map(|(info, dtype, child_alias)| {
-                let name = child_alias
-                    .as_deref()
-                    .unwrap_or(info.alias_or_name())
-                    .to_string();
-                DeviceRow {
-                    name,
-                    model: info.model().to_string(),
-                    category: dtype.category().to_string(),
-                    cloud: info
-                        .cloud_type
-                        .map(|c| c.display_name().to_string())
-                        .unwrap_or_else(|| "kasa".to_string()),
-                    status: if info.status == Some(1) {
-                        "online"
-                    } else {
-                        "offline"
-                    }
-                    .to_string(),
-                    emeter: if dtype.has_emeter() { "yes" } else { "no" }.to_string(),
-                    device_id: info.id().to_string(),
-                }
+            .zip(states)
+            .map(|(entry, state)| DeviceStateRow {
+                device: device_row(entry),
+                power: power_name(state.is_on).to_uppercase(),
+                watts: state
+                    .power_w
+                    .map(|w| format!("{:.1}", w))
+                    .unwrap_or_default(),
             })
             .collect();
-        print_table(&rows);
+        print_table(&rows, config);
     } else {
         let json_devices: Vec<serde_json::Value> = devices
             .iter()
-            .map(|(info, dtype, child_alias)| {
-                let name = child_alias.as_deref().unwrap_or(info.alias_or_name());
-                json!({
-                    "alias": name,
-                    "model": info.model(),
-                    "device_type": format!("{:?}", dtype),
-                    "category": dtype.category(),
-                    "cloud": info.cloud_type.map(|c| c.display_name()).unwrap_or("kasa"),
-                    "device_id": info.id(),
-                    "status": if info.status == Some(1) { "online" } else { "offline" },
-                    "energy_monitoring": dtype.has_emeter(),
-                })
+            .zip(states)
+            .map(|(entry, state)| {
+                let mut value = device_json(entry);
+                value["power"] = json!(power_name(state.is_on));
+                if entry.device_type.has_emeter() {
+                    value["power_w"] = json!(state.power_w);
+                }
+                value
             })
             .collect();
-        print_json(&json!(json_devices));
+        print_output(&json!(json_devices), config);
     }
-
     Ok(())
 }
 
-async fn handle_get(device_name: &str, config: &RuntimeConfig) -> Result<(), AppError> {
-    let device = resolve::resolve_device(device_name, config.verbose).await?;
+async fn handle_get(device_name: Option<&str>, config: &RuntimeConfig) -> Result<(), AppError> {
+    let device = resolve::resolve_device_or_default(device_name, config).await?;
 
     let sys_info = device.get_sys_info().await?;
 
@@ -121,72 +390,560 @@ async fn handle_get(device_name: &str, config: &RuntimeConfig) -> Result<(), App
         "is_child": device.child_id.is_some(),
     });
 
+    if let Some(note) = store::load_notes()?.get(&device.full_id()) {
+        result["note"] = json!(note);
+    }
+    if store::load_hidden()?.contains_key(&device.full_id()) {
+        result["hidden"] = json!(true);
+    }
+
     if let Some(info) = sys_info {
+        let caps = device
+            .cached_capabilities()
+            .unwrap_or_else(|| Capabilities::from_sys_info(&info));
+        if device.device_type == DeviceType::Unknown {
+            result["detected_capabilities"] = json!(caps);
+        }
+        if caps.button_lock {
+            result["button_disabled"] = json!(info.get("child_protection"));
+        }
         result["sys_info"] = info;
     }
 
-    print_json(&result);
+    print_output(&result, config);
 
     Ok(())
 }
 
 async fn handle_search(query: &str, config: &RuntimeConfig) -> Result<(), AppError> {
-    let (devices, _auth) = resolve::fetch_all_devices(config.verbose).await?;
+    let (devices, _auth) = resolve::fetch_listed_devices(config).await?;
 
     let query_lower = query.to_lowercase();
-    let matching: Vec<_> = devices
+    let matching: Vec<&DeviceEntry> = devices
         .iter()
-        .filter(|(info, _, child_alias)| {
-            let name = child_alias.as_deref().unwrap_or(info.alias_or_name());
-            name.to_lowercase().contains(&query_lower)
-        })
+        .filter(|d| d.alias().to_lowercase().contains(&query_lower))
         .collect();
 
-    if config.output_mode == OutputMode::Table {
-        let rows: Vec<DeviceRow> = matching
-            .iter()
-            .map(|(info, dtype, child_alias)| {
-                let name = child_alias
-                    .as_deref()
-                    .unwrap_or(info.alias_or_name())
-                    .to_string();
-                DeviceRow {
-                    name,
-                    model: info.model().to_string(),
-                    category: dtype.category().to_string(),
-                    cloud: info
-                        .cloud_type
-                        .map(|c| c.display_name().to_string())
-                        .unwrap_or_else(|| "kasa".to_string()),
-                    status: if info.status == Some(1) {
-                        "online"
-                    } else {
-                        "offline"
-                    }
-                    .to_string(),
-                    emeter: if dtype.has_emeter() { "yes" } else { "no" }.to_string(),
-                    device_id: info.id().to_string(),
+    if config.renders_as(OutputMode::Table) {
+        let rows: Vec<DeviceRow> = matching.iter().map(|d| device_row(d)).collect();
+        print_table(&rows, config);
+    } else {
+        let json_devices: Vec<serde_json::Value> =
+            matching.iter().map(|d| search_json(d)).collect();
+        print_output(&json!(json_devices), config);
+    }
+
+    Ok(())
+}
+
+async fn handle_hide(
+    device_name: &str,
+    hide: bool,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let device = resolve::resolve_device(device_name, config).await?;
+    let id = device.full_id();
+    let mut hidden = store::load_hidden()?;
+
+    if hide {
+        hidden.insert(id.clone(), device.alias().to_string());
+    } else {
+        hidden.remove(&id);
+    }
+    store::save_hidden(&hidden)?;
+
+    print_output(
+        &json!({
+            "device": device.alias(),
+            "device_id": id,
+            "hidden": hide,
+        }),
+        config,
+    );
+
+    Ok(())
+}
+
+async fn handle_note(
+    device_name: &str,
+    text: Option<&str>,
+    clear: bool,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let device = resolve::resolve_device(device_name, config).await?;
+    let id = device.full_id();
+    let mut notes = store::load_notes()?;
+
+    if clear {
+        notes.remove(&id);
+        store::save_notes(&notes)?;
+    } else if let Some(text) = text {
+        notes.insert(id.clone(), text.to_string());
+        store::save_notes(&notes)?;
+    }
+
+    print_output(
+        &json!({
+            "device": device.alias(),
+            "device_id": id,
+            "note": notes.get(&id),
+        }),
+        config,
+    );
+
+    Ok(())
+}
+
+async fn handle_watch(
+    names: &[String],
+    interval: Duration,
+    exec: Option<&str>,
+    messages: bool,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let mut watched = HashSet::new();
+    for name in names {
+        watched.insert(resolve::resolve_device(name, config).await?.full_id());
+    }
+
+    // Events are one record per line; JSON output becomes NDJSON.
+    let mut event_config = config.clone();
+    if event_config.output_mode == OutputMode::Json {
+        event_config.output_mode = OutputMode::Ndjson;
+    }
+
+    let mut ticker = tokio::time::interval(interval);
+    let mut previous: Option<watch::Snapshot> = None;
+    let mut messages_since = chrono::Utc::now();
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+
+        let fetched = if watched.is_empty() {
+            resolve::fetch_listed_devices(config).await
+        } else {
+            resolve::fetch_all_devices(config).await
+        };
+        let (mut devices, auth) = match fetched {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("Poll failed: {}", e);
+                continue;
+            }
+        };
+        if !watched.is_empty() {
+            devices.retain(|d| watched.contains(&d.full_id()));
+        }
+        let states = resolve::fetch_device_states(&devices, &auth, config.verbose).await;
+        let current = watch::snapshot(&devices, &states);
+        #[cfg(feature = "history")]
+        if config.history {
+            record_rssi(&devices, &states);
+        }
+
+        if let Some(previous) = &previous {
+            let time = chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false);
+            let mut poll_events = watch::diff(previous, &current, &devices, &time);
+            #[cfg(feature = "history")]
+            if config.history {
+                record_activity(&poll_events);
+            }
+            if messages {
+                poll_events
+                    .extend(new_messages(&devices, &auth, &mut messages_since, config).await);
+            }
+            for event in poll_events {
+                print_output(&json!(event), &event_config);
+                if let Some(command) = exec {
+                    run_event_command(command, &event).await;
                 }
+            }
+            apply_led_policy(previous, &devices, &auth, config.verbose).await;
+        }
+        previous = Some(current);
+    }
+}
+
+/// Message-center notices sent since the last call, as watch events. A
+/// failed fetch is reported and retried from the same point next poll.
+async fn new_messages(
+    devices: &[DeviceEntry],
+    auth: &AuthContext,
+    since: &mut chrono::DateTime<chrono::Utc>,
+    config: &RuntimeConfig,
+) -> Vec<watch::Event> {
+    let messages = match events::fetch_messages(devices, auth, *since, config.verbose).await {
+        Ok(messages) => messages,
+        Err(e) => {
+            eprintln!("Message fetch failed: {}", e);
+            return Vec::new();
+        }
+    };
+    if let Some(last) = messages.last() {
+        // Messages at exactly `since` were already reported
+        *since = last.time + chrono::Duration::milliseconds(1);
+    }
+    messages
+        .into_iter()
+        .filter(|m| {
+            devices
+                .iter()
+                .any(|d| d.child_id.is_none() && d.info.id() == m.device_id)
+        })
+        .map(|m| watch::Event {
+            time: m
+                .time
+                .with_timezone(&chrono::Local)
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+            device: m.device,
+            device_id: m.device_id,
+            event: m.event,
+            message: Some(m.message),
+        })
+        .collect()
+}
+
+/// Add this poll's signal readings to the history store. Failures are
+/// reported, not fatal.
+#[cfg(feature = "history")]
+fn record_rssi(devices: &[DeviceEntry], states: &[DeviceState]) {
+    let time = chrono::Utc::now();
+    let samples: Vec<history::RssiSample> = devices
+        .iter()
+        .zip(states)
+        .filter_map(|(entry, state)| {
+            Some(history::RssiSample {
+                time,
+                device_id: entry.full_id(),
+                device: entry.alias().to_string(),
+                rssi: state.rssi?,
             })
-            .collect();
-        print_table(&rows);
-    } else {
-        let json_devices: Vec<serde_json::Value> = matching
+        })
+        .collect();
+    if let Err(e) = history::record_rssi(&samples) {
+        eprintln!("Could not record signal history: {}", e);
+    }
+}
+
+/// Add this poll's power and connection changes to the history store.
+/// Failures are reported, not fatal.
+#[cfg(feature = "history")]
+fn record_activity(events: &[watch::Event]) {
+    let time = chrono::Utc::now();
+    let records: Vec<history::ActivityRecord> = events
+        .iter()
+        .map(|event| history::ActivityRecord {
+            time,
+            device_id: event.device_id.clone(),
+            device: event.device.clone(),
+            event: event.event.to_string(),
+            source: "local".to_string(),
+        })
+        .collect();
+    if let Err(e) = history::record_activity(&records) {
+        eprintln!("Could not record activity history: {}", e);
+    }
+}
+
+/// Apply saved `led policy` settings to devices that were not in the
+/// previous poll. Results go to stderr so the event stream stays clean.
+async fn apply_led_policy(
+    previous: &watch::Snapshot,
+    devices: &[DeviceEntry],
+    auth: &AuthContext,
+    verbose: bool,
+) {
+    let policy = match store::load_led_policy() {
+        Ok(policy) if !policy.is_empty() => policy,
+        Ok(_) => return,
+        Err(e) => {
+            eprintln!("Could not read LED policy: {}", e);
+            return;
+        }
+    };
+    for on in [true, false] {
+        let new_devices: Vec<&DeviceEntry> = devices
             .iter()
-            .map(|(info, dtype, child_alias)| {
-                let name = child_alias.as_deref().unwrap_or(info.alias_or_name());
-                json!({
-                    "alias": name,
-                    "model": info.model(),
-                    "device_type": format!("{:?}", dtype),
-                    "cloud": info.cloud_type.map(|c| c.display_name()).unwrap_or("kasa"),
-                    "device_id": info.id(),
-                    "status": if info.status == Some(1) { "online" } else { "offline" },
-                })
-            })
+            .filter(|d| !previous.contains_key(&d.full_id()))
+            .filter(|d| led::policy_for(&policy, d) == Some(on))
             .collect();
-        print_json(&json!(json_devices));
+        for result in led::apply_led(&new_devices, on, auth, verbose).await {
+            eprintln!(
+                "LED policy ({}) for new device {}: {}",
+                if on { "on" } else { "off" },
+                result["device"].as_str().unwrap_or_default(),
+                result["status"].as_str().unwrap_or_default()
+            );
+        }
+    }
+}
+
+/// Run the `--exec` command for an event. Failures are reported, not fatal.
+async fn run_event_command(command: &str, event: &watch::Event) {
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = tokio::process::Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = tokio::process::Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    };
+    cmd.env("TPLC_EVENT", event.event)
+        .env("TPLC_DEVICE", &event.device)
+        .env("TPLC_DEVICE_ID", &event.device_id);
+    if let Some(message) = &event.message {
+        cmd.env("TPLC_MESSAGE", message);
+    }
+    match cmd.status().await {
+        Ok(status) if !status.success() => eprintln!("--exec command exited with {}", status),
+        Ok(_) => {}
+        Err(e) => eprintln!("--exec command failed to start: {}", e),
+    }
+}
+
+async fn handle_button(
+    device_name: Option<&str>,
+    disable_physical: bool,
+    enable_physical: bool,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let device = resolve::resolve_device_or_default(device_name, config).await?;
+
+    if disable_physical || enable_physical {
+        device.set_button_lock(disable_physical).await?;
+    }
+    let locked = device.is_button_locked().await?;
+
+    print_output(
+        &json!({
+            "device": device.alias(),
+            "device_id": device.full_id(),
+            "button_disabled": locked,
+        }),
+        config,
+    );
+
+    Ok(())
+}
+
+async fn handle_wait(
+    device_name: Option<&str>,
+    online: bool,
+    max_wait: Duration,
+    interval: Duration,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let device = resolve::resolve_device_or_default(device_name, config).await?;
+    let (id, wanted) = (device.full_id(), if online { "online" } else { "offline" });
+
+    // Poll the cloud every time, not a list shared between `tplc run` steps
+    let mut poll_config = config.clone();
+    poll_config.device_cache = None;
+    let spinner = Spinner::start(
+        &format!("Waiting for {} to be {}", device.alias(), wanted),
+        None,
+        config,
+    );
+    let started = tokio::time::Instant::now();
+    let deadline = started + max_wait;
+    loop {
+        match resolve::fetch_all_devices(&poll_config).await {
+            Ok((devices, _)) => {
+                let entry = devices.iter().find(|d| d.full_id() == id);
+                if entry.is_some_and(|d| d.is_online() == online) {
+                    break;
+                }
+            }
+            // Right after a power cut the network may not be back either
+            Err(err) if err.is_retryable() => {}
+            Err(err) => return Err(err),
+        }
+        if tokio::time::Instant::now() + interval > deadline {
+            let message = format!(
+                "{} still not {} after {}s",
+                device.alias(),
+                wanted,
+                max_wait.as_secs()
+            );
+            return Err(if online {
+                AppError::DeviceOffline {
+                    message,
+                    error_code: None,
+                    device: Some(device.alias().to_string()),
+                }
+            } else {
+                AppError::CheckFailed(message)
+            });
+        }
+        tokio::time::sleep(interval).await;
+    }
+    drop(spinner);
+
+    print_output(
+        &json!({
+            "device": device.alias(),
+            "device_id": id,
+            "status": wanted,
+            "waited_s": started.elapsed().as_secs(),
+        }),
+        config,
+    );
+    Ok(())
+}
+
+async fn handle_identify(
+    device_name: Option<&str>,
+    times: u32,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let device = resolve::resolve_device_or_default(device_name, config).await?;
+    let mut identify = device.identify(times);
+
+    // Ctrl-C stops after the current flash, with the light or LED put back
+    let result = tokio::select! {
+        result = &mut identify => result,
+        _ = tokio::signal::ctrl_c() => {
+            identify.cancel();
+            (&mut identify).await
+        }
+    };
+    match result {
+        Ok(()) | Err(AppError::Cancelled) => {}
+        Err(err) => return Err(err),
+    }
+    let flashes = identify.progress().map_or(0, |p| p.done);
+    print_output(
+        &json!({"device": device.alias(), "flashes": flashes}),
+        config,
+    );
+    Ok(())
+}
+
+async fn handle_protection(
+    device_name: Option<&str>,
+    max_watts: Option<u32>,
+    disable: bool,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let device = resolve::resolve_device_or_default(device_name, config).await?;
+
+    if disable || max_watts.is_some() {
+        device.set_power_protection(!disable, max_watts).await?;
+    }
+
+    let settings = device.get_power_protection().await?;
+    // "normal" or "overloaded"; overloaded means the plug tripped and cut power
+    let status = device
+        .get_sys_info()
+        .await?
+        .and_then(|info| info.get("power_protection_status").cloned());
+
+    print_output(
+        &json!({
+            "device": device.alias(),
+            "device_id": device.full_id(),
+            "enabled": settings.as_ref().and_then(|s| s.get("enabled")),
+            "max_watts": settings.as_ref().and_then(|s| s.get("protection_power")),
+            "status": status,
+        }),
+        config,
+    );
+
+    Ok(())
+}
+
+async fn handle_verify(expected: &Path, config: &RuntimeConfig) -> Result<(), AppError> {
+    let inventory = inventory::load_inventory(expected)?;
+    let (devices, _auth) = resolve::fetch_all_devices(config).await?;
+
+    let report = inventory::verify(&inventory, &devices);
+    let mut result = json!(report);
+    result["ok"] = json!(report.is_ok());
+    print_output(&result, config);
+
+    if report.is_ok() {
+        Ok(())
+    } else {
+        Err(AppError::CheckFailed(report.summary()))
+    }
+}
+
+async fn handle_export(config: &RuntimeConfig) -> Result<(), AppError> {
+    let (devices, _auth) = resolve::fetch_all_devices(config).await?;
+    let local = inventory::LocalState {
+        aliases: store::load_aliases()?,
+        tags: store::load_tags()?,
+        notes: store::load_notes()?,
+        hidden: store::load_hidden()?,
+    };
+    let records: Vec<inventory::InventoryRecord> = devices
+        .iter()
+        .map(|entry| inventory::InventoryRecord::from_entry(entry, &local))
+        .collect();
+
+    // Fixed columns so the file imports back and diffs cleanly between runs
+    if config.renders_as(OutputMode::Csv) {
+        emit(&inventory::to_csv(&records));
+    } else {
+        print_output(&json!(records), config);
+    }
+    Ok(())
+}
+
+fn handle_import(file: &Path, config: &RuntimeConfig) -> Result<(), AppError> {
+    let records = inventory::load_export(file)?;
+
+    // Imported entries win over current ones with the same key
+    let mut aliases = store::load_aliases()?;
+    let mut tags = store::load_tags()?;
+    let mut notes = store::load_notes()?;
+    let mut hidden = store::load_hidden()?;
+    let (mut nickname_count, mut tag_count, mut note_count, mut hidden_count) = (0, 0, 0, 0);
+    for record in records.iter().filter(|r| !r.device_id.is_empty()) {
+        for name in &record.nicknames {
+            aliases.insert(
+                name.clone(),
+                store::LocalAlias {
+                    device_id: record.device_id.clone(),
+                    device_name: record.alias.clone(),
+                },
+            );
+            nickname_count += 1;
+        }
+        if !record.tags.is_empty() {
+            let entry = tags.entry(record.device_id.clone()).or_default();
+            entry.device_name = record.alias.clone();
+            entry
+                .tags
+                .extend(record.tags.iter().map(|t| t.to_lowercase()));
+            tag_count += record.tags.len();
+        }
+        if let Some(note) = &record.note {
+            notes.insert(record.device_id.clone(), note.clone());
+            note_count += 1;
+        }
+        if record.hidden {
+            hidden.insert(record.device_id.clone(), record.alias.clone());
+            hidden_count += 1;
+        }
     }
+    store::save_aliases(&aliases)?;
+    store::save_tags(&tags)?;
+    store::save_notes(&notes)?;
+    store::save_hidden(&hidden)?;
 
+    print_output(
+        &json!({
+            "devices": records.len(),
+            "nicknames": nickname_count,
+            "tags": tag_count,
+            "notes": note_count,
+            "hidden": hidden_count,
+        }),
+        config,
+    );
     Ok(())
 }
@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use clap::Subcommand;
+use serde_json::json;
+
+use crate::cli::output::print_output;
+use crate::config::RuntimeConfig;
+use crate::duration::parse_duration;
+use crate::error::AppError;
+
+use super::super::resolve;
+
+#[derive(Subcommand)]
+pub enum DimmerCommand {
+    /// Show or change fade and gentle on/off times (HS220, KS220)
+    Config {
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
+        /// Fade-in time when turned on, e.g. 2s or 500ms
+        #[arg(long, value_parser = parse_duration)]
+        fade_on: Option<Duration>,
+        /// Fade-out time when turned off
+        #[arg(long, value_parser = parse_duration)]
+        fade_off: Option<Duration>,
+        /// Gentle-on time (long press / app "gentle on")
+        #[arg(long, value_parser = parse_duration)]
+        gentle_on: Option<Duration>,
+        /// Gentle-off time
+        #[arg(long, value_parser = parse_duration)]
+        gentle_off: Option<Duration>,
+    },
+}
+
+pub async fn handle(cmd: &DimmerCommand, config: &RuntimeConfig) -> Result<(), AppError> {
+    match cmd {
+        DimmerCommand::Config {
+            device,
+            fade_on,
+            fade_off,
+            gentle_on,
+            gentle_off,
+        } => {
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
+            let ms = |d: &Duration| d.as_millis() as u64;
+
+            if let Some(d) = fade_on {
+                dev.set_fade_on_time(ms(d)).await?;
+            }
+            if let Some(d) = fade_off {
+                dev.set_fade_off_time(ms(d)).await?;
+            }
+            if let Some(d) = gentle_on {
+                dev.set_gentle_on_time(ms(d)).await?;
+            }
+            if let Some(d) = gentle_off {
+                dev.set_gentle_off_time(ms(d)).await?;
+            }
+
+            let params = dev.get_dimmer_parameters().await?.unwrap_or_default();
+            let field = |key: &str| params.get(key).cloned();
+            print_output(
+                &json!({
+                    "device": dev.alias(),
+                    "fade_on_ms": field("fadeOnTime"),
+                    "fade_off_ms": field("fadeOffTime"),
+                    "gentle_on_ms": field("gentleOnTime"),
+                    "gentle_off_ms": field("gentleOffTime"),
+                    "ramp_rate": field("rampRate"),
+                    "min_threshold": field("minThreshold"),
+                }),
+                config,
+            );
+            Ok(())
+        }
+    }
+}
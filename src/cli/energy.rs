@@ -1,69 +1,292 @@
-use chrono::Datelike;
-use clap::Subcommand;
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDate};
+use clap::{Args, Subcommand};
+use futures::stream::{self, StreamExt};
 use serde_json::json;
+use tabled::Tabled;
 
-use crate::cli::output::print_json;
-use crate::config::RuntimeConfig;
+use crate::cli::output::{emit, influx_line, print_output, print_table_without};
+use crate::cli::progress::Spinner;
+use crate::config::{OutputMode, RuntimeConfig};
+use crate::duration::parse_duration;
 use crate::error::AppError;
-use crate::models::energy::{CurrentPower, DayPowerSummary, MonthPowerSummary};
+use crate::models::device::Device;
+use crate::models::energy::{DayPowerSummary, EmeterRealtime, EnergyFormat, MonthPowerSummary};
+use crate::resolve::MAX_CONCURRENT_REQUESTS;
 
 use super::super::resolve;
 
+#[derive(Args)]
+pub struct FormatArgs {
+    /// Output raw milli-unit readings (mV, mA, mW, Wh) as reported by the device
+    #[arg(long)]
+    raw: bool,
+    /// Decimal places for W, V, A and kWh values
+    #[arg(long, default_value_t = 2)]
+    decimals: usize,
+    /// Electricity price per kWh, to include costs
+    #[arg(long, visible_alias = "rate", env = "TPLC_PRICE")]
+    price: Option<f64>,
+    /// Currency code for costs (e.g. USD, EUR, GBP) [default: USD]
+    #[arg(long, env = "TPLC_CURRENCY")]
+    currency: Option<String>,
+}
+
+impl FormatArgs {
+    /// Flags (or their environment variables) override the config file tariff.
+    fn energy_format(&self, config: &RuntimeConfig) -> EnergyFormat {
+        EnergyFormat {
+            decimals: self.decimals,
+            price_per_kwh: self.price.or(config.price_per_kwh),
+            currency: self
+                .currency
+                .as_deref()
+                .unwrap_or(&config.currency)
+                .to_uppercase(),
+        }
+    }
+}
+
+#[derive(Tabled)]
+struct RealtimeRow {
+    #[tabled(rename = "DEVICE")]
+    device: String,
+    #[tabled(rename = "VOLTAGE (V)")]
+    voltage: String,
+    #[tabled(rename = "CURRENT (A)")]
+    current: String,
+    #[tabled(rename = "POWER (W)")]
+    power: String,
+    #[tabled(rename = "TOTAL (kWh)")]
+    total: String,
+    #[tabled(rename = "COST")]
+    cost: String,
+}
+
+/// One device of `energy report`, or its total.
+#[derive(Tabled)]
+struct ReportRow {
+    #[tabled(rename = "DEVICE")]
+    device: String,
+    #[tabled(rename = "MODEL")]
+    model: String,
+    #[tabled(rename = "ENERGY (kWh)")]
+    energy: String,
+    #[tabled(rename = "COST")]
+    cost: String,
+}
+
+/// One day of `energy daily` or one month of `energy monthly`.
+#[derive(Tabled)]
+struct UsageRow {
+    #[tabled(rename = "DATE")]
+    date: String,
+    #[tabled(rename = "ENERGY (kWh)")]
+    energy: String,
+    #[tabled(rename = "COST")]
+    cost: String,
+}
+
+/// A milli-unit reading in base units with the chosen decimals, for tables.
+fn fixed(fmt: &EnergyFormat, milli: Option<f64>) -> String {
+    fmt.base(milli)
+        .map(|v| format!("{:.*}", fmt.decimals, v))
+        .unwrap_or_default()
+}
+
+/// Table columns to leave out: COST without a price.
+fn hidden_columns(fmt: &EnergyFormat) -> &'static [&'static str] {
+    if fmt.price_per_kwh.is_some() {
+        &[]
+    } else {
+        &["COST"]
+    }
+}
+
+/// Tags of every line-protocol point (`-o influx`) for a device.
+fn influx_tags(dev: &Device) -> serde_json::Value {
+    json!({
+        "device": dev.alias(),
+        "model": dev.info.model(),
+        "cloud": dev.info.cloud_type.map(|c| c.display_name()).unwrap_or("kasa"),
+    })
+}
+
+/// Nanosecond timestamp of local midnight at the start of a day, for
+/// daily and monthly points.
+fn local_midnight_ns(year: i32, month: u32, day: u32) -> Option<i64> {
+    NaiveDate::from_ymd_opt(year, month, day)?
+        .and_hms_opt(0, 0, 0)?
+        .and_local_timezone(chrono::Local)
+        .earliest()?
+        .timestamp_nanos_opt()
+}
+
+/// Emit one line-protocol point per `(fields, time)`, tagged for `dev`.
+fn print_influx(
+    dev: &Device,
+    measurement: &str,
+    points: Vec<(serde_json::Value, Option<i64>)>,
+    config: &RuntimeConfig,
+) {
+    if config.quiet {
+        return;
+    }
+    let mut out = String::new();
+    for (fields, time) in points {
+        let mut record = influx_tags(dev);
+        if let (Some(record), Some(fields)) = (record.as_object_mut(), fields.as_object()) {
+            record.extend(fields.clone());
+        }
+        if let Some(line) = influx_line(measurement, &record, time) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+    emit(&out);
+}
+
 #[derive(Subcommand)]
 pub enum EnergyCommand {
     /// Current power usage (realtime)
     Realtime {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
+        #[command(flatten)]
+        format: FormatArgs,
+    },
+
+    /// Print a realtime reading every interval until interrupted (Ctrl-C);
+    /// JSON output becomes one object per line
+    Watch {
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
+        /// Poll interval
+        #[arg(long, default_value = "10s", value_parser = parse_duration)]
+        interval: Duration,
+        #[command(flatten)]
+        format: FormatArgs,
     },
 
     /// Daily power usage statistics
     Daily {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
         #[arg(long)]
         year: Option<i32>,
         #[arg(long)]
         month: Option<u32>,
+        /// First day of a date range (YYYY-MM-DD) instead of one month; adds
+        /// the total and daily average
+        #[arg(long, conflicts_with_all = ["year", "month"])]
+        from: Option<NaiveDate>,
+        /// Last day of the range [default: today]
+        #[arg(long, requires = "from")]
+        to: Option<NaiveDate>,
+        #[command(flatten)]
+        format: FormatArgs,
     },
 
     /// Monthly power usage statistics
     Monthly {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
         #[arg(long)]
         year: Option<i32>,
+        #[command(flatten)]
+        format: FormatArgs,
     },
 
     /// Summary of all energy-monitoring devices
     Summary,
+
+    /// Month-end report: kWh and cost of every energy-monitoring device
+    /// (including power strip outlets) and their total
+    Report {
+        /// Month to report (YYYY-MM) [default: current month]
+        #[arg(long, value_parser = parse_month)]
+        month: Option<(i32, u32)>,
+        #[command(flatten)]
+        format: FormatArgs,
+    },
+
+    /// Check current power draw against limits; exits with code 5 when a
+    /// limit is crossed (for cron-based alerts)
+    Assert {
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
+        /// Fail if the device draws more than this many watts
+        #[arg(long, required_unless_present = "min_watts")]
+        max_watts: Option<f64>,
+        /// Fail if the device draws less than this many watts
+        #[arg(long)]
+        min_watts: Option<f64>,
+    },
 }
 
 pub async fn handle(cmd: &EnergyCommand, config: &RuntimeConfig) -> Result<(), AppError> {
     match cmd {
-        EnergyCommand::Realtime { device } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+        EnergyCommand::Realtime { device, format } => {
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
             let data = dev.get_power_usage_realtime().await?;
             if let Some(data) = data {
-                let power = CurrentPower::from_json(&data);
-                print_json(&json!({
-                    "device": dev.alias(),
-                    "voltage_mv": power.voltage_mv,
-                    "current_ma": power.current_ma,
-                    "power_mw": power.power_mw,
-                    "total_wh": power.total_wh,
-                }));
+                let power = EmeterRealtime::from_json(&data, dev.emeter_units());
+                print_realtime(&dev, &power, format, config);
             } else {
-                print_json(&json!({"device": dev.alias(), "error": "no data"}));
+                print_output(&json!({"device": dev.alias(), "error": "no data"}), config);
             }
             Ok(())
         }
+        EnergyCommand::Watch {
+            device,
+            interval,
+            format,
+        } => {
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
+            // Readings are one record per line; JSON output becomes NDJSON.
+            let mut reading_config = config.clone();
+            if reading_config.output_mode == OutputMode::Json {
+                reading_config.output_mode = OutputMode::Ndjson;
+            }
+            let mut ticker = tokio::time::interval(*interval);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = tokio::signal::ctrl_c() => return Ok(()),
+                }
+                match dev.get_power_usage_realtime().await {
+                    Ok(Some(data)) => {
+                        let power = EmeterRealtime::from_json(&data, dev.emeter_units());
+                        print_realtime(&dev, &power, format, &reading_config);
+                    }
+                    Ok(None) => eprintln!("Poll failed: {} returned no data", dev.alias()),
+                    Err(e) => eprintln!("Poll failed: {}", e),
+                }
+            }
+        }
         EnergyCommand::Daily {
             device,
             year,
             month,
+            from,
+            to,
+            format,
         } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
+            if let Some(from) = *from {
+                let to = to.unwrap_or_else(|| chrono::Local::now().date_naive());
+                if to < from {
+                    return Err(AppError::InvalidInput(format!(
+                        "--to {} is before --from {}",
+                        to, from
+                    )));
+                }
+                let days = dev.daily_usage_range(from, to).await?;
+                let period = json!({"from": from.to_string(), "to": to.to_string()});
+                print_days(&dev, days, period, true, format, config);
+                return Ok(());
+            }
             let now = chrono::Local::now();
             let y = year.unwrap_or(now.year());
             let m = month.unwrap_or(now.month());
@@ -74,26 +297,34 @@ pub async fn handle(cmd: &EnergyCommand, config: &RuntimeConfig) -> Result<(), A
                     .and_then(|v| v.as_array())
                     .cloned()
                     .unwrap_or_default();
-                let summaries: Vec<serde_json::Value> = day_list
+                let days: Vec<DayPowerSummary> = day_list
                     .iter()
                     .map(|d| {
-                        let s = DayPowerSummary::from_json(d);
-                        json!(s)
+                        let mut day = DayPowerSummary::from_json(d, dev.emeter_units());
+                        day.year.get_or_insert(y);
+                        day.month.get_or_insert(m);
+                        day
                     })
                     .collect();
-                print_json(&json!({
-                    "device": dev.alias(),
-                    "year": y,
-                    "month": m,
-                    "days": summaries,
-                }));
+                print_days(
+                    &dev,
+                    days,
+                    json!({"year": y, "month": m}),
+                    false,
+                    format,
+                    config,
+                );
             } else {
-                print_json(&json!({"device": dev.alias(), "error": "no data"}));
+                print_output(&json!({"device": dev.alias(), "error": "no data"}), config);
             }
             Ok(())
         }
-        EnergyCommand::Monthly { device, year } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+        EnergyCommand::Monthly {
+            device,
+            year,
+            format,
+        } => {
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
             let now = chrono::Local::now();
             let y = year.unwrap_or(now.year());
             let data = dev.get_power_usage_month(y).await?;
@@ -103,33 +334,124 @@ pub async fn handle(cmd: &EnergyCommand, config: &RuntimeConfig) -> Result<(), A
                     .and_then(|v| v.as_array())
                     .cloned()
                     .unwrap_or_default();
-                let summaries: Vec<serde_json::Value> = month_list
+                let months: Vec<MonthPowerSummary> = month_list
                     .iter()
-                    .map(|m| {
-                        let s = MonthPowerSummary::from_json(m);
-                        json!(s)
+                    .map(|m| MonthPowerSummary::from_json(m, dev.emeter_units()))
+                    .collect();
+                if !format.raw && config.renders_as(OutputMode::Influx) {
+                    let fmt = format.energy_format(config);
+                    let points = months
+                        .iter()
+                        .map(|s| {
+                            let time =
+                                local_midnight_ns(s.year.unwrap_or(y), s.month.unwrap_or(1), 1);
+                            (json!({"kwh": fmt.base(s.energy_wh)}), time)
+                        })
+                        .collect();
+                    print_influx(&dev, "energy_monthly", points, config);
+                    return Ok(());
+                }
+                if !format.raw && config.renders_as(OutputMode::Table) {
+                    let fmt = format.energy_format(config);
+                    let rows: Vec<UsageRow> = months
+                        .iter()
+                        .map(|s| UsageRow {
+                            date: format!(
+                                "{:04}-{:02}",
+                                s.year.unwrap_or(y),
+                                s.month.unwrap_or_default()
+                            ),
+                            energy: fixed(&fmt, s.energy_wh),
+                            cost: fmt.cost(s.energy_wh).unwrap_or_default(),
+                        })
+                        .collect();
+                    print_table_without(&rows, hidden_columns(&fmt), config);
+                    return Ok(());
+                }
+                let summaries: Vec<serde_json::Value> = months
+                    .into_iter()
+                    .map(|s| {
+                        if format.raw {
+                            return json!(s);
+                        }
+                        let fmt = format.energy_format(config);
+                        let mut month = json!({
+                            "year": s.year,
+                            "month": s.month,
+                            "energy_kwh": fmt.base(s.energy_wh),
+                        });
+                        if let Some(cost) = fmt.cost(s.energy_wh) {
+                            month["cost"] = json!(cost);
+                        }
+                        month
                     })
                     .collect();
-                print_json(&json!({
-                    "device": dev.alias(),
-                    "year": y,
-                    "months": summaries,
-                }));
+                print_output(
+                    &json!({
+                        "device": dev.alias(),
+                        "year": y,
+                        "months": summaries,
+                    }),
+                    config,
+                );
             } else {
-                print_json(&json!({"device": dev.alias(), "error": "no data"}));
+                print_output(&json!({"device": dev.alias(), "error": "no data"}), config);
             }
             Ok(())
         }
+        EnergyCommand::Assert {
+            device,
+            max_watts,
+            min_watts,
+        } => {
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
+            let reading = dev.emeter_realtime().await?;
+            let Some(watts) = reading.power_mw.map(|mw| mw / 1000.0) else {
+                return Err(AppError::CheckFailed(format!(
+                    "{} reported no power reading",
+                    dev.alias()
+                )));
+            };
+            let violation = power_violation(watts, *min_watts, *max_watts);
+            print_output(
+                &json!({
+                    "device": dev.alias(),
+                    "power_w": watts,
+                    "min_watts": min_watts,
+                    "max_watts": max_watts,
+                    "ok": violation.is_none(),
+                    "violation": violation,
+                }),
+                config,
+            );
+            match violation {
+                Some(violation) => Err(AppError::CheckFailed(format!(
+                    "{} draws {} W, {}",
+                    dev.alias(),
+                    watts,
+                    violation
+                ))),
+                None => Ok(()),
+            }
+        }
+        EnergyCommand::Report { month, format } => {
+            let now = chrono::Local::now();
+            let (year, month) = month.unwrap_or((now.year(), now.month()));
+            handle_report(year, month, format, config).await
+        }
         EnergyCommand::Summary => {
-            let (devices, _) = resolve::fetch_all_devices(config.verbose).await?;
+            let spinner = Spinner::start("Listing energy-monitoring devices", None, config);
+            let (devices, _) = resolve::fetch_all_devices(config).await?;
+            drop(spinner);
             let emeter_devices: Vec<_> = devices
                 .iter()
-                .filter(|(_, dtype, _)| dtype.has_emeter())
+                .filter(|d| d.device_type.has_emeter())
                 .collect();
 
             if emeter_devices.is_empty() {
-                print_json(
+                print_output(
                     &json!({"devices": [], "message": "No energy monitoring devices found"}),
+                    config,
                 );
                 return Ok(());
             }
@@ -138,17 +460,335 @@ pub async fn handle(cmd: &EnergyCommand, config: &RuntimeConfig) -> Result<(), A
             // For now, just list the emeter-capable devices.
             let summaries: Vec<serde_json::Value> = emeter_devices
                 .iter()
-                .map(|(info, _dtype, child_alias)| {
-                    let name = child_alias.as_deref().unwrap_or(info.alias_or_name());
+                .map(|d| {
                     json!({
-                        "alias": name,
-                        "model": info.model(),
-                        "device_id": info.id(),
+                        "alias": d.alias(),
+                        "model": d.info.model(),
+                        "device_id": d.info.id(),
                     })
                 })
                 .collect();
-            print_json(&json!({"emeter_devices": summaries}));
+            print_output(&json!({"emeter_devices": summaries}), config);
             Ok(())
         }
     }
 }
+
+/// Parse a `--month` value, `YYYY-MM`.
+fn parse_month(value: &str) -> Result<(i32, u32), String> {
+    let date = NaiveDate::parse_from_str(&format!("{}-01", value), "%Y-%m-%d")
+        .map_err(|_| format!("invalid month '{}'. Use YYYY-MM", value))?;
+    Ok((date.year(), date.month()))
+}
+
+async fn handle_report(
+    year: i32,
+    month: u32,
+    format: &FormatArgs,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let (entries, auth) = resolve::fetch_listed_devices(config).await?;
+    let devices = entries
+        .iter()
+        .filter(|e| e.device_type.has_emeter())
+        .map(|e| resolve::build_device(e, &auth, config.verbose))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let spinner = Spinner::start(
+        "Reading energy-monitoring devices",
+        Some(devices.len()),
+        config,
+    );
+    let progress = &spinner;
+    let usage: Vec<Result<f64, AppError>> = stream::iter(&devices)
+        .map(|dev| async move {
+            let result = dev.monthly_usage(year).await.map(|months| {
+                months
+                    .iter()
+                    .filter(|m| m.month == Some(month))
+                    .filter_map(|m| m.energy_wh)
+                    .sum()
+            });
+            progress.tick();
+            result
+        })
+        .buffered(MAX_CONCURRENT_REQUESTS)
+        .collect()
+        .await;
+    drop(spinner);
+
+    let fmt = format.energy_format(config);
+    let mut total_wh = 0.0;
+    let mut failed = 0;
+    let mut records = Vec::new();
+    let mut rows = Vec::new();
+    for (dev, usage) in devices.iter().zip(usage) {
+        let mut record = json!({
+            "device": dev.alias(),
+            "device_id": dev.full_id(),
+            "model": dev.info.model(),
+        });
+        let (energy, cost) = match usage {
+            Ok(wh) => {
+                total_wh += wh;
+                record["energy_kwh"] = json!(fmt.base(Some(wh)));
+                if let Some(cost) = fmt.cost(Some(wh)) {
+                    record["cost"] = json!(cost);
+                }
+                (
+                    fixed(&fmt, Some(wh)),
+                    fmt.cost(Some(wh)).unwrap_or_default(),
+                )
+            }
+            Err(err) => {
+                failed += 1;
+                record["error"] = json!(err.to_string());
+                ("error".to_string(), String::new())
+            }
+        };
+        rows.push(ReportRow {
+            device: dev.alias().to_string(),
+            model: dev.info.model().to_string(),
+            energy,
+            cost,
+        });
+        records.push(record);
+    }
+
+    if config.renders_as(OutputMode::Table) {
+        rows.push(ReportRow {
+            device: "Total".to_string(),
+            model: String::new(),
+            energy: fixed(&fmt, Some(total_wh)),
+            cost: fmt.cost(Some(total_wh)).unwrap_or_default(),
+        });
+        print_table_without(&rows, hidden_columns(&fmt), config);
+    } else if config.renders_as(OutputMode::Csv) {
+        // A spreadsheet-ready sheet: one line per device, then the total
+        let mut total = json!({"device": "Total", "energy_kwh": fmt.base(Some(total_wh))});
+        if let Some(cost) = fmt.cost(Some(total_wh)) {
+            total["cost"] = json!(cost);
+        }
+        records.push(total);
+        print_output(&json!(records), config);
+    } else {
+        let mut result = json!({
+            "month": format!("{:04}-{:02}", year, month),
+            "devices": records,
+            "total_kwh": fmt.base(Some(total_wh)),
+        });
+        if let Some(cost) = fmt.cost(Some(total_wh)) {
+            result["total_cost"] = json!(cost);
+        }
+        print_output(&result, config);
+    }
+
+    if failed > 0 {
+        return Err(AppError::CheckFailed(format!(
+            "{} of {} devices could not be read; the total leaves them out",
+            failed,
+            devices.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Print `energy daily` results, headed by the `period` fields. With
+/// `totals` (date ranges), the total and the average per day with data
+/// follow the days, as fields or as table rows.
+fn print_days(
+    dev: &Device,
+    days: Vec<DayPowerSummary>,
+    period: serde_json::Value,
+    totals: bool,
+    format: &FormatArgs,
+    config: &RuntimeConfig,
+) {
+    let fmt = format.energy_format(config);
+    let total_wh: f64 = days.iter().filter_map(|s| s.energy_wh).sum();
+    let average_wh = (!days.is_empty()).then(|| total_wh / days.len() as f64);
+
+    if !format.raw && config.renders_as(OutputMode::Influx) {
+        let points = days
+            .iter()
+            .map(|s| {
+                let time = local_midnight_ns(
+                    s.year.unwrap_or_default(),
+                    s.month.unwrap_or(1),
+                    s.day.unwrap_or(1),
+                );
+                (json!({"kwh": fmt.base(s.energy_wh)}), time)
+            })
+            .collect();
+        print_influx(dev, "energy_daily", points, config);
+        return;
+    }
+    if !format.raw && config.renders_as(OutputMode::Table) {
+        let mut rows: Vec<UsageRow> = days
+            .iter()
+            .map(|s| UsageRow {
+                date: format!(
+                    "{:04}-{:02}-{:02}",
+                    s.year.unwrap_or_default(),
+                    s.month.unwrap_or_default(),
+                    s.day.unwrap_or_default()
+                ),
+                energy: fixed(&fmt, s.energy_wh),
+                cost: fmt.cost(s.energy_wh).unwrap_or_default(),
+            })
+            .collect();
+        if totals {
+            for (label, wh) in [("Total", Some(total_wh)), ("Daily average", average_wh)] {
+                rows.push(UsageRow {
+                    date: label.to_string(),
+                    energy: fixed(&fmt, wh),
+                    cost: fmt.cost(wh).unwrap_or_default(),
+                });
+            }
+        }
+        print_table_without(&rows, hidden_columns(&fmt), config);
+        return;
+    }
+
+    let summaries: Vec<serde_json::Value> = days
+        .into_iter()
+        .map(|s| {
+            if format.raw {
+                return json!(s);
+            }
+            let mut day = json!({
+                "year": s.year,
+                "month": s.month,
+                "day": s.day,
+                "energy_kwh": fmt.base(s.energy_wh),
+            });
+            if let Some(cost) = fmt.cost(s.energy_wh) {
+                day["cost"] = json!(cost);
+            }
+            day
+        })
+        .collect();
+    let mut result = json!({"device": dev.alias()});
+    if let (Some(result), Some(period)) = (result.as_object_mut(), period.as_object()) {
+        result.extend(period.clone());
+    }
+    result["days"] = json!(summaries);
+    if totals {
+        if format.raw {
+            result["total_wh"] = json!(total_wh);
+            result["average_wh"] = json!(average_wh);
+        } else {
+            result["total_kwh"] = json!(fmt.base(Some(total_wh)));
+            result["average_kwh"] = json!(fmt.base(average_wh));
+            if let Some(cost) = fmt.cost(Some(total_wh)) {
+                result["total_cost"] = json!(cost);
+            }
+        }
+    }
+    print_output(&result, config);
+}
+
+/// Print one realtime reading: raw milli-units with `--raw`, the readable
+/// table, a line-protocol point stamped now, or the JSON record.
+fn print_realtime(
+    dev: &Device,
+    power: &EmeterRealtime,
+    format: &FormatArgs,
+    config: &RuntimeConfig,
+) {
+    let fmt = format.energy_format(config);
+    if format.raw {
+        print_output(
+            &json!({
+                "device": dev.alias(),
+                "voltage_mv": power.voltage_mv,
+                "current_ma": power.current_ma,
+                "power_mw": power.power_mw,
+                "total_wh": power.total_wh,
+            }),
+            config,
+        );
+    } else if config.renders_as(OutputMode::Table) {
+        let row = RealtimeRow {
+            device: dev.alias().to_string(),
+            voltage: fixed(&fmt, power.voltage_mv),
+            current: fixed(&fmt, power.current_ma),
+            power: fixed(&fmt, power.power_mw),
+            total: fixed(&fmt, power.total_wh),
+            cost: fmt.cost(power.total_wh).unwrap_or_default(),
+        };
+        print_table_without(&[row], hidden_columns(&fmt), config);
+    } else if config.renders_as(OutputMode::Influx) {
+        let fields = json!({
+            "watts": fmt.base(power.power_mw),
+            "kwh": fmt.base(power.total_wh),
+            "volts": fmt.base(power.voltage_mv),
+            "amps": fmt.base(power.current_ma),
+        });
+        let now = chrono::Utc::now().timestamp_nanos_opt();
+        print_influx(dev, "energy", vec![(fields, now)], config);
+    } else {
+        let mut result = json!({
+            "device": dev.alias(),
+            "voltage_v": fmt.base(power.voltage_mv),
+            "current_a": fmt.base(power.current_ma),
+            "power_w": fmt.base(power.power_mw),
+            "total_kwh": fmt.base(power.total_wh),
+        });
+        if let Some(cost) = fmt.cost(power.total_wh) {
+            result["total_cost"] = json!(cost);
+        }
+        print_output(&result, config);
+    }
+}
+
+/// Which limit `watts` crosses, if any.
+fn power_violation(watts: f64, min: Option<f64>, max: Option<f64>) -> Option<String> {
+    match (min, max) {
+        (_, Some(max)) if watts > max => Some(format!("above the {} W maximum", max)),
+        (Some(min), _) if watts < min => Some(format!("below the {} W minimum", min)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_month() {
+        assert_eq!(parse_month("2024-06"), Ok((2024, 6)));
+        assert!(parse_month("2024-13").is_err());
+        assert!(parse_month("June").is_err());
+    }
+
+    #[test]
+    fn test_power_violation() {
+        assert_eq!(
+            power_violation(1720.0, None, Some(1500.0)).as_deref(),
+            Some("above the 1500 W maximum")
+        );
+        assert_eq!(power_violation(900.0, None, Some(1500.0)), None);
+        assert_eq!(
+            power_violation(2.0, Some(20.0), None).as_deref(),
+            Some("below the 20 W minimum")
+        );
+        assert_eq!(power_violation(25.0, Some(20.0), None), None);
+
+        // Both limits
+        assert_eq!(
+            power_violation(2.0, Some(20.0), Some(1500.0)).as_deref(),
+            Some("below the 20 W minimum")
+        );
+        assert_eq!(
+            power_violation(1720.0, Some(20.0), Some(1500.0)).as_deref(),
+            Some("above the 1500 W maximum")
+        );
+        assert_eq!(power_violation(100.0, Some(20.0), Some(1500.0)), None);
+
+        // A reading exactly at a limit passes
+        assert_eq!(power_violation(20.0, Some(20.0), Some(1500.0)), None);
+        assert_eq!(power_violation(1500.0, Some(20.0), Some(1500.0)), None);
+        assert_eq!(power_violation(0.0, None, None), None);
+    }
+}
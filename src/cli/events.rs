@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use clap::Subcommand;
+use serde_json::json;
+
+use crate::cli::output::print_output;
+use crate::config::RuntimeConfig;
+use crate::duration::parse_duration;
+use crate::error::AppError;
+use crate::events;
+use crate::resolve;
+
+#[derive(Subcommand)]
+pub enum EventsCommand {
+    /// List message-center notices such as offline and overload alerts
+    List {
+        /// Only notices about this device
+        device: Option<String>,
+        /// How far back to look
+        #[arg(long, default_value = "24h", value_parser = parse_duration)]
+        since: Duration,
+    },
+}
+
+pub async fn handle(cmd: &EventsCommand, config: &RuntimeConfig) -> Result<(), AppError> {
+    match cmd {
+        EventsCommand::List { device, since } => {
+            handle_list(device.as_deref(), *since, config).await
+        }
+    }
+}
+
+async fn handle_list(
+    device: Option<&str>,
+    since: Duration,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let since = chrono::Duration::from_std(since)
+        .map_err(|_| AppError::InvalidInput("--since is too long".into()))?;
+    let device_id = match device {
+        Some(name) => Some(resolve::resolve_device(name, config).await?.device_id),
+        None => None,
+    };
+
+    let (devices, auth) = resolve::fetch_listed_devices(config).await?;
+    let mut messages =
+        events::fetch_messages(&devices, &auth, chrono::Utc::now() - since, config.verbose).await?;
+    if let Some(id) = device_id {
+        messages.retain(|m| m.device_id == id);
+    }
+    print_output(&json!(messages), config);
+    Ok(())
+}
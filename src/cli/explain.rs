@@ -0,0 +1,30 @@
+use serde_json::json;
+
+use crate::api::errors::{error_for_code, lookup_error_code};
+use crate::cli::output::print_output;
+use crate::config::RuntimeConfig;
+use crate::error::AppError;
+
+pub async fn handle(code: i32, config: &RuntimeConfig) -> Result<(), AppError> {
+    // Cloud error codes are always negative; accept "20651" as shorthand for -20651.
+    let code = if code > 0 { -code } else { code };
+
+    let info = lookup_error_code(code)
+        .ok_or_else(|| AppError::InvalidInput(format!("Unknown error code {}", code)))?;
+
+    // What tplc reports when a request fails with this code
+    let error = error_for_code(code, None);
+    print_output(
+        &json!({
+            "error_code": info.code,
+            "name": info.name,
+            "error": error.error_type(),
+            "exit_code": error.exit_code(),
+            "explanation": info.explanation,
+            "hint": info.hint,
+        }),
+        config,
+    );
+
+    Ok(())
+}
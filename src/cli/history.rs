@@ -0,0 +1,331 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use clap::{Subcommand, ValueEnum};
+use futures::stream::{self, StreamExt};
+use serde_json::json;
+
+use crate::api::client::TPLinkApi;
+use crate::api::cloud_type::CloudType;
+use crate::auth::credentials;
+use crate::cli::output::print_output;
+use crate::cli::progress::Spinner;
+use crate::config::RuntimeConfig;
+use crate::duration::parse_duration;
+use crate::error::AppError;
+use crate::history::db::{DailyEnergy, HistoryDb, HistoryFilter, MonthlyEnergy};
+use crate::history::{self, ActivityRecord};
+use crate::models::device::Device;
+use crate::resolve::{self, MAX_CONCURRENT_REQUESTS};
+
+#[derive(Subcommand)]
+pub enum HistoryCommand {
+    /// On/off and online/offline history from the cloud's device log, merged
+    /// with changes recorded by `devices watch`
+    Cloud {
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
+        /// How far back to look
+        #[arg(long, default_value = "24h", value_parser = parse_duration)]
+        since: Duration,
+    },
+
+    /// Copy daily and monthly energy stats and on/off events into the local
+    /// history database; run it regularly (e.g. from `tplc cron`) to keep
+    /// more than the device's rolling window
+    Sync {
+        /// Devices to sync (default: all)
+        devices: Vec<String>,
+        /// Months of daily stats to fetch, including the current one
+        #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u32).range(1..=24))]
+        months: u32,
+    },
+
+    /// Read synced history from the local database, without contacting the
+    /// cloud
+    Query {
+        /// What to read
+        #[arg(value_enum)]
+        kind: QueryKind,
+        /// Device name or ID as recorded (default: all)
+        device: Option<String>,
+        /// First day to include (YYYY-MM-DD)
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// Last day to include (YYYY-MM-DD)
+        #[arg(long)]
+        to: Option<NaiveDate>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum QueryKind {
+    /// kWh per day
+    Daily,
+    /// kWh per month
+    Monthly,
+    /// On/off and online/offline changes
+    Events,
+}
+
+/// What `history sync` fetched for one device.
+#[derive(Default)]
+struct DeviceHistory {
+    days: Vec<DailyEnergy>,
+    months: Vec<MonthlyEnergy>,
+    events: Vec<ActivityRecord>,
+}
+
+pub async fn handle(cmd: &HistoryCommand, config: &RuntimeConfig) -> Result<(), AppError> {
+    match cmd {
+        HistoryCommand::Cloud { device, since } => {
+            handle_cloud(device.as_deref(), *since, config).await
+        }
+        HistoryCommand::Sync { devices, months } => handle_sync(devices, *months, config).await,
+        HistoryCommand::Query {
+            kind,
+            device,
+            from,
+            to,
+        } => {
+            let filter = HistoryFilter {
+                device: device.clone(),
+                from: *from,
+                to: *to,
+            };
+            let db = HistoryDb::open()?;
+            let records = match kind {
+                QueryKind::Daily => db
+                    .daily(&filter)?
+                    .iter()
+                    .map(|d| {
+                        json!({
+                            "device": d.device,
+                            "device_id": d.device_id,
+                            "date": d.date.to_string(),
+                            "energy_kwh": kwh(d.energy_wh),
+                        })
+                    })
+                    .collect(),
+                QueryKind::Monthly => db
+                    .monthly(&filter)?
+                    .iter()
+                    .map(|m| {
+                        json!({
+                            "device": m.device,
+                            "device_id": m.device_id,
+                            "year": m.year,
+                            "month": m.month,
+                            "energy_kwh": kwh(m.energy_wh),
+                        })
+                    })
+                    .collect(),
+                QueryKind::Events => db.events(&filter)?.iter().map(|e| json!(e)).collect(),
+            };
+            print_output(&serde_json::Value::Array(records), config);
+            Ok(())
+        }
+    }
+}
+
+/// Watt-hours as kWh, to the Wh the devices report.
+fn kwh(wh: f64) -> f64 {
+    (wh / 1000.0 * 1000.0).round() / 1000.0
+}
+
+async fn handle_sync(
+    names: &[String],
+    months: u32,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let devices = if names.is_empty() {
+        let (entries, auth) = resolve::fetch_listed_devices(config).await?;
+        entries
+            .iter()
+            .filter(|e| !e.device_type.has_children() || e.child_id.is_some())
+            .map(|e| resolve::build_device(e, &auth, config.verbose))
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        let mut devices = Vec::new();
+        for name in names {
+            devices.push(resolve::resolve_device(name, config).await?);
+        }
+        devices
+    };
+
+    let mut db = HistoryDb::open()?;
+    // Cloud events are fetched from the newest one already stored, or as
+    // far back as the daily stats on the first sync
+    let first_sync = Utc::now() - chrono::Duration::days(31 * i64::from(months));
+    let mut since = Vec::new();
+    for dev in &devices {
+        since.push(db.last_event_time(&dev.full_id())?.unwrap_or(first_sync));
+    }
+
+    let spinner = Spinner::start("Syncing history", Some(devices.len()), config);
+    let progress = &spinner;
+    let fetched: Vec<Result<DeviceHistory, AppError>> = stream::iter(devices.iter().zip(since))
+        .map(|(dev, since)| async move {
+            let result = fetch_history(dev, months, since, config.verbose).await;
+            progress.tick();
+            result
+        })
+        .buffered(MAX_CONCURRENT_REQUESTS)
+        .collect()
+        .await;
+    drop(spinner);
+
+    let mut results = Vec::new();
+    let mut failed = 0;
+    for (dev, fetched) in devices.iter().zip(fetched) {
+        let mut result = json!({"device": dev.alias(), "device_id": dev.full_id()});
+        match fetched {
+            Ok(found) => {
+                db.upsert_daily(&found.days)?;
+                db.upsert_monthly(&found.months)?;
+                result["days"] = json!(found.days.len());
+                result["months"] = json!(found.months.len());
+                result["new_events"] = json!(db.insert_events(&found.events)?);
+            }
+            Err(err) => {
+                failed += 1;
+                result["error"] = json!(err.to_string());
+            }
+        }
+        results.push(result);
+    }
+    // Changes `devices watch` recorded while `[history] enabled` was set
+    let local_events = db.insert_events(&history::load_activity(DateTime::<Utc>::MIN_UTC)?)?;
+
+    print_output(
+        &json!({"devices": results, "new_local_events": local_events}),
+        config,
+    );
+    if failed > 0 {
+        return Err(AppError::CheckFailed(format!(
+            "{} of {} devices could not be synced",
+            failed,
+            devices.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Energy stats for the last `months` months (for devices with energy
+/// monitoring) and cloud activity since `since` for one device. Offline
+/// devices only get their cloud activity.
+async fn fetch_history(
+    dev: &Device,
+    months: u32,
+    since: DateTime<Utc>,
+    verbose: bool,
+) -> Result<DeviceHistory, AppError> {
+    let mut found = DeviceHistory {
+        events: fetch_cloud_activity(dev, since, verbose).await?,
+        ..Default::default()
+    };
+    if !dev.device_type.has_emeter() || dev.is_offline() {
+        return Ok(found);
+    }
+
+    let today = chrono::Local::now().date_naive();
+    let mut years = Vec::new();
+    for back in 0..months {
+        let Some(first) = today
+            .with_day(1)
+            .and_then(|d| d.checked_sub_months(chrono::Months::new(back)))
+        else {
+            continue;
+        };
+        for day in dev.daily_usage(first.year(), first.month()).await? {
+            let date = NaiveDate::from_ymd_opt(
+                day.year.unwrap_or(first.year()),
+                day.month.unwrap_or(first.month()),
+                day.day.unwrap_or_default(),
+            );
+            if let (Some(date), Some(energy_wh)) = (date, day.energy_wh) {
+                found.days.push(DailyEnergy {
+                    device_id: dev.full_id(),
+                    device: dev.alias().to_string(),
+                    date,
+                    energy_wh,
+                });
+            }
+        }
+        if !years.contains(&first.year()) {
+            years.push(first.year());
+        }
+    }
+    for year in years {
+        for month in dev.monthly_usage(year).await? {
+            if let (Some(m), Some(energy_wh)) = (month.month, month.energy_wh) {
+                found.months.push(MonthlyEnergy {
+                    device_id: dev.full_id(),
+                    device: dev.alias().to_string(),
+                    year: month.year.unwrap_or(year),
+                    month: m,
+                    energy_wh,
+                });
+            }
+        }
+    }
+    Ok(found)
+}
+
+async fn handle_cloud(
+    device: Option<&str>,
+    since: Duration,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let since = chrono::Utc::now()
+        - chrono::Duration::from_std(since)
+            .map_err(|_| AppError::InvalidInput("--since is too long".into()))?;
+    let dev = resolve::resolve_device_or_default(device, config).await?;
+
+    let cloud = fetch_cloud_activity(&dev, since, config.verbose).await?;
+    let mut local = if config.history {
+        history::load_activity(since)?
+    } else {
+        Vec::new()
+    };
+    local.retain(|r| r.device_id == dev.full_id());
+
+    print_output(&json!(history::merge_activity(cloud, local)), config);
+    Ok(())
+}
+
+/// The device's cloud activity log. Models without one make the cloud
+/// answer with an error, which is treated as an empty log.
+async fn fetch_cloud_activity(
+    dev: &Device,
+    since: chrono::DateTime<chrono::Utc>,
+    verbose: bool,
+) -> Result<Vec<ActivityRecord>, AppError> {
+    let auth = credentials::get_auth_context(verbose).await?;
+    let cloud = dev.info.cloud_type.unwrap_or(CloudType::Kasa);
+    let (token, url) = auth.session(cloud).ok_or(AppError::NotAuthenticated)?;
+    let api = TPLinkApi::new(
+        Some(url.to_string()),
+        verbose,
+        Some(auth.term_id.clone()),
+        cloud,
+    )?;
+    let entries = match api
+        .get_device_activity(token, &dev.device_id, since.timestamp_millis())
+        .await
+    {
+        Ok(entries) => entries,
+        Err(e @ AppError::Api { .. }) => {
+            if verbose {
+                eprintln!("No cloud activity log for {}: {}", dev.alias(), e);
+            }
+            Vec::new()
+        }
+        Err(e) => return Err(e),
+    };
+    Ok(entries
+        .iter()
+        .filter_map(|v| history::parse_activity(v, &dev.full_id(), dev.alias()))
+        .filter(|r| r.time >= since)
+        .collect())
+}
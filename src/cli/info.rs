@@ -1,9 +1,18 @@
 use clap::Subcommand;
 use serde_json::json;
+use tabled::Tabled;
 
-use crate::cli::output::print_json;
-use crate::config::RuntimeConfig;
+use std::time::Duration;
+
+use crate::cli::output::{print_output, print_table};
+use crate::config::{OutputMode, RuntimeConfig};
+use crate::duration::parse_duration;
 use crate::error::AppError;
+#[cfg(feature = "history")]
+use crate::history;
+use crate::models::sys_info::SysInfo;
+use crate::models::time::{DeviceTime, DeviceTimezone};
+use crate::models::timezone::{find_timezone, DisplayZone};
 
 use super::super::resolve;
 
@@ -11,54 +20,252 @@ use super::super::resolve;
 pub enum InfoCommand {
     /// System information
     Sysinfo {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
     },
 
     /// WiFi network information
     Network {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`; with --history,
+        /// every recorded device)
+        device: Option<String>,
+        /// Show the signal strength trend recorded by `devices watch`
+        /// (needs `[history] enabled = true`)
+        #[arg(long)]
+        history: bool,
+        /// How far back the history goes
+        #[arg(long, default_value = "24h", value_parser = parse_duration, requires = "history")]
+        since: Duration,
     },
 
     /// Device time
     Time {
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
+    },
+
+    /// Set the device clock
+    SetTime {
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
+        /// Use this machine's local clock
+        #[arg(long, conflicts_with = "time")]
+        sync: bool,
+        /// Time to set (YYYY-MM-DD HH:MM:SS)
+        #[arg(long)]
+        time: Option<String>,
+    },
+
+    /// Set the device timezone
+    SetTimezone {
         /// Device name or ID
         device: String,
+        /// Timezone name (e.g. Europe/Amsterdam) or TP-Link timezone index
+        timezone: String,
     },
 }
 
+/// The main `info sysinfo` fields; the JSON output has every field.
+#[derive(Tabled)]
+struct SysInfoRow {
+    #[tabled(rename = "DEVICE")]
+    device: String,
+    #[tabled(rename = "MODEL")]
+    model: String,
+    #[tabled(rename = "HARDWARE")]
+    hardware: String,
+    #[tabled(rename = "FIRMWARE")]
+    firmware: String,
+    #[tabled(rename = "MAC")]
+    mac: String,
+    #[tabled(rename = "RSSI")]
+    rssi: String,
+    #[tabled(rename = "POWER")]
+    power: String,
+    #[tabled(rename = "ON FOR")]
+    on_for: String,
+}
+
+fn sys_info_row(device: &str, info: &SysInfo) -> SysInfoRow {
+    SysInfoRow {
+        device: device.to_string(),
+        model: info.model.clone().unwrap_or_default(),
+        hardware: info.hw_ver.clone().unwrap_or_default(),
+        firmware: info.sw_ver.clone().unwrap_or_default(),
+        mac: info.mac.clone().unwrap_or_default(),
+        rssi: info.rssi.map(|r| format!("{} dBm", r)).unwrap_or_default(),
+        power: match info.relay_on {
+            Some(true) => "ON",
+            Some(false) => "OFF",
+            None => "",
+        }
+        .to_string(),
+        on_for: info
+            .on_time
+            .filter(|_| info.relay_on == Some(true))
+            .map(|secs| format!("{}h {:02}m", secs / 3600, secs % 3600 / 60))
+            .unwrap_or_default(),
+    }
+}
+
 pub async fn handle(cmd: &InfoCommand, config: &RuntimeConfig) -> Result<(), AppError> {
     match cmd {
         InfoCommand::Sysinfo { device } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
             let info = dev.get_sys_info().await?;
             if let Some(info) = info {
-                print_json(&json!({"device": dev.alias(), "sys_info": info}));
+                if config.renders_as(OutputMode::Table) {
+                    print_table(
+                        &[sys_info_row(dev.alias(), &SysInfo::from_json(&info))],
+                        config,
+                    );
+                    return Ok(());
+                }
+                print_output(&json!({"device": dev.alias(), "sys_info": info}), config);
             } else {
-                print_json(&json!({"device": dev.alias(), "error": "no data"}));
+                print_output(&json!({"device": dev.alias(), "error": "no data"}), config);
             }
             Ok(())
         }
-        InfoCommand::Network { device } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+        #[cfg(feature = "history")]
+        InfoCommand::Network {
+            device,
+            history: true,
+            since,
+        } => handle_network_history(device.as_deref(), *since, config).await,
+        #[cfg(not(feature = "history"))]
+        InfoCommand::Network { history: true, .. } => Err(AppError::UnsupportedOperation(
+            "This tplc was built without the `history` feature".into(),
+        )),
+        InfoCommand::Network { device, .. } => {
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
             let info = dev.get_net_info().await?;
             if let Some(info) = info {
-                print_json(&json!({"device": dev.alias(), "net_info": info}));
+                print_output(&json!({"device": dev.alias(), "net_info": info}), config);
             } else {
-                print_json(&json!({"device": dev.alias(), "error": "no data"}));
+                print_output(&json!({"device": dev.alias(), "error": "no data"}), config);
             }
             Ok(())
         }
         InfoCommand::Time { device } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
             let time = dev.get_time().await?;
             if let Some(time) = time {
-                print_json(&json!({"device": dev.alias(), "time": time}));
+                let mut result = json!({"device": dev.alias(), "time": time});
+                if config.display_zone != DisplayZone::Device {
+                    let device_tz = dev.timezone_entry().await?;
+                    let converted = DeviceTime::from_json(&time)
+                        .to_naive()
+                        .zip(device_tz)
+                        .and_then(|(local, tz)| config.display_zone.convert(local, tz));
+                    result["display_zone"] = json!(config.display_zone.name());
+                    result["display_time"] = json!(converted.map(|dt| dt.to_rfc3339()));
+                }
+                print_output(&result, config);
             } else {
-                print_json(&json!({"device": dev.alias(), "error": "no data"}));
+                print_output(&json!({"device": dev.alias(), "error": "no data"}), config);
             }
             Ok(())
         }
+        InfoCommand::SetTime { device, sync, time } => {
+            let local_time = if *sync {
+                chrono::Local::now().naive_local()
+            } else if let Some(time_str) = time {
+                parse_datetime(time_str)?
+            } else {
+                return Err(AppError::InvalidInput(
+                    "Specify --sync or --time \"YYYY-MM-DD HH:MM:SS\"".into(),
+                ));
+            };
+
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
+            // set_timezone always carries an index, so keep the device's current one.
+            let index = dev
+                .get_timezone()
+                .await?
+                .and_then(|tz| DeviceTimezone::from_json(&tz).index)
+                .ok_or_else(|| AppError::Api {
+                    message: "Could not read device timezone".into(),
+                    error_code: None,
+                })?;
+            dev.set_timezone(index, local_time).await?;
+            print_output(
+                &json!({
+                    "device": dev.alias(),
+                    "time": local_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    "timezone_index": index,
+                }),
+                config,
+            );
+            Ok(())
+        }
+        InfoCommand::SetTimezone { device, timezone } => {
+            let tz = find_timezone(timezone).ok_or_else(|| {
+                AppError::InvalidInput(format!("Unknown timezone '{}'", timezone))
+            })?;
+            let dev = resolve::resolve_device(device, config).await?;
+            let local_time = tz.local_time(chrono::Utc::now());
+            dev.set_timezone(tz.index, local_time).await?;
+            print_output(
+                &json!({
+                    "device": dev.alias(),
+                    "timezone": tz.name,
+                    "timezone_index": tz.index,
+                    "time": local_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                }),
+                config,
+            );
+            Ok(())
+        }
     }
 }
+
+fn parse_datetime(value: &str) -> Result<chrono::NaiveDateTime, AppError> {
+    chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S"))
+        .map_err(|_| {
+            AppError::InvalidInput(format!("Invalid time '{}'. Use YYYY-MM-DD HH:MM:SS", value))
+        })
+}
+
+/// Per-device RSSI trends from the history store, for one device or all.
+#[cfg(feature = "history")]
+async fn handle_network_history(
+    device: Option<&str>,
+    since: Duration,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    if !config.history {
+        return Err(AppError::InvalidInput(
+            "History is off. Set `[history] enabled = true` in config.toml and keep \
+             `tplc devices watch` running to record signal strength"
+                .into(),
+        ));
+    }
+    let since = chrono::Duration::from_std(since)
+        .map_err(|_| AppError::InvalidInput("--since is too long".into()))?;
+    let mut samples = history::load_rssi(chrono::Utc::now() - since)?;
+
+    if let Some(name) = device.or(config.default_device.as_deref()) {
+        // Recorded names first, so this works offline and for removed devices
+        let recorded = samples
+            .iter()
+            .rev()
+            .find(|s| s.device_id == name || s.device.eq_ignore_ascii_case(name))
+            .map(|s| s.device_id.clone());
+        let device_id = match recorded {
+            Some(id) => id,
+            None => resolve::resolve_device(name, config).await?.full_id(),
+        };
+        samples.retain(|s| s.device_id == device_id);
+        if samples.is_empty() {
+            return Err(AppError::InvalidInput(format!(
+                "No signal history for '{}' in that period",
+                name
+            )));
+        }
+    }
+
+    print_output(&json!(history::rssi_trends(&samples)), config);
+    Ok(())
+}
@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+
+use clap::{Subcommand, ValueEnum};
+use futures::stream::{self, StreamExt};
+use serde_json::{json, Value};
+
+use crate::auth::credentials::AuthContext;
+use crate::cli::output::print_output;
+use crate::config::RuntimeConfig;
+use crate::error::AppError;
+use crate::models::registry::registry;
+use crate::resolve::{self, DeviceEntry, MAX_CONCURRENT_REQUESTS};
+use crate::store;
+
+#[derive(Subcommand)]
+pub enum LedCommand {
+    /// Turn the indicator LED on
+    On {
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
+    },
+
+    /// Turn the indicator LED off
+    Off {
+        /// Device name or ID (defaults to `default_device`)
+        device: Option<String>,
+    },
+
+    /// Set the LED of every device in a category and remember it for new
+    /// devices; without a state, list saved policies
+    Policy {
+        /// Device category: plug, light or switch
+        #[arg(long)]
+        category: Option<String>,
+        /// LED state to apply
+        #[arg(value_enum, requires = "category")]
+        state: Option<LedState>,
+        /// Forget the category's policy instead of applying one
+        #[arg(long, requires = "category", conflicts_with = "state")]
+        clear: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LedState {
+    On,
+    Off,
+}
+
+impl LedState {
+    fn is_on(self) -> bool {
+        matches!(self, LedState::On)
+    }
+}
+
+fn state_name(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+pub async fn handle(cmd: &LedCommand, config: &RuntimeConfig) -> Result<(), AppError> {
+    match cmd {
+        LedCommand::On { device } => handle_set(device.as_deref(), true, config).await,
+        LedCommand::Off { device } => handle_set(device.as_deref(), false, config).await,
+        LedCommand::Policy {
+            category,
+            state: Some(state),
+            ..
+        } => {
+            // clap requires --category alongside a state
+            let category = category.as_deref().unwrap_or_default();
+            handle_policy(category, state.is_on(), config).await
+        }
+        LedCommand::Policy {
+            category, clear, ..
+        } => handle_policy_list(category.as_deref(), *clear, config),
+    }
+}
+
+async fn handle_set(
+    device: Option<&str>,
+    on: bool,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let dev = resolve::resolve_device_or_default(device, config).await?;
+    dev.set_led_state(on).await?;
+    print_output(
+        &json!({"device": dev.alias(), "led": state_name(on)}),
+        config,
+    );
+    Ok(())
+}
+
+/// Save the policy, then apply it to every listed device in the category.
+async fn handle_policy(category: &str, on: bool, config: &RuntimeConfig) -> Result<(), AppError> {
+    let category = validate_category(category)?;
+    let mut policy = store::load_led_policy()?;
+    policy.insert(category.to_string(), on);
+    store::save_led_policy(&policy)?;
+
+    let (devices, auth) = resolve::fetch_listed_devices(config).await?;
+    let targets: Vec<&DeviceEntry> = devices
+        .iter()
+        .filter(|d| policy_for(&policy, d) == Some(on) && d.device_type.category() == category)
+        .collect();
+    let results = apply_led(&targets, on, &auth, config.verbose).await;
+
+    let count = |status: &str| results.iter().filter(|r| r["status"] == status).count();
+    let (applied, offline) = (count("applied"), count("offline"));
+    let failed = results.len() - applied - offline;
+    print_output(
+        &json!({
+            "category": category,
+            "led": state_name(on),
+            "devices": results,
+            "applied": applied,
+            "offline": offline,
+            "failed": failed,
+        }),
+        config,
+    );
+    if failed > 0 {
+        return Err(AppError::CheckFailed(format!(
+            "LED {} failed on {} of {} {} devices",
+            state_name(on),
+            failed,
+            results.len(),
+            category
+        )));
+    }
+    Ok(())
+}
+
+fn handle_policy_list(
+    category: Option<&str>,
+    clear: bool,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let mut policy = store::load_led_policy()?;
+    if clear {
+        let category = validate_category(category.unwrap_or_default())?;
+        if policy.remove(category).is_none() {
+            return Err(AppError::InvalidInput(format!(
+                "No LED policy for category '{}'",
+                category
+            )));
+        }
+        store::save_led_policy(&policy)?;
+        print_output(&json!({"category": category, "cleared": true}), config);
+        return Ok(());
+    }
+
+    let rows: Vec<Value> = policy
+        .iter()
+        .filter(|(c, _)| category.is_none_or(|wanted| wanted.eq_ignore_ascii_case(c)))
+        .map(|(c, on)| json!({"category": c, "led": state_name(*on)}))
+        .collect();
+    print_output(&json!(rows), config);
+    Ok(())
+}
+
+/// The registry's spelling of `category`, or an error listing the valid ones.
+fn validate_category(category: &str) -> Result<&'static str, AppError> {
+    let categories = registry().categories();
+    categories
+        .iter()
+        .find(|c| c.eq_ignore_ascii_case(category))
+        .copied()
+        .ok_or_else(|| {
+            AppError::InvalidInput(format!(
+                "Unknown category '{}' (expected one of: {})",
+                category,
+                categories.join(", ")
+            ))
+        })
+}
+
+/// Set the LED on each device concurrently (bounded). Offline devices are
+/// skipped; failures are reported per device rather than stopping the rest.
+/// Only devices with a power switch have an indicator LED, so hubs, hub
+/// sensors, radiator valves and cameras are left out.
+pub async fn apply_led(
+    devices: &[&DeviceEntry],
+    on: bool,
+    auth: &AuthContext,
+    verbose: bool,
+) -> Vec<Value> {
+    let devices: Vec<&DeviceEntry> = devices
+        .iter()
+        .copied()
+        .filter(|entry| {
+            resolve::build_device(entry, auth, verbose)
+                .map_or(true, |device| device.as_switchable().is_some())
+        })
+        .collect();
+    stream::iter(devices)
+        .map(|entry| async move {
+            let status = if !entry.is_online() {
+                "offline".to_string()
+            } else {
+                let result = async {
+                    resolve::build_device(entry, auth, verbose)?
+                        .set_led_state(on)
+                        .await
+                }
+                .await;
+                match result {
+                    Ok(_) => "applied".to_string(),
+                    Err(e) => format!("failed: {}", e),
+                }
+            };
+            json!({
+                "device": entry.alias(),
+                "device_id": entry.full_id(),
+                "status": status,
+            })
+        })
+        .buffered(MAX_CONCURRENT_REQUESTS)
+        .collect()
+        .await
+}
+
+/// LED state the saved policies ask for on `entry`, if any. Outlets follow
+/// their strip, so only whole devices have a policy.
+pub fn policy_for(policy: &BTreeMap<String, bool>, entry: &DeviceEntry) -> Option<bool> {
+    if entry.child_id.is_some() {
+        return None;
+    }
+    policy.get(entry.device_type.category()).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_category() {
+        assert_eq!(validate_category("plug").unwrap(), "plug");
+        assert_eq!(validate_category("Light").unwrap(), "light");
+        assert_eq!(validate_category("SWITCH").unwrap(), "switch");
+        assert!(matches!(
+            validate_category("lamp"),
+            Err(AppError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_policy_for() {
+        let policy = BTreeMap::from([("plug".to_string(), false), ("light".to_string(), true)]);
+        let plug = DeviceEntry::test("AAA", "Heater").with_model("KP115(US)");
+        let bulb = DeviceEntry::test("BBB", "Desk").with_model("KL430(US)");
+        let switch = DeviceEntry::test("CCC", "Porch");
+        assert_eq!(policy_for(&policy, &plug), Some(false));
+        assert_eq!(policy_for(&policy, &bulb), Some(true));
+        assert_eq!(policy_for(&policy, &switch), None);
+
+        let mut outlet = DeviceEntry::test("DDD", "Strip").with_model("HS300(US)");
+        outlet.child_id = Some("00".to_string());
+        assert_eq!(policy_for(&policy, &outlet), None);
+    }
+}
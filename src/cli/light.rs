@@ -1,18 +1,26 @@
-use clap::Subcommand;
+use std::time::Duration;
+
+use clap::{Subcommand, ValueEnum};
 use serde_json::json;
 
-use crate::cli::output::print_json;
+use crate::cli::output::print_output;
+use crate::cli::power::verify_state;
 use crate::config::RuntimeConfig;
+use crate::duration::parse_duration;
 use crate::error::AppError;
+use crate::models::device::Device;
+use crate::models::light_state::{LightState, TurnOnBehavior};
 
 use super::super::resolve;
 
 #[derive(Subcommand)]
 pub enum LightCommand {
     /// Set brightness (0-100)
+    #[command(allow_missing_positional = true)]
     Brightness {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`)
+        #[arg(conflicts_with = "tag")]
+        device: Option<String>,
         /// Brightness level
         #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
         level: u8,
@@ -20,8 +28,9 @@ pub enum LightCommand {
 
     /// Set color by HSB
     Color {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`)
+        #[arg(conflicts_with = "tag")]
+        device: Option<String>,
         /// Hue (0-360)
         #[arg(long, value_parser = clap::value_parser!(u16).range(0..=360))]
         hue: u16,
@@ -34,9 +43,11 @@ pub enum LightCommand {
     },
 
     /// Set color temperature (2500-9000K)
+    #[command(allow_missing_positional = true)]
     Temp {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`)
+        #[arg(conflicts_with = "tag")]
+        device: Option<String>,
         /// Color temperature in Kelvin
         #[arg(value_parser = clap::value_parser!(u16).range(2500..=9000))]
         kelvin: u16,
@@ -47,17 +58,77 @@ pub enum LightCommand {
 
     /// Get current light state
     State {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`)
+        #[arg(conflicts_with = "tag")]
+        device: Option<String>,
+    },
+
+    /// Gradually change brightness, and optionally color temperature, over
+    /// a period: a sunrise for waking up or a sunset for winding down
+    Ramp {
+        /// Device name or ID (defaults to `default_device`)
+        #[arg(conflicts_with = "tag")]
+        device: Option<String>,
+        /// Brightness to end at (0-100)
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+        to: u8,
+        /// Brightness to start at [default: current, or 1 when off]
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+        from: Option<u8>,
+        /// How long the ramp takes, e.g. 30m
+        #[arg(long, value_parser = parse_duration)]
+        duration: Duration,
+        /// Color temperature to start at, in Kelvin
+        #[arg(long, requires = "end_temp", value_parser = clap::value_parser!(u16).range(2500..=9000))]
+        start_temp: Option<u16>,
+        /// Color temperature to end at, in Kelvin
+        #[arg(long, requires = "start_temp", value_parser = clap::value_parser!(u16).range(2500..=9000))]
+        end_temp: Option<u16>,
+    },
+
+    /// Show or set how a light comes on: as it was, or at a fixed preset
+    DefaultBehavior {
+        /// Device name or ID (defaults to `default_device`)
+        #[arg(conflicts_with = "tag")]
+        device: Option<String>,
+        /// last restores the state before it went off; preset always uses
+        /// --brightness and --temp. Without it, show the current behavior
+        #[arg(long, value_enum)]
+        mode: Option<BehaviorMode>,
+        /// Preset brightness (0-100)
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+        brightness: Option<u8>,
+        /// Preset color temperature in Kelvin
+        #[arg(long = "temp", value_parser = clap::value_parser!(u16).range(2500..=9000))]
+        kelvin: Option<u16>,
+        /// Which way of coming on to change
+        #[arg(long, value_enum, default_value = "both")]
+        when: TurnOnTrigger,
     },
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+pub enum BehaviorMode {
+    Last,
+    Preset,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TurnOnTrigger {
+    /// Power returning after a cut, or the wall switch
+    PowerCut,
+    /// The app, schedules and remotes
+    Remote,
+    Both,
+}
+
 pub async fn handle(cmd: &LightCommand, config: &RuntimeConfig) -> Result<(), AppError> {
     match cmd {
         LightCommand::Brightness { device, level } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
             dev.set_brightness(*level).await?;
-            print_json(&json!({"device": dev.alias(), "brightness": level}));
+            verify_state(&dev, "brightness", *level, || dev.brightness(), config).await?;
+            print_output(&json!({"device": dev.alias(), "brightness": level}), config);
             Ok(())
         }
         LightCommand::Color {
@@ -66,14 +137,24 @@ pub async fn handle(cmd: &LightCommand, config: &RuntimeConfig) -> Result<(), Ap
             saturation,
             brightness,
         } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
             dev.set_color(*hue, *saturation, *brightness).await?;
-            print_json(&json!({
-                "device": dev.alias(),
-                "hue": hue,
-                "saturation": saturation,
-                "brightness": brightness,
-            }));
+            let expected = color_name(Some(*hue), Some(*saturation), *brightness);
+            let read = || async {
+                Ok(light_state(&dev)
+                    .await?
+                    .map(|s| color_name(s.hue, s.saturation, brightness.and(s.brightness))))
+            };
+            verify_state(&dev, "color", expected, read, config).await?;
+            print_output(
+                &json!({
+                    "device": dev.alias(),
+                    "hue": hue,
+                    "saturation": saturation,
+                    "brightness": brightness,
+                }),
+                config,
+            );
             Ok(())
         }
         LightCommand::Temp {
@@ -81,24 +162,144 @@ pub async fn handle(cmd: &LightCommand, config: &RuntimeConfig) -> Result<(), Ap
             kelvin,
             brightness,
         } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
             dev.set_color_temp(*kelvin, *brightness).await?;
-            print_json(&json!({
-                "device": dev.alias(),
-                "color_temp": kelvin,
-                "brightness": brightness,
-            }));
+            let read = || async { Ok(light_state(&dev).await?.and_then(|s| s.color_temp)) };
+            verify_state(&dev, "color temperature", *kelvin, read, config).await?;
+            if let Some(level) = brightness {
+                verify_state(&dev, "brightness", *level, || dev.brightness(), config).await?;
+            }
+            print_output(
+                &json!({
+                    "device": dev.alias(),
+                    "color_temp": kelvin,
+                    "brightness": brightness,
+                }),
+                config,
+            );
             Ok(())
         }
         LightCommand::State { device } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
             let state = dev.get_light_state().await?;
             if let Some(state) = state {
-                print_json(&json!({"device": dev.alias(), "light_state": state}));
+                print_output(
+                    &json!({"device": dev.alias(), "light_state": state}),
+                    config,
+                );
             } else {
-                print_json(&json!({"device": dev.alias(), "error": "no data"}));
+                print_output(&json!({"device": dev.alias(), "error": "no data"}), config);
             }
             Ok(())
         }
+        LightCommand::Ramp {
+            device,
+            to,
+            from,
+            duration,
+            start_temp,
+            end_temp,
+        } => {
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
+            let temps = start_temp.zip(*end_temp);
+            let mut ramp = dev.ramp(*from, *to, temps, *duration);
+
+            // Ctrl-C stops the ramp where it is
+            let result = tokio::select! {
+                result = &mut ramp => result,
+                _ = tokio::signal::ctrl_c() => {
+                    ramp.cancel();
+                    (&mut ramp).await
+                }
+            };
+            let cancelled = matches!(result, Err(AppError::Cancelled));
+            if !cancelled {
+                result?;
+            }
+            print_output(
+                &json!({
+                    "device": dev.alias(),
+                    "target_brightness": to,
+                    "target_color_temp": end_temp,
+                    "completed": !cancelled,
+                    "last_step": ramp.progress().map(|p| p.message),
+                }),
+                config,
+            );
+            Ok(())
+        }
+        LightCommand::DefaultBehavior {
+            device,
+            mode,
+            brightness,
+            kelvin,
+            when,
+        } => {
+            let behavior = match mode {
+                None if brightness.is_some() || kelvin.is_some() => {
+                    return Err(AppError::InvalidInput(
+                        "--brightness and --temp need --mode preset".into(),
+                    ))
+                }
+                None => None,
+                Some(BehaviorMode::Last) if brightness.is_some() || kelvin.is_some() => {
+                    return Err(AppError::InvalidInput(
+                        "--brightness and --temp only apply to --mode preset".into(),
+                    ))
+                }
+                Some(BehaviorMode::Last) => Some(TurnOnBehavior::Last),
+                Some(BehaviorMode::Preset) if brightness.is_none() && kelvin.is_none() => {
+                    return Err(AppError::InvalidInput(
+                        "--mode preset needs --brightness or --temp".into(),
+                    ))
+                }
+                Some(BehaviorMode::Preset) => Some(TurnOnBehavior::Preset {
+                    brightness: *brightness,
+                    color_temp: *kelvin,
+                }),
+            };
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
+            if let Some(behavior) = &behavior {
+                let hard_on = (*when != TurnOnTrigger::Remote).then_some(behavior);
+                let soft_on = (*when != TurnOnTrigger::PowerCut).then_some(behavior);
+                dev.set_default_behavior(hard_on, soft_on).await?;
+            }
+            print_default_behavior(&dev, config).await
+        }
+    }
+}
+
+async fn light_state(dev: &Device) -> Result<Option<LightState>, AppError> {
+    Ok(dev
+        .get_light_state()
+        .await?
+        .map(|s| LightState::from_json(&s)))
+}
+
+/// A color as `--verify` compares it: hue, saturation and, when one was
+/// set, brightness.
+fn color_name(hue: Option<u16>, saturation: Option<u8>, brightness: Option<u8>) -> String {
+    let show = |v: Option<String>| v.unwrap_or_else(|| "?".into());
+    let mut name = format!(
+        "hue {}, saturation {}",
+        show(hue.map(|h| h.to_string())),
+        show(saturation.map(|s| s.to_string()))
+    );
+    if let Some(level) = brightness {
+        name.push_str(&format!(", brightness {}", level));
     }
+    name
+}
+
+async fn print_default_behavior(dev: &Device, config: &RuntimeConfig) -> Result<(), AppError> {
+    let (hard_on, soft_on) = dev.default_behavior().await?;
+    print_output(
+        &json!({
+            "device": dev.alias(),
+            "power_cut": hard_on,
+            "remote": soft_on,
+        }),
+        config,
+    );
+    Ok(())
 }
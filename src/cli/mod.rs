@@ -1,14 +1,46 @@
+pub mod alias;
+pub mod at;
 pub mod auth;
+pub mod backup;
+pub mod camera;
+pub mod cron;
 pub mod devices;
+pub mod dimmer;
 pub mod energy;
+pub mod events;
+pub mod explain;
+#[cfg(feature = "history")]
+pub mod history;
 pub mod info;
+pub mod led;
 pub mod light;
 pub mod output;
 pub mod power;
+pub mod progress;
+pub mod raw;
+pub mod run;
 pub mod schedule;
+pub mod sensor;
+pub mod shell;
+pub mod support;
+pub mod tag;
+pub mod thermostat;
+#[cfg(feature = "tui")]
+pub mod tui;
+
+use std::path::PathBuf;
+use std::time::Duration;
 
 use clap::{Parser, Subcommand, ValueEnum};
 
+use crate::api::cloud_type::CloudType;
+use crate::auth::token_store::TokenStoreKind;
+use crate::config::OutputMode;
+use crate::duration::parse_duration;
+use crate::migrate::{self, Deprecation};
+use crate::models::device_type::DeviceType;
+use crate::models::timezone::DisplayZone;
+
 #[derive(Parser)]
 #[command(
     name = "tplc",
@@ -19,25 +51,182 @@ pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
 
-    /// Output as human-readable table instead of JSON
-    #[arg(short = 't', long = "table", global = true)]
+    /// Output format [default: json]
+    #[arg(short, long, global = true, value_enum, env = "TPLC_OUTPUT")]
+    pub output: Option<OutputMode>,
+
+    /// Shorthand for --output table
+    #[arg(short = 't', global = true)]
     pub table: bool,
 
+    /// Deprecated spelling of -t
+    #[arg(long = "table", global = true, hide = true)]
+    pub table_long: bool,
+
+    /// Only include these fields (comma-separated; dotted paths reach nested values)
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub fields: Option<Vec<String>>,
+
+    /// Wrap JSON output as {"schema": N, "data": ...}; fails unless this tplc
+    /// writes schema N, so scripts notice breaking output changes
+    #[arg(long, global = true, env = "TPLC_SCHEMA_VERSION", value_name = "N")]
+    pub schema_version: Option<u32>,
+
+    /// Print each record through a template, e.g. '{{alias}} is {{power}}'
+    #[arg(long, global = true, conflicts_with = "fields")]
+    pub format: Option<String>,
+
+    /// Write the output to this file instead of stdout, replacing it only
+    /// once the command has succeeded; errors and warnings stay on stderr
+    #[arg(long, global = true, value_name = "PATH")]
+    pub out: Option<PathBuf>,
+
+    /// Print nothing; report the result through the exit code only
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Don't color on/off/offline in table output (also set by NO_COLOR)
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
     /// Verbose output (show HTTP requests/responses)
     #[arg(short, long, global = true)]
     pub verbose: bool,
+
+    /// Treat device arguments as device IDs and skip the device list lookup
+    #[arg(long, global = true)]
+    pub device_id: bool,
+
+    /// Match device arguments as a regular expression against device names
+    #[arg(long, global = true, conflicts_with = "device_id")]
+    pub regex: bool,
+
+    /// Skip safety checks: send commands to devices the cloud reports as
+    /// offline, add conflicting schedule rules, overwrite files on restore
+    #[arg(long, global = true)]
+    pub force: bool,
+
+    /// Read the state back after a power or light change and exit with code
+    /// 8 if the device doesn't report what was set
+    #[arg(long, global = true)]
+    pub verify: bool,
+
+    /// Include devices hidden with `devices hide` in listings
+    #[arg(long, global = true)]
+    pub include_hidden: bool,
+
+    /// Control the device as this model, e.g. KL430, when its own model is
+    /// unknown or misdetected
+    #[arg(long, global = true, value_name = "MODEL", value_parser = DeviceType::parse)]
+    pub assume_type: Option<DeviceType>,
+
+    /// Run a power, light or schedule command on every device with this tag
+    /// (`tplc tag add`) instead of one named device
+    #[arg(long, global = true, value_name = "TAG")]
+    pub tag: Option<String>,
+
+    /// Only list and resolve devices on this cloud; with login, log in to
+    /// this cloud only
+    #[arg(long, global = true, value_enum)]
+    pub cloud: Option<CloudType>,
+
+    /// Show device times in this zone: utc, local, or a name like Europe/Amsterdam
+    #[arg(long, global = true, value_parser = DisplayZone::parse)]
+    pub tz: Option<DisplayZone>,
+
+    /// Give up on a request after this long (overrides [timeouts] api/device)
+    #[arg(long, global = true, env = "TPLC_TIMEOUT", value_parser = parse_duration)]
+    pub timeout: Option<Duration>,
+
+    /// Send requests through this HTTP(S) proxy, or "none" to ignore
+    /// HTTPS_PROXY/HTTP_PROXY (overrides `proxy` in config.toml)
+    #[arg(long, global = true, env = "TPLC_PROXY")]
+    pub proxy: Option<String>,
+
+    /// Retry failed requests this many times (timeouts, 5xx, transient cloud errors)
+    #[arg(long, global = true, env = "TPLC_RETRIES", default_value_t = 2)]
+    pub retries: u32,
+
+    /// Wait before the first retry; doubled for each retry after it
+    #[arg(long, global = true, env = "TPLC_RETRY_DELAY", default_value = "500ms", value_parser = parse_duration)]
+    pub retry_delay: Duration,
+
+    /// Config file (default: config.toml in the tplc config directory)
+    #[arg(long, global = true, env = "TPLC_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Log in from TPLC_USERNAME/TPLC_PASSWORD and keep tokens in memory only
+    /// (nothing is read from or written to the keychain)
+    #[arg(long, global = true, env = "TPLC_EPHEMERAL_AUTH")]
+    pub ephemeral_auth: bool,
+
+    /// Where login tokens are kept between runs (default: keyring)
+    #[arg(long, global = true, env = "TPLC_TOKEN_STORE", value_enum)]
+    pub token_store: Option<TokenStoreKind>,
+
+    /// Write every cloud request and response to this directory, with tokens
+    /// and credentials blanked (for bug reports and tests)
+    #[arg(
+        long,
+        global = true,
+        env = "TPLC_RECORD",
+        value_name = "DIR",
+        conflicts_with = "replay"
+    )]
+    pub record: Option<PathBuf>,
+
+    /// Answer cloud requests from a --record directory instead of the network
+    /// (no login needed)
+    #[arg(long, global = true, env = "TPLC_REPLAY", value_name = "DIR")]
+    pub replay: Option<PathBuf>,
+
+    /// Print a timing breakdown to stderr (for diagnosing slow commands)
+    #[arg(long, global = true, hide = true)]
+    pub profile_timing: bool,
+}
+
+impl Cli {
+    /// `-t` or the deprecated `--table`.
+    pub fn wants_table(&self) -> bool {
+        self.table || self.table_long
+    }
+
+    /// Deprecated flags on this command line, to warn about.
+    pub fn deprecations(&self) -> Vec<&'static Deprecation> {
+        let mut found = Vec::new();
+        if self.table_long {
+            found.extend(migrate::flag("--table"));
+        }
+        found
+    }
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// Authenticate with TP-Link Cloud
-    Login,
+    Login {
+        /// Add Tapo to the current login without logging in to Kasa again
+        #[arg(long, conflicts_with = "cloud")]
+        tapo: bool,
+        /// Also keep the password with the tokens, so an expired session
+        /// logs in again unattended
+        #[arg(long)]
+        save_password: bool,
+    },
 
     /// Clear stored authentication tokens
     Logout,
 
+    /// Move a login between machines
+    #[command(subcommand)]
+    Auth(auth::AuthCommand),
+
     /// Show authentication status
-    Status,
+    Status {
+        /// Ask each cloud whether the stored tokens still work
+        #[arg(long)]
+        check: bool,
+    },
 
     /// Manage devices
     #[command(subcommand)]
@@ -55,6 +244,22 @@ pub enum Commands {
     #[command(subcommand)]
     Light(light::LightCommand),
 
+    /// Dimmer switch settings
+    #[command(subcommand)]
+    Dimmer(dimmer::DimmerCommand),
+
+    /// Tapo hub sensors
+    #[command(subcommand)]
+    Sensor(sensor::SensorCommand),
+
+    /// Radiator valves on a Kasa hub
+    #[command(subcommand)]
+    Thermostat(thermostat::ThermostatCommand),
+
+    /// Camera privacy mode and status
+    #[command(subcommand)]
+    Camera(camera::CameraCommand),
+
     /// Device schedules
     #[command(subcommand)]
     Schedule(schedule::ScheduleCommand),
@@ -64,19 +269,75 @@ pub enum Commands {
     Info(info::InfoCommand),
 
     /// Control indicator LED
-    Led {
-        /// LED state
-        #[arg(value_enum)]
-        state: LedState,
-        /// Device name or ID
-        device: String,
+    #[command(subcommand)]
+    Led(led::LedCommand),
+
+    /// Cloud message-center notices (offline, overload)
+    #[command(subcommand)]
+    Events(events::EventsCommand),
+
+    /// Device activity and energy history
+    #[cfg(feature = "history")]
+    #[command(subcommand)]
+    History(history::HistoryCommand),
+
+    /// Send a raw passthrough request and print the device's response
+    Raw(raw::RawArgs),
+
+    /// Interactive shell with history, tab completion and favorites
+    Shell,
+
+    /// Run a tplc command later: power changes are left to the device's
+    /// countdown timer, anything else waits with --detach or --wait
+    At(at::AtArgs),
+
+    /// Run a script of tplc commands with one device-list fetch, then report each step
+    Run(run::RunArgs),
+
+    /// Back up and restore local state and device schedules
+    #[command(subcommand)]
+    Backup(backup::BackupCommand),
+
+    /// Live dashboard: power state and wattage, toggle and dim from the keyboard
+    #[cfg(feature = "tui")]
+    Dashboard(tui::DashboardArgs),
+
+    /// Install recurring jobs from config.toml with the system scheduler
+    #[command(subcommand)]
+    Cron(cron::CronCommand),
+
+    /// Write a redacted diagnostics file to attach to bug reports
+    SupportBundle {
+        /// File to write (default: tplc-support-<timestamp>.json)
+        file: Option<PathBuf>,
+    },
+
+    /// Local device nicknames
+    #[command(subcommand)]
+    Alias(alias::AliasCommand),
+
+    /// Local device tags, for targeting several devices with --tag
+    #[command(subcommand)]
+    Tag(tag::TagCommand),
+
+    /// Explain a TP-Link cloud error code
+    Explain {
+        /// Error code (e.g. -20651)
+        #[arg(allow_hyphen_values = true)]
+        code: i32,
     },
 }
 
-#[derive(Clone, ValueEnum)]
-pub enum LedState {
-    On,
-    Off,
+impl Commands {
+    /// Commands that need a terminal, which scripts and `at` refuse to run.
+    pub fn is_interactive(&self) -> bool {
+        match self {
+            Commands::Login { .. } | Commands::Shell => true,
+            #[cfg(feature = "tui")]
+            Commands::Dashboard(_) => true,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Clone, ValueEnum)]
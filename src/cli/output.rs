@@ -1,38 +1,669 @@
-use tabled::settings::Style;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde_json::{Map, Value};
+use tabled::builder::Builder;
+use tabled::settings::location::ByColumnName;
+use tabled::settings::{Remove, Style};
 use tabled::{Table, Tabled};
 
-use crate::config::OutputMode;
+use crate::config::{OutputMode, RuntimeConfig};
+use crate::error::AppError;
+use crate::timing;
+
+/// Output held back for `--out`, written to the file when the command ends.
+static OUT_BUFFER: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+/// Version of the JSON output shape, bumped when a field is renamed or
+/// removed. `--schema-version` checks it and wraps output in an envelope.
+pub const OUTPUT_SCHEMA: u32 = 1;
+
+/// Hold back everything printed through `emit` until `write_out_file`.
+pub fn redirect_to_file() {
+    *OUT_BUFFER.lock().unwrap_or_else(|p| p.into_inner()) = Some(Vec::new());
+}
+
+/// Write primary output: to stdout, or to the `--out` buffer once
+/// `redirect_to_file` has been called. Diagnostics go to stderr directly.
+pub fn emit(text: &str) {
+    let mut buffer = OUT_BUFFER.lock().unwrap_or_else(|p| p.into_inner());
+    match buffer.as_mut() {
+        Some(buffer) => buffer.extend_from_slice(text.as_bytes()),
+        None => {
+            let mut out = std::io::stdout().lock();
+            let _ = out.write_all(text.as_bytes());
+            let _ = out.flush();
+        }
+    }
+}
+
+/// Write the output held back since `redirect_to_file` to `path`. The file
+/// is written next to its destination and renamed over it, so readers never
+/// see a half-written file.
+pub fn write_out_file(path: &Path) -> Result<(), AppError> {
+    let contents = OUT_BUFFER
+        .lock()
+        .unwrap_or_else(|p| p.into_inner())
+        .take()
+        .unwrap_or_default();
+    let name = path
+        .file_name()
+        .ok_or_else(|| AppError::InvalidInput(format!("--out {} is not a file", path.display())))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(".tmp");
+    let temp = path.with_file_name(temp_name);
+    std::fs::write(&temp, &contents)?;
+    std::fs::rename(&temp, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp);
+    })?;
+    Ok(())
+}
 
 pub fn print_json(value: &serde_json::Value) {
-    println!(
-        "{}",
+    let _span = timing::span("render");
+    emit(&format!(
+        "{}\n",
         serde_json::to_string_pretty(value).unwrap_or_default()
-    );
+    ));
+}
+
+/// Print a JSON array one element at a time, flushing after each element,
+/// so large lists never need to be collected or pretty-printed as a whole.
+pub fn print_json_stream<I>(items: I)
+where
+    I: IntoIterator<Item = serde_json::Value>,
+{
+    let _span = timing::span("render");
+    let mut first = true;
+    emit("[");
+    for item in items {
+        let separator = if first { "" } else { "," };
+        first = false;
+        emit(&format!(
+            "{}\n  {}",
+            separator,
+            serde_json::to_string(&item).unwrap_or_default()
+        ));
+    }
+    emit(&format!("{}]\n", if first { "" } else { "\n" }));
 }
 
-pub fn print_table<T: Tabled>(data: &[T]) {
+/// Whether table cells get colored: not with `--no-color`, a non-empty
+/// `NO_COLOR` (https://no-color.org) or stdout redirected away from a terminal.
+pub fn color_enabled(no_color: bool) -> bool {
+    !no_color
+        && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+        && std::io::stdout().is_terminal()
+}
+
+pub fn print_table<T: Tabled>(data: &[T], config: &RuntimeConfig) {
+    print_table_without(data, &[], config);
+}
+
+/// `print_table` leaving out the columns headed `hidden`, e.g. COST when no
+/// price is set.
+pub fn print_table_without<T: Tabled>(data: &[T], hidden: &[&str], config: &RuntimeConfig) {
+    let _span = timing::span("render");
     if data.is_empty() {
-        println!("No results.");
+        emit("No results.\n");
+        return;
+    }
+    let mut table = Table::new(data);
+    table.with(Style::rounded());
+    for column in hidden {
+        table.with(Remove::column(ByColumnName::new(*column)));
+    }
+    emit(&format!("{}\n", colorize(table.to_string(), config.color)));
+}
+
+/// ANSI color of a status cell: green "on", red "off", yellow "offline",
+/// in either case since hand-built tables print "ON"/"OFF".
+fn status_color(text: &str) -> Option<&'static str> {
+    match text.to_ascii_lowercase().as_str() {
+        "on" => Some("32"),
+        "off" => Some("31"),
+        "offline" => Some("33"),
+        _ => None,
+    }
+}
+
+/// Color the status cells of a rendered table. Cells are matched whole, so
+/// "off" inside a longer value is left alone; the padding tabled measured
+/// stays outside the escape codes and columns keep lining up.
+fn colorize(table: String, color: bool) -> String {
+    if !color {
+        return table;
+    }
+    table
+        .lines()
+        .map(|line| {
+            line.split('│')
+                .map(|cell| {
+                    let text = cell.trim();
+                    match status_color(text) {
+                        Some(code) => {
+                            cell.replacen(text, &format!("\x1b[{}m{}\x1b[0m", code, text), 1)
+                        }
+                        None => cell.to_string(),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("│")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Print a command result in the selected output format. Table and CSV
+/// output flatten the value into rows (see `tabulate`). `--format` prints
+/// each record through a template instead; `--fields` narrows each record
+/// before rendering. `--quiet` prints nothing. With a capture set, the value
+/// (narrowed by `--fields`) is collected instead of printed.
+pub fn print_output(value: &Value, config: &RuntimeConfig) {
+    if let Some(capture) = &config.capture {
+        let value = match &config.fields {
+            Some(fields) => select_fields(value, fields),
+            None => value.clone(),
+        };
+        capture
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(value);
         return;
     }
-    let table = Table::new(data).with(Style::rounded()).to_string();
-    println!("{}", table);
+    if config.quiet {
+        return;
+    }
+    let _span = timing::span("render");
+    if let Some(template) = &config.format {
+        emit(&render_template(value, template));
+        return;
+    }
+    let (value, order) = match &config.fields {
+        Some(fields) => (select_fields(value, fields), fields.as_slice()),
+        None => (value.clone(), &[][..]),
+    };
+    let rendered = match config.output_mode {
+        OutputMode::Json if config.envelope => format!(
+            "{}\n",
+            serde_json::to_string_pretty(&envelope(value)).unwrap_or_default()
+        ),
+        OutputMode::Json => format!(
+            "{}\n",
+            serde_json::to_string_pretty(&value).unwrap_or_default()
+        ),
+        OutputMode::Table => format!("{}\n", colorize(render_table(&value, order), config.color)),
+        OutputMode::Csv => render_csv(&value, order),
+        OutputMode::Yaml => render_yaml(&value),
+        OutputMode::Ndjson => render_ndjson(&value),
+        OutputMode::Influx => render_influx(&value),
+    };
+    emit(&rendered);
+}
+
+/// `{"schema": OUTPUT_SCHEMA, "data": value}`.
+fn envelope(value: Value) -> Value {
+    serde_json::json!({"schema": OUTPUT_SCHEMA, "data": value})
+}
+
+/// Look up a dotted path ("sys_info.rssi") in a record.
+fn lookup<'a>(record: &'a Value, path: &str) -> Option<&'a Value> {
+    if let Some(found) = record.get(path) {
+        return Some(found);
+    }
+    path.split('.')
+        .try_fold(record, |current, key| current.get(key))
+}
+
+/// Keep only `fields` in each record. A result with several records stays a
+/// list; a single record stays an object.
+fn select_fields(value: &Value, fields: &[String]) -> Value {
+    let project = |record: &Value| {
+        let selected: Map<String, Value> = fields
+            .iter()
+            .map(|f| (f.clone(), lookup(record, f).cloned().unwrap_or(Value::Null)))
+            .collect();
+        Value::Object(selected)
+    };
+    if split_records(value).single {
+        return project(value);
+    }
+    Value::Array(merged_records(value).iter().map(project).collect())
+}
+
+/// Fill `{{path}}` placeholders from each record, one line per record.
+/// Missing fields render empty.
+fn render_template(value: &Value, template: &str) -> String {
+    let mut out = String::new();
+    for record in merged_records(value) {
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let Some(end) = after.find("}}") else {
+                rest = &rest[start..];
+                break;
+            };
+            out.push_str(
+                &lookup(&record, after[..end].trim())
+                    .map(cell)
+                    .unwrap_or_default(),
+            );
+            rest = &after[end + 2..];
+        }
+        out.push_str(rest);
+        out.push('\n');
+    }
+    out
+}
+
+/// Split a result into its records. Arrays yield their elements. An object
+/// holding exactly one list of records (e.g. `{"device": .., "days": [..]}`,
+/// or one level down as in `{"device": .., "rules": {"rule_list": [..]}}`)
+/// yields those records, along with the object's other top-level fields to
+/// repeat on each one. Anything else is a single record.
+fn split_records(value: &Value) -> SplitRecords<'_> {
+    let single = || SplitRecords {
+        shared: Map::new(),
+        items: vec![value],
+        single: true,
+    };
+    match value {
+        Value::Array(items) => SplitRecords {
+            shared: Map::new(),
+            items: items.iter().collect(),
+            single: false,
+        },
+        Value::Object(obj) => {
+            let direct: Vec<&String> = obj
+                .iter()
+                .filter(|(_, v)| is_record_list(v))
+                .map(|(k, _)| k)
+                .collect();
+            let nested: Vec<(&String, &Value)> = obj
+                .iter()
+                .filter_map(|(k, v)| Some((k, single_record_list(v.as_object()?)?)))
+                .collect();
+
+            let (key, list) = match (direct.as_slice(), nested.as_slice()) {
+                ([key], _) => (*key, &obj[*key]),
+                ([], [(key, list)]) => (*key, *list),
+                _ => return single(),
+            };
+            let mut shared = obj.clone();
+            shared.remove(key);
+            let items = list.as_array().map(|a| a.iter().collect());
+            SplitRecords {
+                shared,
+                items: items.unwrap_or_default(),
+                single: false,
+            }
+        }
+        _ => single(),
+    }
+}
+
+/// The records of a result, as found by `split_records`.
+struct SplitRecords<'a> {
+    /// Top-level fields to repeat on each record
+    shared: Map<String, Value>,
+    items: Vec<&'a Value>,
+    /// The whole value is one record, not a list of them
+    single: bool,
+}
+
+fn single_record_list(obj: &Map<String, Value>) -> Option<&Value> {
+    let mut lists = obj.values().filter(|v| is_record_list(v));
+    match (lists.next(), lists.next()) {
+        (Some(list), None) => Some(list),
+        _ => None,
+    }
+}
+
+/// Each record of the result with the shared top-level fields merged in.
+fn merged_records(value: &Value) -> Vec<Value> {
+    let SplitRecords { shared, items, .. } = split_records(value);
+    items
+        .into_iter()
+        .map(|item| match item {
+            Value::Object(fields) => {
+                let mut record = shared.clone();
+                record.extend(fields.clone());
+                Value::Object(record)
+            }
+            other => other.clone(),
+        })
+        .collect()
+}
+
+/// One compact JSON object per line, for each record of the result.
+fn render_ndjson(value: &Value) -> String {
+    let mut out = String::new();
+    for record in merged_records(value) {
+        out.push_str(&record.to_string());
+        out.push('\n');
+    }
+    out
+}
+
+/// Measurement for records without one of their own.
+const INFLUX_MEASUREMENT: &str = "tplc";
+
+/// One line-protocol line per record of the result, under the `tplc`
+/// measurement and without a timestamp (see `influx_line`).
+fn render_influx(value: &Value) -> String {
+    merged_records(value)
+        .iter()
+        .filter_map(|record| influx_line(INFLUX_MEASUREMENT, record, None))
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// An InfluxDB line-protocol line for a record: string values become tags,
+/// numbers and booleans fields (nested objects use dotted names). Numbers
+/// are always written as floats so a field keeps one type across writes.
+/// `None` when the record has no fields.
+pub fn influx_line(measurement: &str, record: &Value, time_ns: Option<i64>) -> Option<String> {
+    let mut tags = Vec::new();
+    let mut fields = Vec::new();
+    for (key, value) in flatten(record) {
+        match value {
+            Value::String(s) if !s.is_empty() => {
+                tags.push(format!("{}={}", influx_escape(&key), influx_escape(&s)))
+            }
+            Value::Number(n) => fields.push(format!("{}={}", influx_escape(&key), n)),
+            Value::Bool(b) => fields.push(format!("{}={}", influx_escape(&key), b)),
+            _ => {}
+        }
+    }
+    if fields.is_empty() {
+        return None;
+    }
+    tags.sort();
+    let mut line = measurement.replace(',', "\\,").replace(' ', "\\ ");
+    for tag in tags {
+        line.push(',');
+        line.push_str(&tag);
+    }
+    line.push(' ');
+    line.push_str(&fields.join(","));
+    if let Some(time) = time_ns {
+        line.push_str(&format!(" {}", time));
+    }
+    Some(line)
+}
+
+/// Escape a tag key, tag value or field key.
+fn influx_escape(text: &str) -> String {
+    text.replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+/// Flatten a JSON value into columns and rows, one row per record (see
+/// `split_records`). Nested objects use dotted column names. Columns named
+/// in `order` come first, in that order.
+fn tabulate(value: &Value, order: &[String]) -> (Vec<String>, Vec<Vec<String>>) {
+    let SplitRecords { shared, items, .. } = split_records(value);
+    let shared = flatten(&Value::Object(shared));
+    let records: Vec<Record> = items
+        .into_iter()
+        .map(|item| {
+            let mut record = shared.clone();
+            record.extend(flatten(item));
+            record
+        })
+        .collect();
+
+    let mut columns: Vec<String> = Vec::new();
+    for record in &records {
+        for (key, _) in record {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+    }
+    columns.sort_by_key(|c| order.iter().position(|o| o == c).unwrap_or(order.len()));
+    let rows = records
+        .iter()
+        .map(|record| {
+            columns
+                .iter()
+                .map(|c| {
+                    record
+                        .iter()
+                        .find(|(key, _)| key == c)
+                        .map(|(_, v)| cell(v))
+                        .unwrap_or_default()
+                })
+                .collect()
+        })
+        .collect();
+    (columns, rows)
 }
 
-pub fn print_output(value: &serde_json::Value, mode: &OutputMode) {
-    match mode {
-        OutputMode::Json => print_json(value),
-        OutputMode::Table => {
-            // For table mode, if the value is an array of objects, display as table.
-            // Otherwise fall back to JSON.
-            print_json(value);
+fn is_record_list(value: &Value) -> bool {
+    value
+        .as_array()
+        .is_some_and(|items| !items.is_empty() && items.iter().all(Value::is_object))
+}
+
+/// A flattened row: (column, value) pairs in column order.
+type Record = Vec<(String, Value)>;
+
+fn flatten(value: &Value) -> Record {
+    let mut out = Vec::new();
+    match value {
+        Value::Object(obj) => flatten_into("", obj, &mut out),
+        other => out.push(("value".into(), other.clone())),
+    }
+    out
+}
+
+fn flatten_into(prefix: &str, obj: &Map<String, Value>, out: &mut Record) {
+    for (key, value) in obj {
+        let name = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match value {
+            Value::Object(inner) if !inner.is_empty() => flatten_into(&name, inner, out),
+            other => out.push((name, other.clone())),
+        }
+    }
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) if items.iter().all(|v| !v.is_object() && !v.is_array()) => {
+            items.iter().map(cell).collect::<Vec<_>>().join(", ")
         }
+        other => other.to_string(),
     }
 }
 
+fn render_table(value: &Value, order: &[String]) -> String {
+    let (columns, rows) = tabulate(value, order);
+    if rows.is_empty() {
+        return "No results.".to_string();
+    }
+
+    let mut builder = Builder::default();
+    if rows.len() == 1 {
+        // A single record reads better vertically.
+        builder.push_record(["FIELD", "VALUE"]);
+        for (column, value) in columns.iter().zip(&rows[0]) {
+            builder.push_record([column.clone(), value.clone()]);
+        }
+    } else {
+        builder.push_record(columns.iter().map(|c| c.to_uppercase()));
+        for row in rows {
+            builder.push_record(row);
+        }
+    }
+    let mut table = builder.build();
+    table.with(Style::rounded());
+    table.to_string()
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn render_csv(value: &Value, order: &[String]) -> String {
+    let (columns, rows) = tabulate(value, order);
+    let mut out = String::new();
+    for row in std::iter::once(&columns).chain(rows.iter()) {
+        let fields: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn render_yaml(value: &Value) -> String {
+    // A serde_json::Value always serializes
+    serde_norway::to_string(value).expect("JSON values serialize as YAML")
+}
+
 pub fn print_error(err: &crate::error::AppError) {
     eprintln!(
         "{}",
         serde_json::to_string_pretty(&err.to_json()).unwrap_or_default()
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tabulate_record_list_with_shared_fields() {
+        let value = json!({"device": "Plug", "days": [{"day": 1, "energy_kwh": 0.5}, {"day": 2}]});
+        let (columns, rows) = tabulate(&value, &[]);
+        assert_eq!(columns, vec!["device", "day", "energy_kwh"]);
+        assert_eq!(rows[1], vec!["Plug", "2", ""]);
+    }
+
+    #[test]
+    fn test_tabulate_nested_object() {
+        let (columns, rows) = tabulate(&json!({"device": "Lamp", "state": {"on": true}}), &[]);
+        assert_eq!(columns, vec!["device", "state.on"]);
+        assert_eq!(rows, vec![vec!["Lamp", "true"]]);
+    }
+
+    #[test]
+    fn test_ndjson_lines() {
+        let value = json!({"device": "Plug", "days": [{"day": 1}, {"day": 2}]});
+        assert_eq!(
+            render_ndjson(&value),
+            "{\"day\":1,\"device\":\"Plug\"}\n{\"day\":2,\"device\":\"Plug\"}\n"
+        );
+        assert_eq!(render_ndjson(&json!({"ok": true})), "{\"ok\":true}\n");
+
+        let rules = json!({"device": "Lamp", "rules": {"rule_list": [{"id": "A"}], "enable": 1}});
+        assert_eq!(
+            render_ndjson(&rules),
+            "{\"device\":\"Lamp\",\"id\":\"A\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_select_fields() {
+        let devices = json!([
+            {"alias": "Lamp", "status": "online", "sys_info": {"rssi": -60}},
+            {"alias": "Fan", "status": "offline"}
+        ]);
+        let fields = vec!["status".to_string(), "sys_info.rssi".to_string()];
+        assert_eq!(
+            select_fields(&devices, &fields),
+            json!([
+                {"status": "online", "sys_info.rssi": -60},
+                {"status": "offline", "sys_info.rssi": null}
+            ])
+        );
+        assert_eq!(
+            select_fields(&json!({"alias": "Lamp", "power": "on"}), &fields[..1]),
+            json!({"status": null})
+        );
+
+        let csv = render_csv(&select_fields(&devices, &fields), &fields);
+        assert_eq!(csv, "status,sys_info.rssi\nonline,-60\noffline,\n");
+    }
+
+    #[test]
+    fn test_render_template() {
+        let value = json!({"device": "Plug", "days": [{"day": 1, "energy_kwh": 0.5}, {"day": 2}]});
+        assert_eq!(
+            render_template(&value, "{{device}} day {{ day }}: {{energy_kwh}} {{"),
+            "Plug day 1: 0.5 {{\nPlug day 2:  {{\n"
+        );
+    }
+
+    #[test]
+    fn test_colorize() {
+        let table = render_table(
+            &json!([
+                {"alias": "Lamp", "power": "on"},
+                {"alias": "Fan", "power": "OFFLINE"},
+                {"alias": "off", "power": "off (scheduled)"}
+            ]),
+            &[],
+        );
+        let colored = colorize(table.clone(), true);
+        assert!(colored.contains("│ \x1b[32mon\x1b[0m      "));
+        assert!(colored.contains("│ \x1b[33mOFFLINE\x1b[0m "));
+        assert!(colored.contains("│ \x1b[31moff\x1b[0m   │ off (scheduled) │"));
+        assert_eq!(colorize(table.clone(), false), table);
+    }
+
+    #[test]
+    fn test_influx_line() {
+        let record = json!({"device": "Space Heater", "model": "KP115", "watts": 1720.5, "on": true, "note": null});
+        assert_eq!(
+            influx_line("energy", &record, Some(1_700_000_000_000_000_000)).unwrap(),
+            "energy,device=Space\\ Heater,model=KP115 on=true,watts=1720.5 1700000000000000000"
+        );
+        assert_eq!(
+            influx_line("energy", &json!({"device": "Lamp"}), None),
+            None
+        );
+        assert_eq!(
+            render_influx(
+                &json!({"device": "a=b", "days": [{"day": 1, "energy_kwh": 0.5}, {"day": 2, "energy_kwh": 1}]})
+            ),
+            "tplc,device=a\\=b day=1,energy_kwh=0.5\ntplc,device=a\\=b day=2,energy_kwh=1\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_escaping() {
+        let csv = render_csv(&json!([{"name": "Desk, left", "note": "say \"hi\""}]), &[]);
+        assert_eq!(csv, "name,note\n\"Desk, left\",\"say \"\"hi\"\"\"\n");
+    }
+
+    #[test]
+    fn test_yaml_output() {
+        let value = json!({
+            "device": "Lamp",
+            "rules": [{"id": "A", "wday": [1, 0]}],
+            "note": "on: yes",
+            "empty": [],
+            "time": "22:00",
+        });
+        let yaml = render_yaml(&value);
+        assert!(yaml.starts_with("device: Lamp\nempty: []\n"));
+        let parsed: Value = serde_norway::from_str(&yaml).unwrap();
+        assert_eq!(parsed, value);
+    }
+}
@@ -1,71 +1,545 @@
-use clap::Subcommand;
+use std::fmt::Display;
+use std::future::Future;
+use std::time::Duration;
+
+use clap::{Args, Subcommand};
+use dialoguer::Confirm;
+use futures::stream::{self, StreamExt};
 use serde_json::json;
+use tabled::Tabled;
 
-use crate::cli::output::print_json;
-use crate::config::RuntimeConfig;
+use crate::auth::credentials::AuthContext;
+use crate::cli::output::{print_output, print_table};
+use crate::cli::progress::Spinner;
+use crate::config::{OutputMode, RuntimeConfig};
 use crate::error::AppError;
+use crate::models::capability::Switchable;
+use crate::models::device::Device;
+use crate::models::energy::ReadingCondition;
+use crate::resolve::{DeviceEntry, MAX_CONCURRENT_REQUESTS};
+use crate::store;
 
 use super::super::resolve;
 
+/// Device argument of `power off` that sweeps every listed device.
+pub const ALL_DEVICES: &str = "all";
+
+/// Reads `--verify` makes before giving up, and the pause before each:
+/// devices can take a moment to report a change.
+const VERIFY_ATTEMPTS: u32 = 3;
+const VERIFY_PAUSE: Duration = Duration::from_millis(700);
+
 #[derive(Subcommand)]
 pub enum PowerCommand {
     /// Turn device on
     On {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`)
+        #[arg(conflicts_with = "tag")]
+        device: Option<String>,
+        #[command(flatten)]
+        guard: PowerGuard,
     },
 
     /// Turn device off
     Off {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`), or "all" for
+        /// every listed device and outlet
+        #[arg(conflicts_with = "tag")]
+        device: Option<String>,
+
+        /// With "all": leave these devices on (comma-separated names,
+        /// nicknames or IDs; a strip's ID covers its outlets)
+        #[arg(long, value_delimiter = ',', value_name = "DEVICES")]
+        except: Vec<String>,
+
+        /// With "all": skip the confirmation prompt (required when not
+        /// running in a terminal)
+        #[arg(long, short = 'y')]
+        yes: bool,
+
+        #[command(flatten)]
+        guard: PowerGuard,
     },
 
     /// Toggle device power state
     Toggle {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`)
+        #[arg(conflicts_with = "tag")]
+        device: Option<String>,
+        #[command(flatten)]
+        guard: PowerGuard,
     },
 
     /// Check device power status
     Status {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`)
+        #[arg(conflicts_with = "tag")]
+        device: Option<String>,
     },
 }
 
+/// Conditions a power command checks first; when one is not met the
+/// device is left alone and the result says why.
+#[derive(Args)]
+pub struct PowerGuard {
+    /// Only act if the device is currently on
+    #[arg(long, conflicts_with = "if_off")]
+    if_on: bool,
+    /// Only act if the device is currently off
+    #[arg(long)]
+    if_off: bool,
+    /// Only act if a realtime reading meets a condition, e.g.
+    /// 'power_mw<5000' (energy-monitoring devices)
+    #[arg(long, value_name = "CONDITION", value_parser = ReadingCondition::parse)]
+    only_if: Option<ReadingCondition>,
+}
+
+impl PowerGuard {
+    pub fn is_set(&self) -> bool {
+        self.if_on || self.if_off || self.only_if.is_some()
+    }
+
+    /// Why `dev` should be left alone, or `None` to go ahead.
+    async fn unmet(&self, dev: &Device) -> Result<Option<String>, AppError> {
+        if self.if_on || self.if_off {
+            let is_on = dev.is_on().await?;
+            if self.if_on && is_on != Some(true) {
+                return Ok(Some("not on (--if-on)".into()));
+            }
+            if self.if_off && is_on != Some(false) {
+                return Ok(Some("not off (--if-off)".into()));
+            }
+        }
+        if let Some(condition) = &self.only_if {
+            let reading = dev.emeter_realtime().await?;
+            match (condition.holds(&reading), condition.reading(&reading)) {
+                (Some(true), _) => {}
+                (Some(false), Some(value)) => {
+                    return Ok(Some(format!(
+                        "{} is {} (--only-if {})",
+                        condition.field, value, condition
+                    )))
+                }
+                _ => {
+                    return Err(AppError::UnsupportedOperation(format!(
+                        "{} does not report {}",
+                        dev.alias(),
+                        condition.field
+                    )))
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// With `--verify`, read `what` back with `read` until the device reports
+/// `expected`, and fail with `StateMismatch` if it never does.
+pub async fn verify_state<T, F, Fut>(
+    dev: &Device,
+    what: &str,
+    expected: T,
+    read: F,
+    config: &RuntimeConfig,
+) -> Result<(), AppError>
+where
+    T: PartialEq + Display,
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<Option<T>, AppError>>,
+{
+    if !config.verify {
+        return Ok(());
+    }
+    let mut reported = None;
+    for _ in 0..VERIFY_ATTEMPTS {
+        tokio::time::sleep(VERIFY_PAUSE).await;
+        reported = read().await?;
+        if reported.as_ref() == Some(&expected) {
+            return Ok(());
+        }
+    }
+    Err(AppError::StateMismatch {
+        message: format!(
+            "{} set to {} but the device reports {}",
+            what,
+            expected,
+            reported.map_or("nothing".to_string(), |v| v.to_string())
+        ),
+        device: dev.alias().to_string(),
+    })
+}
+
+/// `verify_state` for the power state.
+async fn verify_power(dev: &Device, on: bool, config: &RuntimeConfig) -> Result<(), AppError> {
+    let read = || async { Ok(dev.is_on().await?.map(power_name)) };
+    verify_state(dev, "power", power_name(on), read, config).await
+}
+
+fn power_name(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+/// Print that `device` was left alone, and why.
+fn print_skipped(device: &str, reason: &str, config: &RuntimeConfig) {
+    print_output(&json!({"device": device, "skipped": reason}), config);
+}
+
+#[derive(Tabled)]
+struct PowerRow {
+    #[tabled(rename = "DEVICE")]
+    device: String,
+    #[tabled(rename = "POWER")]
+    power: String,
+}
+
+/// Outcome of `power off all` for one device.
+#[derive(Tabled)]
+struct SweepRow {
+    #[tabled(rename = "DEVICE")]
+    device: String,
+    #[tabled(rename = "RESULT")]
+    result: String,
+}
+
+/// Print a device's power state: "on", "off" or "unknown".
+fn print_power(device: &str, state: &str, config: &RuntimeConfig) {
+    if config.renders_as(OutputMode::Table) {
+        print_table(
+            &[PowerRow {
+                device: device.to_string(),
+                power: state.to_uppercase(),
+            }],
+            config,
+        );
+    } else {
+        print_output(&json!({"device": device, "power": state}), config);
+    }
+}
+
 pub async fn handle(cmd: &PowerCommand, config: &RuntimeConfig) -> Result<(), AppError> {
     match cmd {
-        PowerCommand::On { device } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
-            dev.power_on().await?;
-            print_json(&json!({"device": dev.alias(), "power": "on"}));
+        PowerCommand::On { device, guard } => {
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
+            if let Some(reason) = guard.unmet(&dev).await? {
+                print_skipped(dev.alias(), &reason, config);
+                return Ok(());
+            }
+            switch_of(&dev)?.turn_on().await?;
+            verify_power(&dev, true, config).await?;
+            print_power(dev.alias(), "on", config);
             Ok(())
         }
-        PowerCommand::Off { device } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
-            dev.power_off().await?;
-            print_json(&json!({"device": dev.alias(), "power": "off"}));
+        PowerCommand::Off {
+            device,
+            except,
+            yes,
+            guard,
+        } => {
+            if device.as_deref() == Some(ALL_DEVICES) {
+                if guard.is_set() {
+                    return Err(AppError::InvalidInput(
+                        "--if-on, --if-off and --only-if don't apply to `power off all`".into(),
+                    ));
+                }
+                return handle_off_all(except, *yes, config).await;
+            }
+            if !except.is_empty() {
+                return Err(AppError::InvalidInput(
+                    "--except only applies to `power off all`".into(),
+                ));
+            }
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
+            if let Some(reason) = guard.unmet(&dev).await? {
+                print_skipped(dev.alias(), &reason, config);
+                return Ok(());
+            }
+            switch_of(&dev)?.turn_off().await?;
+            verify_power(&dev, false, config).await?;
+            print_power(dev.alias(), "off", config);
             Ok(())
         }
-        PowerCommand::Toggle { device } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
-            let was_on = dev.is_on().await?;
-            dev.toggle().await?;
-            let new_state = if was_on == Some(true) { "off" } else { "on" };
-            print_json(&json!({"device": dev.alias(), "power": new_state}));
+        PowerCommand::Toggle { device, guard } => {
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
+            if let Some(reason) = guard.unmet(&dev).await? {
+                print_skipped(dev.alias(), &reason, config);
+                return Ok(());
+            }
+            let switch = switch_of(&dev)?;
+            let now_on = match switch.is_on().await? {
+                Some(true) => false,
+                Some(false) => true,
+                None => {
+                    return Err(AppError::Api {
+                        message: "Could not determine device power state".into(),
+                        error_code: None,
+                    })
+                }
+            };
+            if now_on {
+                switch.turn_on().await?;
+            } else {
+                switch.turn_off().await?;
+            }
+            verify_power(&dev, now_on, config).await?;
+            print_power(dev.alias(), power_name(now_on), config);
             Ok(())
         }
         PowerCommand::Status { device } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
             let is_on = dev.is_on().await?;
             let state = match is_on {
                 Some(true) => "on",
                 Some(false) => "off",
                 None => "unknown",
             };
-            print_json(&json!({"device": dev.alias(), "power": state}));
+            if config.quiet && is_on == Some(false) {
+                return Err(AppError::DeviceOff(dev.alias().to_string()));
+            }
+            print_power(dev.alias(), state, config);
             Ok(())
         }
     }
 }
+
+/// `dev` as a power switch, or why it has none.
+fn switch_of(dev: &Device) -> Result<&dyn Switchable, AppError> {
+    dev.as_switchable().ok_or_else(|| {
+        AppError::UnsupportedOperation(format!(
+            "{} has no power switch",
+            dev.device_type.display_name()
+        ))
+    })
+}
+
+/// The devices `power off all` may switch, each with its handle: those
+/// `as_switchable` accepts, so hubs, hub sensors, radiator valves and
+/// cameras are left out. Strip parents are skipped too, since their outlets
+/// are switched one by one.
+fn switchable_devices<'a>(
+    devices: &'a [DeviceEntry],
+    auth: &AuthContext,
+    verbose: bool,
+    force: bool,
+) -> Result<Vec<(&'a DeviceEntry, Device)>, AppError> {
+    let mut switchable = Vec::new();
+    for entry in devices {
+        let device = resolve::build_device(entry, auth, verbose)?.with_force(force);
+        let strip =
+            device.child_id.is_none() && device.cached_capabilities().is_some_and(|c| c.children);
+        if device.as_switchable().is_some() && !strip {
+            switchable.push((entry, device));
+        }
+    }
+    Ok(switchable)
+}
+
+/// Turn off every listed device except `except`, after showing what will be
+/// switched off. Strips are switched outlet by outlet so single outlets can
+/// be excluded. Every `except` entry has to name a device: a typo must not
+/// switch off the one device meant to stay on.
+async fn handle_off_all(
+    except: &[String],
+    yes: bool,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let (devices, auth) = resolve::fetch_listed_devices(config).await?;
+
+    let mut excluded_ids = Vec::new();
+    for name in except.iter().map(|n| n.trim()).filter(|n| !n.is_empty()) {
+        let query = match store::find_alias(name)? {
+            Some(alias) => alias.device_id,
+            None => name.to_string(),
+        };
+        let matches = resolve::exact_matches(&query, &devices);
+        if matches.is_empty() {
+            return Err(AppError::DeviceNotFound(format!(
+                "{} (in --except; nothing was switched off)",
+                name
+            )));
+        }
+        excluded_ids.extend(matches.iter().map(|d| d.full_id()));
+    }
+
+    let switchable = switchable_devices(&devices, &auth, config.verbose, config.force)?;
+    let (excluded, included): (Vec<_>, Vec<_>) = switchable
+        .into_iter()
+        .partition(|(entry, _)| excluded_ids.contains(&entry.full_id()));
+    let (targets, offline): (Vec<_>, Vec<_>) = included
+        .into_iter()
+        .partition(|(entry, _)| entry.is_online() || config.force);
+    let excluded: Vec<&DeviceEntry> = excluded.into_iter().map(|(entry, _)| entry).collect();
+    let offline: Vec<&DeviceEntry> = offline.into_iter().map(|(entry, _)| entry).collect();
+
+    if targets.is_empty() {
+        return Err(AppError::InvalidInput(
+            "No online devices left to switch off".into(),
+        ));
+    }
+
+    if !yes {
+        if !resolve::is_interactive() {
+            return Err(AppError::InvalidInput(format!(
+                "Refusing to switch off {} devices without confirmation; pass --yes",
+                targets.len()
+            )));
+        }
+        eprintln!("Switching off {} devices:", targets.len());
+        for (entry, _) in &targets {
+            eprintln!("  {}", entry.alias());
+        }
+        if !excluded.is_empty() {
+            let names: Vec<&str> = excluded.iter().map(|d| d.alias()).collect();
+            eprintln!("Leaving on: {}", names.join(", "));
+        }
+        let confirmed = Confirm::new()
+            .with_prompt("Continue?")
+            .default(false)
+            .interact()
+            .map_err(|e| AppError::InvalidInput(e.to_string()))?;
+        if !confirmed {
+            return Err(AppError::InvalidInput("Cancelled".into()));
+        }
+    }
+
+    let spinner = Spinner::start("Switching off", Some(targets.len()), config);
+    let progress = &spinner;
+    let results: Vec<(&str, Result<(), AppError>)> = stream::iter(&targets)
+        .map(|(entry, device)| async move {
+            let result = device.turn_off().await;
+            progress.tick();
+            (entry.alias(), result)
+        })
+        .buffered(MAX_CONCURRENT_REQUESTS)
+        .collect()
+        .await;
+    drop(spinner);
+
+    let failed: Vec<serde_json::Value> = results
+        .iter()
+        .filter_map(|(alias, result)| {
+            let err = result.as_ref().err()?;
+            Some(json!({"device": alias, "error": err.to_string()}))
+        })
+        .collect();
+    let alias_list = |entries: &[&DeviceEntry]| -> Vec<String> {
+        entries.iter().map(|d| d.alias().to_string()).collect()
+    };
+    if config.renders_as(OutputMode::Table) {
+        let row = |device: &str, result: String| SweepRow {
+            device: device.to_string(),
+            result,
+        };
+        let rows: Vec<SweepRow> = results
+            .iter()
+            .map(|(alias, result)| match result {
+                Ok(()) => row(alias, "OFF".into()),
+                Err(err) => row(alias, format!("FAILED: {}", err)),
+            })
+            .chain(excluded.iter().map(|d| row(d.alias(), "EXCLUDED".into())))
+            .chain(offline.iter().map(|d| row(d.alias(), "OFFLINE".into())))
+            .collect();
+        print_table(&rows, config);
+    } else {
+        print_output(
+            &json!({
+                "power": "off",
+                "switched_off": results
+                    .iter()
+                    .filter(|(_, result)| result.is_ok())
+                    .map(|(alias, _)| alias)
+                    .collect::<Vec<_>>(),
+                "excluded": alias_list(&excluded),
+                "offline": alias_list(&offline),
+                "failed": failed,
+            }),
+            config,
+        );
+    }
+    if !failed.is_empty() {
+        return Err(AppError::CheckFailed(format!(
+            "{} of {} devices failed to switch off",
+            failed.len(),
+            targets.len()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::token::TokenSet;
+
+    fn auth() -> AuthContext {
+        AuthContext::from(TokenSet {
+            token: "t".into(),
+            refresh_token: None,
+            username: "me@example.com".into(),
+            regional_url: "https://example.com".into(),
+            term_id: "term".into(),
+            tapo_token: None,
+            tapo_refresh_token: None,
+            tapo_regional_url: None,
+            password: None,
+        })
+    }
+
+    fn child(parent: &DeviceEntry, child_id: &str, model: &str, alias: &str) -> DeviceEntry {
+        let mut entry = parent.clone().with_model(model);
+        entry.child_id = Some(child_id.to_string());
+        entry.child_alias = Some(alias.to_string());
+        entry
+    }
+
+    #[test]
+    fn test_off_all_skips_hub_sensors() {
+        let hub = DeviceEntry::test("HUB", "Hub").with_model("H100(EU)");
+        let devices = vec![
+            DeviceEntry::test("AAA", "Porch").with_model("HS100(US)"),
+            child(&hub, "S1", "T310(EU)", "Hallway Sensor"),
+            hub,
+            DeviceEntry::test("BBB", "Heater").with_model("P110(EU)"),
+        ];
+        let targets: Vec<&str> = switchable_devices(&devices, &auth(), false, false)
+            .unwrap()
+            .iter()
+            .map(|(entry, _)| entry.alias())
+            .collect();
+        assert_eq!(targets, vec!["Porch", "Heater"]);
+    }
+
+    #[test]
+    fn test_off_all_skips_radiator_valves() {
+        let hub = DeviceEntry::test("HUB", "Kasa Hub").with_model("KH100(EU)");
+        let devices = vec![
+            child(&hub, "V1", "KE100(EU)", "Bedroom Radiator"),
+            hub,
+            DeviceEntry::test("AAA", "Lamp").with_model("KP115(EU)"),
+        ];
+        let targets: Vec<&str> = switchable_devices(&devices, &auth(), false, false)
+            .unwrap()
+            .iter()
+            .map(|(entry, _)| entry.alias())
+            .collect();
+        assert_eq!(targets, vec!["Lamp"]);
+    }
+
+    #[test]
+    fn test_off_all_skips_cameras() {
+        let devices = vec![
+            DeviceEntry::test("CAM1", "Front Door").with_model("C200"),
+            DeviceEntry::test("CAM2", "Garage Camera").with_model("KC410S(US)"),
+            DeviceEntry::test("AAA", "Porch").with_model("HS200(US)"),
+        ];
+        let targets: Vec<&str> = switchable_devices(&devices, &auth(), false, false)
+            .unwrap()
+            .iter()
+            .map(|(entry, _)| entry.alias())
+            .collect();
+        assert_eq!(targets, vec!["Porch"]);
+    }
+}
@@ -0,0 +1,72 @@
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+
+use crate::config::RuntimeConfig;
+
+const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const FRAME_INTERVAL: Duration = Duration::from_millis(80);
+
+/// A spinner on stderr while a slow multi-device operation runs, with a
+/// "done/total" count when the number of steps is known. Drawn only when
+/// stderr is a terminal and nothing else writes there (`-q`, `-v`, captured
+/// `tplc run` steps); the line is cleared when the spinner is dropped.
+pub struct Spinner {
+    done: Arc<AtomicUsize>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl Spinner {
+    /// Start spinning with `message`. Needs a Tokio runtime.
+    pub fn start(message: &str, total: Option<usize>, config: &RuntimeConfig) -> Self {
+        let done = Arc::new(AtomicUsize::new(0));
+        let visible = !config.quiet
+            && !config.verbose
+            && config.capture.is_none()
+            && std::io::stderr().is_terminal();
+        let task = visible.then(|| {
+            let (done, message) = (done.clone(), message.to_string());
+            tokio::spawn(async move {
+                for frame in FRAMES.iter().cycle() {
+                    let line = match total {
+                        Some(total) => format!(
+                            "{} {} {}/{}",
+                            frame,
+                            message,
+                            done.load(Ordering::Relaxed),
+                            total
+                        ),
+                        None => format!("{} {}", frame, message),
+                    };
+                    redraw_line(&line);
+                    tokio::time::sleep(FRAME_INTERVAL).await;
+                }
+            })
+        });
+        Self { done, task }
+    }
+
+    /// Count one more finished step.
+    pub fn tick(&self) {
+        self.done.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+            redraw_line("");
+        }
+    }
+}
+
+/// Replace the current stderr line with `text`.
+fn redraw_line(text: &str) {
+    let mut err = std::io::stderr().lock();
+    let _ = write!(err, "\r\x1b[2K{}", text);
+    let _ = err.flush();
+}
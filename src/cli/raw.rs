@@ -0,0 +1,41 @@
+use clap::Args;
+use serde_json::{json, Value};
+
+use crate::cli::output::print_output;
+use crate::config::RuntimeConfig;
+use crate::error::AppError;
+use crate::resolve;
+
+#[derive(Args)]
+pub struct RawArgs {
+    /// Device name or ID
+    device: String,
+
+    /// Request JSON, e.g. '{"system":{"get_sysinfo":null}}'
+    request: String,
+
+    /// Address a child (strip outlet) by full ID or two-digit index, e.g. 01
+    #[arg(long)]
+    child: Option<String>,
+}
+
+pub async fn handle(args: &RawArgs, config: &RuntimeConfig) -> Result<(), AppError> {
+    let request: Value = serde_json::from_str(&args.request)
+        .map_err(|e| AppError::InvalidInput(format!("Request is not valid JSON: {}", e)))?;
+    if !request.is_object() {
+        return Err(AppError::InvalidInput(
+            "Request must be a JSON object, e.g. '{\"system\":{\"get_sysinfo\":null}}'".into(),
+        ));
+    }
+
+    let dev = resolve::resolve_device(&args.device, config).await?;
+    let response = dev.raw(request, args.child.as_deref()).await?;
+    print_output(
+        &json!({
+            "device": dev.alias(),
+            "response": response,
+        }),
+        config,
+    );
+    Ok(())
+}
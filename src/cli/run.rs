@@ -0,0 +1,247 @@
+use std::io::Read;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use clap::{Args, Parser};
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::cli::output::print_output;
+use crate::cli::{Cli, Commands};
+use crate::config::{OutputMode, RuntimeConfig};
+use crate::error::AppError;
+use crate::resolve::MAX_CONCURRENT_REQUESTS;
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// Script with one tplc command per line, or - for stdin
+    file: String,
+
+    /// Run the steps concurrently instead of one after another
+    #[arg(long)]
+    parallel: bool,
+
+    /// Keep running after a step fails (steps always all run with --parallel)
+    #[arg(long)]
+    keep_going: bool,
+}
+
+/// One line of a script, split into arguments.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Step {
+    pub line: usize,
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct StepResult {
+    line: usize,
+    command: String,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Value>,
+    /// What the command would have printed, one entry per printed result
+    output: Vec<Value>,
+    duration_ms: u128,
+}
+
+pub async fn handle(args: &RunArgs, config: &RuntimeConfig) -> Result<(), AppError> {
+    let contents = if args.file == "-" {
+        let mut contents = String::new();
+        std::io::stdin().read_to_string(&mut contents)?;
+        contents
+    } else {
+        std::fs::read_to_string(Path::new(&args.file))?
+    };
+    let steps = parse_script(&contents)?;
+
+    // Parse every step up front so a typo fails the run before anything runs
+    let mut parsed = Vec::new();
+    for step in &steps {
+        let cli = Cli::try_parse_from(std::iter::once("tplc".to_string()).chain(step.args.clone()))
+            .map_err(|e| {
+                AppError::InvalidInput(format!(
+                    "Line {}: {}",
+                    step.line,
+                    e.render().to_string().trim()
+                ))
+            })?;
+        if cli.command.is_interactive() || matches!(cli.command, Commands::Run(_)) {
+            return Err(AppError::InvalidInput(format!(
+                "Line {}: interactive commands and nested `run` are not allowed in scripts",
+                step.line
+            )));
+        }
+        parsed.push((step, cli));
+    }
+
+    let mut shared = config.clone();
+    shared.device_cache = Some(Default::default());
+
+    let mut results = Vec::new();
+    if args.parallel {
+        results = stream::iter(parsed)
+            .map(|(step, cli)| run_step(step, cli, &shared))
+            .buffered(MAX_CONCURRENT_REQUESTS)
+            .collect()
+            .await;
+    } else {
+        for (step, cli) in parsed {
+            let result = run_step(step, cli, &shared).await;
+            let failed = !result.ok;
+            results.push(result);
+            if failed && !args.keep_going {
+                break;
+            }
+        }
+    }
+
+    let failed = results.iter().filter(|r| !r.ok).count();
+    let skipped = steps.len() - results.len();
+    print_output(
+        &json!({
+            "steps": results,
+            "succeeded": results.len() - failed,
+            "failed": failed,
+            "skipped": skipped,
+        }),
+        config,
+    );
+    if failed > 0 {
+        return Err(AppError::CheckFailed(format!(
+            "{} of {} steps failed",
+            failed,
+            steps.len()
+        )));
+    }
+    Ok(())
+}
+
+async fn run_step(step: &Step, cli: Cli, shared: &RuntimeConfig) -> StepResult {
+    let capture = Arc::new(Mutex::new(Vec::new()));
+    let config = step_config(shared, &cli, capture.clone());
+    let started = Instant::now();
+    let result = Box::pin(crate::dispatch(cli.command, &config)).await;
+    let output = std::mem::take(&mut *capture.lock().unwrap_or_else(|p| p.into_inner()));
+    StepResult {
+        line: step.line,
+        command: shell_words::join(&step.args),
+        ok: result.is_ok(),
+        exit_code: result.as_ref().err().map(AppError::exit_code),
+        error: result.as_ref().err().map(AppError::to_json),
+        output,
+        duration_ms: started.elapsed().as_millis(),
+    }
+}
+
+/// The run's config with a step's own flags applied on top. Output is
+/// always captured as JSON for the report.
+fn step_config(
+    shared: &RuntimeConfig,
+    cli: &Cli,
+    capture: crate::config::OutputCapture,
+) -> RuntimeConfig {
+    let mut config = shared.clone();
+    apply_step_flags(&mut config, cli);
+    config.output_mode = OutputMode::Json;
+    config.format = None;
+    config.quiet = false;
+    config.capture = Some(capture);
+    config
+}
+
+/// Apply the global flags given on a script or shell line that affect how
+/// devices are matched, which fields are kept, and which checks are skipped.
+pub(crate) fn apply_step_flags(config: &mut RuntimeConfig, cli: &Cli) {
+    if cli.fields.is_some() {
+        config.fields = cli.fields.clone();
+    }
+    if let Some(zone) = cli.tz {
+        config.display_zone = zone;
+    }
+    config.device_id |= cli.device_id;
+    config.regex |= cli.regex;
+    config.verbose |= cli.verbose;
+    config.force |= cli.force;
+    config.verify |= cli.verify;
+    config.include_hidden |= cli.include_hidden;
+    if let Some(device_type) = cli.assume_type {
+        config.assume_type = Some(device_type);
+    }
+    if cli.tag.is_some() {
+        config.tag = cli.tag.clone();
+    }
+    if cli.cloud.is_some() && cli.cloud != config.only_cloud {
+        // The shared device list covers other clouds; fetch this step's own
+        config.only_cloud = cli.cloud;
+        config.preferred_cloud = cli.cloud.unwrap_or(config.preferred_cloud);
+        config.device_cache = None;
+    }
+}
+
+/// Apply the output flags (`-t`, `-o`, `--format`) given on a shell line or
+/// a delayed `at` command.
+pub(crate) fn apply_output_flags(config: &mut RuntimeConfig, cli: &Cli) {
+    if cli.wants_table() {
+        config.output_mode = OutputMode::Table;
+    } else if let Some(mode) = cli.output {
+        config.output_mode = mode;
+    }
+    if cli.format.is_some() {
+        config.format = cli.format.clone();
+    }
+}
+
+/// Split a script into steps. Blank lines and `#` comments are skipped, and
+/// a leading `tplc` on a line is optional.
+pub fn parse_script(contents: &str) -> Result<Vec<Step>, AppError> {
+    let mut steps = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let mut args = shell_words::split(trimmed)
+            .map_err(|e| AppError::InvalidInput(format!("Line {}: {}", i + 1, e)))?;
+        if args.first().is_some_and(|a| a == "tplc") {
+            args.remove(0);
+        }
+        if !args.is_empty() {
+            steps.push(Step { line: i + 1, args });
+        }
+    }
+    if steps.is_empty() {
+        return Err(AppError::InvalidInput("Script has no commands".into()));
+    }
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let steps = parse_script(
+            "# morning\n\ntplc power on Porch\n  power off 'Space Heater'  # heater\nenergy realtime Heater -t\n",
+        )
+        .unwrap();
+        assert_eq!(steps.len(), 3);
+        assert_eq!(
+            steps[0],
+            Step {
+                line: 3,
+                args: vec!["power".into(), "on".into(), "Porch".into()]
+            }
+        );
+        // shell_words treats an unquoted # as the start of a comment
+        assert_eq!(steps[1].args, vec!["power", "off", "Space Heater"]);
+        assert_eq!(steps[2].line, 5);
+        assert!(parse_script("# nothing\n").is_err());
+        assert!(parse_script("power on 'Porch\n").is_err());
+    }
+}
@@ -1,34 +1,49 @@
+use std::path::PathBuf;
+
+use chrono::{NaiveDate, Timelike};
 use clap::Subcommand;
 use serde_json::json;
+use tabled::Tabled;
 
 use super::PowerAction;
-use crate::cli::output::print_json;
-use crate::config::RuntimeConfig;
+use crate::cli::output::{print_output, print_table};
+use crate::config::{OutputMode, RuntimeConfig};
 use crate::error::AppError;
-use crate::models::schedule::{parse_days, parse_time, ScheduleRuleBuilder};
+use crate::models::schedule::{
+    find_conflicts, parse_days, parse_time, rules_from_json, simulate, ScheduleRule,
+    ScheduleRuleBuilder, Transition, MAX_SCHEDULE_RULES,
+};
+use crate::models::timezone::{DisplayZone, TimezoneEntry};
 
 use super::super::resolve;
 
+/// Placeholder ID for the rule being added when checking it against existing rules.
+const NEW_RULE_LABEL: &str = "(new)";
+
 #[derive(Subcommand)]
 pub enum ScheduleCommand {
     /// List schedule rules
     List {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`)
+        #[arg(conflicts_with = "tag")]
+        device: Option<String>,
     },
 
     /// Get a specific schedule rule
     Get {
-        /// Device name or ID
+        /// Device name or ID (rule IDs are per device, so not with --tag)
+        #[arg(conflicts_with = "tag")]
         device: String,
         /// Rule ID
         rule_id: String,
     },
 
-    /// Add a new schedule rule
+    /// Add a new schedule rule. Rules that conflict with existing ones or
+    /// exceed the rule limit are refused unless --force is given
     Add {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`)
+        #[arg(conflicts_with = "tag")]
+        device: Option<String>,
         /// Action: on or off
         #[arg(long, value_enum)]
         action: PowerAction,
@@ -51,7 +66,8 @@ pub enum ScheduleCommand {
 
     /// Edit an existing schedule rule
     Edit {
-        /// Device name or ID
+        /// Device name or ID (rule IDs are per device, so not with --tag)
+        #[arg(conflicts_with = "tag")]
         device: String,
         /// Rule ID
         rule_id: String,
@@ -74,7 +90,8 @@ pub enum ScheduleCommand {
 
     /// Delete a schedule rule
     Delete {
-        /// Device name or ID
+        /// Device name or ID (rule IDs are per device, so not with --tag)
+        #[arg(conflicts_with = "tag")]
         device: String,
         /// Rule ID
         rule_id: String,
@@ -82,31 +99,144 @@ pub enum ScheduleCommand {
 
     /// Delete all schedule rules
     Clear {
-        /// Device name or ID
-        device: String,
+        /// Device name or ID (defaults to `default_device`)
+        #[arg(conflicts_with = "tag")]
+        device: Option<String>,
     },
+
+    /// Simulate upcoming on/off transitions and report conflicting rules
+    Simulate {
+        /// Device name or ID (omit when using --file; defaults to `default_device`)
+        #[arg(conflicts_with = "tag")]
+        device: Option<String>,
+        /// Read rules from a JSON file (e.g. saved `schedule list` output) instead of a device
+        #[arg(long, conflicts_with_all = ["device", "tag"])]
+        file: Option<PathBuf>,
+        /// Number of days to simulate
+        #[arg(long, default_value_t = 7)]
+        days: u32,
+        /// First day to simulate (YYYY-MM-DD, default: today)
+        #[arg(long)]
+        start: Option<String>,
+    },
+}
+
+#[derive(Tabled)]
+struct TransitionRow {
+    #[tabled(rename = "DATE")]
+    date: String,
+    #[tabled(rename = "DAY")]
+    day: String,
+    #[tabled(rename = "TIME")]
+    time: String,
+    #[tabled(rename = "ACTION")]
+    action: String,
+    #[tabled(rename = "RULE")]
+    rule: String,
+}
+
+#[derive(Tabled)]
+struct RuleRow {
+    #[tabled(rename = "ID")]
+    id: String,
+    #[tabled(rename = "NAME")]
+    name: String,
+    #[tabled(rename = "ENABLED")]
+    enabled: String,
+    #[tabled(rename = "DAYS")]
+    days: String,
+    #[tabled(rename = "TIME")]
+    time: String,
+    #[tabled(rename = "ACTION")]
+    action: String,
+}
+
+/// A `schedule list` rule as a table row. `display_time` (from `--tz`)
+/// replaces the device-local start time when present.
+fn rule_row(rule_json: &serde_json::Value) -> Option<RuleRow> {
+    let rule = ScheduleRule::from_json(rule_json)?;
+    let offset = |minutes: Option<i32>| match minutes.unwrap_or(0) {
+        0 => String::new(),
+        m => format!(" {:+}m", m),
+    };
+    let time = match rule.stime_opt {
+        Some(1) => format!("sunrise{}", offset(rule.soffset)),
+        Some(2) => format!("sunset{}", offset(rule.soffset)),
+        _ => match rule_json.get("display_time").and_then(|v| v.as_str()) {
+            Some(display) => display.to_string(),
+            None => rule
+                .smin
+                .map(|m| format!("{:02}:{:02}", m / 60, m % 60))
+                .unwrap_or_default(),
+        },
+    };
+    const DAY_NAMES: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    let days = match (&rule.wday, rule.repeat) {
+        (_, Some(0)) => format!(
+            "{:04}-{:02}-{:02}",
+            rule.year.unwrap_or_default(),
+            rule.month.unwrap_or_default(),
+            rule.day.unwrap_or_default()
+        ),
+        (Some(wday), _) if wday.iter().all(|&d| d != 0) => "every day".to_string(),
+        (Some(wday), _) => wday
+            .iter()
+            .zip(DAY_NAMES)
+            .filter(|(on, _)| **on != 0)
+            .map(|(_, name)| name)
+            .collect::<Vec<_>>()
+            .join(","),
+        (None, _) => "every day".to_string(),
+    };
+    Some(RuleRow {
+        id: rule.id.clone().unwrap_or_default(),
+        name: rule.name.clone().unwrap_or_default(),
+        enabled: if rule.enable.unwrap_or(1) != 0 {
+            "yes"
+        } else {
+            "no"
+        }
+        .to_string(),
+        days,
+        time,
+        action: if rule.sact == Some(1) { "ON" } else { "OFF" }.to_string(),
+    })
 }
 
 pub async fn handle(cmd: &ScheduleCommand, config: &RuntimeConfig) -> Result<(), AppError> {
     match cmd {
         ScheduleCommand::List { device } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
             let rules = dev.get_schedule_rules().await?;
-            if let Some(rules) = rules {
-                print_json(&json!({"device": dev.alias(), "rules": rules}));
+            if let Some(mut rules) = rules {
+                if config.display_zone != DisplayZone::Device {
+                    if let Some(device_tz) = dev.timezone_entry().await? {
+                        add_display_times(&mut rules, device_tz, config.display_zone);
+                    }
+                }
+                if config.renders_as(OutputMode::Table) {
+                    let rows: Vec<RuleRow> = rules
+                        .get("rule_list")
+                        .and_then(|v| v.as_array())
+                        .map(|list| list.iter().filter_map(rule_row).collect())
+                        .unwrap_or_default();
+                    print_table(&rows, config);
+                    return Ok(());
+                }
+                print_output(&json!({"device": dev.alias(), "rules": rules}), config);
             } else {
-                print_json(&json!({"device": dev.alias(), "rules": []}));
+                print_output(&json!({"device": dev.alias(), "rules": []}), config);
             }
             Ok(())
         }
         ScheduleCommand::Get { device, rule_id } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+            let dev = resolve::resolve_device(device, config).await?;
             let rules = dev.get_schedule_rules().await?;
             if let Some(rules_data) = rules {
                 if let Some(rule_list) = rules_data.get("rule_list").and_then(|v| v.as_array()) {
                     for rule in rule_list {
                         if rule.get("id").and_then(|v| v.as_str()) == Some(rule_id) {
-                            print_json(&json!({"device": dev.alias(), "rule": rule}));
+                            print_output(&json!({"device": dev.alias(), "rule": rule}), config);
                             return Ok(());
                         }
                     }
@@ -126,7 +256,7 @@ pub async fn handle(cmd: &ScheduleCommand, config: &RuntimeConfig) -> Result<(),
             days,
             name,
         } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
 
             let turn_on = matches!(action, PowerAction::On);
             let mut builder = ScheduleRuleBuilder::new().with_action(turn_on);
@@ -154,8 +284,23 @@ pub async fn handle(cmd: &ScheduleCommand, config: &RuntimeConfig) -> Result<(),
             }
 
             let rule = builder.build()?;
+
+            let existing = dev.get_schedule_rules().await?.unwrap_or(json!({}));
+            let problems = check_new_rule(&rules_from_json(&existing), &rule);
+            if !problems.is_empty() {
+                if !config.force {
+                    return Err(AppError::InvalidInput(format!(
+                        "{}. Use --force to add it anyway",
+                        problems.join("; ")
+                    )));
+                }
+                for problem in &problems {
+                    eprintln!("Warning: {}", problem);
+                }
+            }
+
             let result = dev.add_schedule_rule(rule).await?;
-            print_json(&json!({"device": dev.alias(), "result": result}));
+            print_output(&json!({"device": dev.alias(), "result": result}), config);
             Ok(())
         }
         ScheduleCommand::Edit {
@@ -167,7 +312,7 @@ pub async fn handle(cmd: &ScheduleCommand, config: &RuntimeConfig) -> Result<(),
             enable,
             disable,
         } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+            let dev = resolve::resolve_device(device, config).await?;
 
             // Fetch existing rule
             let rules = dev.get_schedule_rules().await?;
@@ -208,20 +353,261 @@ pub async fn handle(cmd: &ScheduleCommand, config: &RuntimeConfig) -> Result<(),
             }
 
             let result = dev.edit_schedule_rule(updated).await?;
-            print_json(&json!({"device": dev.alias(), "result": result}));
+            print_output(&json!({"device": dev.alias(), "result": result}), config);
             Ok(())
         }
         ScheduleCommand::Delete { device, rule_id } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+            let dev = resolve::resolve_device(device, config).await?;
             let result = dev.delete_schedule_rule(rule_id).await?;
-            print_json(&json!({"device": dev.alias(), "deleted": rule_id, "result": result}));
+            print_output(
+                &json!({"device": dev.alias(), "deleted": rule_id, "result": result}),
+                config,
+            );
             Ok(())
         }
         ScheduleCommand::Clear { device } => {
-            let dev = resolve::resolve_device(device, config.verbose).await?;
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
             let result = dev.delete_all_schedule_rules().await?;
-            print_json(&json!({"device": dev.alias(), "cleared": true, "result": result}));
+            print_output(
+                &json!({"device": dev.alias(), "cleared": true, "result": result}),
+                config,
+            );
             Ok(())
         }
+        ScheduleCommand::Simulate {
+            device,
+            file,
+            days,
+            start,
+        } => {
+            handle_simulate(
+                device.as_deref(),
+                file.as_ref(),
+                *days,
+                start.as_deref(),
+                config,
+            )
+            .await
+        }
+    }
+}
+
+/// Annotate fixed-time rules with their start time in the display zone,
+/// as it falls today (the offset between zones can change with DST).
+fn add_display_times(rules: &mut serde_json::Value, device_tz: &TimezoneEntry, zone: DisplayZone) {
+    let today = chrono::Local::now().date_naive();
+    let Some(rule_list) = rules.get_mut("rule_list").and_then(|v| v.as_array_mut()) else {
+        return;
+    };
+    for rule in rule_list {
+        if rule.get("stime_opt").and_then(|v| v.as_i64()) != Some(0) {
+            continue;
+        }
+        let Some(smin) = rule.get("smin").and_then(|v| v.as_u64()) else {
+            continue;
+        };
+        let local = today.and_hms_opt(smin as u32 / 60, smin as u32 % 60, 0);
+        if let Some(converted) = local.and_then(|local| zone.convert(local, device_tz)) {
+            rule["display_time"] = json!(converted.format("%H:%M").to_string());
+            rule["display_zone"] = json!(zone.name());
+        }
+    }
+}
+
+/// Describe why a new rule may misbehave: the device is already at its rule
+/// limit, or the rule fires at the same minute as an existing rule with the
+/// opposite action. Sunrise/sunset rules are not compared, since their times
+/// are only known to the device.
+fn check_new_rule(existing: &[ScheduleRule], new_rule: &serde_json::Value) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if existing.len() >= MAX_SCHEDULE_RULES {
+        problems.push(format!(
+            "Device already has {} rules (limit {})",
+            existing.len(),
+            MAX_SCHEDULE_RULES
+        ));
+    }
+
+    let Some(mut new_rule) = ScheduleRule::from_json(new_rule) else {
+        return problems;
+    };
+    new_rule.id = Some(NEW_RULE_LABEL.to_string());
+
+    let mut rules = existing.to_vec();
+    rules.push(new_rule);
+    let transitions: Vec<_> = simulate(&rules, chrono::Local::now().date_naive(), 7)
+        .into_iter()
+        .filter(|t| !t.approximate)
+        .collect();
+
+    let mut conflicting: Vec<String> = find_conflicts(&transitions)
+        .into_iter()
+        .filter(|c| c.rules.iter().any(|r| r == NEW_RULE_LABEL))
+        .flat_map(|c| c.rules)
+        .filter(|r| r != NEW_RULE_LABEL)
+        .collect();
+    conflicting.sort();
+    conflicting.dedup();
+    if !conflicting.is_empty() {
+        problems.push(format!(
+            "Rule fires at the same time as rule(s) {} with the opposite action",
+            conflicting.join(", ")
+        ));
+    }
+
+    problems
+}
+
+/// Move simulated transitions from device-local time into the display zone.
+fn shift_transitions(transitions: &mut [Transition], device_tz: &TimezoneEntry, zone: DisplayZone) {
+    for t in transitions.iter_mut() {
+        let local = t
+            .date
+            .and_hms_opt(t.minute as u32 / 60, t.minute as u32 % 60, 0);
+        if let Some(converted) = local.and_then(|local| zone.convert(local, device_tz)) {
+            t.date = converted.date_naive();
+            t.minute = (converted.hour() * 60 + converted.minute()) as i32;
+        }
+    }
+    transitions.sort_by_key(|t| (t.date, t.minute));
+}
+
+async fn handle_simulate(
+    device: Option<&str>,
+    file: Option<&PathBuf>,
+    days: u32,
+    start: Option<&str>,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    let start = match start {
+        Some(s) => NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .map_err(|_| AppError::InvalidInput(format!("Invalid date '{}'. Use YYYY-MM-DD", s)))?,
+        None => chrono::Local::now().date_naive(),
+    };
+
+    let (source, rules, device_tz) = if let Some(path) = file {
+        if config.display_zone != DisplayZone::Device {
+            return Err(AppError::InvalidInput(
+                "--tz needs a device to know which timezone the rules run in".into(),
+            ));
+        }
+        let contents = std::fs::read_to_string(path)?;
+        let data: serde_json::Value = serde_json::from_str(&contents)?;
+        (path.display().to_string(), rules_from_json(&data), None)
+    } else {
+        let dev = resolve::resolve_device_or_default(device, config).await?;
+        let data = dev.get_schedule_rules().await?.unwrap_or(json!({}));
+        let device_tz = if config.display_zone != DisplayZone::Device {
+            dev.timezone_entry().await?
+        } else {
+            None
+        };
+        (dev.alias().to_string(), rules_from_json(&data), device_tz)
+    };
+
+    let mut transitions = simulate(&rules, start, days);
+    if let Some(device_tz) = device_tz {
+        shift_transitions(&mut transitions, device_tz, config.display_zone);
+    }
+    let conflicts = find_conflicts(&transitions);
+
+    if config.renders_as(OutputMode::Table) {
+        let rows: Vec<TransitionRow> = transitions
+            .iter()
+            .map(|t| TransitionRow {
+                date: t.date.to_string(),
+                day: t.date.format("%a").to_string(),
+                time: if t.approximate {
+                    format!("~{}", t.time())
+                } else {
+                    t.time()
+                },
+                action: if t.turn_on { "ON" } else { "OFF" }.to_string(),
+                rule: t.rule.clone(),
+            })
+            .collect();
+        print_table(&rows, config);
+        for conflict in &conflicts {
+            eprintln!(
+                "Conflict on {} at {:02}:{:02}: rules {} turn the device both on and off",
+                conflict.date,
+                conflict.minute / 60,
+                conflict.minute % 60,
+                conflict.rules.join(", ")
+            );
+        }
+    } else {
+        let transitions_json: Vec<serde_json::Value> = transitions
+            .iter()
+            .map(|t| {
+                json!({
+                    "date": t.date.to_string(),
+                    "weekday": t.date.format("%a").to_string(),
+                    "time": t.time(),
+                    "action": if t.turn_on { "on" } else { "off" },
+                    "rule": t.rule,
+                    "approximate": t.approximate,
+                })
+            })
+            .collect();
+        print_output(
+            &json!({
+                "source": source,
+                "start": start.to_string(),
+                "days": days,
+                "display_zone": config.display_zone.name(),
+                "transitions": transitions_json,
+                "conflicts": conflicts,
+            }),
+            config,
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule_json(id: &str, sact: i32, smin: i32) -> serde_json::Value {
+        json!({
+            "id": id, "enable": 1, "sact": sact, "stime_opt": 0, "smin": smin,
+            "etime_opt": -1, "eact": -1, "emin": 0, "repeat": 1, "wday": [1, 1, 1, 1, 1, 1, 1],
+        })
+    }
+
+    fn rule(id: &str, sact: i32, smin: i32) -> ScheduleRule {
+        ScheduleRule::from_json(&rule_json(id, sact, smin)).unwrap()
+    }
+
+    #[test]
+    fn test_check_new_rule_no_conflict() {
+        let existing = vec![rule("A", 1, 420), rule("B", 0, 1320)];
+        assert!(check_new_rule(&existing, &rule_json("", 1, 420)).is_empty());
+        assert!(check_new_rule(&existing, &rule_json("", 0, 600)).is_empty());
+    }
+
+    #[test]
+    fn test_check_new_rule_overlap() {
+        let existing = vec![rule("A", 1, 1320), rule("B", 0, 420)];
+        let problems = check_new_rule(&existing, &rule_json("", 0, 1320));
+        assert_eq!(
+            problems,
+            vec!["Rule fires at the same time as rule(s) A with the opposite action"]
+        );
+    }
+
+    #[test]
+    fn test_check_new_rule_limit() {
+        let existing: Vec<ScheduleRule> = (0..MAX_SCHEDULE_RULES)
+            .map(|i| rule(&i.to_string(), 1, i as i32))
+            .collect();
+        let problems = check_new_rule(&existing, &rule_json("", 1, 1200));
+        assert_eq!(problems.len(), 1);
+        assert!(
+            problems[0].starts_with(&format!("Device already has {} rules", MAX_SCHEDULE_RULES))
+        );
     }
 }
@@ -0,0 +1,36 @@
+use clap::Subcommand;
+use serde_json::json;
+
+use crate::cli::output::print_output;
+use crate::config::RuntimeConfig;
+use crate::error::AppError;
+
+use super::super::resolve;
+
+#[derive(Subcommand)]
+pub enum SensorCommand {
+    /// Temperature, humidity, battery and last trigger of a hub sensor
+    /// (T310, T315, T100, T110)
+    Read {
+        /// Sensor name or ID (defaults to `default_device`)
+        device: Option<String>,
+    },
+}
+
+pub async fn handle(cmd: &SensorCommand, config: &RuntimeConfig) -> Result<(), AppError> {
+    match cmd {
+        SensorCommand::Read { device } => {
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
+            let reading = dev.sensor_reading().await?;
+            let mut result = serde_json::to_value(&reading)?;
+            result["device"] = json!(dev.alias());
+            if let Some(time) = reading.last_triggered {
+                result["last_triggered"] = json!(time
+                    .with_timezone(&chrono::Local)
+                    .to_rfc3339_opts(chrono::SecondsFormat::Secs, false));
+            }
+            print_output(&result, config);
+            Ok(())
+        }
+    }
+}
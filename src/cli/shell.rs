@@ -0,0 +1,386 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+
+use clap::{CommandFactory, Parser};
+use dialoguer::{Completion, History, Input};
+
+use crate::cli::output::print_error;
+use crate::cli::run::{apply_output_flags, apply_step_flags};
+use crate::cli::{Cli, Commands};
+use crate::config::RuntimeConfig;
+use crate::error::AppError;
+use crate::resolve::{self, DeviceCache};
+use crate::store;
+
+const HISTORY_FILE: &str = "history.txt";
+
+/// Entries kept in the history file.
+const HISTORY_SIZE: usize = 500;
+
+/// Words handled by the shell itself rather than passed to tplc.
+const BUILTINS: &[&str] = &["exit", "quit", "help", "refresh", "fav"];
+
+const HELP: &str = "\
+Type tplc commands without the leading `tplc`, e.g. `power on Porch -t`.
+Tab completes commands and device names; up/down browse history.
+
+  fav                       List favorites
+  fav add <name> <command>  Save a command under a name; type the name to run it
+  fav rm <name>             Remove a favorite
+  refresh                   Fetch the device list again
+  exit                      Leave the shell";
+
+pub async fn handle(config: &RuntimeConfig) -> Result<(), AppError> {
+    if !std::io::stdin().is_terminal() {
+        return Err(AppError::InvalidInput(
+            "tplc shell needs an interactive terminal; use `tplc run -` for scripts".into(),
+        ));
+    }
+
+    let cache = DeviceCache::default();
+    let mut shared = config.clone();
+    shared.device_cache = Some(cache.clone());
+
+    let mut favorites = store::load_favorites()?;
+    let mut completer = Completer {
+        devices: device_names(&shared).await,
+        favorites: favorites.keys().cloned().collect(),
+    };
+    let mut history = FileHistory::load(store::config_dir()?.join(HISTORY_FILE));
+    eprintln!("tplc shell: `help` for shell commands, `exit` to leave");
+
+    // Ends on `exit`, end of input, or when the terminal goes away
+    while let Ok(line) = Input::<String>::new()
+        .with_prompt("tplc")
+        .allow_empty(true)
+        .history_with(&mut history)
+        .completion_with(&completer)
+        .interact_text()
+    {
+        let args = match shell_words::split(&line) {
+            Ok(args) => args,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        let args: Vec<String> = match args.first().map(String::as_str) {
+            Some("tplc") => args[1..].to_vec(),
+            _ => args,
+        };
+
+        match args.first().map(String::as_str) {
+            None => {}
+            Some("exit" | "quit") => break,
+            Some("help") => println!("{}", HELP),
+            Some("refresh") => {
+                cache.clear().await;
+                completer.devices = device_names(&shared).await;
+            }
+            Some("fav") => {
+                if let Err(e) = handle_fav(&args[1..], &mut favorites) {
+                    print_error(&e);
+                }
+                completer.favorites = favorites.keys().cloned().collect();
+            }
+            Some(name) if args.len() == 1 && favorites.contains_key(name) => {
+                let command = favorites[name].clone();
+                eprintln!("> {}", command);
+                match shell_words::split(&command) {
+                    Ok(args) => run_line(args, &shared).await,
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            Some(_) => run_line(args, &shared).await,
+        }
+    }
+    Ok(())
+}
+
+/// Parse and run one tplc command, printing its output or error.
+async fn run_line(args: Vec<String>, shared: &RuntimeConfig) {
+    let cli = match Cli::try_parse_from(std::iter::once("tplc".to_string()).chain(args)) {
+        Ok(cli) => cli,
+        Err(e) => {
+            // Prints help and usage errors the same way the CLI does
+            let _ = e.print();
+            return;
+        }
+    };
+    if matches!(cli.command, Commands::Shell) {
+        eprintln!("Already in the shell");
+        return;
+    }
+
+    let mut config = shared.clone();
+    apply_step_flags(&mut config, &cli);
+    apply_output_flags(&mut config, &cli);
+
+    if let Err(e) = Box::pin(crate::dispatch(cli.command, &config)).await {
+        print_error(&e);
+    }
+    let _ = std::io::stdout().flush();
+}
+
+fn handle_fav(args: &[String], favorites: &mut BTreeMap<String, String>) -> Result<(), AppError> {
+    match args.first().map(String::as_str) {
+        None | Some("list") => {
+            for (name, command) in favorites.iter() {
+                println!("{:<16} {}", name, command);
+            }
+        }
+        Some("add") if args.len() >= 3 => {
+            let name = &args[1];
+            let shadows_command = Cli::command()
+                .get_subcommands()
+                .any(|c| c.get_name() == name);
+            if shadows_command || BUILTINS.contains(&name.as_str()) {
+                return Err(AppError::InvalidInput(format!(
+                    "'{}' is a command name; pick another favorite name",
+                    name
+                )));
+            }
+            favorites.insert(name.clone(), shell_words::join(&args[2..]));
+            store::save_favorites(favorites)?;
+        }
+        Some("rm") if args.len() == 2 => {
+            if favorites.remove(&args[1]).is_none() {
+                return Err(AppError::InvalidInput(format!(
+                    "No favorite named '{}'",
+                    args[1]
+                )));
+            }
+            store::save_favorites(favorites)?;
+        }
+        _ => {
+            return Err(AppError::InvalidInput(
+                "Usage: fav [list] | fav add <name> <command> | fav rm <name>".into(),
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Device names and local nicknames for completion. Without a device list
+/// (not logged in, offline) only nicknames complete.
+async fn device_names(config: &RuntimeConfig) -> Vec<String> {
+    let mut names: Vec<String> = store::load_aliases()
+        .map(|aliases| aliases.into_keys().collect())
+        .unwrap_or_default();
+    if let Ok((devices, _)) = resolve::fetch_listed_devices(config).await {
+        names.extend(devices.iter().map(|d| d.alias().to_string()));
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Command history kept in memory (newest first) and appended to a file.
+struct FileHistory {
+    entries: VecDeque<String>,
+    path: PathBuf,
+}
+
+impl FileHistory {
+    fn load(path: PathBuf) -> Self {
+        let contents = std::fs::read_to_string(&path).unwrap_or_default();
+        let lines: Vec<&str> = contents.lines().filter(|l| !l.is_empty()).collect();
+        let keep = &lines[lines.len().saturating_sub(HISTORY_SIZE)..];
+        if keep.len() < lines.len() {
+            // Trim the file so it does not grow forever
+            let _ = std::fs::write(&path, keep.join("\n") + "\n");
+        }
+        Self {
+            entries: keep.iter().rev().map(|l| l.to_string()).collect(),
+            path,
+        }
+    }
+}
+
+impl History<String> for FileHistory {
+    fn read(&self, pos: usize) -> Option<String> {
+        self.entries.get(pos).cloned()
+    }
+
+    fn write(&mut self, val: &String) {
+        let val = val.trim();
+        if val.is_empty() || self.entries.front().is_some_and(|last| last == val) {
+            return;
+        }
+        self.entries.push_front(val.to_string());
+        self.entries.truncate(HISTORY_SIZE);
+        // History is a convenience; failing to save it is not worth an error
+        if let Some(dir) = self.path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        if let Ok(mut file) = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+        {
+            let _ = writeln!(file, "{}", val);
+        }
+    }
+}
+
+struct Completer {
+    devices: Vec<String>,
+    favorites: Vec<String>,
+}
+
+impl Completion for Completer {
+    fn get(&self, input: &str) -> Option<String> {
+        complete(input, &Cli::command(), &self.devices, &self.favorites)
+    }
+}
+
+/// Complete the word being typed at the end of `input`: subcommand names
+/// while they apply (favorites too for the first word), device names after
+/// that. Returns the whole new input line.
+pub fn complete(
+    input: &str,
+    command: &clap::Command,
+    devices: &[String],
+    favorites: &[String],
+) -> Option<String> {
+    let (head, partial) = split_partial(input)?;
+    let words = shell_words::split(head).ok()?;
+
+    // Walk down the subcommand tree along the words already typed
+    let mut current = command;
+    let mut at_subcommand = true;
+    for word in &words {
+        match current.find_subcommand(word) {
+            Some(sub) => current = sub,
+            None => {
+                at_subcommand = false;
+                break;
+            }
+        }
+    }
+
+    let candidates: Vec<String> = if at_subcommand && current.has_subcommands() {
+        let mut names: Vec<String> = current
+            .get_subcommands()
+            .filter(|c| !c.is_hide_set())
+            .map(|c| c.get_name().to_string())
+            .collect();
+        if words.is_empty() {
+            names.extend(favorites.iter().cloned());
+            names.extend(BUILTINS.iter().map(|b| b.to_string()));
+        }
+        names
+    } else {
+        devices.to_vec()
+    };
+
+    let bare = partial.trim_start_matches(['"', '\'']);
+    let bare_lower = bare.to_lowercase();
+    let matches: Vec<&String> = candidates
+        .iter()
+        .filter(|c| c.to_lowercase().starts_with(&bare_lower))
+        .collect();
+    let completed = match matches.as_slice() {
+        [] => return None,
+        [only] => format!("{} ", shell_words::quote(only)),
+        [first, rest @ ..] => {
+            let prefix = common_prefix(first, rest);
+            if prefix.chars().count() <= bare.chars().count() {
+                return None;
+            }
+            // Leave the quote open so the name can still be finished
+            if prefix.contains(' ') {
+                format!("'{}", prefix)
+            } else {
+                prefix
+            }
+        }
+    };
+    Some(format!("{}{}", head, completed))
+}
+
+/// Split off the word being typed: everything from an unclosed quote, or
+/// from the last space. `None` when the input ends with a space.
+fn split_partial(input: &str) -> Option<(&str, &str)> {
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in input.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => {
+                quote = Some((c, i));
+            }
+            (Some((q, _)), c) if c == q => quote = None,
+            (None, c) if c.is_whitespace() => start = i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    match quote {
+        Some((_, i)) => Some(input.split_at(i)),
+        None if start == input.len() && !input.is_empty() => None,
+        None => Some(input.split_at(start)),
+    }
+}
+
+/// Longest case-insensitive common prefix, in the first candidate's case.
+fn common_prefix(first: &str, rest: &[&String]) -> String {
+    let mut len = first.len();
+    for other in rest {
+        len = first
+            .char_indices()
+            .zip(other.chars())
+            .take_while(|((_, a), b)| a.to_lowercase().eq(b.to_lowercase()))
+            .map(|((i, a), _)| i + a.len_utf8())
+            .last()
+            .unwrap_or(0)
+            .min(len);
+    }
+    first[..len].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn devices() -> Vec<String> {
+        vec![
+            "Porch Light".to_string(),
+            "Porch Plug".to_string(),
+            "Kitchen".to_string(),
+        ]
+    }
+
+    fn complete_line(input: &str) -> Option<String> {
+        complete(
+            input,
+            &Cli::command(),
+            &devices(),
+            &["goodnight".to_string()],
+        )
+    }
+
+    #[test]
+    fn test_complete_commands() {
+        assert_eq!(complete_line("pow").as_deref(), Some("power "));
+        assert_eq!(complete_line("power of").as_deref(), Some("power off "));
+        assert_eq!(complete_line("goo").as_deref(), Some("goodnight "));
+        assert_eq!(complete_line("power "), None);
+    }
+
+    #[test]
+    fn test_complete_devices() {
+        assert_eq!(
+            complete_line("power on kit").as_deref(),
+            Some("power on Kitchen ")
+        );
+        assert_eq!(
+            complete_line("power on po").as_deref(),
+            Some("power on 'Porch ")
+        );
+        assert_eq!(
+            complete_line("power on 'Porch Li").as_deref(),
+            Some("power on 'Porch Light' ")
+        );
+        assert_eq!(complete_line("power on zz"), None);
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+
+use serde_json::json;
+
+use crate::auth::keychain;
+use crate::cli::output::print_output;
+use crate::config::{self, RuntimeConfig};
+use crate::error::AppError;
+use crate::resolve;
+use crate::store;
+use crate::support::{
+    self, AuthSummary, Environment, InventorySummary, SupportBundle, BUNDLE_VERSION, LOG_TAIL_LINES,
+};
+
+/// Log files in the config directory worth including.
+const LOG_FILES: &[&str] = &["cron.log", crate::cli::at::LOG_FILE];
+
+pub async fn handle(file: Option<&Path>, config: &RuntimeConfig) -> Result<(), AppError> {
+    let home = dirs::home_dir().map(|h| h.to_string_lossy().into_owned());
+    let home = home.as_deref();
+    let config_dir = store::config_dir()?;
+
+    let config_toml = read_optional(&config::default_config_path()?)?
+        .map(|contents| support::redact_config(&contents, home));
+
+    let mut logs = BTreeMap::new();
+    for name in LOG_FILES {
+        if let Some(contents) = read_optional(&config_dir.join(name))? {
+            let contents = support::redact_text(&contents, home);
+            logs.insert(name.to_string(), support::tail(&contents, LOG_TAIL_LINES));
+        }
+    }
+
+    let auth = match keychain::get_tokens() {
+        Ok(tokens) => AuthSummary {
+            authenticated: tokens.is_some(),
+            tapo: tokens.is_some_and(|t| t.tapo_token.is_some()),
+            storage: keychain::store().name(),
+            error: None,
+        },
+        Err(e) => AuthSummary {
+            authenticated: false,
+            tapo: false,
+            storage: keychain::store().name(),
+            error: Some(e.to_string()),
+        },
+    };
+
+    // A bundle is most needed when things are broken, so a failed device
+    // fetch is recorded rather than fatal.
+    let (inventory, inventory_error) = match resolve::fetch_all_devices(config).await {
+        Ok((devices, _)) => (Some(InventorySummary::from_devices(&devices)), None),
+        Err(e) => (None, Some(support::redact_text(&e.to_string(), home))),
+    };
+
+    let mut tplc_env: Vec<String> = std::env::vars_os()
+        .filter_map(|(key, _)| key.into_string().ok())
+        .filter(|key| key.starts_with("TPLC_"))
+        .collect();
+    tplc_env.sort();
+
+    let bundle = SupportBundle {
+        version: BUNDLE_VERSION,
+        created_at: chrono::Local::now().to_rfc3339(),
+        tplc_version: env!("CARGO_PKG_VERSION"),
+        environment: Environment {
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            cpu_features: support::cpu_features(),
+            terminal: std::io::stdout().is_terminal(),
+            config_dir: support::redact_text(&config_dir.display().to_string(), home),
+            tplc_env,
+        },
+        auth,
+        config_toml,
+        inventory,
+        inventory_error,
+        logs,
+    };
+
+    let file = file.map(Path::to_path_buf).unwrap_or_else(|| {
+        PathBuf::from(format!(
+            "tplc-support-{}.json",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        ))
+    });
+    std::fs::write(&file, serde_json::to_string_pretty(&bundle)?)?;
+
+    print_output(
+        &json!({
+            "file": file.display().to_string(),
+            "config": bundle.config_toml.is_some(),
+            "devices": bundle.inventory.as_ref().map(|i| i.total),
+            "logs": bundle.logs.keys().collect::<Vec<_>>(),
+        }),
+        config,
+    );
+    Ok(())
+}
+
+fn read_optional(path: &Path) -> Result<Option<String>, AppError> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
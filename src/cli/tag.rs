@@ -0,0 +1,168 @@
+use std::sync::{Arc, Mutex};
+
+use clap::Subcommand;
+use futures::stream::{self, StreamExt};
+use serde_json::json;
+
+use crate::cli::output::print_output;
+use crate::cli::progress::Spinner;
+use crate::cli::{light, power, schedule, Commands};
+use crate::config::RuntimeConfig;
+use crate::error::AppError;
+use crate::resolve::{DeviceCache, MAX_CONCURRENT_REQUESTS};
+use crate::store::{self, DeviceTags};
+
+use super::super::resolve;
+
+#[derive(Subcommand)]
+pub enum TagCommand {
+    /// Tag a device; target every device with a tag using --tag
+    Add {
+        /// Device name or ID
+        device: String,
+        /// Tags to add (case-insensitive)
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+
+    /// Remove tags from a device
+    Remove {
+        /// Device name or ID
+        device: String,
+        /// Tags to remove
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+
+    /// List tagged devices, or only those with one tag
+    List {
+        /// Only show devices with this tag
+        tag: Option<String>,
+    },
+}
+
+pub async fn handle(cmd: &TagCommand, config: &RuntimeConfig) -> Result<(), AppError> {
+    match cmd {
+        TagCommand::Add { device, tags } => {
+            let dev = resolve::resolve_device(device, config).await?;
+            let mut all_tags = store::load_tags()?;
+            let entry = all_tags.entry(dev.full_id()).or_default();
+            entry.device_name = dev.alias().to_string();
+            entry
+                .tags
+                .extend(tags.iter().map(|t| t.trim().to_lowercase()));
+            entry.tags.retain(|t| !t.is_empty());
+            let result = tags_json(&dev.full_id(), entry);
+            store::save_tags(&all_tags)?;
+            print_output(&result, config);
+            Ok(())
+        }
+        TagCommand::Remove { device, tags } => {
+            let dev = resolve::resolve_device(device, config).await?;
+            let mut all_tags = store::load_tags()?;
+            let mut entry = all_tags.remove(&dev.full_id()).unwrap_or_default();
+            entry.device_name = dev.alias().to_string();
+            for tag in tags {
+                entry.tags.remove(&tag.trim().to_lowercase());
+            }
+            let result = tags_json(&dev.full_id(), &entry);
+            if !entry.tags.is_empty() {
+                all_tags.insert(dev.full_id(), entry);
+            }
+            store::save_tags(&all_tags)?;
+            print_output(&result, config);
+            Ok(())
+        }
+        TagCommand::List { tag } => {
+            let tag = tag.as_deref().map(str::to_lowercase);
+            let devices: Vec<serde_json::Value> = store::load_tags()?
+                .iter()
+                .filter(|(_, entry)| tag.as_ref().is_none_or(|t| entry.tags.contains(t)))
+                .map(|(device_id, entry)| tags_json(device_id, entry))
+                .collect();
+            print_output(&json!(devices), config);
+            Ok(())
+        }
+    }
+}
+
+fn tags_json(device_id: &str, entry: &DeviceTags) -> serde_json::Value {
+    json!({
+        "device": entry.device_name,
+        "device_id": device_id,
+        "tags": entry.tags,
+    })
+}
+
+/// Run a power, light or schedule command once for each device tagged
+/// `tag`, as if it had been named in place of the device argument. Results
+/// are printed together; a device that fails is reported with its error and
+/// does not stop the others.
+pub async fn dispatch_tagged(
+    command: Commands,
+    tag: &str,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    if !matches!(
+        command,
+        Commands::Power(_) | Commands::Light(_) | Commands::Schedule(_)
+    ) {
+        return Err(AppError::InvalidInput(
+            "--tag only applies to power, light and schedule commands".into(),
+        ));
+    }
+
+    let mut shared = config.clone();
+    shared.tag = None;
+    shared.device_cache.get_or_insert_with(DeviceCache::default);
+    let devices = resolve::tagged_devices(tag, &shared).await?;
+
+    let spinner = Spinner::start(
+        &format!("Running on devices tagged '{}'", tag),
+        Some(devices.len()),
+        config,
+    );
+    let (command, shared, progress) = (&command, &shared, &spinner);
+    let results: Vec<(&str, Result<(), AppError>, Vec<serde_json::Value>)> = stream::iter(&devices)
+        .map(|entry| async move {
+            let capture = Arc::new(Mutex::new(Vec::new()));
+            let mut device_config = shared.clone();
+            device_config.default_device = Some(entry.full_id());
+            device_config.capture = Some(capture.clone());
+            let result = match command {
+                Commands::Power(cmd) => power::handle(cmd, &device_config).await,
+                Commands::Light(cmd) => light::handle(cmd, &device_config).await,
+                Commands::Schedule(cmd) => schedule::handle(cmd, &device_config).await,
+                _ => unreachable!("checked above"),
+            };
+            let output = std::mem::take(&mut *capture.lock().unwrap_or_else(|p| p.into_inner()));
+            progress.tick();
+            (entry.alias(), result, output)
+        })
+        .buffered(MAX_CONCURRENT_REQUESTS)
+        .collect()
+        .await;
+    drop(spinner);
+
+    let mut records = Vec::new();
+    let mut failed = 0;
+    for (alias, result, output) in results {
+        match result {
+            Ok(()) => records.extend(output),
+            Err(err) => {
+                failed += 1;
+                records.push(json!({"device": alias, "error": err.to_string()}));
+            }
+        }
+    }
+    print_output(&json!(records), config);
+    if failed > 0 {
+        return Err(AppError::CheckFailed(format!(
+            "{} of {} devices tagged '{}' failed",
+            failed,
+            devices.len(),
+            tag
+        )));
+    }
+    Ok(())
+}
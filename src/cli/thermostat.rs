@@ -0,0 +1,89 @@
+use clap::{Subcommand, ValueEnum};
+use serde_json::json;
+
+use crate::cli::output::print_output;
+use crate::config::RuntimeConfig;
+use crate::error::AppError;
+use crate::models::device::Device;
+
+use super::super::resolve;
+
+#[derive(Subcommand)]
+pub enum ThermostatCommand {
+    /// Target and current temperature, mode, child lock and battery of a
+    /// radiator valve (KE100)
+    Get {
+        /// Valve name or ID (defaults to `default_device`)
+        device: Option<String>,
+    },
+    /// Change a radiator valve's target temperature, mode or child lock
+    Set {
+        /// Valve name or ID (defaults to `default_device`)
+        device: Option<String>,
+        /// Temperature to heat to, in the valve's unit (usually 5-30)
+        #[arg(long)]
+        target: Option<f64>,
+        /// heat follows the target; off keeps frost protection only
+        #[arg(long, value_enum)]
+        mode: Option<ThermostatModeArg>,
+        /// Disable (on) or enable (off) the buttons on the valve
+        #[arg(long, value_enum)]
+        child_lock: Option<Toggle>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ThermostatModeArg {
+    Heat,
+    Off,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Toggle {
+    On,
+    Off,
+}
+
+pub async fn handle(cmd: &ThermostatCommand, config: &RuntimeConfig) -> Result<(), AppError> {
+    match cmd {
+        ThermostatCommand::Get { device } => {
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
+            print_state(&dev, config).await
+        }
+        ThermostatCommand::Set {
+            device,
+            target,
+            mode,
+            child_lock,
+        } => {
+            if target.is_none() && mode.is_none() && child_lock.is_none() {
+                return Err(AppError::InvalidInput(
+                    "Nothing to change: pass --target, --mode or --child-lock".into(),
+                ));
+            }
+            let dev = resolve::resolve_device_or_default(device.as_deref(), config).await?;
+
+            // A target implies heating, so apply an explicit --mode off after it
+            if let Some(target) = target {
+                dev.set_target_temperature(*target).await?;
+            }
+            if let Some(mode) = mode {
+                dev.set_heating(matches!(mode, ThermostatModeArg::Heat))
+                    .await?;
+            }
+            if let Some(lock) = child_lock {
+                dev.set_thermostat_child_lock(matches!(lock, Toggle::On))
+                    .await?;
+            }
+            print_state(&dev, config).await
+        }
+    }
+}
+
+async fn print_state(dev: &Device, config: &RuntimeConfig) -> Result<(), AppError> {
+    let state = dev.thermostat_state().await?;
+    let mut result = serde_json::to_value(&state)?;
+    result["device"] = json!(dev.alias());
+    print_output(&result, config);
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use console::Key;
+use tabled::builder::Builder;
+use tabled::settings::Style;
+
+use crate::resolve::{DeviceEntry, DeviceState};
+
+/// Brightness change per `+` / `-` key press.
+const BRIGHTNESS_STEP: i16 = 10;
+
+/// What the event loop should do after a key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    None,
+    Quit,
+    Refresh,
+    Toggle,
+    /// Change brightness by this many percentage points
+    Dim(i16),
+}
+
+/// Dashboard state: the device list, the last polled state of each device,
+/// and the selected row.
+pub struct App {
+    pub devices: Vec<DeviceEntry>,
+    pub states: Vec<DeviceState>,
+    pub brightness: Vec<Option<u8>>,
+    pub selected: usize,
+    /// Last action result or error, shown under the table
+    pub status: String,
+}
+
+impl App {
+    pub fn new(devices: Vec<DeviceEntry>) -> Self {
+        let count = devices.len();
+        Self {
+            devices,
+            states: vec![DeviceState::default(); count],
+            brightness: vec![None; count],
+            selected: 0,
+            status: String::new(),
+        }
+    }
+
+    pub fn selected(&self) -> Option<&DeviceEntry> {
+        self.devices.get(self.selected)
+    }
+
+    pub fn is_dimmable(entry: &DeviceEntry) -> bool {
+        entry.device_type.is_light() || entry.device_type.is_dimmer()
+    }
+
+    pub fn handle_key(&mut self, key: &Key) -> Action {
+        match key {
+            Key::ArrowUp | Key::Char('k') => {
+                self.selected = self.selected.saturating_sub(1);
+                Action::None
+            }
+            Key::ArrowDown | Key::Char('j') => {
+                if self.selected + 1 < self.devices.len() {
+                    self.selected += 1;
+                }
+                Action::None
+            }
+            Key::Enter | Key::Char(' ') => Action::Toggle,
+            Key::Char('+') | Key::Char('=') => Action::Dim(BRIGHTNESS_STEP),
+            Key::Char('-') => Action::Dim(-BRIGHTNESS_STEP),
+            Key::Char('r') => Action::Refresh,
+            Key::Char('q') | Key::Escape | Key::CtrlC => Action::Quit,
+            _ => Action::None,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut builder = Builder::default();
+        builder.push_record([
+            "",
+            "NAME",
+            "MODEL",
+            "STATUS",
+            "POWER",
+            "WATTS",
+            "BRIGHTNESS",
+        ]);
+        for (i, entry) in self.devices.iter().enumerate() {
+            let state = &self.states[i];
+            builder.push_record([
+                if i == self.selected { ">" } else { "" }.to_string(),
+                entry.alias().to_string(),
+                entry.info.model().to_string(),
+                if entry.is_online() {
+                    "online"
+                } else {
+                    "offline"
+                }
+                .to_string(),
+                match state.is_on {
+                    Some(true) => "ON",
+                    Some(false) => "off",
+                    None => "",
+                }
+                .to_string(),
+                state
+                    .power_w
+                    .map(|w| format!("{:.1}", w))
+                    .unwrap_or_default(),
+                self.brightness[i]
+                    .map(|b| format!("{}%", b))
+                    .unwrap_or_default(),
+            ]);
+        }
+        let mut table = builder.build();
+        table.with(Style::rounded());
+        format!(
+            "tplc dashboard  (up/down select, space toggle, +/- dim, r refresh, q quit)\n{}\n{}\n",
+            table, self.status
+        )
+    }
+}
+
+/// Apply a brightness step, staying within 1-100 (0 would turn a light off).
+pub fn step_brightness(current: Option<u8>, delta: i16) -> u8 {
+    let current = current.unwrap_or(50) as i16;
+    (current + delta).clamp(1, 100) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selection_stays_in_range() {
+        let mut app = App::new(vec![
+            DeviceEntry::test("1", "Porch"),
+            DeviceEntry::test("2", "Hall"),
+        ]);
+        assert_eq!(app.handle_key(&Key::ArrowUp), Action::None);
+        assert_eq!(app.selected, 0);
+        app.handle_key(&Key::ArrowDown);
+        app.handle_key(&Key::Char('j'));
+        assert_eq!(app.selected().unwrap().alias(), "Hall");
+        assert_eq!(app.handle_key(&Key::Char(' ')), Action::Toggle);
+        assert_eq!(app.handle_key(&Key::Char('q')), Action::Quit);
+    }
+
+    #[test]
+    fn test_step_brightness() {
+        assert_eq!(step_brightness(Some(95), BRIGHTNESS_STEP), 100);
+        assert_eq!(step_brightness(Some(5), -BRIGHTNESS_STEP), 1);
+        assert_eq!(step_brightness(None, BRIGHTNESS_STEP), 60);
+    }
+}
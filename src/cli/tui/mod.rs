@@ -0,0 +1,119 @@
+mod app;
+
+use std::time::Duration;
+
+use clap::Args;
+use console::Term;
+use futures::stream::{self, StreamExt};
+use tokio::sync::mpsc;
+
+use crate::auth::credentials::AuthContext;
+use crate::config::RuntimeConfig;
+use crate::duration::parse_duration;
+use crate::error::AppError;
+use crate::resolve::{self, MAX_CONCURRENT_REQUESTS};
+
+use app::{step_brightness, Action, App};
+
+#[derive(Args)]
+pub struct DashboardArgs {
+    /// How often to poll device state
+    #[arg(long, default_value = "5s", value_parser = parse_duration)]
+    interval: Duration,
+}
+
+pub async fn handle(args: &DashboardArgs, config: &RuntimeConfig) -> Result<(), AppError> {
+    let term = Term::stdout();
+    if !term.is_term() {
+        return Err(AppError::InvalidInput(
+            "The dashboard needs an interactive terminal".into(),
+        ));
+    }
+
+    let (devices, auth) = resolve::fetch_listed_devices(config).await?;
+    let mut app = App::new(devices);
+
+    // Keys are read on a plain thread since reading blocks.
+    let (tx, mut keys) = mpsc::channel(16);
+    std::thread::spawn(move || {
+        let term = Term::stdout();
+        while let Ok(key) = term.read_key_raw() {
+            if tx.blocking_send(key).is_err() {
+                break;
+            }
+        }
+    });
+
+    term.hide_cursor()?;
+    let mut ticker = tokio::time::interval(args.interval);
+    let result = async {
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => refresh(&mut app, &auth, config).await,
+                key = keys.recv() => {
+                    let Some(key) = key else { break };
+                    match app.handle_key(&key) {
+                        Action::None => {}
+                        Action::Quit => break,
+                        Action::Refresh => refresh(&mut app, &auth, config).await,
+                        action => {
+                            app.status = apply(&app, action, &auth, config)
+                                .await
+                                .unwrap_or_else(|e| format!("Error: {}", e));
+                            refresh(&mut app, &auth, config).await;
+                        }
+                    }
+                }
+            }
+            term.clear_screen()?;
+            term.write_str(&app.render())?;
+        }
+        Ok(())
+    }
+    .await;
+    term.show_cursor()?;
+    term.clear_screen()?;
+    result
+}
+
+/// Poll power state for every device and brightness for dimmable ones.
+async fn refresh(app: &mut App, auth: &AuthContext, config: &RuntimeConfig) {
+    app.states = resolve::fetch_device_states(&app.devices, auth, config.verbose).await;
+    app.brightness = stream::iter(&app.devices)
+        .map(|entry| async move {
+            if !entry.is_online() || !App::is_dimmable(entry) {
+                return None;
+            }
+            let device = resolve::build_device(entry, auth, config.verbose).ok()?;
+            device.brightness().await.ok().flatten()
+        })
+        .buffered(MAX_CONCURRENT_REQUESTS)
+        .collect()
+        .await;
+}
+
+/// Run a toggle or dim action on the selected device; returns a status line.
+async fn apply(
+    app: &App,
+    action: Action,
+    auth: &AuthContext,
+    config: &RuntimeConfig,
+) -> Result<String, AppError> {
+    let Some(entry) = app.selected() else {
+        return Ok(String::new());
+    };
+    let device = resolve::build_device(entry, auth, config.verbose)?;
+    match action {
+        Action::Toggle => {
+            device.toggle().await?;
+            Ok(format!("Toggled {}", entry.alias()))
+        }
+        Action::Dim(delta) if App::is_dimmable(entry) => {
+            let level = step_brightness(app.brightness[app.selected], delta);
+            device.set_brightness(level).await?;
+            Ok(format!("{} brightness {}%", entry.alias(), level))
+        }
+        Action::Dim(_) => Ok(format!("{} is not dimmable", entry.alias())),
+        _ => Ok(String::new()),
+    }
+}
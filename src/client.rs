@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use crate::api::cloud_type::CloudType;
+use crate::auth::credentials::{get_auth_context, AuthContext};
+use crate::auth::keychain;
+use crate::auth::login::{log_in, MfaPrompt};
+use crate::auth::token::TokenSet;
+use crate::auth::token_store::TokenStore;
+use crate::error::AppError;
+use crate::models::device::Device;
+use crate::resolve::{self, DeviceEntry};
+
+/// Entry point for using tplc as a library: log in, list the account's
+/// devices and get `Device` handles for them. Nothing is printed and no
+/// config file is read.
+///
+/// A client made from an email and password or from saved tokens keeps its
+/// tokens in memory (the OS keychain is left alone for the whole process);
+/// save `tokens()` to skip the login next time.
+pub struct Client {
+    auth: AuthContext,
+    preferred_cloud: CloudType,
+    verbose: bool,
+}
+
+impl Client {
+    /// Log in to Kasa (required) and Tapo (best-effort). `mfa` is asked for
+    /// the code when a cloud requires one; without it such a login fails.
+    pub async fn login(
+        username: &str,
+        password: &str,
+        mfa: Option<MfaPrompt<'_>>,
+    ) -> Result<Self, AppError> {
+        let tokens = log_in(username, password, mfa, false).await?;
+        Ok(Self::from_tokens(tokens))
+    }
+
+    /// A client for tokens saved from an earlier `tokens()`.
+    pub fn from_tokens(tokens: TokenSet) -> Self {
+        keychain::use_memory();
+        Self {
+            auth: AuthContext::from(tokens),
+            preferred_cloud: CloudType::Kasa,
+            verbose: false,
+        }
+    }
+
+    /// A client for tokens kept in `store`, which also receives the tokens
+    /// refreshed from now on. This sets the store for the whole process.
+    pub async fn from_store(store: Arc<dyn TokenStore>) -> Result<Self, AppError> {
+        keychain::set_store(store);
+        Self::from_keychain().await
+    }
+
+    /// A client for the account `tplc login` stored in the OS keychain (or
+    /// in the store set with `keychain::set_store`).
+    pub async fn from_keychain() -> Result<Self, AppError> {
+        Ok(Self {
+            auth: get_auth_context(false).await?,
+            preferred_cloud: CloudType::Kasa,
+            verbose: false,
+        })
+    }
+
+    /// Log requests and responses to stderr.
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Which cloud's entry to keep for a device both clouds list (Kasa by
+    /// default).
+    pub fn with_preferred_cloud(mut self, cloud: CloudType) -> Self {
+        self.preferred_cloud = cloud;
+        self
+    }
+
+    /// The current tokens, including any refreshed while listing devices.
+    pub fn tokens(&self) -> TokenSet {
+        self.auth.to_token_set()
+    }
+
+    /// Every device and strip outlet on the account, from both clouds.
+    pub async fn devices(&mut self) -> Result<Vec<DeviceEntry>, AppError> {
+        let (devices, auth) =
+            resolve::fetch_devices_with(self.auth.clone(), self.preferred_cloud, self.verbose)
+                .await?;
+        self.auth = auth;
+        Ok(devices)
+    }
+
+    /// The device `name_or_id` names exactly: its alias (ignoring case),
+    /// device ID or full outlet ID.
+    pub async fn device(&mut self, name_or_id: &str) -> Result<Device, AppError> {
+        let devices = self.devices().await?;
+        let mut matches = resolve::exact_matches(name_or_id, &devices);
+        if matches.len() > 1 {
+            // A strip's ID names its outlets too; prefer the strip itself
+            matches.retain(|d| d.full_id() == name_or_id);
+        }
+        match matches.as_slice() {
+            [entry] => self.device_for(entry),
+            [] => Err(AppError::DeviceNotFound(name_or_id.to_string())),
+            _ => Err(AppError::DeviceNotFound(format!(
+                "Multiple devices match '{}'",
+                name_or_id
+            ))),
+        }
+    }
+
+    /// A handle for an entry from `devices()`.
+    pub fn device_for(&self, entry: &DeviceEntry) -> Result<Device, AppError> {
+        resolve::build_device(entry, &self.auth, self.verbose)
+    }
+}
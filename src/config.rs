@@ -1,11 +1,269 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::api::cloud_type::CloudType;
+use crate::api::http::Timeouts;
+use crate::auth::token_store::TokenStoreKind;
+use crate::cron::CronJob;
+use crate::duration;
+use crate::error::AppError;
+use crate::history::HistoryConfig;
+use crate::manifest::ManifestConfig;
+use crate::migrate;
+use crate::models::device_type::DeviceType;
+use crate::models::timezone::DisplayZone;
+use crate::resolve::{DeviceCache, DeviceEntry};
+use crate::store;
+
+const CONFIG_FILE: &str = "config.toml";
+
+/// `config.toml` in the tplc config directory.
+pub fn default_config_path() -> Result<PathBuf, AppError> {
+    Ok(store::config_dir()?.join(CONFIG_FILE))
+}
+
+/// Results collected by `print_output` instead of being printed.
+pub type OutputCapture = Arc<Mutex<Vec<Value>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
 pub enum OutputMode {
     Json,
     Table,
+    Csv,
+    Yaml,
+    /// One JSON object per line for each record
+    Ndjson,
+    /// InfluxDB line protocol, one line per record
+    Influx,
 }
 
 #[derive(Debug, Clone)]
 pub struct RuntimeConfig {
     pub output_mode: OutputMode,
+    /// Fields to keep in each record (`--fields`).
+    pub fields: Option<Vec<String>>,
+    /// Per-record output template (`--format`).
+    pub format: Option<String>,
+    /// Wrap JSON output in `{"schema": .., "data": ..}` (`--schema-version`).
+    pub envelope: bool,
+    /// Suppress stdout and error output (`-q`); only the exit code is reported.
+    pub quiet: bool,
+    /// Color status cells in table output (off with `--no-color` or `NO_COLOR`).
+    pub color: bool,
     pub verbose: bool,
+    /// Treat device arguments as device IDs (skip the device list lookup).
+    pub device_id: bool,
+    /// Match device arguments as regular expressions against device names.
+    pub regex: bool,
+    /// Zone for displaying device times (`--tz`).
+    pub display_zone: DisplayZone,
+    /// Electricity price per kWh for energy costs (`--price`).
+    pub price_per_kwh: Option<f64>,
+    /// Currency code for energy costs (`--currency`).
+    pub currency: String,
+    /// Cloud whose entry is kept when both clouds list the same device.
+    pub preferred_cloud: CloudType,
+    /// Only list and resolve devices on this cloud (`--cloud`).
+    pub only_cloud: Option<CloudType>,
+    /// Device used when a command's device argument is omitted.
+    pub default_device: Option<String>,
+    /// Recurring jobs for `tplc cron install`.
+    pub cron_jobs: Vec<CronJob>,
+    /// Devices from the static manifest, consulted before the cloud list.
+    pub manifest: Vec<DeviceEntry>,
+    /// Use the manifest instead of listing devices from the cloud.
+    pub manifest_only: bool,
+    /// HTTP timeouts (`--timeout`, `[timeouts]`).
+    pub timeouts: Timeouts,
+    /// Record samples to the local history store (`[history] enabled`).
+    pub history: bool,
+    /// Skip safety checks such as refusing to contact offline devices (`--force`).
+    pub force: bool,
+    /// Read back power and light changes (`--verify`).
+    pub verify: bool,
+    /// Show devices hidden with `devices hide` in listings.
+    pub include_hidden: bool,
+    /// Treat a resolved device as this model (`--assume-type`).
+    pub assume_type: Option<DeviceType>,
+    /// Run the command on every device with this tag (`--tag`).
+    pub tag: Option<String>,
+    /// Device list shared between the steps of `tplc run`, fetched once.
+    pub device_cache: Option<DeviceCache>,
+    /// Collects results instead of printing them (`tplc run` steps).
+    pub capture: Option<OutputCapture>,
+}
+
+impl RuntimeConfig {
+    /// True when output is rendered in `mode` as-is, without `--fields` or
+    /// `--format` reshaping it and without being captured. Handlers with a
+    /// hand-built table check this.
+    pub fn renders_as(&self, mode: OutputMode) -> bool {
+        self.output_mode == mode
+            && self.fields.is_none()
+            && self.format.is_none()
+            && self.capture.is_none()
+    }
+}
+
+/// Settings from `config.toml`. Every key is optional; flags and environment
+/// variables take precedence over the file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    /// Schema version of this file (`migrate::CONFIG_VERSION`)
+    pub version: Option<i64>,
+    /// Default output format
+    pub output: Option<OutputMode>,
+    /// Preferred cloud when a device is listed by both Kasa and Tapo
+    pub cloud: Option<CloudType>,
+    /// Device used when a command's device argument is omitted
+    pub default_device: Option<String>,
+    pub energy: EnergyConfig,
+    /// Recurring jobs (`[[cron]]`) for `tplc cron install`
+    pub cron: Vec<CronJob>,
+    /// Static device manifest
+    pub manifest: ManifestConfig,
+    /// Local history store
+    pub history: HistoryConfig,
+    /// HTTP timeouts
+    pub timeouts: TimeoutConfig,
+    /// HTTP(S) proxy URL, or "none" to ignore proxy environment variables
+    pub proxy: Option<String>,
+    /// Where login tokens are kept: "keyring", "file" or "memory"
+    pub token_store: Option<TokenStoreKind>,
+    /// Deprecated keys found while loading, to show the user
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnergyConfig {
+    /// Electricity price per kWh
+    pub price: Option<f64>,
+    /// Currency code, e.g. EUR
+    pub currency: Option<String>,
+}
+
+/// `[timeouts]`: durations like "5s"; unset ones keep their defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutConfig {
+    /// Connecting to the cloud
+    #[serde(deserialize_with = "duration::deserialize_optional")]
+    pub connect: Option<Duration>,
+    /// Account requests (login, token refresh, device list)
+    #[serde(deserialize_with = "duration::deserialize_optional")]
+    pub api: Option<Duration>,
+    /// Device commands
+    #[serde(deserialize_with = "duration::deserialize_optional")]
+    pub device: Option<Duration>,
+}
+
+impl TimeoutConfig {
+    /// These settings over the defaults, with `total` (`--timeout`) taking
+    /// precedence for both kinds of request.
+    pub fn resolve(&self, total: Option<Duration>) -> Timeouts {
+        let defaults = Timeouts::default();
+        Timeouts {
+            connect: self.connect.unwrap_or(defaults.connect),
+            api: total.or(self.api).unwrap_or(defaults.api),
+            device: total.or(self.device).unwrap_or(defaults.device),
+        }
+    }
+}
+
+impl FileConfig {
+    /// Load the config file. An explicit path (`--config` / `TPLC_CONFIG`) must
+    /// exist; the default `config.toml` in the config dir is optional.
+    pub fn load(path: Option<&Path>) -> Result<Self, AppError> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => (default_config_path()?, false),
+        };
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default())
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let invalid = |e: toml::de::Error| {
+            AppError::InvalidInput(format!("Invalid config file {}: {}", path.display(), e))
+        };
+        let mut table: toml::Table = toml::from_str(&contents).map_err(invalid)?;
+        let warnings = migrate::migrate_config(&mut table)?;
+        // Parse the text when nothing moved, so errors keep their line numbers
+        let mut config: Self = if warnings.is_empty() {
+            toml::from_str(&contents).map_err(invalid)?
+        } else {
+            table.try_into().map_err(invalid)?
+        };
+        config.warnings = warnings;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config_file() {
+        let config: FileConfig = toml::from_str(
+            r#"
+            version = 1
+            output = "table"
+            cloud = "tapo"
+            default_device = "desk-lamp"
+            proxy = "http://proxy.lan:3128"
+
+            [energy]
+            price = 0.31
+            currency = "EUR"
+
+            [[cron]]
+            name = "nightly-energy"
+            schedule = "5 0 * * *"
+            command = "energy daily Heater -o csv"
+
+            [manifest]
+            path = "fleet.toml"
+            only = true
+
+            [history]
+            enabled = true
+
+            [timeouts]
+            connect = "3s"
+            device = "20s"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.version, Some(1));
+        assert_eq!(config.output, Some(OutputMode::Table));
+        assert_eq!(config.cloud, Some(CloudType::Tapo));
+        assert_eq!(config.default_device.as_deref(), Some("desk-lamp"));
+        assert_eq!(config.proxy.as_deref(), Some("http://proxy.lan:3128"));
+        assert_eq!(config.energy.price, Some(0.31));
+        assert_eq!(config.cron[0].schedule, "5 0 * * *");
+        assert!(config.manifest.only);
+        assert!(config.history.enabled);
+        let timeouts = config.timeouts.resolve(None);
+        assert_eq!(timeouts.connect, Duration::from_secs(3));
+        assert_eq!(timeouts.api, Timeouts::default().api);
+        assert_eq!(timeouts.device, Duration::from_secs(20));
+        assert_eq!(
+            config.timeouts.resolve(Some(Duration::from_secs(5))).device,
+            Duration::from_secs(5)
+        );
+        assert!(toml::from_str::<FileConfig>("[timeouts]\napi = \"soon\"").is_err());
+        assert!(toml::from_str::<FileConfig>("outptu = \"csv\"").is_err());
+    }
 }
@@ -0,0 +1,169 @@
+use std::path::Path;
+
+use super::{validate_job, xml_escape, CronJob, Schedule};
+use crate::error::AppError;
+
+/// launchd agents are labelled `tplc.<job name>`.
+pub const LABEL_PREFIX: &str = "tplc.";
+
+pub fn label(job_name: &str) -> String {
+    format!("{}{}", LABEL_PREFIX, job_name)
+}
+
+/// The LaunchAgent plist for one job. Output is appended to `log`.
+pub fn render_plist(
+    job: &CronJob,
+    exe: &Path,
+    env: &[(String, String)],
+    log: &Path,
+) -> Result<String, AppError> {
+    let schedule = validate_job(job)?;
+    let string = |s: &str| format!("<string>{}</string>", xml_escape(s));
+
+    let mut plist = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+         \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+         <plist version=\"1.0\">\n<dict>\n",
+    );
+    plist.push_str(&format!(
+        "  <key>Label</key>\n  {}\n",
+        string(&label(&job.name))
+    ));
+
+    plist.push_str("  <key>ProgramArguments</key>\n  <array>\n");
+    let exe = exe.to_string_lossy();
+    for arg in std::iter::once(exe.into_owned()).chain(job.args()?) {
+        plist.push_str(&format!("    {}\n", string(&arg)));
+    }
+    plist.push_str("  </array>\n");
+
+    if !env.is_empty() {
+        plist.push_str("  <key>EnvironmentVariables</key>\n  <dict>\n");
+        for (key, value) in env {
+            plist.push_str(&format!("    <key>{}</key>\n    {}\n", key, string(value)));
+        }
+        plist.push_str("  </dict>\n");
+    }
+
+    let log = log.to_string_lossy();
+    plist.push_str(&format!(
+        "  <key>StandardOutPath</key>\n  {}\n",
+        string(&log)
+    ));
+    plist.push_str(&format!(
+        "  <key>StandardErrorPath</key>\n  {}\n",
+        string(&log)
+    ));
+
+    if schedule.at_boot {
+        plist.push_str("  <key>RunAtLoad</key>\n  <true/>\n");
+    } else {
+        plist.push_str("  <key>StartCalendarInterval</key>\n  <array>\n");
+        for interval in calendar_intervals(&schedule) {
+            plist.push_str("    <dict>\n");
+            for (key, value) in interval {
+                plist.push_str(&format!(
+                    "      <key>{}</key>\n      <integer>{}</integer>\n",
+                    key, value
+                ));
+            }
+            plist.push_str("    </dict>\n");
+        }
+        plist.push_str("  </array>\n");
+    }
+
+    plist.push_str("</dict>\n</plist>\n");
+    Ok(plist)
+}
+
+/// One StartCalendarInterval entry per combination of listed values; missing
+/// keys are wildcards, as in cron. launchd ANDs Day and Weekday while cron
+/// ORs them when both are restricted, so that case gets one set of entries
+/// for each.
+fn calendar_intervals(schedule: &Schedule) -> Vec<Vec<(&'static str, u32)>> {
+    let base = [
+        ("Minute", &schedule.minutes),
+        ("Hour", &schedule.hours),
+        ("Month", &schedule.months),
+    ];
+    let day_sets: Vec<(&'static str, &Option<Vec<u32>>)> =
+        match (&schedule.days, &schedule.weekdays) {
+            (Some(_), Some(_)) => vec![("Day", &schedule.days), ("Weekday", &schedule.weekdays)],
+            (Some(_), None) => vec![("Day", &schedule.days)],
+            (None, _) => vec![("Weekday", &schedule.weekdays)],
+        };
+
+    let mut intervals = Vec::new();
+    for day_set in day_sets {
+        let mut partial: Vec<Vec<(&'static str, u32)>> = vec![Vec::new()];
+        for (key, values) in base.iter().copied().chain(std::iter::once(day_set)) {
+            let Some(values) = values else { continue };
+            partial = partial
+                .into_iter()
+                .flat_map(|entry| {
+                    values.iter().map(move |&v| {
+                        let mut entry = entry.clone();
+                        entry.push((key, v));
+                        entry
+                    })
+                })
+                .collect();
+        }
+        intervals.extend(partial);
+    }
+    intervals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse_schedule;
+    use super::*;
+
+    #[test]
+    fn test_calendar_intervals() {
+        let intervals = calendar_intervals(&parse_schedule("30 6,18 * * 1-5").unwrap());
+        assert_eq!(intervals.len(), 10);
+        assert_eq!(
+            intervals[0],
+            vec![("Minute", 30), ("Hour", 6), ("Weekday", 1)]
+        );
+
+        // Day and weekday both restricted: either one triggers the job
+        let intervals = calendar_intervals(&parse_schedule("0 9 1 * 1").unwrap());
+        assert_eq!(
+            intervals,
+            vec![
+                vec![("Minute", 0), ("Hour", 9), ("Day", 1)],
+                vec![("Minute", 0), ("Hour", 9), ("Weekday", 1)],
+            ]
+        );
+
+        assert_eq!(
+            calendar_intervals(&parse_schedule("* * * * *").unwrap()),
+            vec![Vec::<(&str, u32)>::new()]
+        );
+    }
+
+    #[test]
+    fn test_render_plist() {
+        let job = CronJob {
+            name: "nightly".to_string(),
+            schedule: "@daily".to_string(),
+            command: "energy daily 'Space Heater' -o csv".to_string(),
+        };
+        let plist = render_plist(
+            &job,
+            Path::new("/usr/local/bin/tplc"),
+            &[("TPLC_CONFIG_DIR".to_string(), "/Users/me/tplc".to_string())],
+            Path::new("/Users/me/tplc/cron.log"),
+        )
+        .unwrap();
+        assert!(plist.contains("<string>tplc.nightly</string>"));
+        assert!(plist.contains("    <string>Space Heater</string>\n    <string>-o</string>\n"));
+        assert!(plist.contains("<key>TPLC_CONFIG_DIR</key>\n    <string>/Users/me/tplc</string>"));
+        assert!(plist.contains(
+            "<key>Minute</key>\n      <integer>0</integer>\n      <key>Hour</key>\n      <integer>0</integer>"
+        ));
+    }
+}
@@ -0,0 +1,307 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::error::AppError;
+
+pub mod launchd;
+pub mod task_scheduler;
+
+const BLOCK_BEGIN: &str = "# BEGIN tplc (managed by `tplc cron install`; edit config.toml instead)";
+const BLOCK_END: &str = "# END tplc";
+
+/// Environment passed through to cron jobs when set, since cron starts
+/// commands with an almost empty environment.
+pub const PASSTHROUGH_ENV: &[&str] = &[
+    "TPLC_CONFIG",
+    "TPLC_CONFIG_DIR",
+    "XDG_CONFIG_HOME",
+    // Needed to reach the Secret Service keychain on Linux
+    "DBUS_SESSION_BUS_ADDRESS",
+];
+
+/// A recurring job from `[[cron]]` in config.toml.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CronJob {
+    pub name: String,
+    /// Five-field cron schedule ("30 6 * * 1-5") or a macro like "@daily"
+    pub schedule: String,
+    /// tplc arguments, e.g. "energy daily Heater -o csv"
+    pub command: String,
+}
+
+impl CronJob {
+    /// The job's tplc arguments, split as a shell would.
+    pub fn args(&self) -> Result<Vec<String>, AppError> {
+        shell_words::split(&self.command).map_err(|e| {
+            AppError::InvalidInput(format!("Cron job '{}': invalid command: {}", self.name, e))
+        })
+    }
+}
+
+/// A parsed cron schedule. `None` fields are wildcards; lists are sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schedule {
+    pub minutes: Option<Vec<u32>>,
+    pub hours: Option<Vec<u32>>,
+    pub days: Option<Vec<u32>>,
+    pub months: Option<Vec<u32>>,
+    /// 0 = Sunday
+    pub weekdays: Option<Vec<u32>>,
+    /// `@reboot`: run at startup instead of on a calendar
+    pub at_boot: bool,
+}
+
+const MONTH_NAMES: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAY_NAMES: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+pub fn parse_schedule(schedule: &str) -> Result<Schedule, String> {
+    let expanded = match schedule {
+        "@reboot" => {
+            return Ok(Schedule {
+                at_boot: true,
+                ..Default::default()
+            })
+        }
+        "@yearly" | "@annually" => "0 0 1 1 *",
+        "@monthly" => "0 0 1 * *",
+        "@weekly" => "0 0 * * 0",
+        "@daily" | "@midnight" => "0 0 * * *",
+        "@hourly" => "0 * * * *",
+        s if s.starts_with('@') => return Err(format!("unknown schedule macro '{}'", s)),
+        s => s,
+    };
+    let fields: Vec<&str> = expanded.split_whitespace().collect();
+    let [minute, hour, day, month, weekday] = fields[..] else {
+        return Err(format!(
+            "schedule '{}' needs 5 fields (minute hour day month weekday)",
+            schedule
+        ));
+    };
+    let weekdays = parse_field(weekday, 0, 7, WEEKDAY_NAMES, 0)?.map(|days| {
+        // 7 is another name for Sunday
+        let mut days: Vec<u32> = days.into_iter().map(|d| d % 7).collect();
+        days.sort_unstable();
+        days.dedup();
+        days
+    });
+    Ok(Schedule {
+        minutes: parse_field(minute, 0, 59, &[], 0)?,
+        hours: parse_field(hour, 0, 23, &[], 0)?,
+        days: parse_field(day, 1, 31, &[], 0)?,
+        months: parse_field(month, 1, 12, MONTH_NAMES, 1)?,
+        weekdays,
+        at_boot: false,
+    })
+}
+
+/// Parse one cron field: `*`, values, `a-b` ranges and `/n` steps, comma
+/// separated. `names` are accepted in place of numbers, starting at `first`.
+fn parse_field(
+    field: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+    first: u32,
+) -> Result<Option<Vec<u32>>, String> {
+    if field == "*" {
+        return Ok(None);
+    }
+    let invalid = || format!("invalid schedule field '{}'", field);
+    let value = |s: &str| -> Result<u32, String> {
+        let n = match names.iter().position(|n| n.eq_ignore_ascii_case(s)) {
+            Some(i) => i as u32 + first,
+            None => s.parse().map_err(|_| invalid())?,
+        };
+        if (min..=max).contains(&n) {
+            Ok(n)
+        } else {
+            Err(format!(
+                "'{}' is out of range {}-{} in '{}'",
+                s, min, max, field
+            ))
+        }
+    };
+
+    let mut values = Vec::new();
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(invalid());
+        }
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (value(a)?, value(b)?),
+                // "5/15" means from 5 to the end of the range
+                None if part.contains('/') => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        values.extend((start..=end).step_by(step as usize));
+    }
+    values.sort_unstable();
+    values.dedup();
+    Ok(Some(values))
+}
+
+pub fn validate_schedule(schedule: &str) -> Result<(), String> {
+    parse_schedule(schedule).map(|_| ())
+}
+
+/// Job names become file and task names, so keep them to a safe alphabet.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || "-_.".contains(c);
+    if name.is_empty() || !name.chars().all(allowed) {
+        return Err(format!(
+            "job name '{}' may only use letters, digits, '-', '_' and '.'",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Check a job's name and schedule, with the job named in any error.
+pub fn validate_job(job: &CronJob) -> Result<Schedule, AppError> {
+    validate_name(&job.name)
+        .and_then(|_| parse_schedule(&job.schedule))
+        .map_err(|e| AppError::InvalidInput(format!("Cron job '{}': {}", job.name, e)))
+}
+
+pub(crate) fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The managed crontab block for `jobs`. Output is appended to `log`.
+pub fn render_block(
+    exe: &Path,
+    env: &[(String, String)],
+    log: &Path,
+    jobs: &[CronJob],
+) -> Result<String, AppError> {
+    let env: String = env
+        .iter()
+        .map(|(key, value)| format!("{}={} ", key, shell_words::quote(value)))
+        .collect();
+    let exe = shell_words::quote(&exe.to_string_lossy()).into_owned();
+    let log = shell_words::quote(&log.to_string_lossy()).into_owned();
+
+    let mut block = format!("{}\n", BLOCK_BEGIN);
+    for job in jobs {
+        validate_job(job)?;
+        let args = shell_words::join(job.args()?);
+        block.push_str(&format!("# {}\n", job.name));
+        // cron treats an unescaped % as a newline
+        let line = format!("{} {}{} {} >> {} 2>&1", job.schedule, env, exe, args, log);
+        block.push_str(&line.replace('%', "\\%"));
+        block.push('\n');
+    }
+    block.push_str(BLOCK_END);
+    block.push('\n');
+    Ok(block)
+}
+
+/// Replace (or with `None`, remove) the managed block in a crontab, keeping
+/// every other line as it was.
+pub fn replace_block(crontab: &str, block: Option<&str>) -> String {
+    let mut out = String::new();
+    let mut inside = false;
+    for line in crontab.lines() {
+        if line == BLOCK_BEGIN {
+            inside = true;
+        } else if inside && line == BLOCK_END {
+            inside = false;
+        } else if !inside {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    if let Some(block) = block {
+        out.push_str(block);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(schedule: &str, command: &str) -> CronJob {
+        CronJob {
+            name: "nightly-energy".to_string(),
+            schedule: schedule.to_string(),
+            command: command.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_validate_schedule() {
+        assert!(validate_schedule("30 6 * * 1-5").is_ok());
+        assert!(validate_schedule("*/15 * * * *").is_ok());
+        assert!(validate_schedule("@daily").is_ok());
+        assert!(validate_schedule("@sometimes").is_err());
+        assert!(validate_schedule("30 6 * *").is_err());
+        assert!(validate_schedule("30 6 * * ;rm").is_err());
+        assert!(validate_schedule("60 6 * * *").is_err());
+    }
+
+    #[test]
+    fn test_parse_schedule() {
+        let schedule = parse_schedule("*/20 6,18 * jan-mar 1-5/2,7").unwrap();
+        assert_eq!(schedule.minutes, Some(vec![0, 20, 40]));
+        assert_eq!(schedule.hours, Some(vec![6, 18]));
+        assert_eq!(schedule.days, None);
+        assert_eq!(schedule.months, Some(vec![1, 2, 3]));
+        assert_eq!(schedule.weekdays, Some(vec![0, 1, 3, 5]));
+        assert_eq!(
+            parse_schedule("@weekly").unwrap(),
+            parse_schedule("0 0 * * sun").unwrap()
+        );
+        assert!(parse_schedule("@reboot").unwrap().at_boot);
+        assert!(parse_schedule("5-1 * * * *").is_err());
+    }
+
+    #[test]
+    fn test_render_and_replace_block() {
+        let block = render_block(
+            Path::new("/usr/local/bin/tplc"),
+            &[(
+                "TPLC_CONFIG_DIR".to_string(),
+                "/home/me/.config/tplc".to_string(),
+            )],
+            Path::new("/home/me/.config/tplc/cron.log"),
+            &[job(
+                "5 0 * * *",
+                "energy daily 'Space Heater' --format '{{day}}%'",
+            )],
+        )
+        .unwrap();
+        assert!(block.contains(
+            "5 0 * * * TPLC_CONFIG_DIR=/home/me/.config/tplc /usr/local/bin/tplc energy daily \
+             'Space Heater' --format '{{day}}\\%' >> /home/me/.config/tplc/cron.log 2>&1\n"
+        ));
+
+        let existing = format!("MAILTO=me\n{}0 1 * * * backup.sh\n", block);
+        let updated = replace_block(&existing, Some(&block));
+        assert_eq!(
+            updated,
+            format!("MAILTO=me\n0 1 * * * backup.sh\n{}", block)
+        );
+        assert_eq!(
+            replace_block(&updated, None),
+            "MAILTO=me\n0 1 * * * backup.sh\n"
+        );
+    }
+}
@@ -0,0 +1,308 @@
+use std::path::Path;
+
+use super::{validate_job, xml_escape, CronJob, Schedule};
+use crate::error::AppError;
+
+/// Scheduled tasks live in a `\tplc\` folder named after the job.
+pub const TASK_FOLDER: &str = "\\tplc\\";
+
+/// Task Scheduler refuses tasks with more triggers than this.
+const MAX_TRIGGERS: usize = 48;
+
+/// Any past date works as a trigger's start; only its time of day matters.
+const START_DATE: &str = "2000-01-01";
+
+const MONTH_ELEMENTS: &[&str] = &[
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+const WEEKDAY_ELEMENTS: &[&str] = &[
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+pub fn task_name(job_name: &str) -> String {
+    format!("{}{}", TASK_FOLDER, job_name)
+}
+
+/// The task definition XML for one job. Output is appended to `log` by
+/// running the command through `cmd /C`, which also sets `env`.
+pub fn render_task(
+    job: &CronJob,
+    exe: &Path,
+    env: &[(String, String)],
+    log: &Path,
+) -> Result<String, AppError> {
+    let schedule = validate_job(job)?;
+    let triggers = triggers(&schedule)
+        .map_err(|e| AppError::InvalidInput(format!("Cron job '{}': {}", job.name, e)))?;
+
+    let args = job.args()?;
+    for (key, value) in env {
+        check_cmd_safe(job, &format!("environment variable {}", key), value)?;
+    }
+    for arg in &args {
+        check_cmd_safe(job, &format!("argument '{}'", arg), arg)?;
+    }
+
+    let mut line: String = env
+        .iter()
+        .map(|(key, value)| format!("set \"{}={}\" && ", key, value))
+        .collect();
+    line.push_str(&quote_arg(&exe.to_string_lossy()));
+    for arg in args {
+        line.push(' ');
+        line.push_str(&quote_arg(&arg));
+    }
+    line.push_str(&format!(" >> {} 2>&1", quote_arg(&log.to_string_lossy())));
+    // cmd strips the outer quotes and runs the rest as typed
+    let arguments = format!("/C \"{}\"", line);
+
+    Ok(format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-16\"?>\n\
+         <Task version=\"1.2\" xmlns=\"http://schemas.microsoft.com/windows/2004/02/mit/task\">\n\
+         \x20 <RegistrationInfo>\n\
+         \x20   <Description>{}</Description>\n\
+         \x20 </RegistrationInfo>\n\
+         \x20 <Triggers>\n{}\
+         \x20 </Triggers>\n\
+         \x20 <Settings>\n\
+         \x20   <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>\n\
+         \x20   <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>\n\
+         \x20   <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>\n\
+         \x20   <StartWhenAvailable>true</StartWhenAvailable>\n\
+         \x20   <ExecutionTimeLimit>PT1H</ExecutionTimeLimit>\n\
+         \x20 </Settings>\n\
+         \x20 <Actions>\n\
+         \x20   <Exec>\n\
+         \x20     <Command>cmd.exe</Command>\n\
+         \x20     <Arguments>{}</Arguments>\n\
+         \x20   </Exec>\n\
+         \x20 </Actions>\n\
+         </Task>\n",
+        xml_escape(&format!("tplc job '{}' ({})", job.name, job.schedule)),
+        triggers,
+        xml_escape(&arguments),
+    ))
+}
+
+/// `schtasks /XML` expects UTF-16 with a byte order mark, matching the
+/// declared encoding.
+pub fn encode(xml: &str) -> Vec<u8> {
+    let mut bytes = vec![0xFF, 0xFE];
+    bytes.extend(xml.encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+    bytes
+}
+
+/// cmd expands `%` even inside quotes and cannot pass a `"` through a
+/// quoted argument, so values holding either are refused.
+fn check_cmd_safe(job: &CronJob, what: &str, value: &str) -> Result<(), AppError> {
+    if value.contains(['"', '%']) {
+        return Err(AppError::InvalidInput(format!(
+            "Cron job '{}': {} contains '\"' or '%', which cmd cannot pass on",
+            job.name, what
+        )));
+    }
+    Ok(())
+}
+
+/// Quote an argument for cmd when it contains spaces or characters cmd
+/// would otherwise interpret. `check_cmd_safe` keeps `"` and `%` out.
+fn quote_arg(arg: &str) -> String {
+    let special = |c: char| c.is_whitespace() || "&|<>^()".contains(c);
+    if !arg.is_empty() && !arg.contains(special) {
+        return arg.to_string();
+    }
+    format!("\"{}\"", arg)
+}
+
+/// Calendar triggers for a schedule. Task Scheduler triggers fire at fixed
+/// times of day, so minutes must be listed; a wildcard hour becomes an
+/// hourly repetition. `@reboot` maps to a logon trigger, since boot triggers
+/// need an administrator.
+fn triggers(schedule: &Schedule) -> Result<String, String> {
+    if schedule.at_boot {
+        return Ok("    <LogonTrigger />\n".to_string());
+    }
+    let Some(minutes) = &schedule.minutes else {
+        return Err("Task Scheduler cannot run a job every minute; list the minutes".into());
+    };
+    let (hours, repetition) = match &schedule.hours {
+        Some(hours) => (hours.clone(), ""),
+        None => (
+            vec![0],
+            "      <Repetition>\n        <Interval>PT1H</Interval>\n        \
+             <Duration>P1D</Duration>\n      </Repetition>\n",
+        ),
+    };
+
+    let calendars = calendars(schedule);
+    let count = hours.len() * minutes.len() * calendars.len();
+    if count > MAX_TRIGGERS {
+        return Err(format!(
+            "schedule needs {} triggers but Task Scheduler allows {}",
+            count, MAX_TRIGGERS
+        ));
+    }
+
+    let mut xml = String::new();
+    for hour in &hours {
+        for minute in minutes {
+            for calendar in &calendars {
+                xml.push_str(&format!(
+                    "    <CalendarTrigger>\n{}      \
+                     <StartBoundary>{}T{:02}:{:02}:00</StartBoundary>\n{}    \
+                     </CalendarTrigger>\n",
+                    repetition, START_DATE, hour, minute, calendar
+                ));
+            }
+        }
+    }
+    Ok(xml)
+}
+
+/// The day-selection part of each trigger. As in cron, restricting both day
+/// of month and weekday runs the job on either, so that gets two calendars.
+fn calendars(schedule: &Schedule) -> Vec<String> {
+    let elements = |values: &[u32], names: &[&str], offset: u32| -> String {
+        values
+            .iter()
+            .map(|&v| format!("<{} />", names[(v - offset) as usize]))
+            .collect()
+    };
+    let all_months: Vec<u32> = (1..=12).collect();
+    let months = elements(
+        schedule.months.as_deref().unwrap_or(&all_months),
+        MONTH_ELEMENTS,
+        1,
+    );
+    let by_month = |days: &[u32]| {
+        let days: String = days.iter().map(|d| format!("<Day>{}</Day>", d)).collect();
+        format!(
+            "      <ScheduleByMonth>\n        <DaysOfMonth>{}</DaysOfMonth>\n        \
+             <Months>{}</Months>\n      </ScheduleByMonth>\n",
+            days, months
+        )
+    };
+    let by_weekday = |weekdays: &[u32]| {
+        let weekdays = elements(weekdays, WEEKDAY_ELEMENTS, 0);
+        if schedule.months.is_some() {
+            format!(
+                "      <ScheduleByMonthDayOfWeek>\n        <Weeks><Week>1</Week><Week>2</Week>\
+                 <Week>3</Week><Week>4</Week><Week>Last</Week></Weeks>\n        \
+                 <DaysOfWeek>{}</DaysOfWeek>\n        <Months>{}</Months>\n      \
+                 </ScheduleByMonthDayOfWeek>\n",
+                weekdays, months
+            )
+        } else {
+            format!(
+                "      <ScheduleByWeek>\n        <DaysOfWeek>{}</DaysOfWeek>\n        \
+                 <WeeksInterval>1</WeeksInterval>\n      </ScheduleByWeek>\n",
+                weekdays
+            )
+        }
+    };
+
+    match (&schedule.days, &schedule.weekdays) {
+        (Some(days), Some(weekdays)) => vec![by_month(days), by_weekday(weekdays)],
+        (Some(days), None) => vec![by_month(days)],
+        (None, Some(weekdays)) => vec![by_weekday(weekdays)],
+        (None, None) if schedule.months.is_some() => {
+            vec![by_month(&(1..=31).collect::<Vec<_>>())]
+        }
+        (None, None) => vec![
+            "      <ScheduleByDay>\n        <DaysInterval>1</DaysInterval>\n      \
+             </ScheduleByDay>\n"
+                .to_string(),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse_schedule;
+    use super::*;
+
+    fn triggers_for(schedule: &str) -> Result<String, String> {
+        triggers(&parse_schedule(schedule).unwrap())
+    }
+
+    #[test]
+    fn test_triggers() {
+        let xml = triggers_for("30 6 * * 1-5").unwrap();
+        assert!(xml.contains("<StartBoundary>2000-01-01T06:30:00</StartBoundary>"));
+        assert!(xml.contains(
+            "<DaysOfWeek><Monday /><Tuesday /><Wednesday /><Thursday /><Friday /></DaysOfWeek>"
+        ));
+
+        let xml = triggers_for("0 9 1,15 * 0").unwrap();
+        assert!(xml.contains("<DaysOfMonth><Day>1</Day><Day>15</Day></DaysOfMonth>"));
+        assert!(xml.contains("<ScheduleByWeek>"));
+
+        let xml = triggers_for("15 * * * *").unwrap();
+        assert!(xml.contains("<Interval>PT1H</Interval>"));
+        assert!(xml.contains("T00:15:00"));
+        assert!(xml.contains("<ScheduleByDay>"));
+
+        assert!(triggers_for("* 6 * * *").is_err());
+        assert!(triggers_for("*/5 */2 * * *").is_err());
+        assert!(triggers_for("@reboot")
+            .unwrap()
+            .contains("<LogonTrigger />"));
+    }
+
+    #[test]
+    fn test_render_task() {
+        let job = CronJob {
+            name: "nightly".to_string(),
+            schedule: "@daily".to_string(),
+            command: "energy daily 'Space Heater' -o csv".to_string(),
+        };
+        let xml = render_task(
+            &job,
+            Path::new(r"C:\Program Files\tplc\tplc.exe"),
+            &[("TPLC_CONFIG_DIR".to_string(), r"C:\tplc".to_string())],
+            Path::new(r"C:\tplc\cron.log"),
+        )
+        .unwrap();
+        assert!(xml.contains(
+            "<Arguments>/C &quot;set &quot;TPLC_CONFIG_DIR=C:\\tplc&quot; &amp;&amp; \
+             &quot;C:\\Program Files\\tplc\\tplc.exe&quot; energy daily &quot;Space Heater&quot; \
+             -o csv &gt;&gt; C:\\tplc\\cron.log 2&gt;&amp;1&quot;</Arguments>"
+        ));
+        assert_eq!(&encode("<")[..4], &[0xFF, 0xFE, b'<', 0]);
+
+        let exe = Path::new(r"C:\tplc\tplc.exe");
+        let log = Path::new(r"C:\tplc\cron.log");
+        let job = CronJob {
+            command: "power on '50% \"lamp\"'".to_string(),
+            ..job
+        };
+        let err = render_task(&job, exe, &[], log).unwrap_err();
+        assert!(err.to_string().contains("'nightly'"));
+        let job = CronJob {
+            command: "power on Lamp".to_string(),
+            ..job
+        };
+        let env = [("TPLC_CONFIG_DIR".to_string(), r"C:\%USER%".to_string())];
+        assert!(matches!(
+            render_task(&job, exe, &env, log),
+            Err(AppError::InvalidInput(_))
+        ));
+    }
+}
@@ -1,70 +1,202 @@
+use crate::api::errors::lookup_error_code;
+use crate::api::retry::is_transient;
+
+/// Everything that can go wrong in tplc. Match on `category()` rather than
+/// on variants where possible: variants may be added in any release.
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum AppError {
+    /// The cloud rejected the credentials or the account.
     #[error("Authentication failed: {message}")]
     Auth {
         message: String,
         error_code: Option<i32>,
     },
 
+    /// Login needs a code from the account's MFA app or email.
     #[error("MFA verification required")]
     MfaRequired {
         mfa_type: Option<String>,
         email: Option<String>,
     },
 
+    /// The token expired and could not be refreshed.
     #[error("Token expired: {message}")]
     TokenExpired {
         message: String,
         error_code: Option<i32>,
     },
 
+    /// No device matches the name or ID given.
     #[error("Device not found: {0}")]
     DeviceNotFound(String),
 
-    #[error("Device offline: {0}")]
-    DeviceOffline(String),
+    /// The cloud could not reach the device.
+    #[error("Device offline: {message}")]
+    DeviceOffline {
+        message: String,
+        error_code: Option<i32>,
+        /// Name of the device, when known
+        device: Option<String>,
+    },
+
+    /// The cloud is throttling requests (exit code 6).
+    #[error("Rate limited: {message}")]
+    RateLimited {
+        message: String,
+        error_code: Option<i32>,
+    },
+
+    /// The account may not access the device (exit code 7).
+    #[error("Permission denied: {message}")]
+    PermissionDenied {
+        message: String,
+        error_code: Option<i32>,
+    },
 
+    /// Any other error response from the cloud.
     #[error("API error: {message}")]
     Api {
         message: String,
         error_code: Option<i32>,
     },
 
+    /// No tokens are stored.
     #[error("Not authenticated. Run 'tplc login' first.")]
     NotAuthenticated,
 
+    /// The OS keychain could not be read or written.
     #[error("Keychain error: {0}")]
     Keychain(String),
 
+    /// The local history database could not be read or written.
+    #[error("History database error: {0}")]
+    Database(String),
+
+    /// The device's model cannot do what was asked.
     #[error("Device does not support this operation: {0}")]
     UnsupportedOperation(String),
 
+    /// An argument, file or setting is not valid.
     #[error("{0}")]
     InvalidInput(String),
 
+    /// A verification command ran but its check did not pass.
+    #[error("Check failed: {0}")]
+    CheckFailed(String),
+
+    /// `--verify` read back a different state than was set (exit code 8).
+    #[error("State not applied: {message}")]
+    StateMismatch { message: String, device: String },
+
+    /// `power status --quiet` found the device off (exit code 10).
+    #[error("Device is off: {0}")]
+    DeviceOff(String),
+
+    /// A long-running operation was cancelled through its handle.
+    #[error("Operation cancelled")]
+    Cancelled,
+
+    /// The request did not get an HTTP response, or got a 5xx one.
     #[error(transparent)]
     Http(#[from] reqwest::Error),
 
+    /// A response or file was not the JSON expected.
     #[error(transparent)]
     Json(#[from] serde_json::Error),
 
+    /// Reading or writing a local file failed.
     #[error(transparent)]
     Io(#[from] std::io::Error),
 }
 
+/// Broad kinds of `AppError`, stable across releases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorCategory {
+    /// Not logged in, bad credentials, MFA needed or an expired session
+    Auth,
+    DeviceNotFound,
+    DeviceOffline,
+    RateLimited,
+    PermissionDenied,
+    Unsupported,
+    InvalidInput,
+    CheckFailed,
+    /// The device accepted a change but reports the old state (`--verify`)
+    StateMismatch,
+    DeviceOff,
+    /// Other error responses from the cloud
+    Cloud,
+    /// Connection failures, timeouts and 5xx responses
+    Network,
+    /// Keychain, file and parsing errors on this machine
+    Local,
+    /// Stopped through an operation handle
+    Cancelled,
+}
+
 impl AppError {
-    pub fn exit_code(&self) -> i32 {
+    pub fn category(&self) -> ErrorCategory {
         match self {
             AppError::Auth { .. }
             | AppError::MfaRequired { .. }
             | AppError::TokenExpired { .. }
-            | AppError::NotAuthenticated => 2,
-            AppError::DeviceNotFound(_) => 3,
-            AppError::DeviceOffline(_) => 4,
+            | AppError::NotAuthenticated => ErrorCategory::Auth,
+            AppError::DeviceNotFound(_) => ErrorCategory::DeviceNotFound,
+            AppError::DeviceOffline { .. } => ErrorCategory::DeviceOffline,
+            AppError::RateLimited { .. } => ErrorCategory::RateLimited,
+            AppError::PermissionDenied { .. } => ErrorCategory::PermissionDenied,
+            AppError::UnsupportedOperation(_) => ErrorCategory::Unsupported,
+            AppError::InvalidInput(_) => ErrorCategory::InvalidInput,
+            AppError::CheckFailed(_) => ErrorCategory::CheckFailed,
+            AppError::StateMismatch { .. } => ErrorCategory::StateMismatch,
+            AppError::DeviceOff(_) => ErrorCategory::DeviceOff,
+            AppError::Api { .. } => ErrorCategory::Cloud,
+            AppError::Cancelled => ErrorCategory::Cancelled,
+            AppError::Http(_) => ErrorCategory::Network,
+            AppError::Keychain(_) | AppError::Database(_) | AppError::Json(_) | AppError::Io(_) => {
+                ErrorCategory::Local
+            }
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self.category() {
+            ErrorCategory::Auth => 2,
+            ErrorCategory::DeviceNotFound => 3,
+            ErrorCategory::DeviceOffline => 4,
+            ErrorCategory::CheckFailed => 5,
+            ErrorCategory::RateLimited => 6,
+            ErrorCategory::PermissionDenied => 7,
+            ErrorCategory::StateMismatch => 8,
+            ErrorCategory::DeviceOff => 10,
             _ => 1,
         }
     }
 
+    /// Whether repeating the request may succeed (see `retry::is_transient`).
+    pub fn is_retryable(&self) -> bool {
+        is_transient(self)
+    }
+
+    /// Name of the device found offline or off.
+    pub fn device(&self) -> Option<&str> {
+        match self {
+            AppError::DeviceOff(device) | AppError::StateMismatch { device, .. } => Some(device),
+            AppError::DeviceOffline { device, .. } => device.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Name the device an offline error is about, unless it already names one.
+    pub fn with_device(mut self, name: &str) -> Self {
+        if let AppError::DeviceOffline { device, .. } = &mut self {
+            device.get_or_insert_with(|| name.to_string());
+        }
+        self
+    }
+
     pub fn error_type(&self) -> &'static str {
         match self {
             AppError::Auth { .. } => "auth",
@@ -72,11 +204,18 @@ impl AppError {
             AppError::TokenExpired { .. } => "token_expired",
             AppError::NotAuthenticated => "not_authenticated",
             AppError::DeviceNotFound(_) => "device_not_found",
-            AppError::DeviceOffline(_) => "device_offline",
+            AppError::DeviceOffline { .. } => "device_offline",
+            AppError::RateLimited { .. } => "rate_limited",
+            AppError::PermissionDenied { .. } => "permission_denied",
             AppError::Api { .. } => "api",
             AppError::Keychain(_) => "keychain",
+            AppError::Database(_) => "database",
             AppError::UnsupportedOperation(_) => "unsupported_operation",
             AppError::InvalidInput(_) => "invalid_input",
+            AppError::CheckFailed(_) => "check_failed",
+            AppError::StateMismatch { .. } => "state_mismatch",
+            AppError::DeviceOff(_) => "device_off",
+            AppError::Cancelled => "cancelled",
             AppError::Http(_) => "http",
             AppError::Json(_) => "json",
             AppError::Io(_) => "io",
@@ -88,16 +227,26 @@ impl AppError {
             "error": self.error_type(),
             "message": self.to_string(),
         });
-        if let Some(code) = self.api_error_code() {
+        if let Some(device) = self.device() {
+            obj["device"] = serde_json::json!(device);
+        }
+        if let Some(code) = self.error_code() {
             obj["error_code"] = serde_json::json!(code);
+            if let Some(info) = lookup_error_code(code) {
+                obj["hint"] = serde_json::json!(info.hint);
+            }
         }
         obj
     }
 
-    fn api_error_code(&self) -> Option<i32> {
+    /// The cloud's numeric error code, for errors the cloud reported.
+    pub fn error_code(&self) -> Option<i32> {
         match self {
             AppError::Auth { error_code, .. }
             | AppError::TokenExpired { error_code, .. }
+            | AppError::DeviceOffline { error_code, .. }
+            | AppError::RateLimited { error_code, .. }
+            | AppError::PermissionDenied { error_code, .. }
             | AppError::Api { error_code, .. } => *error_code,
             _ => None,
         }
@@ -0,0 +1,186 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::api::client::TPLinkApi;
+use crate::api::cloud_type::CloudType;
+use crate::auth::credentials::AuthContext;
+use crate::error::AppError;
+use crate::resolve::DeviceEntry;
+
+/// Timestamps above this are milliseconds; below, seconds.
+const MILLIS_THRESHOLD: i64 = 100_000_000_000;
+
+/// A notice from a cloud's message center.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CloudMessage {
+    pub time: DateTime<Utc>,
+    pub cloud: CloudType,
+    pub device: String,
+    pub device_id: String,
+    /// "offline", "overload" or "notice"
+    pub event: &'static str,
+    pub message: String,
+}
+
+/// Read one message-center entry. Entries without a time are skipped; the
+/// device name falls back to the ID when the entry does not carry one.
+pub fn parse_message(value: &Value, cloud: CloudType) -> Option<CloudMessage> {
+    let text = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|k| value.get(*k).and_then(Value::as_str))
+            .unwrap_or_default()
+            .to_string()
+    };
+    let time = cloud_time(value, &["msgTime", "time", "createTime"])?;
+
+    let device_id = text(&["deviceId", "deviceID"]);
+    let mut device = text(&["deviceName", "alias", "deviceAlias"]);
+    if device.is_empty() {
+        device = device_id.clone();
+    }
+    let title = text(&["title", "msgTitle"]);
+    let body = text(&["content", "msgContent", "msg"]);
+    let message = match (title.is_empty(), body.is_empty()) {
+        (false, false) => format!("{}: {}", title, body),
+        (false, true) => title,
+        _ => body,
+    };
+    let kind = format!("{} {}", text(&["msgType", "type"]), message);
+
+    Some(CloudMessage {
+        time,
+        cloud,
+        device,
+        device_id,
+        event: classify(&kind),
+        message,
+    })
+}
+
+/// The first of `keys` in `value` holding a Unix timestamp, in seconds or
+/// milliseconds (the clouds use both).
+pub fn cloud_time(value: &Value, keys: &[&str]) -> Option<DateTime<Utc>> {
+    let stamp = keys
+        .iter()
+        .find_map(|k| value.get(*k).and_then(Value::as_i64))?;
+    if stamp > MILLIS_THRESHOLD {
+        DateTime::from_timestamp_millis(stamp)
+    } else {
+        DateTime::from_timestamp(stamp, 0)
+    }
+}
+
+/// Sort a message into the handful of kinds tplc reacts to.
+fn classify(text: &str) -> &'static str {
+    let text = text.to_lowercase();
+    if text.contains("offline") || text.contains("disconnected") {
+        "offline"
+    } else if text.contains("overload") || text.contains("overcurrent") {
+        "overload"
+    } else {
+        "notice"
+    }
+}
+
+/// Messages from every logged-in cloud sent at or after `since`, oldest
+/// first. Device names come from `devices` where the ID matches. Tapo
+/// failures are non-fatal, as for the device list.
+pub async fn fetch_messages(
+    devices: &[DeviceEntry],
+    auth: &AuthContext,
+    since: DateTime<Utc>,
+    verbose: bool,
+) -> Result<Vec<CloudMessage>, AppError> {
+    let mut messages = Vec::new();
+    for cloud in [CloudType::Kasa, CloudType::Tapo] {
+        let Some((token, url)) = auth.session(cloud) else {
+            continue;
+        };
+        match fetch_cloud_messages(cloud, token, url, auth, since, verbose).await {
+            Ok(fetched) => messages.extend(fetched),
+            Err(e) if cloud == CloudType::Kasa => return Err(e),
+            Err(e) => {
+                if verbose {
+                    eprintln!("Tapo message fetch failed (non-fatal): {}", e);
+                }
+            }
+        }
+    }
+
+    for message in &mut messages {
+        let listed = devices
+            .iter()
+            .find(|d| d.child_id.is_none() && d.info.id() == message.device_id);
+        if let Some(entry) = listed {
+            message.device = entry.alias().to_string();
+        }
+    }
+    messages.sort_by_key(|m| m.time);
+    Ok(messages)
+}
+
+async fn fetch_cloud_messages(
+    cloud_type: CloudType,
+    token: &str,
+    regional_url: &str,
+    auth: &AuthContext,
+    since: DateTime<Utc>,
+    verbose: bool,
+) -> Result<Vec<CloudMessage>, AppError> {
+    let api = TPLinkApi::new(
+        Some(regional_url.to_string()),
+        verbose,
+        Some(auth.term_id.clone()),
+        cloud_type,
+    )?;
+    let list = api
+        .get_message_list(token, since.timestamp_millis())
+        .await?;
+    Ok(list
+        .iter()
+        .filter_map(|v| parse_message(v, cloud_type))
+        .filter(|m| m.time >= since)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_message() {
+        let message = parse_message(
+            &json!({
+                "msgTime": 1_791_000_000_000i64,
+                "deviceId": "8006AB",
+                "title": "Overload protection",
+                "content": "Space Heater was turned off",
+            }),
+            CloudType::Tapo,
+        )
+        .unwrap();
+        assert_eq!(message.time.timestamp(), 1_791_000_000);
+        assert_eq!(message.device, "8006AB");
+        assert_eq!(message.event, "overload");
+        assert_eq!(
+            message.message,
+            "Overload protection: Space Heater was turned off"
+        );
+
+        let message = parse_message(
+            &json!({"time": 1_791_000_000, "deviceName": "Porch", "msg": "Device is offline"}),
+            CloudType::Kasa,
+        )
+        .unwrap();
+        assert_eq!(message.time.timestamp(), 1_791_000_000);
+        assert_eq!(
+            (message.device.as_str(), message.event),
+            ("Porch", "offline")
+        );
+
+        assert!(parse_message(&json!({"content": "no time"}), CloudType::Kasa).is_none());
+    }
+}
@@ -0,0 +1,348 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::AppError;
+use crate::events::cloud_time;
+use crate::store;
+
+#[cfg(feature = "history")]
+pub mod db;
+
+/// Directory in the config dir holding the history files.
+const HISTORY_DIR: &str = "history";
+
+const RSSI_FILE: &str = "rssi.jsonl";
+
+const ACTIVITY_FILE: &str = "activity.jsonl";
+
+/// A locally observed change this close to the same change in the cloud's
+/// log is taken to be that change. `devices watch` only sees it at its next
+/// poll, so this covers the usual poll intervals.
+const MERGE_WINDOW_SECS: i64 = 300;
+
+/// Points in a sparkline; longer series are averaged into this many buckets.
+const SPARKLINE_WIDTH: usize = 40;
+
+const SPARK_CHARS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// `[history]` in config.toml.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// Record samples while `devices watch` polls
+    pub enabled: bool,
+}
+
+/// One WiFi signal reading, a line of `history/rssi.jsonl`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RssiSample {
+    pub time: DateTime<Utc>,
+    pub device_id: String,
+    pub device: String,
+    /// Signal strength in dBm
+    pub rssi: i64,
+}
+
+/// One power or connection change, a line of `history/activity.jsonl` or an
+/// entry of the cloud's device activity log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActivityRecord {
+    pub time: DateTime<Utc>,
+    pub device_id: String,
+    pub device: String,
+    /// "on", "off", "online" or "offline"; other cloud entries as logged
+    pub event: String,
+    /// "local" (seen by `devices watch`) or "cloud"
+    pub source: String,
+}
+
+/// Signal summary for one device over the queried period.
+#[derive(Debug, Serialize)]
+pub struct RssiTrend {
+    pub device: String,
+    pub device_id: String,
+    pub samples: usize,
+    pub min: i64,
+    pub avg: f64,
+    pub max: i64,
+    pub last: i64,
+    pub first_time: DateTime<Utc>,
+    pub last_time: DateTime<Utc>,
+    pub trend: String,
+}
+
+pub(crate) fn history_file(name: &str) -> Result<PathBuf, AppError> {
+    Ok(store::config_dir()?.join(HISTORY_DIR).join(name))
+}
+
+/// Append samples to the RSSI history.
+pub fn record_rssi(samples: &[RssiSample]) -> Result<(), AppError> {
+    append(RSSI_FILE, samples)
+}
+
+/// RSSI samples recorded at or after `since`, oldest first.
+pub fn load_rssi(since: DateTime<Utc>) -> Result<Vec<RssiSample>, AppError> {
+    let mut samples: Vec<RssiSample> = load(RSSI_FILE)?;
+    samples.retain(|s| s.time >= since);
+    samples.sort_by_key(|s| s.time);
+    Ok(samples)
+}
+
+/// Append observed changes to the activity history.
+pub fn record_activity(records: &[ActivityRecord]) -> Result<(), AppError> {
+    append(ACTIVITY_FILE, records)
+}
+
+/// Activity recorded at or after `since`, oldest first.
+pub fn load_activity(since: DateTime<Utc>) -> Result<Vec<ActivityRecord>, AppError> {
+    let mut records: Vec<ActivityRecord> = load(ACTIVITY_FILE)?;
+    records.retain(|r| r.time >= since);
+    records.sort_by_key(|r| r.time);
+    Ok(records)
+}
+
+fn append<T: Serialize>(name: &str, items: &[T]) -> Result<(), AppError> {
+    if items.is_empty() {
+        return Ok(());
+    }
+    let path = history_file(name)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let mut lines = String::new();
+    for item in items {
+        lines.push_str(&serde_json::to_string(item)?);
+        lines.push('\n');
+    }
+    file.write_all(lines.as_bytes())?;
+    Ok(())
+}
+
+/// Every line of a history file. Lines that do not parse (e.g. a write cut
+/// short) are skipped.
+fn load<T: DeserializeOwned>(name: &str) -> Result<Vec<T>, AppError> {
+    let contents = match std::fs::read_to_string(history_file(name)?) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Read one entry of the cloud's device activity log. Entries without a
+/// time or an event are skipped.
+pub fn parse_activity(value: &Value, device_id: &str, device: &str) -> Option<ActivityRecord> {
+    let time = cloud_time(value, &["time", "timestamp", "eventTime"])?;
+    let raw = ["event", "action", "eventType"]
+        .iter()
+        .find_map(|k| match value.get(*k)? {
+            Value::String(s) => Some(s.to_lowercase()),
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        })?;
+    let event = match raw.as_str() {
+        "on" | "1" | "turn_on" | "power_on" | "relay_on" => "on",
+        "off" | "0" | "turn_off" | "power_off" | "relay_off" => "off",
+        "online" | "connected" => "online",
+        "offline" | "disconnected" => "offline",
+        other => other,
+    };
+    Some(ActivityRecord {
+        time,
+        device_id: device_id.to_string(),
+        device: device.to_string(),
+        event: event.to_string(),
+        source: "cloud".to_string(),
+    })
+}
+
+/// Cloud and local activity in one timeline, oldest first. A local record
+/// is dropped when the cloud logged the same change within
+/// `MERGE_WINDOW_SECS` before it.
+pub fn merge_activity(
+    cloud: Vec<ActivityRecord>,
+    local: Vec<ActivityRecord>,
+) -> Vec<ActivityRecord> {
+    let window = chrono::Duration::seconds(MERGE_WINDOW_SECS);
+    let unmatched: Vec<ActivityRecord> = local
+        .into_iter()
+        .filter(|l| {
+            !cloud.iter().any(|c| {
+                c.device_id == l.device_id
+                    && c.event == l.event
+                    && c.time <= l.time
+                    && l.time - c.time <= window
+            })
+        })
+        .collect();
+    let mut merged = cloud;
+    merged.extend(unmatched);
+    merged.sort_by_key(|r| r.time);
+    merged
+}
+
+/// One trend per device, in order of first appearance. The device name is
+/// the most recently recorded one.
+pub fn rssi_trends(samples: &[RssiSample]) -> Vec<RssiTrend> {
+    let mut ids: Vec<&str> = Vec::new();
+    for sample in samples {
+        if !ids.contains(&sample.device_id.as_str()) {
+            ids.push(&sample.device_id);
+        }
+    }
+    ids.into_iter()
+        .filter_map(|id| {
+            let series: Vec<&RssiSample> = samples.iter().filter(|s| s.device_id == id).collect();
+            let (first, last) = (series.first()?, series.last()?);
+            let values: Vec<i64> = series.iter().map(|s| s.rssi).collect();
+            Some(RssiTrend {
+                device: last.device.clone(),
+                device_id: id.to_string(),
+                samples: values.len(),
+                min: *values.iter().min()?,
+                avg: (values.iter().sum::<i64>() as f64 / values.len() as f64 * 10.0).round()
+                    / 10.0,
+                max: *values.iter().max()?,
+                last: last.rssi,
+                first_time: first.time,
+                last_time: last.time,
+                trend: sparkline(&values),
+            })
+        })
+        .collect()
+}
+
+/// A one-line chart of `values`, averaged down to at most `SPARKLINE_WIDTH`
+/// points and scaled between the lowest and highest of them.
+pub fn sparkline(values: &[i64]) -> String {
+    if values.is_empty() {
+        return String::new();
+    }
+    let buckets = values.len().min(SPARKLINE_WIDTH);
+    let points: Vec<f64> = (0..buckets)
+        .map(|i| {
+            let bucket = &values[i * values.len() / buckets..(i + 1) * values.len() / buckets];
+            bucket.iter().sum::<i64>() as f64 / bucket.len() as f64
+        })
+        .collect();
+    let low = points.iter().copied().fold(f64::INFINITY, f64::min);
+    let high = points.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let top = (SPARK_CHARS.len() - 1) as f64;
+    points
+        .iter()
+        .map(|p| {
+            let level = if high > low {
+                ((p - low) / (high - low) * top).round() as usize
+            } else {
+                SPARK_CHARS.len() / 2
+            };
+            SPARK_CHARS[level]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(minute: u32, id: &str, rssi: i64) -> RssiSample {
+        RssiSample {
+            time: format!("2026-10-01T12:{:02}:00Z", minute).parse().unwrap(),
+            device_id: id.to_string(),
+            device: format!("Plug {}", id),
+            rssi,
+        }
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[-70, -60, -50]), "▁▅█");
+        assert_eq!(sparkline(&[-55, -55]), "▅▅");
+        assert_eq!(sparkline(&[]), "");
+        assert_eq!(
+            sparkline(&(0..100).collect::<Vec<_>>()).chars().count(),
+            SPARKLINE_WIDTH
+        );
+    }
+
+    fn activity(minute: u32, event: &str, source: &str) -> ActivityRecord {
+        ActivityRecord {
+            time: format!("2026-10-01T12:{:02}:00Z", minute).parse().unwrap(),
+            device_id: "A".to_string(),
+            device: "Porch".to_string(),
+            event: event.to_string(),
+            source: source.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_merge_activity() {
+        let cloud = vec![activity(0, "on", "cloud"), activity(30, "off", "cloud")];
+        let local = vec![
+            activity(1, "on", "local"),       // same change, seen at the next poll
+            activity(10, "offline", "local"), // only seen locally
+            activity(40, "off", "local"),     // too long after the cloud's entry
+        ];
+        let merged: Vec<(String, String)> = merge_activity(cloud, local)
+            .into_iter()
+            .map(|r| (r.event, r.source))
+            .collect();
+        let expected = [
+            ("on", "cloud"),
+            ("offline", "local"),
+            ("off", "cloud"),
+            ("off", "local"),
+        ];
+        assert_eq!(
+            merged,
+            expected.map(|(e, s)| (e.to_string(), s.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_activity() {
+        let record = parse_activity(
+            &serde_json::json!({"timestamp": 1_791_000_000_000i64, "action": "TURN_ON"}),
+            "A",
+            "Porch",
+        )
+        .unwrap();
+        assert_eq!(
+            (record.event.as_str(), record.source.as_str()),
+            ("on", "cloud")
+        );
+        assert_eq!(record.time.timestamp(), 1_791_000_000);
+        assert!(parse_activity(&serde_json::json!({"action": "on"}), "A", "Porch").is_none());
+    }
+
+    #[test]
+    fn test_rssi_trends() {
+        let samples = vec![
+            sample(0, "A", -60),
+            sample(1, "B", -40),
+            sample(2, "A", -70),
+            sample(3, "A", -50),
+        ];
+        let trends = rssi_trends(&samples);
+        assert_eq!(trends.len(), 2);
+        assert_eq!(trends[0].device_id, "A");
+        assert_eq!(
+            (trends[0].min, trends[0].avg, trends[0].max, trends[0].last),
+            (-70, -60.0, -50, -50)
+        );
+        assert_eq!(trends[0].trend, "▅▁█");
+        assert_eq!(trends[1].samples, 1);
+    }
+}
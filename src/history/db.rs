@@ -0,0 +1,437 @@
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use crate::error::AppError;
+use crate::history::{history_file, ActivityRecord};
+
+/// SQLite database in the history directory, filled by `tplc history sync`.
+const DB_FILE: &str = "history.db";
+
+/// Schema changes in order. `PRAGMA user_version` holds how many have been
+/// applied; add new steps at the end and never edit a released one.
+const MIGRATIONS: &[&str] = &[
+    // 1: daily and monthly energy totals, power and connection events
+    "CREATE TABLE daily_energy (
+        device_id TEXT NOT NULL,
+        device TEXT NOT NULL,
+        date TEXT NOT NULL,
+        energy_wh REAL NOT NULL,
+        PRIMARY KEY (device_id, date)
+    );
+    CREATE TABLE monthly_energy (
+        device_id TEXT NOT NULL,
+        device TEXT NOT NULL,
+        month TEXT NOT NULL,
+        energy_wh REAL NOT NULL,
+        PRIMARY KEY (device_id, month)
+    );
+    CREATE TABLE events (
+        time TEXT NOT NULL,
+        device_id TEXT NOT NULL,
+        device TEXT NOT NULL,
+        event TEXT NOT NULL,
+        source TEXT NOT NULL,
+        PRIMARY KEY (device_id, time, event)
+    );",
+];
+
+impl From<rusqlite::Error> for AppError {
+    fn from(err: rusqlite::Error) -> Self {
+        AppError::Database(err.to_string())
+    }
+}
+
+/// One day's energy total for a device.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DailyEnergy {
+    pub device_id: String,
+    pub device: String,
+    pub date: NaiveDate,
+    pub energy_wh: f64,
+}
+
+/// One month's energy total for a device.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MonthlyEnergy {
+    pub device_id: String,
+    pub device: String,
+    pub year: i32,
+    pub month: u32,
+    pub energy_wh: f64,
+}
+
+/// Which rows a query returns. `device` matches a device ID or, ignoring
+/// case, the name recorded with the row; the dates are inclusive.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    pub device: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl HistoryFilter {
+    /// SQL condition on the `device_id`/`device` columns and `column`, which
+    /// holds ISO dates or timestamps cut to `len` characters, and its
+    /// parameters.
+    fn condition(&self, column: &str, len: usize) -> (String, Vec<String>) {
+        let mut clauses = vec!["1".to_string()];
+        let mut values = Vec::new();
+        if let Some(device) = &self.device {
+            clauses.push("(device_id = ? OR lower(device) = lower(?))".into());
+            values.extend([device.clone(), device.clone()]);
+        }
+        if let Some(from) = self.from {
+            clauses.push(format!("substr({}, 1, {}) >= ?", column, len));
+            values.push(from.format("%Y-%m-%d").to_string()[..len].to_string());
+        }
+        if let Some(to) = self.to {
+            clauses.push(format!("substr({}, 1, {}) <= ?", column, len));
+            values.push(to.format("%Y-%m-%d").to_string()[..len].to_string());
+        }
+        (clauses.join(" AND "), values)
+    }
+}
+
+/// The local history database. Opening it applies any pending migrations.
+pub struct HistoryDb {
+    conn: Connection,
+}
+
+impl HistoryDb {
+    /// Open (or create) `history/history.db` in the config directory.
+    pub fn open() -> Result<Self, AppError> {
+        let path = history_file(DB_FILE)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        Self::open_at(&path)
+    }
+
+    pub fn open_at(path: &Path) -> Result<Self, AppError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    /// A database that lives only as long as the handle, for tests.
+    pub fn open_in_memory() -> Result<Self, AppError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, AppError> {
+        let mut db = Self { conn };
+        db.migrate()?;
+        Ok(db)
+    }
+
+    /// Apply the migrations past the stored schema version, each in its
+    /// own transaction. A database from a newer tplc is refused.
+    fn migrate(&mut self) -> Result<(), AppError> {
+        let version: usize = self
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > MIGRATIONS.len() {
+            return Err(AppError::Database(format!(
+                "history database has schema version {}, this tplc knows up to {}",
+                version,
+                MIGRATIONS.len()
+            )));
+        }
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", index + 1)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Schema version after migrating.
+    pub fn schema_version(&self) -> Result<usize, AppError> {
+        Ok(self
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))?)
+    }
+
+    /// Insert or replace daily totals; a day synced again (e.g. today's
+    /// running total) keeps the latest value.
+    pub fn upsert_daily(&mut self, rows: &[DailyEnergy]) -> Result<(), AppError> {
+        let tx = self.conn.transaction()?;
+        for row in rows {
+            tx.execute(
+                "INSERT OR REPLACE INTO daily_energy (device_id, device, date, energy_wh)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    row.device_id,
+                    row.device,
+                    row.date.format("%Y-%m-%d").to_string(),
+                    row.energy_wh
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Insert or replace monthly totals.
+    pub fn upsert_monthly(&mut self, rows: &[MonthlyEnergy]) -> Result<(), AppError> {
+        let tx = self.conn.transaction()?;
+        for row in rows {
+            tx.execute(
+                "INSERT OR REPLACE INTO monthly_energy (device_id, device, month, energy_wh)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    row.device_id,
+                    row.device,
+                    format!("{:04}-{:02}", row.year, row.month),
+                    row.energy_wh
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Add events not stored yet and return how many were new. An event is
+    /// the same when device, time and event match, whatever its source.
+    pub fn insert_events(&mut self, records: &[ActivityRecord]) -> Result<usize, AppError> {
+        let tx = self.conn.transaction()?;
+        let mut added = 0;
+        for record in records {
+            added += tx.execute(
+                "INSERT OR IGNORE INTO events (time, device_id, device, event, source)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    record.time.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    record.device_id,
+                    record.device,
+                    record.event,
+                    record.source
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+
+    /// Time of the newest stored event for a device.
+    pub fn last_event_time(&self, device_id: &str) -> Result<Option<DateTime<Utc>>, AppError> {
+        let time: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT max(time) FROM events WHERE device_id = ?1",
+                [device_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+        Ok(time.and_then(|t| t.parse().ok()))
+    }
+
+    /// Stored daily totals, by device and then date.
+    pub fn daily(&self, filter: &HistoryFilter) -> Result<Vec<DailyEnergy>, AppError> {
+        let (condition, values) = filter.condition("date", 10);
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT device_id, device, date, energy_wh FROM daily_energy
+             WHERE {} ORDER BY device, date",
+            condition
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get::<_, String>(2)?,
+                row.get(3)?,
+            ))
+        })?;
+        let mut days = Vec::new();
+        for row in rows {
+            let (device_id, device, date, energy_wh) = row?;
+            if let Ok(date) = NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+                days.push(DailyEnergy {
+                    device_id,
+                    device,
+                    date,
+                    energy_wh,
+                });
+            }
+        }
+        Ok(days)
+    }
+
+    /// Stored monthly totals, by device and then month. The filter's dates
+    /// select whole months.
+    pub fn monthly(&self, filter: &HistoryFilter) -> Result<Vec<MonthlyEnergy>, AppError> {
+        let (condition, values) = filter.condition("month", 7);
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT device_id, device, month, energy_wh FROM monthly_energy
+             WHERE {} ORDER BY device, month",
+            condition
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get::<_, String>(2)?,
+                row.get(3)?,
+            ))
+        })?;
+        let mut months = Vec::new();
+        for row in rows {
+            let (device_id, device, month, energy_wh): (String, String, String, f64) = row?;
+            let Some((year, month)) = month.split_once('-') else {
+                continue;
+            };
+            if let (Ok(year), Ok(month)) = (year.parse(), month.parse()) {
+                months.push(MonthlyEnergy {
+                    device_id,
+                    device,
+                    year,
+                    month,
+                    energy_wh,
+                });
+            }
+        }
+        Ok(months)
+    }
+
+    /// Stored events, oldest first. The filter's dates are UTC days.
+    pub fn events(&self, filter: &HistoryFilter) -> Result<Vec<ActivityRecord>, AppError> {
+        let (condition, values) = filter.condition("time", 10);
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT time, device_id, device, event, source FROM events
+             WHERE {} ORDER BY time",
+            condition
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            ))
+        })?;
+        let mut events = Vec::new();
+        for row in rows {
+            let (time, device_id, device, event, source) = row?;
+            if let Ok(time) = time.parse() {
+                events.push(ActivityRecord {
+                    time,
+                    device_id,
+                    device,
+                    event,
+                    source,
+                });
+            }
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(date: &str, energy_wh: f64) -> DailyEnergy {
+        DailyEnergy {
+            device_id: "A".to_string(),
+            device: "Heater".to_string(),
+            date: date.parse().unwrap(),
+            energy_wh,
+        }
+    }
+
+    fn event(time: &str, event: &str, source: &str) -> ActivityRecord {
+        ActivityRecord {
+            time: time.parse().unwrap(),
+            device_id: "A".to_string(),
+            device: "Heater".to_string(),
+            event: event.to_string(),
+            source: source.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_migrations_applied_once() {
+        let mut db = HistoryDb::open_in_memory().unwrap();
+        assert_eq!(db.schema_version().unwrap(), MIGRATIONS.len());
+        db.migrate().unwrap();
+        assert_eq!(db.schema_version().unwrap(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_daily_upsert_and_filter() {
+        let mut db = HistoryDb::open_in_memory().unwrap();
+        db.upsert_daily(&[day("2026-01-31", 900.0), day("2026-02-01", 400.0)])
+            .unwrap();
+        // Today's running total grows between syncs
+        db.upsert_daily(&[day("2026-02-01", 1200.0)]).unwrap();
+
+        let all = db.daily(&HistoryFilter::default()).unwrap();
+        assert_eq!(
+            all,
+            vec![day("2026-01-31", 900.0), day("2026-02-01", 1200.0)]
+        );
+
+        let filter = HistoryFilter {
+            device: Some("heater".to_string()),
+            from: Some("2026-02-01".parse().unwrap()),
+            to: None,
+        };
+        assert_eq!(db.daily(&filter).unwrap(), vec![day("2026-02-01", 1200.0)]);
+        let other = HistoryFilter {
+            device: Some("Lamp".to_string()),
+            ..Default::default()
+        };
+        assert!(db.daily(&other).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_monthly_filter_selects_whole_months() {
+        let mut db = HistoryDb::open_in_memory().unwrap();
+        let month = |month, energy_wh| MonthlyEnergy {
+            device_id: "A".to_string(),
+            device: "Heater".to_string(),
+            year: 2026,
+            month,
+            energy_wh,
+        };
+        db.upsert_monthly(&[month(1, 30_000.0), month(2, 25_000.0), month(3, 20_000.0)])
+            .unwrap();
+        let filter = HistoryFilter {
+            device: None,
+            from: Some("2026-02-15".parse().unwrap()),
+            to: Some("2026-03-01".parse().unwrap()),
+        };
+        assert_eq!(
+            db.monthly(&filter).unwrap(),
+            vec![month(2, 25_000.0), month(3, 20_000.0)]
+        );
+    }
+
+    #[test]
+    fn test_events_deduplicated() {
+        let mut db = HistoryDb::open_in_memory().unwrap();
+        let first = [
+            event("2026-10-01T12:00:00Z", "on", "cloud"),
+            event("2026-10-01T13:00:00Z", "off", "local"),
+        ];
+        assert_eq!(db.insert_events(&first).unwrap(), 2);
+        let again = [
+            event("2026-10-01T13:00:00Z", "off", "cloud"),
+            event("2026-10-02T08:00:00Z", "offline", "local"),
+        ];
+        assert_eq!(db.insert_events(&again).unwrap(), 1);
+        assert_eq!(
+            db.last_event_time("A").unwrap(),
+            Some("2026-10-02T08:00:00Z".parse().unwrap())
+        );
+        let filter = HistoryFilter {
+            to: Some("2026-10-01".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(db.events(&filter).unwrap(), first);
+    }
+}
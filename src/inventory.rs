@@ -0,0 +1,460 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::cloud_type::CloudType;
+use crate::error::AppError;
+use crate::resolve::DeviceEntry;
+use crate::store::{DeviceTags, LocalAlias};
+
+/// A declared inventory of devices (`devices verify --expected`).
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Inventory {
+    #[serde(default, rename = "device")]
+    pub devices: Vec<ExpectedDevice>,
+}
+
+/// One expected device. Matched by `id` when given, otherwise by `alias`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExpectedDevice {
+    pub id: Option<String>,
+    pub alias: Option<String>,
+    /// Model prefix, e.g. "KP115" matches "KP115(US)"
+    pub model: Option<String>,
+    /// Informational only; echoed in the report
+    pub room: Option<String>,
+    /// Minimum firmware version, e.g. "1.0.12"
+    pub min_firmware: Option<String>,
+}
+
+impl ExpectedDevice {
+    fn label(&self) -> String {
+        self.alias
+            .clone()
+            .or_else(|| self.id.clone())
+            .unwrap_or_else(|| "(unnamed)".to_string())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Mismatch {
+    pub device: String,
+    pub device_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub room: Option<String>,
+    pub field: &'static str,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtraDevice {
+    pub device: String,
+    pub device_id: String,
+    pub model: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifyReport {
+    pub missing: Vec<String>,
+    pub extra: Vec<ExtraDevice>,
+    pub mismatched: Vec<Mismatch>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.mismatched.is_empty()
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} missing, {} extra, {} mismatched",
+            self.missing.len(),
+            self.extra.len(),
+            self.mismatched.len()
+        )
+    }
+}
+
+pub fn load_inventory(path: &Path) -> Result<Inventory, AppError> {
+    let contents = std::fs::read_to_string(path)?;
+    toml::from_str(&contents).map_err(|e| {
+        AppError::InvalidInput(format!("Invalid inventory file {}: {}", path.display(), e))
+    })
+}
+
+/// Leading dotted numbers of a firmware string ("1.0.12 Build 2109..." -> [1, 0, 12]).
+fn firmware_version(value: &str) -> Vec<u32> {
+    value
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+fn compare_firmware(actual: &str, minimum: &str) -> Ordering {
+    firmware_version(actual).cmp(&firmware_version(minimum))
+}
+
+/// Compare the account's devices against the declared inventory.
+pub fn verify(inventory: &Inventory, devices: &[DeviceEntry]) -> VerifyReport {
+    let mut report = VerifyReport::default();
+    let mut matched_parents: HashSet<String> = HashSet::new();
+
+    for expected in &inventory.devices {
+        let entry = match (&expected.id, &expected.alias) {
+            (Some(id), _) => devices.iter().find(|d| &d.full_id() == id),
+            (None, Some(alias)) => devices.iter().find(|d| d.alias() == alias),
+            (None, None) => None,
+        };
+        let Some(entry) = entry else {
+            report.missing.push(expected.label());
+            continue;
+        };
+
+        matched_parents.insert(entry.info.id().to_string());
+
+        let mut mismatch = |field: &'static str, expected_value: &str, actual: &str| {
+            report.mismatched.push(Mismatch {
+                device: entry.alias().to_string(),
+                device_id: entry.full_id(),
+                room: expected.room.clone(),
+                field,
+                expected: expected_value.to_string(),
+                actual: actual.to_string(),
+            });
+        };
+
+        if let (Some(_), Some(alias)) = (&expected.id, &expected.alias) {
+            if entry.alias() != alias {
+                mismatch("alias", alias, entry.alias());
+            }
+        }
+        if let Some(model) = &expected.model {
+            if !entry.info.model().starts_with(model.as_str()) {
+                mismatch("model", model, entry.info.model());
+            }
+        }
+        if let Some(minimum) = &expected.min_firmware {
+            let actual = entry.info.fw_ver.as_deref().unwrap_or("unknown");
+            if compare_firmware(actual, minimum) == Ordering::Less {
+                mismatch("firmware", &format!(">= {}", minimum), actual);
+            }
+        }
+    }
+
+    // Extra devices are reported whole: a strip counts as expected when the
+    // strip or any of its outlets is listed.
+    for entry in devices.iter().filter(|d| d.child_id.is_none()) {
+        if !matched_parents.contains(entry.info.id()) {
+            report.extra.push(ExtraDevice {
+                device: entry.alias().to_string(),
+                device_id: entry.full_id(),
+                model: entry.info.model().to_string(),
+            });
+        }
+    }
+
+    report
+}
+
+/// The local nicknames, tags, notes and hidden list an export reads.
+#[derive(Debug, Clone, Default)]
+pub struct LocalState {
+    pub aliases: BTreeMap<String, LocalAlias>,
+    pub tags: BTreeMap<String, DeviceTags>,
+    pub notes: BTreeMap<String, String>,
+    pub hidden: BTreeMap<String, String>,
+}
+
+/// One row of `devices export`: what the cloud reports about a device, plus
+/// the local nicknames, tags, note and hidden flag that `devices import`
+/// restores.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InventoryRecord {
+    pub device_id: String,
+    pub alias: String,
+    pub model: String,
+    pub mac: Option<String>,
+    pub firmware: Option<String>,
+    pub hardware: Option<String>,
+    pub region: Option<String>,
+    pub cloud: Option<CloudType>,
+    pub online: bool,
+    #[serde(default)]
+    pub nicknames: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+/// CSV columns of an export, in order.
+const CSV_COLUMNS: [&str; 13] = [
+    "device_id",
+    "alias",
+    "model",
+    "mac",
+    "firmware",
+    "hardware",
+    "region",
+    "cloud",
+    "online",
+    "nicknames",
+    "tags",
+    "note",
+    "hidden",
+];
+
+impl InventoryRecord {
+    pub fn from_entry(entry: &DeviceEntry, local: &LocalState) -> Self {
+        let device_id = entry.full_id();
+        Self {
+            nicknames: local
+                .aliases
+                .iter()
+                .filter(|(_, alias)| alias.device_id == device_id)
+                .map(|(name, _)| name.clone())
+                .collect(),
+            tags: local
+                .tags
+                .get(&device_id)
+                .map(|entry| entry.tags.iter().cloned().collect())
+                .unwrap_or_default(),
+            note: local.notes.get(&device_id).cloned(),
+            hidden: local.hidden.contains_key(&device_id),
+            alias: entry.alias().to_string(),
+            model: entry.info.model().to_string(),
+            mac: entry.info.device_mac.clone(),
+            firmware: entry.info.fw_ver.clone(),
+            hardware: entry.info.device_hw_ver.clone(),
+            region: entry.info.device_region.clone(),
+            cloud: entry.info.cloud_type,
+            online: entry.is_online(),
+            device_id,
+        }
+    }
+
+    fn csv_row(&self) -> Vec<String> {
+        let text = |value: &Option<String>| value.clone().unwrap_or_default();
+        vec![
+            self.device_id.clone(),
+            self.alias.clone(),
+            self.model.clone(),
+            text(&self.mac),
+            text(&self.firmware),
+            text(&self.hardware),
+            text(&self.region),
+            self.cloud.map(|c| c.to_string()).unwrap_or_default(),
+            self.online.to_string(),
+            self.nicknames.join("; "),
+            self.tags.join("; "),
+            text(&self.note),
+            self.hidden.to_string(),
+        ]
+    }
+
+    /// A record from CSV cells keyed by column name. Unknown columns are
+    /// ignored, so a spreadsheet with extra asset-tracking columns imports.
+    fn from_csv(cells: &BTreeMap<&str, String>) -> Self {
+        let text = |column: &str| cells.get(column).cloned().unwrap_or_default();
+        let optional = |column: &str| Some(text(column)).filter(|v| !v.is_empty());
+        let flag = |column: &str| text(column).eq_ignore_ascii_case("true");
+        let list = |column: &str| -> Vec<String> {
+            text(column)
+                .split(';')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        Self {
+            device_id: text("device_id"),
+            alias: text("alias"),
+            model: text("model"),
+            mac: optional("mac"),
+            firmware: optional("firmware"),
+            hardware: optional("hardware"),
+            region: optional("region"),
+            cloud: optional("cloud").and_then(|c| serde_json::from_value(c.into()).ok()),
+            online: flag("online"),
+            nicknames: list("nicknames"),
+            tags: list("tags"),
+            note: optional("note"),
+            hidden: flag("hidden"),
+        }
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Records as CSV with a header row.
+pub fn to_csv(records: &[InventoryRecord]) -> String {
+    let header = CSV_COLUMNS.iter().map(|c| c.to_string()).collect();
+    let mut out = String::new();
+    for row in std::iter::once(header).chain(records.iter().map(InventoryRecord::csv_row)) {
+        let fields: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Split CSV text into rows of fields. Quoted fields may hold commas,
+/// doubled quotes and line breaks.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            other => field.push(other),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows.retain(|row| row.iter().any(|field| !field.is_empty()));
+    rows
+}
+
+/// Read an export written by `devices export`: a JSON array, or CSV with a
+/// header row.
+pub fn load_export(path: &Path) -> Result<Vec<InventoryRecord>, AppError> {
+    let contents = std::fs::read_to_string(path)?;
+    let invalid = |e: String| {
+        AppError::InvalidInput(format!("Invalid export file {}: {}", path.display(), e))
+    };
+    if contents.trim_start().starts_with('[') {
+        return serde_json::from_str(&contents).map_err(|e| invalid(e.to_string()));
+    }
+    let mut rows = parse_csv(&contents).into_iter();
+    let header = rows.next().unwrap_or_default();
+    if !header.iter().any(|column| column == "device_id") {
+        return Err(invalid("no device_id column".to_string()));
+    }
+    Ok(rows
+        .map(|row| {
+            let cells = header.iter().map(String::as_str).zip(row).collect();
+            InventoryRecord::from_csv(&cells)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_firmware_version_compare() {
+        assert_eq!(
+            compare_firmware("1.0.12 Build 210914 Rel.103203", "1.0.9"),
+            Ordering::Greater
+        );
+        assert_eq!(compare_firmware("1.0.8", "1.0.9"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_verify_reports_differences() {
+        let inventory: Inventory = toml::from_str(
+            r#"
+            [[device]]
+            id = "AAA"
+            alias = "Kitchen"
+            model = "KP115"
+            min_firmware = "1.0.12"
+
+            [[device]]
+            alias = "Garage"
+            "#,
+        )
+        .unwrap();
+        let devices = vec![
+            DeviceEntry::test("AAA", "Kitchen Plug")
+                .with_model("KP115(US)")
+                .with_firmware("1.0.10 Build 1"),
+            DeviceEntry::test("BBB", "Porch").with_firmware("1.5.0"),
+        ];
+
+        let report = verify(&inventory, &devices);
+        assert_eq!(report.missing, vec!["Garage"]);
+        assert_eq!(report.extra.len(), 1);
+        assert_eq!(report.extra[0].device, "Porch");
+        let fields: Vec<&str> = report.mismatched.iter().map(|m| m.field).collect();
+        assert_eq!(fields, vec!["alias", "firmware"]);
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn test_export_csv_round_trip() {
+        let mut local = LocalState::default();
+        local.aliases.insert(
+            "fridge".to_string(),
+            LocalAlias {
+                device_id: "AAA".to_string(),
+                device_name: "Kitchen Plug".to_string(),
+            },
+        );
+        local.notes.insert(
+            "AAA".to_string(),
+            "Behind the fridge, \"left\" socket".to_string(),
+        );
+        local.tags.insert(
+            "AAA".to_string(),
+            DeviceTags {
+                device_name: "Kitchen Plug".to_string(),
+                tags: ["holiday".to_string(), "kitchen".to_string()].into(),
+            },
+        );
+        let record = InventoryRecord::from_entry(
+            &DeviceEntry::test("AAA", "Kitchen Plug")
+                .with_model("KP115(US)")
+                .with_firmware("1.0.10"),
+            &local,
+        );
+        assert_eq!(record.nicknames, vec!["fridge"]);
+        assert_eq!(record.tags, vec!["holiday", "kitchen"]);
+
+        let csv = to_csv(std::slice::from_ref(&record));
+        assert!(csv.starts_with("device_id,alias,model,mac,"));
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("inventory.csv");
+        std::fs::write(&path, &csv).unwrap();
+        assert_eq!(load_export(&path).unwrap(), vec![record.clone()]);
+
+        let path = dir.path().join("inventory.json");
+        std::fs::write(&path, serde_json::to_string(&vec![&record]).unwrap()).unwrap();
+        assert_eq!(load_export(&path).unwrap(), vec![record]);
+    }
+
+    #[test]
+    fn test_parse_csv_quotes() {
+        let rows = parse_csv("a,b\r\n\"x, y\",\"line\nbreak\"\n\n");
+        assert_eq!(rows, vec![vec!["a", "b"], vec!["x, y", "line\nbreak"]]);
+    }
+}
@@ -1,54 +1,260 @@
 pub mod api;
 pub mod auth;
+pub mod backup;
+#[cfg(feature = "cli")]
 pub mod cli;
+pub mod client;
 pub mod config;
+pub mod cron;
+pub mod duration;
 pub mod error;
+pub mod events;
+pub mod history;
+pub mod inventory;
+pub mod manifest;
+pub mod migrate;
 pub mod models;
+pub mod operation;
 pub mod resolve;
+pub mod store;
+pub mod support;
+pub mod timing;
+pub mod watch;
 
-use cli::output::print_error;
-use config::{OutputMode, RuntimeConfig};
-use error::AppError;
+pub use client::Client;
+pub use error::{AppError, ErrorCategory};
+pub use models::capability::{ColorLight, Dimmable, EnergyMonitor, Switchable};
+pub use models::device::Device;
 
+#[cfg(feature = "cli")]
+use api::cloud_type::CloudType;
+#[cfg(feature = "cli")]
+use auth::keychain;
+#[cfg(feature = "cli")]
+use auth::login::LoginClouds;
+#[cfg(feature = "cli")]
+use cli::output::{print_error, OUTPUT_SCHEMA};
+#[cfg(feature = "cli")]
+use config::{FileConfig, OutputMode, RuntimeConfig};
+
+#[cfg(feature = "cli")]
 pub async fn run(cli_args: cli::Cli) -> i32 {
+    if cli_args.profile_timing {
+        timing::enable();
+    }
+    api::retry::configure(api::retry::RetryPolicy {
+        retries: cli_args.retries,
+        delay: cli_args.retry_delay,
+    });
+
+    let file_config = match FileConfig::load(cli_args.config.as_deref()) {
+        Ok(file_config) => file_config,
+        Err(err) => {
+            print_error(&err);
+            return err.exit_code();
+        }
+    };
+
+    if !cli_args.quiet {
+        let flags = cli_args.deprecations().into_iter().map(|d| d.warning());
+        for warning in file_config.warnings.iter().cloned().chain(flags) {
+            eprintln!("Warning: {}", warning);
+        }
+    }
+
+    let manifest = match &file_config.manifest.path {
+        Some(path) => manifest::Manifest::load(path).map(|m| m.entries()),
+        None if file_config.manifest.only => Err(AppError::InvalidInput(
+            "[manifest] only = true needs a manifest path".into(),
+        )),
+        None => Ok(Vec::new()),
+    };
+    let manifest = match manifest {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            print_error(&err);
+            return err.exit_code();
+        }
+    };
+
+    if let Some(version) = cli_args.schema_version {
+        if version != OUTPUT_SCHEMA {
+            let err = AppError::InvalidInput(format!(
+                "Output schema {} is not available; this tplc writes schema {}",
+                version, OUTPUT_SCHEMA
+            ));
+            print_error(&err);
+            return err.exit_code();
+        }
+    }
+
+    // Precedence: flags > environment > config file > defaults
     let config = RuntimeConfig {
-        output_mode: if cli_args.table {
+        output_mode: if cli_args.wants_table() {
             OutputMode::Table
         } else {
-            OutputMode::Json
+            cli_args
+                .output
+                .or(file_config.output)
+                .unwrap_or(OutputMode::Json)
         },
+        fields: cli_args.fields,
+        format: cli_args.format,
+        envelope: cli_args.schema_version.is_some(),
+        quiet: cli_args.quiet,
+        color: cli_args.out.is_none() && cli::output::color_enabled(cli_args.no_color),
         verbose: cli_args.verbose,
+        device_id: cli_args.device_id,
+        regex: cli_args.regex,
+        display_zone: cli_args.tz.unwrap_or_default(),
+        price_per_kwh: file_config.energy.price,
+        currency: file_config
+            .energy
+            .currency
+            .unwrap_or_else(|| "USD".to_string()),
+        // A device both clouds list is taken from the one --cloud names
+        preferred_cloud: cli_args
+            .cloud
+            .or(file_config.cloud)
+            .unwrap_or(CloudType::Kasa),
+        only_cloud: cli_args.cloud,
+        default_device: std::env::var("TPLC_DEFAULT_DEVICE")
+            .ok()
+            .or(file_config.default_device),
+        cron_jobs: file_config.cron,
+        manifest,
+        manifest_only: file_config.manifest.only,
+        timeouts: file_config.timeouts.resolve(cli_args.timeout),
+        // Nothing records samples without the history feature
+        history: cfg!(feature = "history") && file_config.history.enabled,
+        force: cli_args.force,
+        verify: cli_args.verify,
+        include_hidden: cli_args.include_hidden,
+        assume_type: cli_args.assume_type,
+        tag: cli_args.tag,
+        device_cache: None,
+        capture: None,
     };
 
-    let result = dispatch(cli_args.command, &config).await;
+    api::http::configure_timeouts(config.timeouts);
+    if let Some(proxy) = cli_args.proxy.as_deref().or(file_config.proxy.as_deref()) {
+        if let Err(err) = api::http::configure_proxy(proxy) {
+            print_error(&err);
+            return err.exit_code();
+        }
+    }
+
+    if let Some(kind) = cli_args.token_store.or(file_config.token_store) {
+        match kind.open() {
+            Ok(store) => keychain::set_store(store.into()),
+            Err(err) => {
+                print_error(&err);
+                return err.exit_code();
+            }
+        }
+    }
+
+    let out_file = cli_args.out.clone();
+    if out_file.is_some() {
+        cli::output::redirect_to_file();
+    }
+
+    if let Some(dir) = cli_args.record.clone() {
+        api::transport::configure_fixtures(api::transport::Fixtures::Record(dir));
+    } else if let Some(dir) = cli_args.replay.clone() {
+        api::transport::configure_fixtures(api::transport::Fixtures::Replay(dir));
+    }
 
-    match result {
+    let result = {
+        let _span = timing::span("total");
+        async {
+            if api::transport::is_replaying() && !cli_args.ephemeral_auth {
+                cli::auth::replay_login()?;
+            } else if cli_args.ephemeral_auth {
+                // login/logout manage the in-memory tokens themselves
+                keychain::use_memory();
+                let needs_auth = !matches!(
+                    cli_args.command,
+                    cli::Commands::Login { .. }
+                        | cli::Commands::Logout
+                        | cli::Commands::Explain { .. }
+                );
+                if needs_auth {
+                    cli::auth::ephemeral_login(&config).await?;
+                }
+            }
+            dispatch(cli_args.command, &config).await
+        }
+        .await
+    };
+
+    let result = match (result, &out_file) {
+        (Ok(()), Some(path)) => cli::output::write_out_file(path),
+        (result, _) => result,
+    };
+    let code = match result {
         Ok(()) => 0,
         Err(err) => {
-            print_error(&err);
+            if !config.quiet {
+                print_error(&err);
+            }
             err.exit_code()
         }
-    }
+    };
+    timing::report();
+    code
 }
 
-async fn dispatch(command: cli::Commands, config: &RuntimeConfig) -> Result<(), AppError> {
+#[cfg(feature = "cli")]
+pub(crate) async fn dispatch(
+    command: cli::Commands,
+    config: &RuntimeConfig,
+) -> Result<(), AppError> {
+    if let Some(tag) = &config.tag {
+        return cli::tag::dispatch_tagged(command, tag, config).await;
+    }
     match command {
-        cli::Commands::Login => cli::auth::handle_login(config).await,
+        cli::Commands::Login {
+            tapo,
+            save_password,
+        } => {
+            let clouds = match config.only_cloud {
+                Some(CloudType::Kasa) => LoginClouds::Kasa,
+                Some(CloudType::Tapo) => LoginClouds::Tapo,
+                None => LoginClouds::Both,
+            };
+            cli::auth::handle_login(clouds, tapo, save_password, config).await
+        }
         cli::Commands::Logout => cli::auth::handle_logout(config).await,
-        cli::Commands::Status => cli::auth::handle_status(config).await,
+        cli::Commands::Auth(cmd) => cli::auth::handle_auth(&cmd, config).await,
+        cli::Commands::Status { check } => cli::auth::handle_status(check, config).await,
         cli::Commands::Devices(cmd) => cli::devices::handle(&cmd, config).await,
         cli::Commands::Power(cmd) => cli::power::handle(&cmd, config).await,
         cli::Commands::Energy(cmd) => cli::energy::handle(&cmd, config).await,
         cli::Commands::Light(cmd) => cli::light::handle(&cmd, config).await,
+        cli::Commands::Dimmer(cmd) => cli::dimmer::handle(&cmd, config).await,
+        cli::Commands::Sensor(cmd) => cli::sensor::handle(&cmd, config).await,
+        cli::Commands::Thermostat(cmd) => cli::thermostat::handle(&cmd, config).await,
+        cli::Commands::Camera(cmd) => cli::camera::handle(&cmd, config).await,
         cli::Commands::Schedule(cmd) => cli::schedule::handle(&cmd, config).await,
         cli::Commands::Info(cmd) => cli::info::handle(&cmd, config).await,
-        cli::Commands::Led { state, device } => {
-            let dev = resolve::resolve_device(&device, config.verbose).await?;
-            let on = matches!(state, cli::LedState::On);
-            dev.set_led_state(on).await?;
-            let state_str = if on { "on" } else { "off" };
-            cli::output::print_json(&serde_json::json!({"device": dev.alias(), "led": state_str}));
-            Ok(())
+        cli::Commands::Led(cmd) => cli::led::handle(&cmd, config).await,
+        cli::Commands::Events(cmd) => cli::events::handle(&cmd, config).await,
+        #[cfg(feature = "history")]
+        cli::Commands::History(cmd) => cli::history::handle(&cmd, config).await,
+        cli::Commands::Raw(args) => cli::raw::handle(&args, config).await,
+        cli::Commands::Explain { code } => cli::explain::handle(code, config).await,
+        cli::Commands::Alias(cmd) => cli::alias::handle(&cmd, config).await,
+        cli::Commands::Tag(cmd) => cli::tag::handle(&cmd, config).await,
+        cli::Commands::Shell => cli::shell::handle(config).await,
+        cli::Commands::Run(args) => cli::run::handle(&args, config).await,
+        cli::Commands::At(args) => cli::at::handle(&args, config).await,
+        cli::Commands::Backup(cmd) => cli::backup::handle(&cmd, config).await,
+        #[cfg(feature = "tui")]
+        cli::Commands::Dashboard(args) => cli::tui::handle(&args, config).await,
+        cli::Commands::Cron(cmd) => cli::cron::handle(&cmd, config).await,
+        cli::Commands::SupportBundle { file } => {
+            cli::support::handle(file.as_deref(), config).await
         }
     }
 }
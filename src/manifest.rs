@@ -0,0 +1,176 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::api::cloud_type::CloudType;
+use crate::error::AppError;
+use crate::models::device_info::DeviceInfo;
+use crate::models::device_type::DeviceType;
+use crate::resolve::DeviceEntry;
+use crate::store;
+
+/// `[manifest]` in config.toml.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ManifestConfig {
+    /// Manifest file; relative paths are inside the config directory
+    pub path: Option<PathBuf>,
+    /// Use only the manifest and never list devices from the cloud
+    pub only: bool,
+}
+
+/// Devices declared up front so they can be resolved without listing every
+/// device from the cloud.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default, rename = "device")]
+    pub devices: Vec<ManifestDevice>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestDevice {
+    pub id: String,
+    pub alias: String,
+    /// Model as the cloud reports it, e.g. "HS300(US)"
+    pub model: String,
+    #[serde(default = "default_cloud")]
+    pub cloud: CloudType,
+    /// Per-device API server (`appServerUrl` in `devices get`); the account's
+    /// regional URL is used when omitted
+    pub app_server_url: Option<String>,
+    #[serde(default)]
+    pub children: Vec<ManifestChild>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestChild {
+    /// Full child ID or the two-digit outlet index
+    pub id: String,
+    pub alias: String,
+}
+
+fn default_cloud() -> CloudType {
+    CloudType::Kasa
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self, AppError> {
+        let path = if path.is_relative() {
+            store::config_dir()?.join(path)
+        } else {
+            path.to_path_buf()
+        };
+        let contents = std::fs::read_to_string(&path)?;
+        toml::from_str(&contents).map_err(|e| {
+            AppError::InvalidInput(format!("Invalid manifest {}: {}", path.display(), e))
+        })
+    }
+
+    /// Device list entries for every declared device and outlet, in file
+    /// order with each device's outlets after it. Manifest devices are
+    /// assumed to be online.
+    pub fn entries(&self) -> Vec<DeviceEntry> {
+        let mut entries = Vec::new();
+        for device in &self.devices {
+            let info = DeviceInfo {
+                device_id: Some(device.id.clone()),
+                alias: Some(device.alias.clone()),
+                device_model: Some(device.model.clone()),
+                app_server_url: device.app_server_url.clone(),
+                status: Some(1),
+                cloud_type: Some(device.cloud),
+                ..Default::default()
+            };
+            let device_type = DeviceType::from_model(&device.model);
+            entries.push(DeviceEntry {
+                info: info.clone(),
+                device_type,
+                child_alias: None,
+                child_id: None,
+            });
+            for child in &device.children {
+                entries.push(DeviceEntry {
+                    info: info.clone(),
+                    device_type: device_type.child_type(),
+                    child_alias: Some(child.alias.clone()),
+                    child_id: Some(child.id.clone()),
+                });
+            }
+        }
+        entries
+    }
+}
+
+/// A manifest entry matching `name_or_id` exactly by alias or ID, then by
+/// alias ignoring case. Partial and fuzzy matching is left to the cloud list.
+pub fn find<'a>(entries: &'a [DeviceEntry], name_or_id: &str) -> Option<&'a DeviceEntry> {
+    entries
+        .iter()
+        .find(|d| d.alias() == name_or_id)
+        .or_else(|| {
+            entries
+                .iter()
+                .find(|d| d.child_id.is_none() && d.info.id() == name_or_id)
+        })
+        .or_else(|| entries.iter().find(|d| d.full_id() == name_or_id))
+        .or_else(|| {
+            entries
+                .iter()
+                .find(|d| d.alias().eq_ignore_ascii_case(name_or_id))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARENT_ID: &str = "8006ABCDEF0123456789ABCDEF0123456789ABCD";
+
+    fn manifest() -> Manifest {
+        toml::from_str(&format!(
+            r#"
+            [[device]]
+            id = "{PARENT_ID}"
+            alias = "Office Strip"
+            model = "HS300(US)"
+            app_server_url = "https://use1-wap.tplinkcloud.com"
+            children = [{{ id = "00", alias = "Monitor" }}, {{ id = "01", alias = "Printer" }}]
+
+            [[device]]
+            id = "802A00000000000000000000000000000000000B"
+            alias = "Desk Plug"
+            model = "P110"
+            cloud = "tapo"
+            "#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_entries() {
+        let entries = manifest().entries();
+        let aliases: Vec<&str> = entries.iter().map(|e| e.alias()).collect();
+        assert_eq!(
+            aliases,
+            vec!["Office Strip", "Monitor", "Printer", "Desk Plug"]
+        );
+        assert_eq!(entries[2].full_id(), format!("{}01", PARENT_ID));
+        assert_eq!(entries[3].info.cloud_type, Some(CloudType::Tapo));
+        assert!(entries.iter().all(|e| e.is_online()));
+    }
+
+    #[test]
+    fn test_find() {
+        let entries = manifest().entries();
+        assert_eq!(find(&entries, "printer").unwrap().alias(), "Printer");
+        assert_eq!(find(&entries, PARENT_ID).unwrap().alias(), "Office Strip");
+        assert_eq!(
+            find(&entries, &format!("{}00", PARENT_ID)).unwrap().alias(),
+            "Monitor"
+        );
+        assert!(find(&entries, "Desk").is_none());
+    }
+}
@@ -0,0 +1,170 @@
+use toml::{Table, Value};
+
+use crate::error::AppError;
+
+/// Schema version of config.toml understood by this release. Files without
+/// a `version` key are treated as version 1.
+pub const CONFIG_VERSION: i64 = 1;
+
+/// A flag or config key that was replaced. The old form keeps working, with
+/// a warning, until a later release removes it.
+#[derive(Debug, Clone, Copy)]
+pub struct Deprecation {
+    pub old: &'static str,
+    pub new: &'static str,
+}
+
+impl Deprecation {
+    pub fn warning(&self) -> String {
+        format!(
+            "`{}` is deprecated and will be removed in a future release; use `{}` instead",
+            self.old, self.new
+        )
+    }
+}
+
+/// Deprecated flags, matched by `Cli::deprecations`.
+pub const FLAGS: &[Deprecation] = &[Deprecation {
+    old: "--table",
+    new: "--output table",
+}];
+
+/// Renamed config.toml keys, as dotted paths (`energy.price` is `price` in
+/// `[energy]`). The value moves to the new key before the file is read.
+pub const CONFIG_KEYS: &[Deprecation] = &[];
+
+/// The deprecation entry for an old flag spelling.
+pub fn flag(old: &str) -> Option<&'static Deprecation> {
+    FLAGS.iter().find(|d| d.old == old)
+}
+
+/// Bring a parsed config.toml up to date: refuse files from a newer release
+/// and move renamed keys. Returns a warning for each key moved.
+pub fn migrate_config(table: &mut Table) -> Result<Vec<String>, AppError> {
+    migrate_config_with(table, CONFIG_KEYS)
+}
+
+fn migrate_config_with(
+    table: &mut Table,
+    renames: &[Deprecation],
+) -> Result<Vec<String>, AppError> {
+    if let Some(version) = table.get("version") {
+        match version.as_integer() {
+            Some(v) if (1..=CONFIG_VERSION).contains(&v) => {}
+            Some(v) if v > CONFIG_VERSION => {
+                return Err(AppError::InvalidInput(format!(
+                    "config.toml is version {}, but this tplc reads up to version {}; upgrade tplc",
+                    v, CONFIG_VERSION
+                )))
+            }
+            _ => {
+                return Err(AppError::InvalidInput(format!(
+                    "config.toml version must be a number from 1 to {}",
+                    CONFIG_VERSION
+                )))
+            }
+        }
+    }
+
+    let mut warnings = Vec::new();
+    for rename in renames {
+        let Some(value) = take(table, rename.old) else {
+            continue;
+        };
+        if get(table, rename.new).is_some() {
+            return Err(AppError::InvalidInput(format!(
+                "config.toml sets both `{}` and `{}`; remove `{}`",
+                rename.old, rename.new, rename.old
+            )));
+        }
+        insert(table, rename.new, value)?;
+        warnings.push(format!("config.toml: {}", rename.warning()));
+    }
+    Ok(warnings)
+}
+
+fn get<'a>(table: &'a Table, path: &str) -> Option<&'a Value> {
+    let (parents, key) = split(path);
+    let mut table = table;
+    for part in parents {
+        table = table.get(part)?.as_table()?;
+    }
+    table.get(key)
+}
+
+fn take(table: &mut Table, path: &str) -> Option<Value> {
+    let (parents, key) = split(path);
+    let mut table = table;
+    for part in parents {
+        table = table.get_mut(part)?.as_table_mut()?;
+    }
+    table.remove(key)
+}
+
+fn insert(table: &mut Table, path: &str, value: Value) -> Result<(), AppError> {
+    let (parents, key) = split(path);
+    let mut table = table;
+    for part in parents {
+        table = table
+            .entry(part)
+            .or_insert_with(|| Value::Table(Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| {
+                AppError::InvalidInput(format!("config.toml: `{}` is not a table", part))
+            })?;
+    }
+    table.insert(key.to_string(), value);
+    Ok(())
+}
+
+fn split(path: &str) -> (Vec<&str>, &str) {
+    let mut parts: Vec<&str> = path.split('.').collect();
+    let key = parts.pop().unwrap_or_default();
+    (parts, key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RENAMES: &[Deprecation] = &[
+        Deprecation {
+            old: "price",
+            new: "energy.price",
+        },
+        Deprecation {
+            old: "history.record",
+            new: "history.enabled",
+        },
+    ];
+
+    #[test]
+    fn test_migrate_config_moves_renamed_keys() {
+        let mut table: Table = toml::from_str("price = 0.31\n[history]\nrecord = true\n").unwrap();
+        let warnings = migrate_config_with(&mut table, RENAMES).unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("`energy.price`"));
+        assert_eq!(get(&table, "energy.price"), Some(&Value::Float(0.31)));
+        assert_eq!(get(&table, "history.enabled"), Some(&Value::Boolean(true)));
+        assert!(table.get("price").is_none());
+
+        let mut both: Table = toml::from_str("price = 1.0\n[energy]\nprice = 2.0\n").unwrap();
+        assert!(migrate_config_with(&mut both, RENAMES).is_err());
+
+        let mut current: Table = toml::from_str("[energy]\nprice = 2.0\n").unwrap();
+        assert!(migrate_config_with(&mut current, RENAMES)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_migrate_config_version() {
+        let mut table: Table = toml::from_str("version = 1").unwrap();
+        assert!(migrate_config(&mut table).is_ok());
+        let mut newer: Table =
+            toml::from_str(&format!("version = {}", CONFIG_VERSION + 1)).unwrap();
+        assert!(migrate_config(&mut newer).is_err());
+        let mut invalid: Table = toml::from_str("version = \"2\"").unwrap();
+        assert!(migrate_config(&mut invalid).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+use serde::Serialize;
+
+/// Privacy, recording and motion detection settings of a camera. Each is
+/// `None` when the camera did not answer for it.
+#[derive(Debug, Clone, Default, Serialize)]
+#[non_exhaustive]
+pub struct CameraStatus {
+    /// The lens is covered (Tapo) or the camera is switched off (Kasa)
+    pub privacy: Option<bool>,
+    /// Continuous or motion-triggered recording is scheduled
+    pub recording: Option<bool>,
+    pub motion_detection: Option<bool>,
+}
+
+/// A camera setting's `enabled` or `value` field: "on"/"off" strings on both
+/// families, plain booleans on some Tapo firmware.
+pub fn setting_enabled(response: &serde_json::Value) -> Option<bool> {
+    let value = response.get("enabled").or_else(|| response.get("value"))?;
+    match value {
+        serde_json::Value::Bool(on) => Some(*on),
+        serde_json::Value::String(text) => match text.as_str() {
+            "on" => Some(true),
+            "off" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_setting_enabled() {
+        assert_eq!(setting_enabled(&json!({"enabled": "on"})), Some(true));
+        assert_eq!(setting_enabled(&json!({"value": "off"})), Some(false));
+        assert_eq!(setting_enabled(&json!({"enabled": true})), Some(true));
+        assert_eq!(setting_enabled(&json!({"enabled": "auto"})), None);
+        assert_eq!(setting_enabled(&json!({"err_code": -1})), None);
+    }
+}
@@ -0,0 +1,225 @@
+use futures::future::BoxFuture;
+
+use crate::error::AppError;
+use crate::models::device::Device;
+use crate::models::energy::EmeterRealtime;
+use crate::models::registry::Capabilities;
+
+/// Anything that can be switched on and off: plugs, outlets, switches and
+/// lights. `Device::as_switchable` and friends hand these out so a mixed
+/// collection can be driven without matching on `DeviceType`.
+pub trait Switchable: Send + Sync {
+    /// Name shown in the Kasa or Tapo app.
+    fn name(&self) -> &str;
+
+    fn turn_on(&self) -> BoxFuture<'_, Result<(), AppError>>;
+
+    fn turn_off(&self) -> BoxFuture<'_, Result<(), AppError>>;
+
+    /// `None` when the device's response has no power state.
+    fn is_on(&self) -> BoxFuture<'_, Result<Option<bool>, AppError>>;
+}
+
+/// Lights and dimmer switches.
+pub trait Dimmable: Switchable {
+    /// Set the brightness (0-100), turning the device on.
+    fn set_brightness(&self, brightness: u8) -> BoxFuture<'_, Result<(), AppError>>;
+
+    /// Current brightness (0-100); lights that are off report the level they
+    /// will turn on at.
+    fn brightness(&self) -> BoxFuture<'_, Result<Option<u8>, AppError>>;
+}
+
+/// Bulbs and light strips with adjustable color.
+pub trait ColorLight: Dimmable {
+    /// Set hue (0-360) and saturation (0-100), keeping the brightness.
+    fn set_hue_saturation(&self, hue: u16, saturation: u8) -> BoxFuture<'_, Result<(), AppError>>;
+
+    /// Set a white color temperature in kelvin, within the model's range.
+    fn set_color_temp(&self, kelvin: u16) -> BoxFuture<'_, Result<(), AppError>>;
+}
+
+/// Plugs and outlets that measure their power draw.
+pub trait EnergyMonitor: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn emeter_realtime(&self) -> BoxFuture<'_, Result<EmeterRealtime, AppError>>;
+}
+
+impl Device {
+    /// This device as something that switches on and off. Every device
+    /// except a hub, hub sensors, radiator valves and cameras qualifies; a
+    /// power strip's parent switches all its outlets. For a model missing
+    /// from models.json, the accessors below only see what `capabilities()`
+    /// has already detected.
+    pub fn as_switchable(&self) -> Option<&dyn Switchable> {
+        let caps = self.known();
+        let hub = self.device_type.category() == "hub";
+        (!hub && !caps.sensor && !caps.thermostat && !caps.camera).then_some(self as _)
+    }
+
+    /// This device as a light or dimmer switch.
+    pub fn as_dimmable(&self) -> Option<&dyn Dimmable> {
+        let caps = self.known();
+        (caps.light || caps.dimmer).then_some(self as _)
+    }
+
+    /// This device as a color light.
+    pub fn as_color_light(&self) -> Option<&dyn ColorLight> {
+        self.known().light.then_some(self as _)
+    }
+
+    /// This device as an energy monitor.
+    pub fn as_energy_monitor(&self) -> Option<&dyn EnergyMonitor> {
+        self.known().emeter.then_some(self as _)
+    }
+
+    fn known(&self) -> Capabilities {
+        self.cached_capabilities().unwrap_or_default()
+    }
+}
+
+impl Switchable for Device {
+    fn name(&self) -> &str {
+        self.alias()
+    }
+
+    fn turn_on(&self) -> BoxFuture<'_, Result<(), AppError>> {
+        Box::pin(async move { self.power_on().await.map(drop) })
+    }
+
+    fn turn_off(&self) -> BoxFuture<'_, Result<(), AppError>> {
+        Box::pin(async move { self.power_off().await.map(drop) })
+    }
+
+    fn is_on(&self) -> BoxFuture<'_, Result<Option<bool>, AppError>> {
+        Box::pin(Device::is_on(self))
+    }
+}
+
+impl Dimmable for Device {
+    fn set_brightness(&self, brightness: u8) -> BoxFuture<'_, Result<(), AppError>> {
+        Box::pin(async move { Device::set_brightness(self, brightness).await.map(drop) })
+    }
+
+    fn brightness(&self) -> BoxFuture<'_, Result<Option<u8>, AppError>> {
+        Box::pin(Device::brightness(self))
+    }
+}
+
+impl ColorLight for Device {
+    fn set_hue_saturation(&self, hue: u16, saturation: u8) -> BoxFuture<'_, Result<(), AppError>> {
+        Box::pin(async move { self.set_color(hue, saturation, None).await.map(drop) })
+    }
+
+    fn set_color_temp(&self, kelvin: u16) -> BoxFuture<'_, Result<(), AppError>> {
+        Box::pin(async move { Device::set_color_temp(self, kelvin, None).await.map(drop) })
+    }
+}
+
+impl EnergyMonitor for Device {
+    fn name(&self) -> &str {
+        self.alias()
+    }
+
+    fn emeter_realtime(&self) -> BoxFuture<'_, Result<EmeterRealtime, AppError>> {
+        Box::pin(Device::emeter_realtime(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::transport::MockTransport;
+    use crate::models::device_type::DeviceType;
+
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        let caps = |d: &Device| {
+            (
+                d.as_switchable().is_some(),
+                d.as_dimmable().is_some(),
+                d.as_color_light().is_some(),
+                d.as_energy_monitor().is_some(),
+            )
+        };
+        assert_eq!(
+            caps(&Device::test(DeviceType::HS100, None)),
+            (true, false, false, false)
+        );
+        assert_eq!(
+            caps(&Device::test(DeviceType::HS220, None)),
+            (true, true, false, false)
+        );
+        assert_eq!(
+            caps(&Device::test(DeviceType::KL430, None)),
+            (true, true, true, false)
+        );
+        assert_eq!(
+            caps(&Device::test(DeviceType::P110, None)),
+            (true, false, false, true)
+        );
+        assert!(Device::test(DeviceType::HS300, None)
+            .as_switchable()
+            .is_some());
+        assert!(Device::test(DeviceType::H100, None)
+            .as_switchable()
+            .is_none());
+        assert_eq!(
+            caps(&Device::test(DeviceType::HS300Child, Some("00"))),
+            (true, false, false, true)
+        );
+        assert_eq!(
+            caps(&Device::test(DeviceType::T310, Some("8022A1"))),
+            (false, false, false, false)
+        );
+        assert!(Device::test(DeviceType::KE100, Some("8022B7"))
+            .as_switchable()
+            .is_none());
+        assert!(Device::test(DeviceType::TapoCam, None)
+            .as_switchable()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_model_capabilities_from_sys_info() {
+        let transport = MockTransport::new(&[(
+            "/",
+            r#"{"error_code":0,"result":{"responseData":"{\"system\":{\"get_sysinfo\":{\"model\":\"HS230(US)\",\"dev_name\":\"Smart Wi-Fi Dimmer\",\"feature\":\"TIM\",\"brightness\":40}}}"}}"#,
+        )]);
+        let dimmer = Device::test(DeviceType::Unknown, None).with_transport(transport.clone());
+        assert!(dimmer.as_dimmable().is_none());
+
+        let caps = dimmer.capabilities().await.unwrap();
+        assert!(caps.dimmer && !caps.light && !caps.emeter);
+        assert!(dimmer.as_dimmable().is_some());
+        assert!(dimmer.as_energy_monitor().is_none());
+
+        // Detected once, then reused
+        dimmer.capabilities().await.unwrap();
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_model_power_tries_relay_then_light() {
+        // Every request gets this answer, so sysinfo shows no light and the
+        // relay request goes unanswered
+        let transport = MockTransport::new(&[(
+            "/",
+            r#"{"error_code":0,"result":{"responseData":"{\"system\":{\"get_sysinfo\":{\"model\":\"XY1(US)\"}}}"}}"#,
+        )]);
+        let device = Device::test(DeviceType::Unknown, None).with_transport(transport.clone());
+        device.turn_on().await.unwrap();
+
+        let requests = transport.requests.lock().unwrap();
+        let sent: Vec<String> = requests
+            .iter()
+            .map(|(_, body)| body["params"]["requestData"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(sent.len(), 3);
+        assert!(sent[0].contains("get_sysinfo"));
+        assert!(sent[1].contains("set_relay_state"));
+        assert!(sent[2].contains("transition_light_state"));
+    }
+}
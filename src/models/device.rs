@@ -1,18 +1,81 @@
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
 use serde_json::json;
 
 use crate::api::device_client::DeviceClient;
 use crate::error::AppError;
-use crate::models::device_info::DeviceInfo;
+use crate::models::camera::{setting_enabled, CameraStatus};
+use crate::models::device_info::{child_full_id, DeviceInfo};
 use crate::models::device_type::DeviceType;
+use crate::models::energy::{months_in_range, DayPowerSummary, EmeterRealtime, MonthPowerSummary};
+use crate::models::light_state::{LightChange, TurnOnBehavior};
+use crate::models::registry::{Capabilities, EmeterUnits, Quirks, DEFAULT_LIGHT_SERVICE};
+use crate::models::schedule::{rules_from_json, ScheduleRule};
+use crate::models::sensor::{last_trigger, SensorReading};
+use crate::models::sys_info::{ChildInfo, SysInfo};
+use crate::models::thermostat::ThermostatState;
+use crate::models::time::DeviceTimezone;
+use crate::models::timezone::{timezone_by_index, TimezoneEntry};
+use crate::operation::{Operation, Progress};
+
+/// Passthrough service for Kasa dimmer switch settings.
+const DIMMER_SERVICE: &str = "smartlife.iot.dimmer";
+
+/// Passthrough service for the one-shot countdown timer of plugs and switches.
+const COUNTDOWN_SERVICE: &str = "count_down";
 
-const LIGHTING_SERVICE: &str = "smartlife.iot.smartbulb.lightingservice";
+/// Passthrough services of Kasa KC cameras.
+const KASA_CAM_SWITCH_SERVICE: &str = "smartlife.cam.ipcamera.switch";
+const KASA_CAM_MOTION_SERVICE: &str = "smartlife.cam.ipcamera.motionDetect";
+const KASA_CAM_RECORD_SERVICE: &str = "smartlife.cam.ipcamera.vod";
+
+/// Shortest pause between brightness steps of a fade.
+const FADE_MIN_STEP: Duration = Duration::from_millis(500);
+/// How long each half of an `identify` flash lasts.
+const IDENTIFY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Number of steps for a fade over `distance` units within `duration`, one
+/// per unit but at most one every `FADE_MIN_STEP`, and the pause between
+/// them.
+fn fade_steps(distance: u32, duration: Duration) -> (u32, Duration) {
+    let max_steps = (duration.as_millis() / FADE_MIN_STEP.as_millis()).max(1) as u32;
+    let steps = distance.clamp(1, max_steps);
+    (steps, duration / steps)
+}
+
+/// The `day_list` or `month_list` of an emeter statistics answer.
+fn stat_list<'a>(data: Option<&'a serde_json::Value>, key: &str) -> &'a [serde_json::Value] {
+    data.and_then(|d| d.get(key))
+        .and_then(|v| v.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+/// A passthrough answer the device did not act on: an error, no answer, or
+/// a nonzero `err_code` such as -1 "module not support".
+fn refused(response: &Result<Option<serde_json::Value>, AppError>) -> bool {
+    match response {
+        Ok(Some(value)) => value
+            .get("err_code")
+            .and_then(|v| v.as_i64())
+            .is_some_and(|code| code != 0),
+        Ok(None) | Err(_) => true,
+    }
+}
 
+#[derive(Clone)]
 pub struct Device {
     client: DeviceClient,
     pub device_id: String,
     pub info: DeviceInfo,
     pub device_type: DeviceType,
     pub child_id: Option<String>,
+    /// Send requests even when the cloud lists the device as offline.
+    force: bool,
+    /// Capabilities inferred from sysinfo for a model missing from models.json.
+    detected: Arc<OnceLock<Capabilities>>,
 }
 
 impl Device {
@@ -29,13 +92,111 @@ impl Device {
             info,
             device_type,
             child_id,
+            force: false,
+            detected: Arc::default(),
         }
     }
 
+    /// Allow requests to a device the cloud lists as offline (`--force`).
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// A Kasa device with ID "8006AB" that sends nowhere until given a
+    /// transport, for tests.
+    #[cfg(test)]
+    pub fn test(device_type: DeviceType, child_id: Option<&str>) -> Self {
+        let client = DeviceClient::new(
+            "https://example.com",
+            "token",
+            "term",
+            false,
+            crate::api::cloud_type::CloudType::Kasa,
+        )
+        .unwrap();
+        Self::new(
+            client,
+            "8006AB".into(),
+            DeviceInfo::default(),
+            device_type,
+            child_id.map(String::from),
+        )
+    }
+
+    /// Send requests through `transport` instead of the default one.
+    #[cfg(test)]
+    pub fn with_transport(
+        mut self,
+        transport: Arc<dyn crate::api::transport::HttpTransport>,
+    ) -> Self {
+        self.client = self.client.with_transport(transport);
+        self
+    }
+
+    /// Cloud endpoint the device's requests go to.
+    pub fn host(&self) -> &str {
+        self.client.host()
+    }
+
+    /// The cloud reports the device as disconnected.
+    pub fn is_offline(&self) -> bool {
+        self.info.status == Some(0)
+    }
+
     pub fn alias(&self) -> &str {
         self.info.alias_or_name()
     }
 
+    /// Device ID, or the full child ID for an outlet.
+    pub fn full_id(&self) -> String {
+        match &self.child_id {
+            Some(child_id) => child_full_id(&self.device_id, child_id),
+            None => self.device_id.clone(),
+        }
+    }
+
+    /// Protocol quirks for this device's model and hardware version.
+    pub fn quirks(&self) -> Quirks {
+        self.device_type
+            .spec()
+            .quirks_for(self.info.device_hw_ver.as_deref())
+    }
+
+    /// What this device can do. Known models answer from models.json. For an
+    /// unknown model the first call reads its sysinfo and infers them, and
+    /// later calls, including the `as_*` accessors, reuse the result.
+    pub async fn capabilities(&self) -> Result<Capabilities, AppError> {
+        if let Some(caps) = self.cached_capabilities() {
+            return Ok(caps);
+        }
+        let sys_info = self.get_sys_info().await?.unwrap_or_default();
+        Ok(*self
+            .detected
+            .get_or_init(|| Capabilities::from_sys_info(&sys_info)))
+    }
+
+    /// Capabilities known without a request: the model's, or those already
+    /// detected for an unknown model.
+    pub(crate) fn cached_capabilities(&self) -> Option<Capabilities> {
+        if self.device_type == DeviceType::Unknown {
+            self.detected.get().copied()
+        } else {
+            Some(self.device_type.spec().capabilities)
+        }
+    }
+
+    /// Emeter units reported by this hardware, if known.
+    pub fn emeter_units(&self) -> Option<EmeterUnits> {
+        self.quirks().emeter_units
+    }
+
+    fn light_service(&self) -> String {
+        self.quirks()
+            .light_service
+            .unwrap_or_else(|| DEFAULT_LIGHT_SERVICE.to_string())
+    }
+
     /// Build and send a passthrough request, handling child device context.
     async fn passthrough(
         &self,
@@ -43,23 +204,12 @@ impl Device {
         sub_request_type: &str,
         request: serde_json::Value,
     ) -> Result<Option<serde_json::Value>, AppError> {
-        let mut request_data = json!({
+        let request_data = json!({
             request_type: {
                 sub_request_type: request,
             }
         });
-
-        // Inject child context if this is a child device
-        if let Some(ref child_id) = self.child_id {
-            request_data["context"] = json!({
-                "child_ids": [child_id]
-            });
-        }
-
-        let response = self
-            .client
-            .passthrough(&self.device_id, request_data)
-            .await?;
+        let response = self.send(request_data, self.child_id.as_deref()).await?;
 
         if let Some(response_data) = response {
             // Navigate to the sub-request response
@@ -85,37 +235,117 @@ impl Device {
         Ok(None)
     }
 
+    /// Send a request as-is, e.g. `{"system":{"get_sysinfo":null}}`, and
+    /// return the whole response. `child_id` (full ID or two-digit index)
+    /// addresses an outlet or other child; by default this device's own.
+    pub async fn raw(
+        &self,
+        request_data: serde_json::Value,
+        child_id: Option<&str>,
+    ) -> Result<Option<serde_json::Value>, AppError> {
+        let child_id = child_id
+            .map(|id| child_full_id(&self.device_id, id))
+            .or_else(|| self.child_id.clone());
+        self.send(request_data, child_id.as_deref()).await
+    }
+
+    /// The cloud can only relay to connected devices; fail now rather than
+    /// after its timeout, unless `--force` was given.
+    fn check_reachable(&self) -> Result<(), AppError> {
+        if self.is_offline() && !self.force {
+            return Err(AppError::DeviceOffline {
+                message: format!("{} (use --force to try anyway)", self.alias()),
+                error_code: None,
+                device: Some(self.alias().to_string()),
+            });
+        }
+        Ok(())
+    }
+
+    /// Send passthrough request data, adding the child context when
+    /// `child_id` is set.
+    async fn send(
+        &self,
+        mut request_data: serde_json::Value,
+        child_id: Option<&str>,
+    ) -> Result<Option<serde_json::Value>, AppError> {
+        self.check_reachable()?;
+
+        if let Some(child_id) = child_id {
+            request_data["context"] = json!({
+                "child_ids": [child_id]
+            });
+        }
+
+        self.client
+            .passthrough(&self.device_id, request_data)
+            .await
+            .map_err(|e| e.with_device(self.alias()))
+    }
+
     // -- Power operations --
 
     pub async fn power_on(&self) -> Result<Option<serde_json::Value>, AppError> {
-        if self.device_type.is_light() {
-            self.passthrough(
-                LIGHTING_SERVICE,
-                "transition_light_state",
-                json!({"on_off": 1}),
-            )
-            .await
-        } else {
-            self.passthrough("system", "set_relay_state", json!({"state": 1}))
-                .await
-        }
+        self.set_power(true).await
     }
 
     pub async fn power_off(&self) -> Result<Option<serde_json::Value>, AppError> {
-        if self.device_type.is_light() {
-            self.passthrough(
-                LIGHTING_SERVICE,
+        self.set_power(false).await
+    }
+
+    /// Switch the relay, or the light state of a light. A model missing from
+    /// models.json whose sysinfo shows no light gets the relay first, and the
+    /// light service when the relay request is refused.
+    async fn set_power(&self, on: bool) -> Result<Option<serde_json::Value>, AppError> {
+        self.require_power_switch().await?;
+        let state = i32::from(on);
+        // Only an unknown model can fail here, when even sysinfo is refused
+        let light = self.capabilities().await.is_ok_and(|caps| caps.light);
+        if light {
+            return self
+                .passthrough(
+                    &self.light_service(),
+                    "transition_light_state",
+                    json!({"on_off": state}),
+                )
+                .await;
+        }
+        let relay = self
+            .passthrough("system", "set_relay_state", json!({"state": state}))
+            .await;
+        if self.device_type != DeviceType::Unknown || !refused(&relay) {
+            return relay;
+        }
+        let light = self
+            .passthrough(
+                &self.light_service(),
                 "transition_light_state",
-                json!({"on_off": 0}),
+                json!({"on_off": state}),
             )
-            .await
+            .await;
+        if refused(&light) {
+            relay
         } else {
-            self.passthrough("system", "set_relay_state", json!({"state": 0}))
-                .await
+            light
         }
     }
 
+    /// Cameras, hub sensors and radiator valves have no relay to switch.
+    async fn require_power_switch(&self) -> Result<(), AppError> {
+        let Ok(caps) = self.capabilities().await else {
+            return Ok(());
+        };
+        if caps.camera || caps.sensor || caps.thermostat {
+            return Err(AppError::UnsupportedOperation(format!(
+                "{} has no power switch",
+                self.device_type.display_name()
+            )));
+        }
+        Ok(())
+    }
+
     pub async fn toggle(&self) -> Result<Option<serde_json::Value>, AppError> {
+        self.require_power_switch().await?;
         match self.is_on().await? {
             Some(true) => self.power_off().await,
             Some(false) => self.power_on().await,
@@ -127,26 +357,68 @@ impl Device {
     }
 
     pub async fn is_on(&self) -> Result<Option<bool>, AppError> {
-        let sys_info = self.get_sys_info().await?;
-        if let Some(info) = sys_info {
-            if self.device_type.is_light() {
-                // Light devices use light_state.on_off
-                if let Some(light_state) = info.get("light_state") {
-                    return Ok(light_state
-                        .get("on_off")
-                        .and_then(|v| v.as_i64())
-                        .map(|v| v == 1));
+        Ok(self
+            .get_sys_info()
+            .await?
+            .and_then(|info| self.power_state(&info)))
+    }
+
+    /// Poll the power state every `interval` and report each change as
+    /// progress ("on", "off" or "unknown"; `done` counts changes). Runs until
+    /// cancelled, which ends it with `Ok`. Failed polls are skipped.
+    pub fn watch_power(&self, interval: Duration) -> Operation<()> {
+        let device = self.clone();
+        Operation::spawn(move |mut ctx| async move {
+            let mut last = None;
+            let mut changes = 0;
+            loop {
+                if let Ok(state) = device.is_on().await {
+                    if last != Some(state) {
+                        last = Some(state);
+                        changes += 1;
+                        ctx.report(Progress {
+                            done: changes,
+                            total: None,
+                            message: match state {
+                                Some(true) => "on",
+                                Some(false) => "off",
+                                None => "unknown",
+                            }
+                            .to_string(),
+                        });
+                    }
+                }
+                if ctx.sleep(interval).await.is_err() {
+                    return Ok(());
                 }
             }
-            if self.child_id.is_some() {
-                return Ok(info.get("state").and_then(|v| v.as_i64()).map(|v| v == 1));
+        })
+    }
+
+    /// Power state from an already fetched sysinfo.
+    pub fn power_state(&self, sys_info: &serde_json::Value) -> Option<bool> {
+        let caps = self
+            .cached_capabilities()
+            .unwrap_or_else(|| Capabilities::from_sys_info(sys_info));
+        if caps.light {
+            // Light devices use light_state.on_off
+            if let Some(light_state) = sys_info.get("light_state") {
+                return light_state
+                    .get("on_off")
+                    .and_then(|v| v.as_i64())
+                    .map(|v| v == 1);
             }
-            return Ok(info
-                .get("relay_state")
+        }
+        if self.child_id.is_some() {
+            return sys_info
+                .get("state")
                 .and_then(|v| v.as_i64())
-                .map(|v| v == 1));
+                .map(|v| v == 1);
         }
-        Ok(None)
+        sys_info
+            .get("relay_state")
+            .and_then(|v| v.as_i64())
+            .map(|v| v == 1)
     }
 
     // -- System info --
@@ -155,6 +427,13 @@ impl Device {
         self.passthrough("system", "get_sysinfo", json!(null)).await
     }
 
+    /// `get_sys_info`, typed.
+    pub async fn sys_info(&self) -> Result<SysInfo, AppError> {
+        Ok(SysInfo::from_json(
+            &self.get_sys_info().await?.unwrap_or_default(),
+        ))
+    }
+
     // -- LED --
 
     pub async fn set_led_state(&self, on: bool) -> Result<Option<serde_json::Value>, AppError> {
@@ -164,10 +443,96 @@ impl Device {
             .await
     }
 
+    /// Flash the device so it can be found: a light switches off and on
+    /// again, anything else blinks its LED (the relay is never switched).
+    /// Progress counts flashes. The light or LED is left as it was, also
+    /// when cancelled mid-flash.
+    pub fn identify(&self, times: u32) -> Operation<()> {
+        let device = self.clone();
+        Operation::spawn(move |mut ctx| async move {
+            let light = device.capabilities().await?.light;
+            let info = device.get_sys_info().await?.unwrap_or_default();
+            let was_on = if light {
+                device.power_state(&info)
+            } else {
+                SysInfo::from_json(&info).led_on
+            }
+            .unwrap_or(true);
+            let device = &device;
+            let set = |on: bool| async move {
+                if light {
+                    device
+                        .set_light_state(Some(i32::from(on)), None, None, None, None, Some(0))
+                        .await
+                } else {
+                    device.set_led_state(on).await
+                }
+            };
+
+            let flashed = async {
+                for flash in 1..=times {
+                    ctx.check()?;
+                    set(!was_on).await?;
+                    ctx.sleep(IDENTIFY_INTERVAL).await?;
+                    set(was_on).await?;
+                    ctx.report(Progress {
+                        done: flash,
+                        total: Some(times),
+                        message: format!("flash {}", flash),
+                    });
+                    if flash < times {
+                        ctx.sleep(IDENTIFY_INTERVAL).await?;
+                    }
+                }
+                Ok(())
+            }
+            .await;
+            if flashed.is_err() {
+                let _ = set(was_on).await;
+            }
+            flashed
+        })
+    }
+
+    // -- Physical button --
+
+    async fn require_button_lock(&self) -> Result<(), AppError> {
+        if self.capabilities().await?.button_lock {
+            Ok(())
+        } else {
+            Err(AppError::UnsupportedOperation(format!(
+                "{} does not support disabling the physical button",
+                self.device_type.display_name()
+            )))
+        }
+    }
+
+    /// Whether the physical button is disabled, from sysinfo `child_protection`.
+    pub async fn is_button_locked(&self) -> Result<Option<bool>, AppError> {
+        self.require_button_lock().await?;
+        Ok(self
+            .get_sys_info()
+            .await?
+            .and_then(|info| info.get("child_protection").and_then(|v| v.as_bool())))
+    }
+
+    pub async fn set_button_lock(
+        &self,
+        locked: bool,
+    ) -> Result<Option<serde_json::Value>, AppError> {
+        self.require_button_lock().await?;
+        self.passthrough(
+            "system",
+            "set_device_info",
+            json!({"child_protection": locked}),
+        )
+        .await
+    }
+
     // -- Energy monitoring --
 
     pub async fn get_power_usage_realtime(&self) -> Result<Option<serde_json::Value>, AppError> {
-        if !self.device_type.has_emeter() {
+        if !self.capabilities().await?.emeter {
             return Err(AppError::UnsupportedOperation(format!(
                 "{} does not support energy monitoring",
                 self.device_type.display_name()
@@ -177,12 +542,20 @@ impl Device {
             .await
     }
 
+    /// `get_power_usage_realtime`, typed and in milli-units.
+    pub async fn emeter_realtime(&self) -> Result<EmeterRealtime, AppError> {
+        Ok(EmeterRealtime::from_json(
+            &self.get_power_usage_realtime().await?.unwrap_or_default(),
+            self.emeter_units(),
+        ))
+    }
+
     pub async fn get_power_usage_day(
         &self,
         year: i32,
         month: u32,
     ) -> Result<Option<serde_json::Value>, AppError> {
-        if !self.device_type.has_emeter() {
+        if !self.capabilities().await?.emeter {
             return Err(AppError::UnsupportedOperation(format!(
                 "{} does not support energy monitoring",
                 self.device_type.display_name()
@@ -200,7 +573,7 @@ impl Device {
         &self,
         year: i32,
     ) -> Result<Option<serde_json::Value>, AppError> {
-        if !self.device_type.has_emeter() {
+        if !self.capabilities().await?.emeter {
             return Err(AppError::UnsupportedOperation(format!(
                 "{} does not support energy monitoring",
                 self.device_type.display_name()
@@ -210,19 +583,153 @@ impl Device {
             .await
     }
 
+    /// `get_power_usage_day`, typed: one summary per day with data.
+    pub async fn daily_usage(
+        &self,
+        year: i32,
+        month: u32,
+    ) -> Result<Vec<DayPowerSummary>, AppError> {
+        let data = self.get_power_usage_day(year, month).await?;
+        Ok(stat_list(data.as_ref(), "day_list")
+            .iter()
+            .map(|d| DayPowerSummary::from_json(d, self.emeter_units()))
+            .collect())
+    }
+
+    /// Daily summaries from `from` to `to` (inclusive), from one
+    /// `get_daystat` request per month the range touches.
+    pub async fn daily_usage_range(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<DayPowerSummary>, AppError> {
+        let mut days = Vec::new();
+        for (year, month) in months_in_range(from, to) {
+            for mut day in self.daily_usage(year, month).await? {
+                day.year.get_or_insert(year);
+                day.month.get_or_insert(month);
+                if day.date().is_some_and(|date| date >= from && date <= to) {
+                    days.push(day);
+                }
+            }
+        }
+        Ok(days)
+    }
+
+    /// `get_power_usage_month`, typed: one summary per month with data.
+    pub async fn monthly_usage(&self, year: i32) -> Result<Vec<MonthPowerSummary>, AppError> {
+        let data = self.get_power_usage_month(year).await?;
+        Ok(stat_list(data.as_ref(), "month_list")
+            .iter()
+            .map(|m| MonthPowerSummary::from_json(m, self.emeter_units()))
+            .collect())
+    }
+
+    // -- Dimmer settings (HS220, KS220) --
+
+    async fn require_dimmer(&self) -> Result<(), AppError> {
+        if self.capabilities().await?.dimmer {
+            Ok(())
+        } else {
+            Err(AppError::UnsupportedOperation(format!(
+                "{} is not a dimmer switch",
+                self.device_type.display_name()
+            )))
+        }
+    }
+
+    /// Fade, gentle on/off and ramp settings (times in milliseconds).
+    pub async fn get_dimmer_parameters(&self) -> Result<Option<serde_json::Value>, AppError> {
+        self.require_dimmer().await?;
+        self.passthrough(DIMMER_SERVICE, "get_dimmer_parameters", json!({}))
+            .await
+    }
+
+    pub async fn set_fade_on_time(&self, ms: u64) -> Result<Option<serde_json::Value>, AppError> {
+        self.require_dimmer().await?;
+        self.passthrough(DIMMER_SERVICE, "set_fade_on_time", json!({"fadeTime": ms}))
+            .await
+    }
+
+    pub async fn set_fade_off_time(&self, ms: u64) -> Result<Option<serde_json::Value>, AppError> {
+        self.require_dimmer().await?;
+        self.passthrough(DIMMER_SERVICE, "set_fade_off_time", json!({"fadeTime": ms}))
+            .await
+    }
+
+    pub async fn set_gentle_on_time(&self, ms: u64) -> Result<Option<serde_json::Value>, AppError> {
+        self.require_dimmer().await?;
+        self.passthrough(
+            DIMMER_SERVICE,
+            "set_gentle_on_time",
+            json!({"duration": ms}),
+        )
+        .await
+    }
+
+    pub async fn set_gentle_off_time(
+        &self,
+        ms: u64,
+    ) -> Result<Option<serde_json::Value>, AppError> {
+        self.require_dimmer().await?;
+        self.passthrough(
+            DIMMER_SERVICE,
+            "set_gentle_off_time",
+            json!({"duration": ms}),
+        )
+        .await
+    }
+
+    // -- Power protection (Tapo energy-monitoring plugs) --
+
+    async fn require_power_protection(&self) -> Result<(), AppError> {
+        let caps = self.capabilities().await?;
+        if caps.tapo && caps.emeter {
+            Ok(())
+        } else {
+            Err(AppError::UnsupportedOperation(format!(
+                "{} does not support power protection",
+                self.device_type.display_name()
+            )))
+        }
+    }
+
+    pub async fn get_power_protection(&self) -> Result<Option<serde_json::Value>, AppError> {
+        self.require_power_protection().await?;
+        self.passthrough("emeter", "get_protection_power", json!({}))
+            .await
+    }
+
+    /// Enable or disable overload protection; `max_watts` sets the trip threshold.
+    pub async fn set_power_protection(
+        &self,
+        enabled: bool,
+        max_watts: Option<u32>,
+    ) -> Result<Option<serde_json::Value>, AppError> {
+        self.require_power_protection().await?;
+        let mut params = json!({"enabled": enabled});
+        if let Some(watts) = max_watts {
+            params["protection_power"] = json!(watts);
+        }
+        self.passthrough("emeter", "set_protection_power", params)
+            .await
+    }
+
     // -- Light operations --
 
     pub async fn get_light_state(&self) -> Result<Option<serde_json::Value>, AppError> {
-        if !self.device_type.is_light() {
+        if !self.capabilities().await?.light {
             return Err(AppError::UnsupportedOperation(format!(
                 "{} is not a light device",
                 self.device_type.display_name()
             )));
         }
-        self.passthrough(LIGHTING_SERVICE, "get_light_state", json!({}))
+        self.passthrough(&self.light_service(), "get_light_state", json!({}))
             .await
     }
 
+    /// Send a light state change; `None` fields are left unchanged. See
+    /// `light()` for a builder.
     pub async fn set_light_state(
         &self,
         on_off: Option<i32>,
@@ -232,12 +739,36 @@ impl Device {
         color_temp: Option<u16>,
         transition_period: Option<u32>,
     ) -> Result<Option<serde_json::Value>, AppError> {
-        if !self.device_type.is_light() {
+        if !self.capabilities().await?.light {
             return Err(AppError::UnsupportedOperation(format!(
                 "{} is not a light device",
                 self.device_type.display_name()
             )));
         }
+        if let (Some(v), Some((min, max))) = (brightness, self.device_type.spec().ranges.brightness)
+        {
+            if !(min..=max).contains(&(v as u16)) {
+                return Err(AppError::InvalidInput(format!(
+                    "{} supports brightness {}-{}",
+                    self.device_type.display_name(),
+                    min,
+                    max
+                )));
+            }
+        }
+        // 0 switches a color light from white to hue/saturation
+        if let (Some(v @ 1..), Some((min, max))) =
+            (color_temp, self.device_type.spec().ranges.color_temp)
+        {
+            if !(min..=max).contains(&v) {
+                return Err(AppError::InvalidInput(format!(
+                    "{} supports color temperatures {}-{}K",
+                    self.device_type.display_name(),
+                    min,
+                    max
+                )));
+            }
+        }
         let mut state = serde_json::Map::new();
         if let Some(v) = on_off {
             state.insert("on_off".into(), json!(v));
@@ -258,21 +789,194 @@ impl Device {
             state.insert("transition_period".into(), json!(v));
         }
         self.passthrough(
-            LIGHTING_SERVICE,
+            &self.light_service(),
             "transition_light_state",
             serde_json::Value::Object(state),
         )
         .await
     }
 
+    async fn require_light(&self) -> Result<(), AppError> {
+        if self.capabilities().await?.light {
+            Ok(())
+        } else {
+            Err(AppError::UnsupportedOperation(format!(
+                "{} is not a light device",
+                self.device_type.display_name()
+            )))
+        }
+    }
+
+    /// How the light comes on after a power cut (`hard_on`) and when switched
+    /// on remotely (`soft_on`). `None` where the bulb reports a mode tplc
+    /// does not know.
+    pub async fn default_behavior(
+        &self,
+    ) -> Result<(Option<TurnOnBehavior>, Option<TurnOnBehavior>), AppError> {
+        self.require_light().await?;
+        let response = self
+            .passthrough(&self.light_service(), "get_default_behavior", json!({}))
+            .await?
+            .unwrap_or_default();
+        let entry = |key: &str| response.get(key).and_then(TurnOnBehavior::from_json);
+        Ok((entry("hard_on"), entry("soft_on")))
+    }
+
+    /// Change how the light comes on; `None` leaves that case as it is.
+    pub async fn set_default_behavior(
+        &self,
+        hard_on: Option<&TurnOnBehavior>,
+        soft_on: Option<&TurnOnBehavior>,
+    ) -> Result<Option<serde_json::Value>, AppError> {
+        self.require_light().await?;
+        let mut params = serde_json::Map::new();
+        if let Some(behavior) = hard_on {
+            params.insert("hard_on".into(), behavior.to_json());
+        }
+        if let Some(behavior) = soft_on {
+            params.insert("soft_on".into(), behavior.to_json());
+        }
+        self.passthrough(
+            &self.light_service(),
+            "set_default_behavior",
+            serde_json::Value::Object(params),
+        )
+        .await
+    }
+
+    /// Change several light settings in one request, e.g.
+    /// `light().brightness(40).temp(2700).transition(secs(2)).apply()`.
+    pub fn light(&self) -> LightChange<'_> {
+        LightChange::new(self)
+    }
+
     pub async fn set_brightness(
         &self,
         brightness: u8,
     ) -> Result<Option<serde_json::Value>, AppError> {
+        if self.capabilities().await?.dimmer {
+            return self
+                .passthrough(
+                    DIMMER_SERVICE,
+                    "set_brightness",
+                    json!({"brightness": brightness}),
+                )
+                .await;
+        }
         self.set_light_state(Some(1), Some(brightness), None, None, None, None)
             .await
     }
 
+    /// Current brightness (0-100) of a light or dimmer switch. Lights that are
+    /// off report the brightness they will turn on at.
+    pub async fn brightness(&self) -> Result<Option<u8>, AppError> {
+        let value = if self.capabilities().await?.dimmer {
+            self.get_sys_info()
+                .await?
+                .and_then(|info| info.get("brightness").cloned())
+        } else {
+            self.get_light_state().await?.and_then(|state| {
+                state
+                    .get("brightness")
+                    .or_else(|| state.pointer("/dft_on_state/brightness"))
+                    .cloned()
+            })
+        };
+        Ok(value.and_then(|v| v.as_u64()).map(|v| v.min(100) as u8))
+    }
+
+    /// Step the brightness of a light or dimmer to `target` over
+    /// `duration`, at most two requests a second. Progress counts steps.
+    pub fn fade_brightness(&self, target: u8, duration: Duration) -> Operation<()> {
+        let device = self.clone();
+        Operation::spawn(move |mut ctx| async move {
+            let caps = device.capabilities().await?;
+            if !caps.light && !caps.dimmer {
+                return Err(AppError::UnsupportedOperation(format!(
+                    "{} has no brightness to fade",
+                    device.device_type.display_name()
+                )));
+            }
+            let start = device.brightness().await?.unwrap_or(0);
+            let distance = (i32::from(target) - i32::from(start)).unsigned_abs();
+            let (steps, pause) = fade_steps(distance, duration);
+            for step in 1..=steps {
+                ctx.check()?;
+                let level = i32::from(start)
+                    + (i32::from(target) - i32::from(start)) * step as i32 / steps as i32;
+                device.set_brightness(level as u8).await?;
+                ctx.report(Progress {
+                    done: step,
+                    total: Some(steps),
+                    message: format!("brightness {}", level),
+                });
+                if step < steps {
+                    ctx.sleep(pause).await?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Ramp a light to `target` brightness over `duration`, and its white
+    /// color temperature from `temps.0` to `temps.1` kelvin if given: a
+    /// sunrise or sunset. Starts from `from`, or the current brightness (1
+    /// if the light is off). Steps are at least half a second apart; progress
+    /// counts them.
+    pub fn ramp(
+        &self,
+        from: Option<u8>,
+        target: u8,
+        temps: Option<(u16, u16)>,
+        duration: Duration,
+    ) -> Operation<()> {
+        let device = self.clone();
+        Operation::spawn(move |mut ctx| async move {
+            if !device.capabilities().await?.light {
+                return Err(AppError::UnsupportedOperation(format!(
+                    "{} is not a light device",
+                    device.device_type.display_name()
+                )));
+            }
+            let start = match from {
+                Some(level) => level,
+                None if device.is_on().await? == Some(true) => {
+                    device.brightness().await?.unwrap_or(1)
+                }
+                None => 1,
+            };
+            let brightness_distance = (i32::from(target) - i32::from(start)).unsigned_abs();
+            // 25K is about the smallest color temperature change you can see
+            let temp_distance = temps.map_or(0, |(from, to)| {
+                (i32::from(to) - i32::from(from)).unsigned_abs() / 25
+            });
+            let (steps, pause) = fade_steps(brightness_distance.max(temp_distance), duration);
+            let between =
+                |from: i32, to: i32, step: u32| from + (to - from) * step as i32 / steps as i32;
+            for step in 0..=steps {
+                ctx.check()?;
+                let level = between(i32::from(start), i32::from(target), step) as u8;
+                let mut change = device.light().brightness(level);
+                let mut message = format!("brightness {}", level);
+                if let Some((from, to)) = temps {
+                    let kelvin = between(i32::from(from), i32::from(to), step) as u16;
+                    change = change.temp(kelvin);
+                    message.push_str(&format!(", {}K", kelvin));
+                }
+                change.apply().await?;
+                ctx.report(Progress {
+                    done: step,
+                    total: Some(steps),
+                    message,
+                });
+                if step < steps {
+                    ctx.sleep(pause).await?;
+                }
+            }
+            Ok(())
+        })
+    }
+
     pub async fn set_color(
         &self,
         hue: u16,
@@ -299,12 +1003,49 @@ impl Device {
             .await
     }
 
+    // -- Countdown timer --
+
+    /// Switch on or off after `delay_secs`, run by the device itself. A
+    /// device keeps a single countdown, so any existing one is replaced.
+    pub async fn set_countdown(
+        &self,
+        delay_secs: u64,
+        on: bool,
+    ) -> Result<Option<serde_json::Value>, AppError> {
+        if self.capabilities().await?.light {
+            return Err(AppError::UnsupportedOperation(format!(
+                "{} has no countdown timer",
+                self.device_type.display_name()
+            )));
+        }
+        self.passthrough(COUNTDOWN_SERVICE, "delete_all_rules", json!({}))
+            .await?;
+        self.passthrough(
+            COUNTDOWN_SERVICE,
+            "add_rule",
+            json!({
+                "enable": 1,
+                "delay": delay_secs,
+                "act": if on { 1 } else { 0 },
+                "name": "tplc at",
+            }),
+        )
+        .await
+    }
+
     // -- Schedules --
 
     pub async fn get_schedule_rules(&self) -> Result<Option<serde_json::Value>, AppError> {
         self.passthrough("schedule", "get_rules", json!({})).await
     }
 
+    /// `get_schedule_rules`, typed.
+    pub async fn schedule_rules(&self) -> Result<Vec<ScheduleRule>, AppError> {
+        Ok(rules_from_json(
+            &self.get_schedule_rules().await?.unwrap_or_default(),
+        ))
+    }
+
     pub async fn add_schedule_rule(
         &self,
         rule: serde_json::Value,
@@ -346,49 +1087,312 @@ impl Device {
         self.passthrough("time", "get_timezone", json!({})).await
     }
 
+    /// The device's configured timezone, if it reports a known index.
+    pub async fn timezone_entry(&self) -> Result<Option<&'static TimezoneEntry>, AppError> {
+        Ok(self
+            .get_timezone()
+            .await?
+            .and_then(|tz| DeviceTimezone::from_json(&tz).index)
+            .and_then(timezone_by_index))
+    }
+
+    /// Set the device clock and timezone index in one call.
+    pub async fn set_timezone(
+        &self,
+        index: i32,
+        local_time: NaiveDateTime,
+    ) -> Result<Option<serde_json::Value>, AppError> {
+        self.passthrough(
+            "time",
+            "set_timezone",
+            json!({
+                "year": local_time.year(),
+                "month": local_time.month(),
+                "mday": local_time.day(),
+                "hour": local_time.hour(),
+                "min": local_time.minute(),
+                "sec": local_time.second(),
+                "index": index,
+            }),
+        )
+        .await
+    }
+
     // -- Children --
 
     pub async fn get_children(&self) -> Result<Vec<ChildInfo>, AppError> {
-        if !self.device_type.has_children() {
+        if !self.capabilities().await?.children {
             return Ok(vec![]);
         }
+        if self.device_type.category() == "hub" {
+            return Ok(self
+                .hub_child_list()
+                .await?
+                .iter()
+                .map(ChildInfo::from_json)
+                .collect());
+        }
+        Ok(self.sys_info().await?.children)
+    }
 
-        let sys_info = self.get_sys_info().await?;
-        let mut children = Vec::new();
-
-        if let Some(info) = sys_info {
-            if let Some(children_arr) = info.get("children").and_then(|v| v.as_array()) {
-                for child in children_arr {
-                    let child_id = child
-                        .get("id")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or_default()
-                        .to_string();
-                    let alias = child
-                        .get("alias")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or_default()
-                        .to_string();
-                    let state = child
-                        .get("state")
-                        .and_then(|v| v.as_i64())
-                        .map(|v| v as i32);
-                    children.push(ChildInfo {
-                        id: child_id,
-                        alias,
-                        state,
-                    });
-                }
+    /// A hub's `child_device_list`: one entry per paired sensor or valve with
+    /// its latest readings. Sent to the hub itself, even from a child's handle.
+    async fn hub_child_list(&self) -> Result<Vec<serde_json::Value>, AppError> {
+        let response = self
+            .send(json!({"system": {"get_child_device_list": {}}}), None)
+            .await?;
+        Ok(response
+            .as_ref()
+            .and_then(|r| r.pointer("/system/get_child_device_list/child_device_list"))
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    /// This child's entry in its hub's `child_device_list`.
+    async fn hub_child_entry(&self, child_id: &str) -> Result<serde_json::Value, AppError> {
+        self.hub_child_list()
+            .await?
+            .into_iter()
+            .find(|child| child.get("device_id").and_then(|v| v.as_str()) == Some(child_id))
+            .ok_or_else(|| AppError::DeviceNotFound(self.alias().to_string()))
+    }
+
+    // -- Hub sensors --
+
+    /// Latest readings of a hub sensor (T310, T315, T100, T110), with the
+    /// time of the last motion or open/close event for sensors that log them.
+    pub async fn sensor_reading(&self) -> Result<SensorReading, AppError> {
+        let child_id = match &self.child_id {
+            Some(child_id) if self.device_type.is_sensor() => child_id,
+            _ => {
+                return Err(AppError::UnsupportedOperation(format!(
+                    "{} is not a hub sensor",
+                    self.device_type.display_name()
+                )))
             }
+        };
+        let entry = self.hub_child_entry(child_id).await?;
+
+        let mut reading = SensorReading::from_json(&entry);
+        if reading.has_events() {
+            // Missing logs only leave `last_triggered` empty
+            if let Ok(Some(logs)) = self
+                .passthrough(
+                    "system",
+                    "get_trigger_logs",
+                    json!({"page_size": 1, "start_id": 0}),
+                )
+                .await
+            {
+                reading.last_triggered = last_trigger(&logs);
+            }
+        }
+        Ok(reading)
+    }
+
+    // -- Radiator valves (KE100) --
+
+    fn thermostat_child_id(&self) -> Result<&str, AppError> {
+        match &self.child_id {
+            Some(child_id) if self.device_type.is_thermostat() => Ok(child_id),
+            _ => Err(AppError::UnsupportedOperation(format!(
+                "{} is not a radiator valve",
+                self.device_type.display_name()
+            ))),
         }
+    }
 
-        Ok(children)
+    /// Target and current temperature, mode, child lock and battery of a
+    /// radiator valve, from its hub's child device list.
+    pub async fn thermostat_state(&self) -> Result<ThermostatState, AppError> {
+        let child_id = self.thermostat_child_id()?;
+        Ok(ThermostatState::from_json(
+            &self.hub_child_entry(child_id).await?,
+        ))
+    }
+
+    /// Set the temperature the valve heats to, in the unit it is set to.
+    /// Also turns frost protection off, as the Kasa app does.
+    pub async fn set_target_temperature(
+        &self,
+        temperature: f64,
+    ) -> Result<Option<serde_json::Value>, AppError> {
+        let (min, max) = self.thermostat_state().await?.target_range;
+        if !(min..=max).contains(&temperature) {
+            return Err(AppError::InvalidInput(format!(
+                "Target temperature must be between {} and {}",
+                min, max
+            )));
+        }
+        self.passthrough(
+            "system",
+            "set_device_info",
+            json!({"target_temp": temperature, "frost_protection_on": false}),
+        )
+        .await
+    }
+
+    /// Turn heating on (follow the target temperature) or off (frost
+    /// protection only).
+    pub async fn set_heating(&self, on: bool) -> Result<Option<serde_json::Value>, AppError> {
+        self.thermostat_child_id()?;
+        self.passthrough(
+            "system",
+            "set_device_info",
+            json!({"frost_protection_on": !on}),
+        )
+        .await
+    }
+
+    /// Disable or enable the buttons on the valve.
+    pub async fn set_thermostat_child_lock(
+        &self,
+        locked: bool,
+    ) -> Result<Option<serde_json::Value>, AppError> {
+        self.thermostat_child_id()?;
+        self.passthrough(
+            "system",
+            "set_device_info",
+            json!({"child_protection": locked}),
+        )
+        .await
+    }
+
+    // -- Cameras (Kasa KC, Tapo C) --
+
+    async fn require_camera(&self) -> Result<(), AppError> {
+        if self.capabilities().await?.camera {
+            Ok(())
+        } else {
+            Err(AppError::UnsupportedOperation(format!(
+                "{} is not a camera",
+                self.device_type.display_name()
+            )))
+        }
+    }
+
+    /// Privacy mode, recording and motion detection. Only the privacy request
+    /// has to succeed; the others are left empty if the camera refuses them.
+    pub async fn camera_status(&self) -> Result<CameraStatus, AppError> {
+        self.require_camera().await?;
+        let enabled =
+            |response: Option<serde_json::Value>| response.as_ref().and_then(setting_enabled);
+        if self.capabilities().await?.tapo {
+            let privacy = self
+                .passthrough("lens_mask", "get_lens_mask_info", json!({}))
+                .await?;
+            let recording = self
+                .passthrough("record_plan", "get_record_plan", json!({}))
+                .await;
+            let motion = self
+                .passthrough("motion_detection", "get_motion_detection", json!({}))
+                .await;
+            Ok(CameraStatus {
+                privacy: enabled(privacy),
+                recording: recording.ok().and_then(enabled),
+                motion_detection: motion.ok().and_then(enabled),
+            })
+        } else {
+            let switch = self
+                .passthrough(KASA_CAM_SWITCH_SERVICE, "get_is_enable", json!({}))
+                .await?;
+            let recording = self
+                .passthrough(KASA_CAM_RECORD_SERVICE, "get_is_enable", json!({}))
+                .await;
+            let motion = self
+                .passthrough(KASA_CAM_MOTION_SERVICE, "get_is_enable", json!({}))
+                .await;
+            Ok(CameraStatus {
+                // A switched-off Kasa camera is its privacy mode
+                privacy: enabled(switch).map(|on| !on),
+                recording: recording.ok().and_then(enabled),
+                motion_detection: motion.ok().and_then(enabled),
+            })
+        }
+    }
+
+    /// Cover the lens (Tapo) or switch the camera off (Kasa) while `on`.
+    pub async fn set_privacy_mode(&self, on: bool) -> Result<Option<serde_json::Value>, AppError> {
+        self.require_camera().await?;
+        let state = |enabled: bool| if enabled { "on" } else { "off" };
+        if self.capabilities().await?.tapo {
+            self.passthrough(
+                "lens_mask",
+                "set_lens_mask_info",
+                json!({"enabled": state(on)}),
+            )
+            .await
+        } else {
+            self.passthrough(
+                KASA_CAM_SWITCH_SERVICE,
+                "set_is_enable",
+                json!({"value": state(!on)}),
+            )
+            .await
+        }
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct ChildInfo {
-    pub id: String,
-    pub alias: String,
-    pub state: Option<i32>,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fade_steps() {
+        // One step per unit when there is time for it
+        assert_eq!(
+            fade_steps(10, Duration::from_secs(30)),
+            (10, Duration::from_secs(3))
+        );
+        // At most one step every FADE_MIN_STEP
+        assert_eq!(fade_steps(100, Duration::from_secs(5)), (10, FADE_MIN_STEP));
+        assert_eq!(fade_steps(0, Duration::ZERO), (1, Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn test_power_needs_a_switch() {
+        let transport = crate::api::transport::MockTransport::new(&[]);
+        for (device_type, child_id) in [
+            (DeviceType::TapoCam, None),
+            (DeviceType::KasaCam, None),
+            (DeviceType::T310, Some("8022A1")),
+            (DeviceType::KE100, Some("8022B7")),
+        ] {
+            let device = Device::test(device_type, child_id).with_transport(transport.clone());
+            assert!(matches!(
+                device.power_off().await,
+                Err(AppError::UnsupportedOperation(_))
+            ));
+            assert!(matches!(
+                device.toggle().await,
+                Err(AppError::UnsupportedOperation(_))
+            ));
+        }
+        assert!(transport.requests.lock().unwrap().is_empty());
+    }
+
+    fn device(status: Option<i32>) -> Device {
+        let mut device = Device::test(DeviceType::HS200, None);
+        device.info.alias = Some("Porch".to_string());
+        device.info.status = status;
+        device
+    }
+
+    #[test]
+    fn test_offline_preflight() {
+        let offline = device(Some(0));
+        assert!(offline.is_offline());
+        let err = offline.check_reachable().unwrap_err();
+        assert_eq!(err.exit_code(), 4);
+        assert_eq!(err.device(), Some("Porch"));
+
+        assert!(device(Some(0)).with_force(true).check_reachable().is_ok());
+
+        // Only a listed status of 0 counts as offline
+        for status in [None, Some(1)] {
+            assert!(!device(status).is_offline());
+            assert!(device(status).check_reachable().is_ok());
+        }
+    }
 }
@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::api::cloud_type::CloudType;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DeviceInfo {
     pub device_type: Option<String>,
@@ -47,3 +47,13 @@ impl DeviceInfo {
         self.device_id.as_deref().unwrap_or("")
     }
 }
+
+/// Full ID of a child outlet. Firmware reports child IDs either in full
+/// (parent ID plus a two-digit index) or as just the index.
+pub fn child_full_id(parent_id: &str, child_id: &str) -> String {
+    if child_id.len() == 2 {
+        format!("{}{}", parent_id, child_id)
+    } else {
+        child_id.to_string()
+    }
+}
@@ -1,6 +1,9 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+use crate::models::registry::{registry, ModelSpec};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(strum::EnumIter))]
 pub enum DeviceType {
     // Kasa devices
     HS100,
@@ -8,6 +11,7 @@ pub enum DeviceType {
     HS105,
     HS110,
     HS200,
+    HS220,
     HS300,
     HS300Child,
     KP115,
@@ -18,139 +22,101 @@ pub enum DeviceType {
     KP303Child,
     KP400,
     KP400Child,
+    KS220,
     KL420L5,
     KL430,
     EP40,
     EP40Child,
+    KH100,
+    KE100,
+    KasaCam,
     // Tapo devices
     P100,
+    P105,
     P110,
+    P115,
+    P125M,
     L530,
+    H100,
+    T310,
+    T315,
+    T100,
+    T110,
+    TapoCam,
     Unknown,
 }
 
-/// Model prefix to DeviceType mapping, ordered by specificity (longest prefix first).
-const MODEL_MAP: &[(&str, DeviceType)] = &[
-    ("KL420L5", DeviceType::KL420L5),
-    ("KL430", DeviceType::KL430),
-    ("HS100", DeviceType::HS100),
-    ("HS103", DeviceType::HS103),
-    ("HS105", DeviceType::HS105),
-    ("HS110", DeviceType::HS110),
-    ("HS200", DeviceType::HS200),
-    ("HS300", DeviceType::HS300),
-    ("KP115", DeviceType::KP115),
-    ("KP125", DeviceType::KP125),
-    ("KP200", DeviceType::KP200),
-    ("KP303", DeviceType::KP303),
-    ("KP400", DeviceType::KP400),
-    ("EP40", DeviceType::EP40),
-    ("P100", DeviceType::P100),
-    ("P110", DeviceType::P110),
-    ("L530", DeviceType::L530),
-];
-
 impl DeviceType {
     pub fn from_model(model: &str) -> Self {
-        for (prefix, device_type) in MODEL_MAP {
-            if model.starts_with(prefix) {
-                return *device_type;
-            }
+        registry().for_model(model).device_type
+    }
+
+    /// A model name given on the command line (`--assume-type KL430`).
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match Self::from_model(&value.to_uppercase()) {
+            DeviceType::Unknown => Err(format!(
+                "unknown model '{}' (use a supported model such as HS100, HS220, KL430 or P110)",
+                value
+            )),
+            device_type => Ok(device_type),
         }
-        DeviceType::Unknown
+    }
+
+    /// The registry entry describing this type's capabilities, ranges, and quirks.
+    pub fn spec(&self) -> &'static ModelSpec {
+        registry().for_type(*self)
     }
 
     pub fn child_type(&self) -> Self {
-        match self {
-            DeviceType::HS300 => DeviceType::HS300Child,
-            DeviceType::KP200 => DeviceType::KP200Child,
-            DeviceType::KP303 => DeviceType::KP303Child,
-            DeviceType::KP400 => DeviceType::KP400Child,
-            DeviceType::EP40 => DeviceType::EP40Child,
-            _ => DeviceType::Unknown,
-        }
+        self.spec().child_type.unwrap_or(DeviceType::Unknown)
     }
 
     pub fn has_children(&self) -> bool {
-        matches!(
-            self,
-            DeviceType::HS300
-                | DeviceType::KP200
-                | DeviceType::KP303
-                | DeviceType::KP400
-                | DeviceType::EP40
-        )
+        self.spec().capabilities.children
     }
 
     pub fn has_emeter(&self) -> bool {
-        matches!(
-            self,
-            DeviceType::HS110
-                | DeviceType::KP115
-                | DeviceType::KP125
-                | DeviceType::HS300Child
-                | DeviceType::P110
-        )
+        self.spec().capabilities.emeter
     }
 
     pub fn is_light(&self) -> bool {
-        matches!(
-            self,
-            DeviceType::KL420L5 | DeviceType::KL430 | DeviceType::L530
-        )
+        self.spec().capabilities.light
     }
 
     pub fn is_child(&self) -> bool {
-        matches!(
-            self,
-            DeviceType::HS300Child
-                | DeviceType::KP200Child
-                | DeviceType::KP303Child
-                | DeviceType::KP400Child
-                | DeviceType::EP40Child
-        )
+        self.spec().capabilities.child
     }
 
     pub fn is_tapo(&self) -> bool {
-        matches!(self, DeviceType::P100 | DeviceType::P110 | DeviceType::L530)
+        self.spec().capabilities.tapo
+    }
+
+    pub fn is_dimmer(&self) -> bool {
+        self.spec().capabilities.dimmer
+    }
+
+    pub fn has_button_lock(&self) -> bool {
+        self.spec().capabilities.button_lock
+    }
+
+    pub fn is_sensor(&self) -> bool {
+        self.spec().capabilities.sensor
+    }
+
+    pub fn is_thermostat(&self) -> bool {
+        self.spec().capabilities.thermostat
+    }
+
+    pub fn is_camera(&self) -> bool {
+        self.spec().capabilities.camera
     }
 
     pub fn category(&self) -> &'static str {
-        if self.is_light() {
-            "light"
-        } else if matches!(self, DeviceType::HS200) {
-            "switch"
-        } else {
-            "plug"
-        }
+        &self.spec().category
     }
 
     pub fn display_name(&self) -> &'static str {
-        match self {
-            DeviceType::HS100 => "HS100",
-            DeviceType::HS103 => "HS103",
-            DeviceType::HS105 => "HS105",
-            DeviceType::HS110 => "HS110",
-            DeviceType::HS200 => "HS200",
-            DeviceType::HS300 => "HS300",
-            DeviceType::HS300Child => "HS300 Outlet",
-            DeviceType::KP115 => "KP115",
-            DeviceType::KP125 => "KP125",
-            DeviceType::KP200 => "KP200",
-            DeviceType::KP200Child => "KP200 Outlet",
-            DeviceType::KP303 => "KP303",
-            DeviceType::KP303Child => "KP303 Outlet",
-            DeviceType::KP400 => "KP400",
-            DeviceType::KP400Child => "KP400 Outlet",
-            DeviceType::KL420L5 => "KL420L5",
-            DeviceType::KL430 => "KL430",
-            DeviceType::EP40 => "EP40",
-            DeviceType::EP40Child => "EP40 Outlet",
-            DeviceType::P100 => "P100",
-            DeviceType::P110 => "P110",
-            DeviceType::L530 => "L530",
-            DeviceType::Unknown => "Unknown",
-        }
+        &self.spec().display_name
     }
 }
 
@@ -164,13 +130,24 @@ mod tests {
         assert_eq!(DeviceType::from_model("KP115(US)"), DeviceType::KP115);
         assert_eq!(DeviceType::from_model("KL430(US)"), DeviceType::KL430);
         assert_eq!(DeviceType::from_model("HS300(US)"), DeviceType::HS300);
+        assert_eq!(DeviceType::from_model("KS220(US)"), DeviceType::KS220);
         assert_eq!(DeviceType::from_model("UNKNOWN_MODEL"), DeviceType::Unknown);
     }
 
+    #[test]
+    fn test_parse() {
+        assert_eq!(DeviceType::parse("kl430"), Ok(DeviceType::KL430));
+        assert_eq!(DeviceType::parse("HS220(US)"), Ok(DeviceType::HS220));
+        assert!(DeviceType::parse("XY999").is_err());
+    }
+
     #[test]
     fn test_tapo_model_mapping() {
         assert_eq!(DeviceType::from_model("P100"), DeviceType::P100);
+        assert_eq!(DeviceType::from_model("P105"), DeviceType::P105);
         assert_eq!(DeviceType::from_model("P110"), DeviceType::P110);
+        assert_eq!(DeviceType::from_model("P115"), DeviceType::P115);
+        assert_eq!(DeviceType::from_model("P125M"), DeviceType::P125M);
         assert_eq!(DeviceType::from_model("L530"), DeviceType::L530);
     }
 
@@ -189,8 +166,11 @@ mod tests {
         assert!(DeviceType::KP115.has_emeter());
         assert!(DeviceType::HS300Child.has_emeter());
         assert!(DeviceType::P110.has_emeter());
+        assert!(DeviceType::P115.has_emeter());
+        assert!(!DeviceType::P125M.has_emeter());
         assert!(!DeviceType::HS100.has_emeter());
         assert!(!DeviceType::P100.has_emeter());
+        assert!(!DeviceType::P105.has_emeter());
     }
 
     #[test]
@@ -211,6 +191,49 @@ mod tests {
         assert!(!DeviceType::KL430.is_tapo());
     }
 
+    #[test]
+    fn test_is_dimmer() {
+        assert!(DeviceType::HS220.is_dimmer());
+        assert!(DeviceType::KS220.is_dimmer());
+        assert!(!DeviceType::HS200.is_dimmer());
+        assert!(!DeviceType::KL430.is_dimmer());
+    }
+
+    #[test]
+    fn test_has_button_lock() {
+        assert!(DeviceType::P100.has_button_lock());
+        assert!(DeviceType::P110.has_button_lock());
+        assert!(!DeviceType::HS100.has_button_lock());
+        assert!(!DeviceType::L530.has_button_lock());
+    }
+
+    #[test]
+    fn test_hub_and_sensors() {
+        assert!(DeviceType::H100.has_children());
+        assert_eq!(DeviceType::from_model("T315"), DeviceType::T315);
+        assert!(DeviceType::T310.is_sensor() && DeviceType::T310.is_child());
+        assert!(DeviceType::T110.is_sensor());
+        assert!(!DeviceType::H100.is_sensor());
+    }
+
+    #[test]
+    fn test_kasa_hub_and_trv() {
+        assert!(DeviceType::KH100.has_children());
+        assert!(!DeviceType::KH100.is_tapo());
+        assert_eq!(DeviceType::from_model("KE100(EU)"), DeviceType::KE100);
+        assert!(DeviceType::KE100.is_thermostat() && DeviceType::KE100.is_child());
+        assert!(!DeviceType::KE100.is_sensor());
+    }
+
+    #[test]
+    fn test_cameras() {
+        assert_eq!(DeviceType::from_model("KC120(US)"), DeviceType::KasaCam);
+        assert_eq!(DeviceType::from_model("C210"), DeviceType::TapoCam);
+        assert!(DeviceType::TapoCam.is_camera() && DeviceType::TapoCam.is_tapo());
+        assert!(DeviceType::KasaCam.is_camera() && !DeviceType::KasaCam.is_tapo());
+        assert!(!DeviceType::HS100.is_camera());
+    }
+
     #[test]
     fn test_child_type() {
         assert_eq!(DeviceType::HS300.child_type(), DeviceType::HS300Child);
@@ -1,36 +1,145 @@
+use chrono::{Datelike, NaiveDate};
 use serde::Serialize;
 
+use crate::models::registry::EmeterUnits;
+
+/// Read a value in milli-units. With known `units` the matching key is read
+/// first: base-unit hardware (HS110 v1) reports V, A, W and kWh, while
+/// milli-unit hardware may use the bare keys for milli values. Without known
+/// units the key decides: `power_mw` is taken as-is, `power` is converted.
+fn milli_reading(
+    data: &serde_json::Value,
+    milli_key: &str,
+    base_key: &str,
+    units: Option<EmeterUnits>,
+) -> Option<f64> {
+    let read = |key: &str| data.get(key).and_then(|v| v.as_f64());
+    match units {
+        Some(EmeterUnits::Milli) => read(milli_key).or_else(|| read(base_key)),
+        Some(EmeterUnits::Base) => read(base_key)
+            .map(|value| value * 1000.0)
+            .or_else(|| read(milli_key)),
+        None => read(milli_key).or_else(|| read(base_key).map(|value| value * 1000.0)),
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
-pub struct CurrentPower {
+pub struct EmeterRealtime {
     pub voltage_mv: Option<f64>,
     pub current_ma: Option<f64>,
     pub power_mw: Option<f64>,
     pub total_wh: Option<f64>,
 }
 
-impl CurrentPower {
-    pub fn from_json(data: &serde_json::Value) -> Self {
+impl EmeterRealtime {
+    pub fn from_json(data: &serde_json::Value, units: Option<EmeterUnits>) -> Self {
         Self {
-            voltage_mv: data
-                .get("voltage_mv")
-                .or_else(|| data.get("voltage"))
-                .and_then(|v| v.as_f64()),
-            current_ma: data
-                .get("current_ma")
-                .or_else(|| data.get("current"))
-                .and_then(|v| v.as_f64()),
-            power_mw: data
-                .get("power_mw")
-                .or_else(|| data.get("power"))
-                .and_then(|v| v.as_f64()),
-            total_wh: data
-                .get("total_wh")
-                .or_else(|| data.get("total"))
-                .and_then(|v| v.as_f64()),
+            voltage_mv: milli_reading(data, "voltage_mv", "voltage", units),
+            current_ma: milli_reading(data, "current_ma", "current", units),
+            power_mw: milli_reading(data, "power_mw", "power", units),
+            total_wh: milli_reading(data, "total_wh", "total", units),
+        }
+    }
+}
+
+/// A comparison against one field of a realtime reading, e.g.
+/// `power_mw<5000` (`power on/off --only-if`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadingCondition {
+    pub field: String,
+    pub op: Comparison,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Comparison {
+    /// Longest first, so "<=" is not read as "<".
+    const OPERATORS: [(&'static str, Comparison); 6] = [
+        ("<=", Comparison::Le),
+        (">=", Comparison::Ge),
+        ("==", Comparison::Eq),
+        ("!=", Comparison::Ne),
+        ("<", Comparison::Lt),
+        (">", Comparison::Gt),
+    ];
+
+    pub fn symbol(self) -> &'static str {
+        Self::OPERATORS
+            .iter()
+            .find(|(_, op)| *op == self)
+            .map(|(symbol, _)| *symbol)
+            .unwrap_or_default()
+    }
+
+    pub fn holds(self, left: f64, right: f64) -> bool {
+        match self {
+            Comparison::Lt => left < right,
+            Comparison::Le => left <= right,
+            Comparison::Gt => left > right,
+            Comparison::Ge => left >= right,
+            Comparison::Eq => left == right,
+            Comparison::Ne => left != right,
         }
     }
 }
 
+impl ReadingCondition {
+    /// Fields of `EmeterRealtime` a condition can test.
+    pub const FIELDS: [&'static str; 4] = ["power_mw", "voltage_mv", "current_ma", "total_wh"];
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (start, symbol, op) = Comparison::OPERATORS
+            .iter()
+            .filter_map(|(symbol, op)| Some((value.find(symbol)?, *symbol, *op)))
+            .min_by_key(|(start, symbol, _)| (*start, std::cmp::Reverse(symbol.len())))
+            .ok_or_else(|| format!("'{}' has no comparison (<, <=, >, >=, ==, !=)", value))?;
+        let field = value[..start].trim().to_lowercase();
+        if !Self::FIELDS.contains(&field.as_str()) {
+            return Err(format!(
+                "unknown field '{}' (use {})",
+                field,
+                Self::FIELDS.join(", ")
+            ));
+        }
+        let number = value[start + symbol.len()..].trim();
+        let value = number
+            .parse()
+            .map_err(|_| format!("'{}' is not a number", number))?;
+        Ok(Self { field, op, value })
+    }
+
+    /// The tested field of `reading`, if the device reported it.
+    pub fn reading(&self, reading: &EmeterRealtime) -> Option<f64> {
+        match self.field.as_str() {
+            "power_mw" => reading.power_mw,
+            "voltage_mv" => reading.voltage_mv,
+            "current_ma" => reading.current_ma,
+            "total_wh" => reading.total_wh,
+            _ => None,
+        }
+    }
+
+    /// Whether `reading` meets the condition; `None` when the field is missing.
+    pub fn holds(&self, reading: &EmeterRealtime) -> Option<bool> {
+        Some(self.op.holds(self.reading(reading)?, self.value))
+    }
+}
+
+impl std::fmt::Display for ReadingCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}{}", self.field, self.op.symbol(), self.value)
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct DayPowerSummary {
     pub year: Option<i32>,
@@ -40,19 +149,39 @@ pub struct DayPowerSummary {
 }
 
 impl DayPowerSummary {
-    pub fn from_json(data: &serde_json::Value) -> Self {
+    pub fn from_json(data: &serde_json::Value, units: Option<EmeterUnits>) -> Self {
         Self {
             year: data.get("year").and_then(|v| v.as_i64()).map(|v| v as i32),
             month: data.get("month").and_then(|v| v.as_i64()).map(|v| v as u32),
             day: data.get("day").and_then(|v| v.as_i64()).map(|v| v as u32),
-            energy_wh: data
-                .get("energy_wh")
-                .or_else(|| data.get("energy"))
-                .and_then(|v| v.as_f64()),
+            energy_wh: milli_reading(data, "energy_wh", "energy", units),
         }
     }
 }
 
+impl DayPowerSummary {
+    /// The calendar day, when the device reported all of it.
+    pub fn date(&self) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(self.year?, self.month?, self.day?)
+    }
+}
+
+/// Each (year, month) from the month of `from` to the month of `to`, in
+/// order; the requests needed to cover a date range with daily stats.
+pub fn months_in_range(from: NaiveDate, to: NaiveDate) -> Vec<(i32, u32)> {
+    let mut months = Vec::new();
+    let (mut year, mut month) = (from.year(), from.month());
+    while (year, month) <= (to.year(), to.month()) {
+        months.push((year, month));
+        (year, month) = if month == 12 {
+            (year + 1, 1)
+        } else {
+            (year, month + 1)
+        };
+    }
+    months
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct MonthPowerSummary {
     pub year: Option<i32>,
@@ -61,14 +190,223 @@ pub struct MonthPowerSummary {
 }
 
 impl MonthPowerSummary {
-    pub fn from_json(data: &serde_json::Value) -> Self {
+    pub fn from_json(data: &serde_json::Value, units: Option<EmeterUnits>) -> Self {
         Self {
             year: data.get("year").and_then(|v| v.as_i64()).map(|v| v as i32),
             month: data.get("month").and_then(|v| v.as_i64()).map(|v| v as u32),
-            energy_wh: data
-                .get("energy_wh")
-                .or_else(|| data.get("energy"))
-                .and_then(|v| v.as_f64()),
+            energy_wh: milli_reading(data, "energy_wh", "energy", units),
+        }
+    }
+}
+
+/// How energy readings are presented: base units (W, V, A, kWh) rounded to
+/// `decimals` places, plus formatted costs when a price per kWh is set.
+#[derive(Debug, Clone)]
+pub struct EnergyFormat {
+    pub decimals: usize,
+    pub price_per_kwh: Option<f64>,
+    pub currency: String,
+}
+
+impl EnergyFormat {
+    /// Convert a milli-unit reading (mW, mV, mA, Wh) to base units, rounded.
+    pub fn base(&self, milli: Option<f64>) -> Option<f64> {
+        let factor = 10f64.powi(self.decimals as i32);
+        milli.map(|v| (v / 1000.0 * factor).round() / factor)
+    }
+
+    /// Formatted cost of an energy amount given in Wh.
+    pub fn cost(&self, wh: Option<f64>) -> Option<String> {
+        let price = self.price_per_kwh?;
+        Some(format_currency(wh? / 1000.0 * price, &self.currency))
+    }
+}
+
+struct CurrencyStyle {
+    code: &'static str,
+    symbol: &'static str,
+    prefix: bool,
+    decimals: usize,
+    decimal_sep: char,
+    group_sep: char,
+}
+
+const fn style(
+    code: &'static str,
+    symbol: &'static str,
+    prefix: bool,
+    decimals: usize,
+    decimal_sep: char,
+    group_sep: char,
+) -> CurrencyStyle {
+    CurrencyStyle {
+        code,
+        symbol,
+        prefix,
+        decimals,
+        decimal_sep,
+        group_sep,
+    }
+}
+
+/// Conventional formatting for common currencies.
+const CURRENCY_STYLES: &[CurrencyStyle] = &[
+    style("USD", "$", true, 2, '.', ','),
+    style("CAD", "CA$", true, 2, '.', ','),
+    style("AUD", "A$", true, 2, '.', ','),
+    style("NZD", "NZ$", true, 2, '.', ','),
+    style("GBP", "£", true, 2, '.', ','),
+    style("EUR", "€", false, 2, ',', '.'),
+    style("CHF", "CHF ", true, 2, '.', '\''),
+    style("SEK", "kr", false, 2, ',', ' '),
+    style("NOK", "kr", false, 2, ',', ' '),
+    style("DKK", "kr.", false, 2, ',', '.'),
+    style("PLN", "zł", false, 2, ',', ' '),
+    style("INR", "₹", true, 2, '.', ','),
+    style("CNY", "¥", true, 2, '.', ','),
+    style("JPY", "¥", true, 0, '.', ','),
+];
+
+/// Format an amount in a currency (ISO code). Unknown codes print as `1.23 XYZ`.
+pub fn format_currency(amount: f64, currency: &str) -> String {
+    let code = currency.to_uppercase();
+    let Some(style) = CURRENCY_STYLES.iter().find(|s| s.code == code) else {
+        return format!("{:.2} {}", amount, code);
+    };
+
+    let formatted = format!("{:.*}", style.decimals, amount.abs());
+    let (int_part, frac_part) = formatted.split_once('.').unwrap_or((&formatted, ""));
+
+    let mut grouped = String::new();
+    for (i, digit) in int_part.chars().enumerate() {
+        if i > 0 && (int_part.len() - i) % 3 == 0 {
+            grouped.push(style.group_sep);
         }
+        grouped.push(digit);
+    }
+    if !frac_part.is_empty() {
+        grouped.push(style.decimal_sep);
+        grouped.push_str(frac_part);
+    }
+
+    let sign = if amount < 0.0 { "-" } else { "" };
+    if style.prefix {
+        format!("{}{}{}", sign, style.symbol, grouped)
+    } else {
+        format!("{}{} {}", sign, grouped, style.symbol)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_milli_unit_keys_used_as_is() {
+        let power = EmeterRealtime::from_json(&json!({"power_mw": 1500.0, "total_wh": 42.0}), None);
+        assert_eq!(power.power_mw, Some(1500.0));
+        assert_eq!(power.total_wh, Some(42.0));
+    }
+
+    #[test]
+    fn test_base_unit_keys_converted() {
+        let data = json!({"voltage": 120.5, "power": 1.5, "total": 0.042});
+        let power = EmeterRealtime::from_json(&data, Some(EmeterUnits::Base));
+        assert_eq!(power.voltage_mv, Some(120500.0));
+        assert_eq!(power.power_mw, Some(1500.0));
+        assert_eq!(power.total_wh, Some(42.0));
+    }
+
+    #[test]
+    fn test_units_pick_the_key() {
+        let data = json!({"power_mw": 1500.0, "power": 1.5});
+        assert_eq!(
+            EmeterRealtime::from_json(&data, None).power_mw,
+            Some(1500.0)
+        );
+        assert_eq!(
+            EmeterRealtime::from_json(&data, Some(EmeterUnits::Base)).power_mw,
+            Some(1500.0)
+        );
+
+        let bare = json!({"power": 1500.0});
+        assert_eq!(
+            EmeterRealtime::from_json(&bare, None).power_mw,
+            Some(1500000.0)
+        );
+        assert_eq!(
+            EmeterRealtime::from_json(&bare, Some(EmeterUnits::Milli)).power_mw,
+            Some(1500.0)
+        );
+        assert_eq!(
+            EmeterRealtime::from_json(&bare, Some(EmeterUnits::Base)).power_mw,
+            Some(1500000.0)
+        );
+    }
+
+    #[test]
+    fn test_reading_condition() {
+        let condition = ReadingCondition::parse("power_mw <= 5000").unwrap();
+        assert_eq!(condition.op, Comparison::Le);
+        assert_eq!(condition.to_string(), "power_mw<=5000");
+
+        let reading = EmeterRealtime::from_json(&json!({"power_mw": 4200.0}), None);
+        assert_eq!(condition.holds(&reading), Some(true));
+        assert_eq!(
+            ReadingCondition::parse("POWER_MW>5000")
+                .unwrap()
+                .holds(&reading),
+            Some(false)
+        );
+        assert_eq!(
+            ReadingCondition::parse("voltage_mv!=0")
+                .unwrap()
+                .holds(&reading),
+            None
+        );
+
+        assert!(ReadingCondition::parse("watts<5").is_err());
+        assert!(ReadingCondition::parse("power_mw 5000").is_err());
+        assert!(ReadingCondition::parse("power_mw<five").is_err());
+    }
+
+    #[test]
+    fn test_format_currency() {
+        assert_eq!(format_currency(1234.5, "usd"), "$1,234.50");
+        assert_eq!(format_currency(1234.5, "EUR"), "1.234,50 €");
+        assert_eq!(format_currency(99.6, "JPY"), "¥100");
+        assert_eq!(format_currency(0.42, "XYZ"), "0.42 XYZ");
+    }
+
+    #[test]
+    fn test_energy_format() {
+        let format = EnergyFormat {
+            decimals: 1,
+            price_per_kwh: Some(0.25),
+            currency: "USD".into(),
+        };
+        assert_eq!(format.base(Some(12345.0)), Some(12.3));
+        assert_eq!(format.cost(Some(2000.0)).as_deref(), Some("$0.50"));
+    }
+
+    #[test]
+    fn test_months_in_range() {
+        let date = |s: &str| s.parse::<NaiveDate>().unwrap();
+        assert_eq!(
+            months_in_range(date("2025-11-15"), date("2026-02-10")),
+            vec![(2025, 11), (2025, 12), (2026, 1), (2026, 2)]
+        );
+        assert_eq!(
+            months_in_range(date("2026-01-15"), date("2026-01-20")),
+            vec![(2026, 1)]
+        );
+        assert!(months_in_range(date("2026-02-01"), date("2026-01-31")).is_empty());
+    }
+
+    #[test]
+    fn test_day_summary_base_units() {
+        let day = DayPowerSummary::from_json(&json!({"day": 3, "energy": 0.25}), None);
+        assert_eq!(day.energy_wh, Some(250.0));
     }
 }
@@ -1,5 +1,10 @@
+use std::time::Duration;
+
 use serde::Serialize;
 
+use crate::error::AppError;
+use crate::models::device::Device;
+
 #[derive(Debug, Clone, Serialize)]
 pub struct LightState {
     pub on_off: Option<i32>,
@@ -37,3 +42,210 @@ impl LightState {
         }
     }
 }
+
+/// How a light comes on: as it was when it went off, or always at the same
+/// settings. Kasa bulbs keep one for turning on after a power cut or at the
+/// wall switch (`hard_on`) and one for the app, schedules and remotes
+/// (`soft_on`).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum TurnOnBehavior {
+    Last,
+    /// Settings left `None` keep the bulb's current preset value
+    Preset {
+        brightness: Option<u8>,
+        color_temp: Option<u16>,
+    },
+}
+
+impl TurnOnBehavior {
+    /// Parse one entry of a `get_default_behavior` response, e.g.
+    /// `{"mode": "customize_preset", "brightness": 20, "color_temp": 2700}`.
+    pub fn from_json(data: &serde_json::Value) -> Option<Self> {
+        match data.get("mode")?.as_str()? {
+            "last_status" => Some(Self::Last),
+            "customize_preset" => Some(Self::Preset {
+                brightness: data
+                    .get("brightness")
+                    .and_then(|v| v.as_u64())
+                    .map(|v| v.min(100) as u8),
+                color_temp: data
+                    .get("color_temp")
+                    .and_then(|v| v.as_u64())
+                    .map(|v| v as u16),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The entry as `set_default_behavior` expects it.
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Self::Last => serde_json::json!({"mode": "last_status"}),
+            Self::Preset {
+                brightness,
+                color_temp,
+            } => {
+                let mut entry = serde_json::json!({"mode": "customize_preset"});
+                if let Some(v) = brightness {
+                    entry["brightness"] = serde_json::json!(v);
+                }
+                if let Some(v) = color_temp {
+                    // A preset with a color temperature is white light
+                    entry["color_temp"] = serde_json::json!(v);
+                    entry["hue"] = serde_json::json!(0);
+                    entry["saturation"] = serde_json::json!(0);
+                }
+                entry
+            }
+        }
+    }
+}
+
+/// A light state change built up one setting at a time, from
+/// `Device::light`. Settings not given are left as they are. The light is
+/// switched on unless `off()` is called.
+#[must_use = "nothing is sent until apply() is awaited"]
+pub struct LightChange<'a> {
+    device: &'a Device,
+    on: bool,
+    brightness: Option<u8>,
+    hue: Option<u16>,
+    saturation: Option<u8>,
+    color_temp: Option<u16>,
+    transition: Option<Duration>,
+}
+
+impl<'a> LightChange<'a> {
+    pub fn new(device: &'a Device) -> Self {
+        Self {
+            device,
+            on: true,
+            brightness: None,
+            hue: None,
+            saturation: None,
+            color_temp: None,
+            transition: None,
+        }
+    }
+
+    /// Switch the light off (with a transition, fades it out).
+    pub fn off(mut self) -> Self {
+        self.on = false;
+        self
+    }
+
+    /// Brightness, 0-100 (or the model's range).
+    pub fn brightness(mut self, brightness: u8) -> Self {
+        self.brightness = Some(brightness);
+        self
+    }
+
+    /// Hue (0-360) and saturation (0-100). Replaces an earlier `temp`.
+    pub fn color(mut self, hue: u16, saturation: u8) -> Self {
+        self.hue = Some(hue);
+        self.saturation = Some(saturation);
+        self.color_temp = Some(0);
+        self
+    }
+
+    /// White color temperature in kelvin. Replaces an earlier `color`.
+    pub fn temp(mut self, kelvin: u16) -> Self {
+        self.hue = None;
+        self.saturation = None;
+        self.color_temp = Some(kelvin);
+        self
+    }
+
+    /// How long the light takes to reach the new state.
+    pub fn transition(mut self, duration: Duration) -> Self {
+        self.transition = Some(duration);
+        self
+    }
+
+    /// Send the change in a single request.
+    pub async fn apply(self) -> Result<Option<serde_json::Value>, AppError> {
+        if self.hue.is_some_and(|h| h > 360) {
+            return Err(AppError::InvalidInput("Hue must be 0-360".into()));
+        }
+        if self.saturation.is_some_and(|s| s > 100) {
+            return Err(AppError::InvalidInput("Saturation must be 0-100".into()));
+        }
+        let transition_period = self
+            .transition
+            .map(|d| {
+                u32::try_from(d.as_millis())
+                    .map_err(|_| AppError::InvalidInput("Transition is too long".into()))
+            })
+            .transpose()?;
+        self.device
+            .set_light_state(
+                Some(i32::from(self.on)),
+                self.brightness,
+                self.hue,
+                self.saturation,
+                self.color_temp,
+                transition_period,
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::device_type::DeviceType;
+
+    use super::*;
+
+    #[test]
+    fn test_turn_on_behavior_json() {
+        let preset = TurnOnBehavior::Preset {
+            brightness: Some(20),
+            color_temp: Some(2700),
+        };
+        assert_eq!(
+            preset.to_json(),
+            serde_json::json!({
+                "mode": "customize_preset",
+                "brightness": 20,
+                "color_temp": 2700,
+                "hue": 0,
+                "saturation": 0,
+            })
+        );
+        assert_eq!(TurnOnBehavior::from_json(&preset.to_json()), Some(preset));
+        assert_eq!(
+            TurnOnBehavior::from_json(&TurnOnBehavior::Last.to_json()),
+            Some(TurnOnBehavior::Last)
+        );
+        assert_eq!(
+            TurnOnBehavior::from_json(&serde_json::json!({"mode": "circadian"})),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_light_change_validation() {
+        // Every one of these fails before a request is sent
+        let bulb = Device::test(DeviceType::KL430, None);
+        let invalid = |r: Result<_, AppError>| matches!(r, Err(AppError::InvalidInput(_)));
+        assert!(invalid(bulb.light().color(400, 50).apply().await));
+        assert!(invalid(bulb.light().color(120, 101).apply().await));
+        assert!(invalid(bulb.light().temp(1000).apply().await));
+        assert!(invalid(bulb.light().brightness(101).apply().await));
+        assert!(invalid(
+            bulb.light()
+                .transition(Duration::from_secs(u64::MAX))
+                .apply()
+                .await
+        ));
+        assert!(matches!(
+            Device::test(DeviceType::HS100, None)
+                .light()
+                .brightness(40)
+                .apply()
+                .await,
+            Err(AppError::UnsupportedOperation(_))
+        ));
+    }
+}
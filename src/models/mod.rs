@@ -1,8 +1,15 @@
+pub mod camera;
+pub mod capability;
 pub mod device;
 pub mod device_info;
 pub mod device_type;
 pub mod energy;
 pub mod light_state;
 pub mod net_info;
+pub mod registry;
 pub mod schedule;
+pub mod sensor;
+pub mod sys_info;
+pub mod thermostat;
 pub mod time;
+pub mod timezone;
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::device_type::DeviceType;
+
+/// Embedded model database. Adding support for a new model is a data change here.
+const MODELS_JSON: &str = include_str!("models.json");
+
+/// Default Kasa lighting service used when a model does not override it.
+pub const DEFAULT_LIGHT_SERVICE: &str = "smartlife.iot.smartbulb.lightingservice";
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Capabilities {
+    pub emeter: bool,
+    pub light: bool,
+    pub children: bool,
+    pub child: bool,
+    pub tapo: bool,
+    /// Dimmer switch with fade and gentle on/off settings
+    pub dimmer: bool,
+    /// The physical button can be disabled (Tapo "child protection")
+    pub button_lock: bool,
+    /// Battery sensor paired with a hub (temperature, motion or contact)
+    pub sensor: bool,
+    /// Radiator valve paired with a hub (target temperature, frost protection)
+    pub thermostat: bool,
+    /// Kasa KC or Tapo C camera with a privacy mode
+    pub camera: bool,
+}
+
+impl Capabilities {
+    /// Best guess from a `get_sysinfo` response, for models missing from
+    /// models.json: `feature` ("TIM:ENE" means an emeter), `mic_type` or
+    /// `type`, `dev_name` and the fields only some kinds of device report.
+    pub fn from_sys_info(sys_info: &serde_json::Value) -> Self {
+        let text = |key: &str| {
+            sys_info
+                .get(key)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_ascii_uppercase()
+        };
+        let has = |key: &str| sys_info.get(key).is_some();
+        let kind = format!("{} {}", text("mic_type"), text("type"));
+
+        let light = kind.contains("BULB") || has("light_state") || has("is_color");
+        Self {
+            emeter: text("feature").split(':').any(|f| f == "ENE"),
+            light,
+            children: sys_info
+                .get("children")
+                .and_then(|v| v.as_array())
+                .is_some_and(|c| !c.is_empty()),
+            child: false,
+            tapo: kind.contains("SMART.TAPO"),
+            dimmer: !light && (text("dev_name").contains("DIMMER") || has("brightness")),
+            button_lock: has("child_protection"),
+            sensor: false,
+            thermostat: false,
+            camera: kind.contains("IPCAMERA"),
+        }
+    }
+}
+
+/// Inclusive value ranges accepted by a model.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ValueRanges {
+    pub brightness: Option<(u16, u16)>,
+    pub color_temp: Option<(u16, u16)>,
+}
+
+/// Units used by a device's emeter responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmeterUnits {
+    /// mV, mA, mW, Wh (`voltage_mv`, `power_mw`, ...)
+    Milli,
+    /// V, A, W, kWh (`voltage`, `power`, ...), used by early hardware revisions
+    Base,
+}
+
+/// Protocol differences between models (or hardware versions of a model).
+///
+/// Child counts are not a quirk: KP400 v1 and v2 differ in outlet count, but
+/// children are always read from the device's own `children` list, never
+/// from the model.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Quirks {
+    /// Passthrough service for light commands, if not the default.
+    pub light_service: Option<String>,
+    pub emeter_units: Option<EmeterUnits>,
+}
+
+impl Quirks {
+    /// Overlay the fields set in `other` on top of these quirks.
+    fn merged(&self, other: &Quirks) -> Quirks {
+        Quirks {
+            light_service: other
+                .light_service
+                .clone()
+                .or_else(|| self.light_service.clone()),
+            emeter_units: other.emeter_units.or(self.emeter_units),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModelSpec {
+    pub device_type: DeviceType,
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+    pub display_name: String,
+    pub category: String,
+    #[serde(default)]
+    pub child_type: Option<DeviceType>,
+    #[serde(default)]
+    pub capabilities: Capabilities,
+    #[serde(default)]
+    pub ranges: ValueRanges,
+    #[serde(default)]
+    pub quirks: Quirks,
+    /// Quirk overrides keyed by hardware version (matched on major version).
+    #[serde(default)]
+    pub hw_versions: HashMap<String, Quirks>,
+}
+
+fn hw_major(version: &str) -> &str {
+    version.split('.').next().unwrap_or(version).trim()
+}
+
+impl ModelSpec {
+    /// Quirks for a specific hardware version (`device_hw_ver`), falling back to the model defaults.
+    pub fn quirks_for(&self, hw_ver: Option<&str>) -> Quirks {
+        let hw_override = hw_ver.and_then(|ver| {
+            self.hw_versions
+                .iter()
+                .find(|(key, _)| hw_major(key) == hw_major(ver))
+                .map(|(_, quirks)| quirks)
+        });
+        match hw_override {
+            Some(quirks) => self.quirks.merged(quirks),
+            None => self.quirks.clone(),
+        }
+    }
+}
+
+pub struct Registry {
+    specs: Vec<ModelSpec>,
+    /// (prefix, index into specs), longest prefix first.
+    prefixes: Vec<(String, usize)>,
+    unknown: usize,
+}
+
+impl Registry {
+    fn load() -> Self {
+        let specs: Vec<ModelSpec> =
+            serde_json::from_str(MODELS_JSON).expect("embedded models.json is valid");
+
+        let mut prefixes: Vec<(String, usize)> = specs
+            .iter()
+            .enumerate()
+            .flat_map(|(i, spec)| spec.prefixes.iter().map(move |p| (p.clone(), i)))
+            .collect();
+        prefixes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+
+        let unknown = specs
+            .iter()
+            .position(|spec| spec.device_type == DeviceType::Unknown)
+            .expect("models.json has an Unknown entry");
+
+        Self {
+            specs,
+            prefixes,
+            unknown,
+        }
+    }
+
+    /// Find the spec whose prefix matches the model string, most specific first.
+    pub fn for_model(&self, model: &str) -> &ModelSpec {
+        self.prefixes
+            .iter()
+            .find(|(prefix, _)| model.starts_with(prefix.as_str()))
+            .map(|(_, i)| &self.specs[*i])
+            .unwrap_or(&self.specs[self.unknown])
+    }
+
+    /// Every device category, sorted, without duplicates.
+    pub fn categories(&self) -> Vec<&str> {
+        let mut categories: Vec<&str> = self.specs.iter().map(|s| s.category.as_str()).collect();
+        categories.sort();
+        categories.dedup();
+        categories
+    }
+
+    pub fn for_type(&self, device_type: DeviceType) -> &ModelSpec {
+        self.specs
+            .iter()
+            .find(|spec| spec.device_type == device_type)
+            .unwrap_or(&self.specs[self.unknown])
+    }
+}
+
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::load)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn test_registry_covers_every_device_type() {
+        for device_type in DeviceType::iter() {
+            assert!(
+                registry()
+                    .specs
+                    .iter()
+                    .any(|spec| spec.device_type == device_type),
+                "models.json has no entry for {:?}",
+                device_type
+            );
+        }
+    }
+
+    #[test]
+    fn test_child_types_are_children() {
+        for spec in &registry().specs {
+            if let Some(child) = spec.child_type {
+                assert!(spec.capabilities.children);
+                assert!(registry().for_type(child).capabilities.child);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hw_version_quirks() {
+        let spec = registry().for_type(DeviceType::HS110);
+        assert_eq!(
+            spec.quirks_for(Some("1.0")).emeter_units,
+            Some(EmeterUnits::Base)
+        );
+        assert_eq!(spec.quirks_for(Some("2.0")).emeter_units, None);
+        assert_eq!(spec.quirks_for(None).emeter_units, None);
+    }
+
+    #[test]
+    fn test_capabilities_from_sys_info() {
+        let plug = Capabilities::from_sys_info(&serde_json::json!({
+            "mic_type": "IOT.SMARTPLUGSWITCH",
+            "feature": "TIM:ENE",
+            "relay_state": 1,
+        }));
+        assert!(plug.emeter && !plug.light && !plug.dimmer && !plug.children);
+
+        let dimmer = Capabilities::from_sys_info(&serde_json::json!({
+            "mic_type": "IOT.SMARTPLUGSWITCH",
+            "dev_name": "Wi-Fi Smart Dimmer",
+            "feature": "TIM",
+            "brightness": 40,
+        }));
+        assert!(dimmer.dimmer && !dimmer.emeter && !dimmer.light);
+
+        let bulb = Capabilities::from_sys_info(&serde_json::json!({
+            "mic_type": "IOT.SMARTBULB",
+            "light_state": {"on_off": 1, "brightness": 40},
+        }));
+        assert!(bulb.light && !bulb.dimmer);
+
+        let strip = Capabilities::from_sys_info(&serde_json::json!({
+            "feature": "TIM:ENE",
+            "children": [{"id": "00", "state": 1}],
+        }));
+        assert!(strip.children && strip.emeter);
+
+        let camera = Capabilities::from_sys_info(&serde_json::json!({
+            "type": "SMART.IPCAMERA",
+        }));
+        assert!(camera.camera && !camera.light);
+    }
+
+    #[test]
+    fn test_light_ranges() {
+        let spec = registry().for_type(DeviceType::L530);
+        assert_eq!(spec.ranges.color_temp, Some((2500, 6500)));
+        assert_eq!(spec.quirks.light_service, None);
+    }
+}
@@ -1,7 +1,11 @@
+use chrono::{Datelike, NaiveDate};
 use serde::{Deserialize, Serialize};
 
 use crate::error::AppError;
 
+/// Rule count at which Kasa firmware stops accepting new schedule rules.
+pub const MAX_SCHEDULE_RULES: usize = 32;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StartOption {
     Time = 0,
@@ -33,6 +37,130 @@ impl ScheduleRule {
     pub fn from_json(data: &serde_json::Value) -> Option<Self> {
         serde_json::from_value(data.clone()).ok()
     }
+
+    /// Rule ID, falling back to its name for rules not yet on a device.
+    pub fn label(&self) -> String {
+        self.id
+            .clone()
+            .or_else(|| self.name.clone())
+            .unwrap_or_else(|| "(unnamed)".to_string())
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enable.unwrap_or(1) != 0
+    }
+
+    /// Whether the rule is active on a given date.
+    fn fires_on(&self, date: NaiveDate) -> bool {
+        if self.repeat == Some(0) {
+            return match (self.year, self.month, self.day) {
+                (Some(year), Some(month), Some(day)) => {
+                    date.year() == year && date.month() as i32 == month && date.day() as i32 == day
+                }
+                _ => false,
+            };
+        }
+        let index = date.weekday().num_days_from_sunday() as usize;
+        match &self.wday {
+            Some(wday) => wday.get(index).copied().unwrap_or(0) != 0,
+            None => true,
+        }
+    }
+}
+
+/// Parse rules from a `get_rules` response, a `schedule list` output, or a plain array.
+pub fn rules_from_json(data: &serde_json::Value) -> Vec<ScheduleRule> {
+    let list = data
+        .get("rules")
+        .unwrap_or(data)
+        .get("rule_list")
+        .or(Some(data))
+        .and_then(|v| v.as_array());
+    list.map(|rules| rules.iter().filter_map(ScheduleRule::from_json).collect())
+        .unwrap_or_default()
+}
+
+/// A single on/off transition produced by simulating schedule rules.
+#[derive(Debug, Clone, Serialize)]
+pub struct Transition {
+    pub date: NaiveDate,
+    /// Minutes after midnight.
+    pub minute: i32,
+    pub turn_on: bool,
+    pub rule: String,
+    /// Sunrise/sunset times are computed by the device; the simulated
+    /// minute is whatever the rule last reported.
+    pub approximate: bool,
+}
+
+impl Transition {
+    pub fn time(&self) -> String {
+        format!("{:02}:{:02}", self.minute / 60, self.minute % 60)
+    }
+}
+
+/// Transitions scheduled at the same minute with opposite actions.
+#[derive(Debug, Clone, Serialize)]
+pub struct Conflict {
+    pub date: NaiveDate,
+    pub minute: i32,
+    pub rules: Vec<String>,
+}
+
+fn is_solar(time_opt: Option<i32>) -> bool {
+    matches!(time_opt, Some(1) | Some(2))
+}
+
+/// Expand enabled rules into the on/off transitions they produce over
+/// `days` days starting at `start`, in chronological order.
+pub fn simulate(rules: &[ScheduleRule], start: NaiveDate, days: u32) -> Vec<Transition> {
+    let mut transitions = Vec::new();
+    for date in start.iter_days().take(days as usize) {
+        for rule in rules.iter().filter(|r| r.is_enabled() && r.fires_on(date)) {
+            if let (Some(sact @ 0..=1), Some(smin)) = (rule.sact, rule.smin) {
+                transitions.push(Transition {
+                    date,
+                    minute: smin,
+                    turn_on: sact == 1,
+                    rule: rule.label(),
+                    approximate: is_solar(rule.stime_opt),
+                });
+            }
+            // Optional end action (e.g. "on at 18:00, off at 23:00")
+            if let (Some(eact @ 0..=1), Some(emin)) = (rule.eact, rule.emin) {
+                if rule.etime_opt.unwrap_or(-1) >= 0 {
+                    transitions.push(Transition {
+                        date,
+                        minute: emin,
+                        turn_on: eact == 1,
+                        rule: rule.label(),
+                        approximate: is_solar(rule.etime_opt),
+                    });
+                }
+            }
+        }
+    }
+    transitions.sort_by_key(|t| (t.date, t.minute));
+    transitions
+}
+
+/// Find minutes where one rule turns the device on and another turns it off.
+pub fn find_conflicts(transitions: &[Transition]) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+    for group in transitions.chunk_by(|a, b| a.date == b.date && a.minute == b.minute) {
+        let has_on = group.iter().any(|t| t.turn_on);
+        let has_off = group.iter().any(|t| !t.turn_on);
+        if has_on && has_off {
+            let mut rules: Vec<String> = group.iter().map(|t| t.rule.clone()).collect();
+            rules.dedup();
+            conflicts.push(Conflict {
+                date: group[0].date,
+                minute: group[0].minute,
+                rules,
+            });
+        }
+    }
+    conflicts
 }
 
 /// Builder for constructing schedule rules.
@@ -208,3 +336,50 @@ pub fn parse_time(time_str: &str) -> Result<(u32, u32), AppError> {
     }
     Ok((hour, minute))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rule(id: &str, sact: i32, smin: i32, wday: [i32; 7]) -> ScheduleRule {
+        ScheduleRule::from_json(&json!({
+            "id": id, "enable": 1, "sact": sact, "stime_opt": 0, "smin": smin,
+            "etime_opt": -1, "eact": -1, "emin": 0, "repeat": 1, "wday": wday,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_simulate_weekdays_only() {
+        // 2026-10-12 is a Monday
+        let start = NaiveDate::from_ymd_opt(2026, 10, 12).unwrap();
+        let rules = vec![rule("A", 1, 420, [0, 1, 1, 1, 1, 1, 0])];
+        let transitions = simulate(&rules, start, 7);
+        assert_eq!(transitions.len(), 5);
+        assert_eq!(transitions[0].time(), "07:00");
+        assert!(transitions.iter().all(|t| t.turn_on));
+    }
+
+    #[test]
+    fn test_find_conflicts() {
+        let start = NaiveDate::from_ymd_opt(2026, 10, 12).unwrap();
+        let rules = vec![
+            rule("A", 1, 1320, [1; 7]),
+            rule("B", 0, 1320, [0, 0, 0, 0, 0, 0, 1]),
+            rule("C", 0, 1380, [1; 7]),
+        ];
+        let conflicts = find_conflicts(&simulate(&rules, start, 7));
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].date.weekday(), chrono::Weekday::Sat);
+        assert_eq!(conflicts[0].rules, vec!["A", "B"]);
+    }
+
+    #[test]
+    fn test_rules_from_list_output() {
+        let data =
+            json!({"device": "Lamp", "rules": {"rule_list": [{"id": "X", "sact": 1, "smin": 60}]}});
+        assert_eq!(rules_from_json(&data).len(), 1);
+        assert_eq!(rules_from_json(&json!([{"id": "Y"}])).len(), 1);
+    }
+}
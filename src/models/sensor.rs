@@ -0,0 +1,100 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// One hub sensor's entry in the hub's child device list. Each model fills
+/// in its own fields: temperature and humidity (T310, T315), `detected`
+/// (T100 motion) or `open` (T110 contact).
+#[derive(Debug, Clone, Default, Serialize)]
+#[non_exhaustive]
+pub struct SensorReading {
+    pub model: Option<String>,
+    pub temperature: Option<f64>,
+    /// "celsius" or "fahrenheit", as set in the Tapo app
+    pub temperature_unit: Option<String>,
+    /// Relative humidity in percent
+    pub humidity: Option<f64>,
+    pub battery_low: Option<bool>,
+    /// Only some firmware reports a level; all report `battery_low`
+    pub battery_percent: Option<u8>,
+    pub motion_detected: Option<bool>,
+    pub open: Option<bool>,
+    /// Most recent motion or open/close event
+    pub last_triggered: Option<DateTime<Utc>>,
+    /// The hub has heard from the sensor recently
+    pub online: Option<bool>,
+}
+
+impl SensorReading {
+    pub fn from_json(data: &serde_json::Value) -> Self {
+        let text = |key: &str| data.get(key).and_then(|v| v.as_str()).map(String::from);
+        let flag = |key: &str| data.get(key).and_then(|v| v.as_bool());
+        Self {
+            model: text("model"),
+            temperature: data.get("current_temp").and_then(|v| v.as_f64()),
+            temperature_unit: text("temp_unit"),
+            humidity: data.get("current_humidity").and_then(|v| v.as_f64()),
+            battery_low: flag("at_low_battery"),
+            battery_percent: data
+                .get("battery_percentage")
+                .and_then(|v| v.as_u64())
+                .map(|v| v.min(100) as u8),
+            motion_detected: flag("detected"),
+            open: flag("open"),
+            last_triggered: None,
+            online: text("status").map(|s| s == "online"),
+        }
+    }
+
+    /// Motion and contact sensors keep a trigger log; others have no events.
+    pub fn has_events(&self) -> bool {
+        self.motion_detected.is_some() || self.open.is_some()
+    }
+}
+
+/// Time of the newest entry in a `get_trigger_logs` response.
+pub fn last_trigger(logs: &serde_json::Value) -> Option<DateTime<Utc>> {
+    logs.get("logs")?
+        .as_array()?
+        .iter()
+        .filter_map(|log| log.get("timestamp").and_then(|v| v.as_i64()))
+        .max()
+        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_sensor_reading_from_json() {
+        let climate = SensorReading::from_json(&json!({
+            "model": "T310",
+            "current_temp": 21.4,
+            "temp_unit": "celsius",
+            "current_humidity": 48,
+            "at_low_battery": false,
+            "status": "online",
+        }));
+        assert_eq!(climate.temperature, Some(21.4));
+        assert_eq!(climate.humidity, Some(48.0));
+        assert_eq!(climate.battery_low, Some(false));
+        assert_eq!(climate.online, Some(true));
+        assert!(!climate.has_events());
+
+        let door = SensorReading::from_json(&json!({"model": "T110", "open": true}));
+        assert!(door.has_events());
+        assert_eq!(door.temperature, None);
+
+        let logs = json!({"logs": [
+            {"id": 7, "timestamp": 1_760_000_000, "event": "open"},
+            {"id": 6, "timestamp": 1_759_990_000, "event": "close"},
+        ]});
+        assert_eq!(
+            last_trigger(&logs).map(|t| t.timestamp()),
+            Some(1_760_000_000)
+        );
+        assert_eq!(last_trigger(&json!({"logs": []})), None);
+    }
+}
@@ -0,0 +1,133 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::Serialize;
+
+use crate::models::light_state::LightState;
+
+/// A device's `get_sysinfo` response. For an outlet it is the outlet's own
+/// entry, which carries little more than `alias`, `state` and `on_time`.
+/// Fields the firmware did not report are `None`; `raw` keeps the whole
+/// response for anything not modelled here.
+#[derive(Debug, Clone, Default, Serialize)]
+#[non_exhaustive]
+pub struct SysInfo {
+    pub alias: Option<String>,
+    pub model: Option<String>,
+    pub hw_ver: Option<String>,
+    pub sw_ver: Option<String>,
+    pub mac: Option<String>,
+    /// WiFi signal strength in dBm
+    pub rssi: Option<i64>,
+    /// Plug and switch relay (`relay_state`), or outlet `state`
+    pub relay_on: Option<bool>,
+    pub led_on: Option<bool>,
+    /// Seconds since the relay was switched on
+    pub on_time: Option<i64>,
+    /// Physical button disabled
+    pub button_locked: Option<bool>,
+    pub light_state: Option<LightState>,
+    pub children: Vec<ChildInfo>,
+    #[serde(skip)]
+    pub raw: serde_json::Value,
+}
+
+/// One outlet of a power strip, as listed in its parent's sysinfo, or a
+/// sensor from a hub's child device list.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChildInfo {
+    pub id: String,
+    pub alias: String,
+    pub state: Option<i32>,
+    /// Set for hub sensors, whose model differs from child to child
+    pub model: Option<String>,
+}
+
+impl SysInfo {
+    pub fn from_json(data: &serde_json::Value) -> Self {
+        let text = |key: &str| data.get(key).and_then(|v| v.as_str()).map(String::from);
+        let int = |key: &str| data.get(key).and_then(|v| v.as_i64());
+        Self {
+            alias: text("alias"),
+            model: text("model"),
+            hw_ver: text("hw_ver"),
+            sw_ver: text("sw_ver"),
+            mac: text("mac").or_else(|| text("mic_mac")),
+            rssi: int("rssi"),
+            relay_on: int("relay_state").or_else(|| int("state")).map(|v| v == 1),
+            led_on: int("led_off").map(|v| v == 0),
+            on_time: int("on_time"),
+            button_locked: data.get("child_protection").and_then(|v| v.as_bool()),
+            light_state: data.get("light_state").map(LightState::from_json),
+            children: data
+                .get("children")
+                .and_then(|v| v.as_array())
+                .map(|children| children.iter().map(ChildInfo::from_json).collect())
+                .unwrap_or_default(),
+            raw: data.clone(),
+        }
+    }
+}
+
+impl ChildInfo {
+    pub fn from_json(data: &serde_json::Value) -> Self {
+        let text = |key: &str| {
+            data.get(key)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+        // Hubs list `device_id` and a base64 `nickname` instead
+        let nickname = || {
+            STANDARD
+                .decode(text("nickname"))
+                .ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+                .unwrap_or_default()
+        };
+        let id = text("id");
+        let alias = text("alias");
+        Self {
+            id: if id.is_empty() { text("device_id") } else { id },
+            alias: if alias.is_empty() { nickname() } else { alias },
+            state: data.get("state").and_then(|v| v.as_i64()).map(|v| v as i32),
+            model: data.get("model").and_then(|v| v.as_str()).map(String::from),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_sys_info_from_json() {
+        let info = SysInfo::from_json(&json!({
+            "alias": "Strip",
+            "model": "HS300(US)",
+            "mic_mac": "AA:BB",
+            "rssi": -52,
+            "led_off": 0,
+            "children": [{"id": "00", "alias": "Fridge", "state": 1}],
+        }));
+        assert_eq!(info.mac.as_deref(), Some("AA:BB"));
+        assert_eq!(info.led_on, Some(true));
+        assert_eq!(info.relay_on, None);
+        assert_eq!(info.children[0].alias, "Fridge");
+        assert_eq!(info.children[0].state, Some(1));
+
+        let sensor = ChildInfo::from_json(&json!({
+            "device_id": "8022A1",
+            "model": "T310",
+            "nickname": "QmF0aHJvb20=",
+        }));
+        assert_eq!(sensor.id, "8022A1");
+        assert_eq!(sensor.alias, "Bathroom");
+        assert_eq!(sensor.model.as_deref(), Some("T310"));
+
+        let outlet = SysInfo::from_json(&json!({"id": "00", "state": 0, "on_time": 0}));
+        assert_eq!(outlet.relay_on, Some(false));
+        assert!(outlet.children.is_empty());
+    }
+}
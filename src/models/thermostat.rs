@@ -0,0 +1,113 @@
+use serde::Serialize;
+
+/// Lowest and highest target temperature of a KE100 when the hub does not
+/// report `min_control_temp`/`max_control_temp`.
+const DEFAULT_TARGET_RANGE: (f64, f64) = (5.0, 30.0);
+
+/// What a radiator valve is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThermostatMode {
+    /// Valve open, warming the room to the target temperature
+    Heating,
+    /// Target temperature reached
+    Idle,
+    /// Frost protection only: the valve opens below about 5 °C
+    Off,
+}
+
+/// One radiator valve's entry in a hub's child device list.
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct ThermostatState {
+    pub model: Option<String>,
+    pub target_temperature: Option<f64>,
+    pub current_temperature: Option<f64>,
+    /// "celsius" or "fahrenheit", as set in the Kasa app
+    pub temperature_unit: Option<String>,
+    pub mode: ThermostatMode,
+    /// The buttons on the valve are disabled
+    pub child_lock: Option<bool>,
+    pub battery_percent: Option<u8>,
+    /// Inclusive range accepted by `Device::set_target_temperature`
+    pub target_range: (f64, f64),
+}
+
+impl ThermostatState {
+    pub fn from_json(data: &serde_json::Value) -> Self {
+        let number = |key: &str| data.get(key).and_then(|v| v.as_f64());
+        let frost_protection = data
+            .get("frost_protection_on")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let heating = data
+            .get("trv_states")
+            .and_then(|v| v.as_array())
+            .is_some_and(|states| states.iter().any(|s| s.as_str() == Some("heating")));
+        let mode = if frost_protection {
+            ThermostatMode::Off
+        } else if heating {
+            ThermostatMode::Heating
+        } else {
+            ThermostatMode::Idle
+        };
+        Self {
+            model: data.get("model").and_then(|v| v.as_str()).map(String::from),
+            target_temperature: number("target_temp"),
+            current_temperature: number("current_temp"),
+            temperature_unit: data
+                .get("temp_unit")
+                .and_then(|v| v.as_str())
+                .map(String::from),
+            mode,
+            child_lock: data.get("child_protection").and_then(|v| v.as_bool()),
+            battery_percent: data
+                .get("battery_percentage")
+                .and_then(|v| v.as_u64())
+                .map(|v| v.min(100) as u8),
+            target_range: (
+                number("min_control_temp").unwrap_or(DEFAULT_TARGET_RANGE.0),
+                number("max_control_temp").unwrap_or(DEFAULT_TARGET_RANGE.1),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_thermostat_state_from_json() {
+        let state = ThermostatState::from_json(&json!({
+            "model": "KE100",
+            "target_temp": 21.0,
+            "current_temp": 19.5,
+            "temp_unit": "celsius",
+            "trv_states": ["heating"],
+            "frost_protection_on": false,
+            "child_protection": true,
+            "battery_percentage": 80,
+            "min_control_temp": 5,
+            "max_control_temp": 30,
+        }));
+        assert_eq!(state.target_temperature, Some(21.0));
+        assert_eq!(state.current_temperature, Some(19.5));
+        assert_eq!(state.mode, ThermostatMode::Heating);
+        assert_eq!(state.child_lock, Some(true));
+        assert_eq!(state.battery_percent, Some(80));
+        assert_eq!(state.target_range, (5.0, 30.0));
+
+        let idle = ThermostatState::from_json(&json!({"trv_states": []}));
+        assert_eq!(idle.mode, ThermostatMode::Idle);
+        assert_eq!(idle.target_range, DEFAULT_TARGET_RANGE);
+
+        let off = ThermostatState::from_json(&json!({
+            "trv_states": ["heating"],
+            "frost_protection_on": true,
+        }));
+        assert_eq!(off.mode, ThermostatMode::Off);
+    }
+}
@@ -1,3 +1,4 @@
+use chrono::{NaiveDate, NaiveDateTime};
 use serde::Serialize;
 
 #[derive(Debug, Clone, Serialize)]
@@ -21,6 +22,14 @@ impl DeviceTime {
             sec: data.get("sec").and_then(|v| v.as_u64()).map(|v| v as u32),
         }
     }
+
+    pub fn to_naive(&self) -> Option<NaiveDateTime> {
+        NaiveDate::from_ymd_opt(self.year?, self.month?, self.mday?)?.and_hms_opt(
+            self.hour?,
+            self.min?,
+            self.sec.unwrap_or(0),
+        )
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -0,0 +1,286 @@
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// A TP-Link timezone index with its zone name and standard UTC offset.
+pub struct TimezoneEntry {
+    pub index: i32,
+    pub name: &'static str,
+    /// Standard (non-DST) offset from UTC in minutes.
+    pub utc_offset_minutes: i32,
+}
+
+const fn tz(index: i32, name: &'static str, utc_offset_minutes: i32) -> TimezoneEntry {
+    TimezoneEntry {
+        index,
+        name,
+        utc_offset_minutes,
+    }
+}
+
+/// Timezone indices understood by Kasa device firmware (`time.set_timezone`).
+pub const TIMEZONES: &[TimezoneEntry] = &[
+    tz(0, "Etc/GMT+12", -720),
+    tz(1, "Pacific/Samoa", -660),
+    tz(2, "US/Hawaii", -600),
+    tz(3, "US/Alaska", -540),
+    tz(4, "Mexico/BajaNorte", -480),
+    tz(5, "Etc/GMT+8", -480),
+    tz(6, "PST8PDT", -480),
+    tz(7, "US/Arizona", -420),
+    tz(8, "America/Mazatlan", -420),
+    tz(9, "MST", -420),
+    tz(10, "MST7MDT", -420),
+    tz(11, "Mexico/General", -360),
+    tz(12, "Etc/GMT+6", -360),
+    tz(13, "CST6CDT", -360),
+    tz(14, "America/Monterrey", -360),
+    tz(15, "Canada/Saskatchewan", -360),
+    tz(16, "America/Bogota", -300),
+    tz(17, "EST", -300),
+    tz(18, "America/Indiana/Indianapolis", -300),
+    tz(19, "America/Caracas", -240),
+    tz(20, "America/Asuncion", -240),
+    tz(21, "Etc/GMT+4", -240),
+    tz(22, "Canada/Atlantic", -240),
+    tz(23, "America/Cuiaba", -240),
+    tz(24, "Brazil/West", -240),
+    tz(25, "America/Santiago", -240),
+    tz(26, "Canada/Newfoundland", -210),
+    tz(27, "America/Sao_Paulo", -180),
+    tz(28, "America/Argentina/Buenos_Aires", -180),
+    tz(29, "America/Cayenne", -180),
+    tz(30, "America/Miquelon", -180),
+    tz(31, "America/Montevideo", -180),
+    tz(32, "Chile/Continental", -240),
+    tz(33, "Etc/GMT+2", -120),
+    tz(34, "Atlantic/Azores", -60),
+    tz(35, "Atlantic/Cape_Verde", -60),
+    tz(36, "Africa/Casablanca", 0),
+    tz(37, "UCT", 0),
+    tz(38, "GB", 0),
+    tz(39, "Africa/Monrovia", 0),
+    tz(40, "Europe/Amsterdam", 60),
+    tz(41, "Europe/Belgrade", 60),
+    tz(42, "Europe/Brussels", 60),
+    tz(43, "Europe/Sarajevo", 60),
+    tz(44, "Africa/Lagos", 60),
+    tz(45, "Africa/Windhoek", 120),
+    tz(46, "Asia/Amman", 120),
+    tz(47, "Europe/Athens", 120),
+    tz(48, "Asia/Beirut", 120),
+    tz(49, "Africa/Cairo", 120),
+    tz(50, "Asia/Damascus", 120),
+    tz(51, "EET", 120),
+    tz(52, "Africa/Harare", 120),
+    tz(53, "Europe/Helsinki", 120),
+    tz(54, "Asia/Istanbul", 180),
+    tz(55, "Asia/Jerusalem", 120),
+    tz(56, "Europe/Kaliningrad", 120),
+    tz(57, "Africa/Tripoli", 120),
+    tz(58, "Asia/Baghdad", 180),
+    tz(59, "Asia/Kuwait", 180),
+    tz(60, "Europe/Minsk", 180),
+    tz(61, "Europe/Moscow", 180),
+    tz(62, "Africa/Nairobi", 180),
+    tz(63, "Asia/Tehran", 210),
+    tz(64, "Asia/Muscat", 240),
+    tz(65, "Asia/Baku", 240),
+    tz(66, "Europe/Samara", 240),
+    tz(67, "Indian/Mauritius", 240),
+    tz(68, "Asia/Tbilisi", 240),
+    tz(69, "Asia/Yerevan", 240),
+    tz(70, "Asia/Kabul", 270),
+    tz(71, "Asia/Tashkent", 300),
+    tz(72, "Asia/Yekaterinburg", 300),
+    tz(73, "Asia/Karachi", 300),
+    tz(74, "Asia/Kolkata", 330),
+    tz(75, "Asia/Colombo", 330),
+    tz(76, "Asia/Kathmandu", 345),
+    tz(77, "Asia/Almaty", 360),
+    tz(78, "Asia/Dhaka", 360),
+    tz(79, "Asia/Novosibirsk", 420),
+    tz(80, "Asia/Rangoon", 390),
+    tz(81, "Asia/Bangkok", 420),
+    tz(82, "Asia/Krasnoyarsk", 420),
+    tz(83, "Asia/Chongqing", 480),
+    tz(84, "Asia/Irkutsk", 480),
+    tz(85, "Asia/Singapore", 480),
+    tz(86, "Australia/Perth", 480),
+    tz(87, "Asia/Taipei", 480),
+    tz(88, "Asia/Ulaanbaatar", 480),
+    tz(89, "Asia/Tokyo", 540),
+    tz(90, "Asia/Seoul", 540),
+    tz(91, "Asia/Yakutsk", 540),
+    tz(92, "Australia/Adelaide", 570),
+    tz(93, "Australia/Darwin", 570),
+    tz(94, "Australia/Brisbane", 600),
+    tz(95, "Australia/Canberra", 600),
+    tz(96, "Pacific/Guam", 600),
+    tz(97, "Australia/Hobart", 600),
+    tz(98, "Antarctica/DumontDUrville", 600),
+    tz(99, "Asia/Magadan", 660),
+    tz(100, "Asia/Srednekolymsk", 660),
+    tz(101, "Etc/GMT-11", 660),
+    tz(102, "Asia/Anadyr", 720),
+    tz(103, "Pacific/Auckland", 720),
+    tz(104, "Etc/GMT-12", 720),
+    tz(105, "Pacific/Fiji", 720),
+    tz(106, "Etc/GMT-13", 780),
+    tz(107, "Pacific/Apia", 780),
+    tz(108, "Etc/GMT-14", 840),
+];
+
+impl TimezoneEntry {
+    /// Interpret a device-local time in this zone. Uses the tz database when
+    /// it knows the zone name (so DST applies), otherwise the standard offset.
+    pub fn localize(&self, local: NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+        match self.name.parse::<Tz>() {
+            Ok(tz) => tz
+                .from_local_datetime(&local)
+                .earliest()
+                .map(|dt| dt.fixed_offset()),
+            Err(_) => FixedOffset::east_opt(self.utc_offset_minutes * 60)?
+                .from_local_datetime(&local)
+                .single(),
+        }
+    }
+
+    /// The wall-clock time in this zone at `instant`, with the DST offset in
+    /// effect then when the tz database knows the zone, otherwise the
+    /// standard offset.
+    pub fn local_time(&self, instant: DateTime<Utc>) -> NaiveDateTime {
+        match self.name.parse::<Tz>() {
+            Ok(tz) => instant.with_timezone(&tz).naive_local(),
+            Err(_) => {
+                (instant + chrono::Duration::minutes(self.utc_offset_minutes as i64)).naive_utc()
+            }
+        }
+    }
+}
+
+/// Zone used to display device times (`--tz`).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DisplayZone {
+    /// As reported by the device, in its own timezone
+    #[default]
+    Device,
+    Utc,
+    /// This machine's local timezone
+    Local,
+    Named(Tz),
+}
+
+impl DisplayZone {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "device" => Ok(DisplayZone::Device),
+            "utc" | "z" => Ok(DisplayZone::Utc),
+            "local" => Ok(DisplayZone::Local),
+            _ => value.parse::<Tz>().map(DisplayZone::Named).map_err(|_| {
+                format!(
+                    "unknown timezone '{}' (use utc, local, device, or a zone like Europe/Amsterdam)",
+                    value
+                )
+            }),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            DisplayZone::Device => "device".to_string(),
+            DisplayZone::Utc => "UTC".to_string(),
+            DisplayZone::Local => "local".to_string(),
+            DisplayZone::Named(tz) => tz.name().to_string(),
+        }
+    }
+
+    /// Convert a device-local time into this zone. Returns `None` for
+    /// `Device` (no conversion) or when the local time does not exist.
+    pub fn convert(
+        &self,
+        local: NaiveDateTime,
+        device_tz: &TimezoneEntry,
+    ) -> Option<DateTime<FixedOffset>> {
+        let instant = device_tz.localize(local)?;
+        match self {
+            DisplayZone::Device => None,
+            DisplayZone::Utc => Some(instant.with_timezone(&Utc).fixed_offset()),
+            DisplayZone::Local => Some(instant.with_timezone(&Local).fixed_offset()),
+            DisplayZone::Named(tz) => Some(instant.with_timezone(tz).fixed_offset()),
+        }
+    }
+}
+
+pub fn timezone_by_index(index: i32) -> Option<&'static TimezoneEntry> {
+    TIMEZONES.iter().find(|tz| tz.index == index)
+}
+
+/// Find a timezone by numeric index or case-insensitive zone name.
+pub fn find_timezone(name_or_index: &str) -> Option<&'static TimezoneEntry> {
+    if let Ok(index) = name_or_index.parse::<i32>() {
+        return timezone_by_index(index);
+    }
+    TIMEZONES
+        .iter()
+        .find(|tz| tz.name.eq_ignore_ascii_case(name_or_index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_timezone_by_name() {
+        let tz = find_timezone("europe/amsterdam").unwrap();
+        assert_eq!(tz.index, 40);
+        assert_eq!(tz.utc_offset_minutes, 60);
+    }
+
+    #[test]
+    fn test_find_timezone_by_index() {
+        assert_eq!(find_timezone("13").unwrap().name, "CST6CDT");
+        assert!(find_timezone("500").is_none());
+    }
+
+    #[test]
+    fn test_convert_device_time_to_utc() {
+        let amsterdam = find_timezone("Europe/Amsterdam").unwrap();
+        let summer =
+            NaiveDateTime::parse_from_str("2026-07-01 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let utc = DisplayZone::Utc.convert(summer, amsterdam).unwrap();
+        assert_eq!(utc.format("%H:%M").to_string(), "10:00");
+        assert!(DisplayZone::Device.convert(summer, amsterdam).is_none());
+    }
+
+    #[test]
+    fn test_local_time_applies_dst() {
+        let amsterdam = find_timezone("Europe/Amsterdam").unwrap();
+        let summer: DateTime<Utc> = "2026-07-01T10:00:00Z".parse().unwrap();
+        let winter: DateTime<Utc> = "2026-01-15T10:00:00Z".parse().unwrap();
+        assert_eq!(
+            amsterdam.local_time(summer).format("%H:%M").to_string(),
+            "12:00"
+        );
+        assert_eq!(
+            amsterdam.local_time(winter).format("%H:%M").to_string(),
+            "11:00"
+        );
+    }
+
+    #[test]
+    fn test_parse_display_zone() {
+        assert_eq!(DisplayZone::parse("UTC").unwrap(), DisplayZone::Utc);
+        assert_eq!(
+            DisplayZone::parse("America/New_York").unwrap().name(),
+            "America/New_York"
+        );
+        assert!(DisplayZone::parse("Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn test_indices_are_sequential() {
+        for (i, tz) in TIMEZONES.iter().enumerate() {
+            assert_eq!(tz.index, i as i32);
+        }
+    }
+}
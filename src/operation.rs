@@ -0,0 +1,178 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::error::AppError;
+
+/// How far a long-running operation has got.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Progress {
+    /// Steps finished so far
+    pub done: u32,
+    /// Total steps, when known (a watch runs until cancelled)
+    pub total: Option<u32>,
+    pub message: String,
+}
+
+type ProgressCallback = Box<dyn Fn(&Progress) + Send + Sync>;
+
+/// Handed to an operation's body: reports progress and notices
+/// cancellation between steps.
+pub struct OperationContext {
+    cancel: watch::Receiver<bool>,
+    progress: watch::Sender<Option<Progress>>,
+    callback: Arc<Mutex<Option<ProgressCallback>>>,
+}
+
+impl OperationContext {
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancel.borrow()
+    }
+
+    /// `Err(AppError::Cancelled)` once the operation has been cancelled.
+    pub fn check(&self) -> Result<(), AppError> {
+        if self.is_cancelled() {
+            return Err(AppError::Cancelled);
+        }
+        Ok(())
+    }
+
+    pub fn report(&self, progress: Progress) {
+        if let Some(callback) = &*self.callback.lock().unwrap_or_else(|p| p.into_inner()) {
+            callback(&progress);
+        }
+        self.progress.send_replace(Some(progress));
+    }
+
+    /// Wait for `duration`, or fail with `AppError::Cancelled` as soon as the
+    /// operation is cancelled.
+    pub async fn sleep(&mut self, duration: Duration) -> Result<(), AppError> {
+        self.check()?;
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => Ok(()),
+            _ = self.cancel.wait_for(|cancelled| *cancelled) => Err(AppError::Cancelled),
+        }
+    }
+}
+
+/// A long-running device operation (a fade, a watch) running on its own
+/// task. Await it for the result. `cancel`, or dropping the handle, stops it
+/// at its next step: a request already sent to the device is allowed to
+/// finish, so the device is never left mid-command.
+pub struct Operation<T> {
+    task: JoinHandle<Result<T, AppError>>,
+    cancel: watch::Sender<bool>,
+    progress: watch::Receiver<Option<Progress>>,
+    callback: Arc<Mutex<Option<ProgressCallback>>>,
+}
+
+impl<T: Send + 'static> Operation<T> {
+    /// Run `body` on a new task. Needs a Tokio runtime.
+    pub fn spawn<F, Fut>(body: F) -> Self
+    where
+        F: FnOnce(OperationContext) -> Fut,
+        Fut: Future<Output = Result<T, AppError>> + Send + 'static,
+    {
+        let (cancel, cancel_rx) = watch::channel(false);
+        let (progress_tx, progress) = watch::channel(None);
+        let callback = Arc::new(Mutex::new(None));
+        let context = OperationContext {
+            cancel: cancel_rx,
+            progress: progress_tx,
+            callback: callback.clone(),
+        };
+        Self {
+            task: tokio::spawn(body(context)),
+            cancel,
+            progress,
+            callback,
+        }
+    }
+}
+
+impl<T> Operation<T> {
+    /// Call `callback` with each progress report from now on.
+    pub fn on_progress(self, callback: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        *self.callback.lock().unwrap_or_else(|p| p.into_inner()) = Some(Box::new(callback));
+        self
+    }
+
+    /// The latest progress report, if any.
+    pub fn progress(&self) -> Option<Progress> {
+        self.progress.borrow().clone()
+    }
+
+    /// Stop at the next step. Awaiting the handle then gives
+    /// `AppError::Cancelled` (or the result, if it had already finished).
+    pub fn cancel(&self) {
+        self.cancel.send_replace(true);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+impl<T> Future for Operation<T> {
+    type Output = Result<T, AppError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.task)
+            .poll(cx)
+            .map(|joined| match joined {
+                Ok(result) => result,
+                Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                Err(_) => Err(AppError::Cancelled),
+            })
+    }
+}
+
+impl<T> Drop for Operation<T> {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counter(steps: u32) -> Operation<u32> {
+        Operation::spawn(move |mut ctx| async move {
+            for done in 1..=steps {
+                ctx.sleep(Duration::from_millis(10)).await?;
+                ctx.report(Progress {
+                    done,
+                    total: Some(steps),
+                    message: format!("step {}", done),
+                });
+            }
+            Ok(steps)
+        })
+    }
+
+    #[tokio::test]
+    async fn test_operation_completes_with_progress() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = seen.clone();
+        let op = counter(3).on_progress(move |p| recorder.lock().unwrap().push(p.done));
+        assert_eq!(op.await.unwrap(), 3);
+        assert_eq!(*seen.lock().unwrap(), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_operation_cancel() {
+        let op = counter(1000);
+        tokio::time::sleep(Duration::from_millis(25)).await;
+        op.cancel();
+        let done = op.progress().map_or(0, |p| p.done);
+        assert!(matches!(op.await, Err(AppError::Cancelled)));
+        assert!(done < 1000);
+    }
+}
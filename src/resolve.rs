@@ -1,43 +1,252 @@
 use std::collections::HashSet;
+#[cfg(feature = "cli")]
+use std::io::IsTerminal;
+use std::sync::Arc;
+
+#[cfg(feature = "cli")]
+use dialoguer::Select;
+use regex::Regex;
+
+use futures::stream::{self, StreamExt};
 
 use crate::api::client::TPLinkApi;
 use crate::api::cloud_type::CloudType;
 use crate::api::device_client::DeviceClient;
 use crate::auth::credentials::{get_auth_context, refresh_auth, refresh_tapo_auth, AuthContext};
+use crate::auth::keychain;
+use crate::config::RuntimeConfig;
 use crate::error::AppError;
+use crate::manifest;
 use crate::models::device::Device;
-use crate::models::device_info::DeviceInfo;
+use crate::models::device_info::{child_full_id, DeviceInfo};
 use crate::models::device_type::DeviceType;
+use crate::store;
+
+/// Maximum number of device requests in flight at once during fan-out.
+pub const MAX_CONCURRENT_REQUESTS: usize = 8;
+
+/// A device list fetched once and reused, see `RuntimeConfig::device_cache`.
+#[derive(Clone, Default)]
+pub struct DeviceCache(Arc<tokio::sync::Mutex<Option<DeviceList>>>);
+
+type DeviceList = (Vec<DeviceEntry>, AuthContext);
+
+impl DeviceCache {
+    /// Forget the cached list so the next fetch asks the cloud again.
+    pub async fn clear(&self) {
+        *self.0.lock().await = None;
+    }
+}
+
+// Not derived: the auth context holds tokens
+impl std::fmt::Debug for DeviceCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DeviceCache")
+    }
+}
+
+/// A device (or child outlet) discovered from the cloud device list.
+#[derive(Debug, Clone)]
+pub struct DeviceEntry {
+    pub info: DeviceInfo,
+    pub device_type: DeviceType,
+    pub child_alias: Option<String>,
+    pub child_id: Option<String>,
+}
+
+impl DeviceEntry {
+    /// The outlet alias for children, otherwise the device alias.
+    pub fn alias(&self) -> &str {
+        self.child_alias
+            .as_deref()
+            .unwrap_or(self.info.alias_or_name())
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.info.status == Some(1)
+    }
+
+    /// Device ID, or the full child ID for an outlet.
+    pub fn full_id(&self) -> String {
+        match &self.child_id {
+            Some(child_id) => child_full_id(self.info.id(), child_id),
+            None => self.info.id().to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl DeviceEntry {
+    /// An online HS200 switch with this ID and alias, for tests.
+    pub fn test(id: &str, alias: &str) -> Self {
+        Self {
+            info: DeviceInfo {
+                device_id: Some(id.to_string()),
+                alias: Some(alias.to_string()),
+                device_model: Some("HS200(US)".to_string()),
+                status: Some(1),
+                ..Default::default()
+            },
+            device_type: DeviceType::HS200,
+            child_alias: None,
+            child_id: None,
+        }
+    }
+
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.info.device_model = Some(model.to_string());
+        self.device_type = DeviceType::from_model(model);
+        self
+    }
+
+    pub fn with_firmware(mut self, fw_ver: &str) -> Self {
+        self.info.fw_ver = Some(fw_ver.to_string());
+        self
+    }
+}
+
+/// Live power state for a device, as reported by its sysinfo.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceState {
+    pub is_on: Option<bool>,
+    pub power_w: Option<f64>,
+    /// WiFi signal strength in dBm, when sysinfo reports it
+    pub rssi: Option<i64>,
+}
 
 /// Fetch all devices (including children) from both Kasa and Tapo clouds.
-/// Deduplicates devices that appear in both clouds (Kasa takes priority).
+/// The two clouds are queried concurrently; devices that appear in both
+/// are deduplicated afterwards (the configured preferred cloud, Kasa by
+/// default, takes priority). With `[manifest] only = true`, the manifest's
+/// devices are returned without asking either cloud. `--cloud` limits both
+/// to one cloud. With a device cache, only the first call fetches.
 pub async fn fetch_all_devices(
+    config: &RuntimeConfig,
+) -> Result<(Vec<DeviceEntry>, AuthContext), AppError> {
+    let Some(cache) = &config.device_cache else {
+        return fetch_all_devices_uncached(config).await;
+    };
+    // Held across the fetch so concurrent callers wait for one fetch
+    let mut cached = cache.0.lock().await;
+    if let Some(cached) = cached.as_ref() {
+        return Ok(cached.clone());
+    }
+    let fetched = fetch_all_devices_uncached(config).await?;
+    *cached = Some(fetched.clone());
+    Ok(fetched)
+}
+
+/// The device cache's list, if one has been fetched already.
+async fn cached_devices(config: &RuntimeConfig) -> Option<DeviceList> {
+    config.device_cache.as_ref()?.0.lock().await.clone()
+}
+
+/// `fetch_all_devices` without devices hidden by `devices hide`, unless
+/// `--include-hidden` is set. Used for listings and "every device" defaults;
+/// hidden devices can still be named directly.
+pub async fn fetch_listed_devices(
+    config: &RuntimeConfig,
+) -> Result<(Vec<DeviceEntry>, AuthContext), AppError> {
+    let (mut devices, auth) = fetch_all_devices(config).await?;
+    if !config.include_hidden {
+        let hidden = store::load_hidden()?;
+        if !hidden.is_empty() {
+            devices.retain(|d| !hidden.contains_key(&d.full_id()));
+        }
+    }
+    Ok((devices, auth))
+}
+
+async fn fetch_all_devices_uncached(
+    config: &RuntimeConfig,
+) -> Result<(Vec<DeviceEntry>, AuthContext), AppError> {
+    let auth = get_auth_context(config.verbose).await?;
+
+    if config.manifest_only {
+        let manifest = config
+            .manifest
+            .iter()
+            .filter(|entry| in_cloud(entry, config.only_cloud))
+            .cloned()
+            .collect();
+        return Ok((manifest, auth));
+    }
+    fetch_devices_from(
+        auth,
+        config.only_cloud,
+        config.preferred_cloud,
+        config.verbose,
+    )
+    .await
+}
+
+/// Whether `entry` is on `only`, or any cloud when `only` is `None`.
+fn in_cloud(entry: &DeviceEntry, only: Option<CloudType>) -> bool {
+    only.is_none_or(|cloud| entry.info.cloud_type.unwrap_or(CloudType::Kasa) == cloud)
+}
+
+/// List the devices of both clouds with the given tokens. A device in both
+/// lists is kept from `preferred_cloud`. Returns the auth context with any
+/// refreshed tokens.
+pub async fn fetch_devices_with(
+    auth: AuthContext,
+    preferred_cloud: CloudType,
     verbose: bool,
-) -> Result<(Vec<(DeviceInfo, DeviceType, Option<String>)>, AuthContext), AppError> {
-    let mut auth = get_auth_context(verbose).await?;
+) -> Result<(Vec<DeviceEntry>, AuthContext), AppError> {
+    fetch_devices_from(auth, None, preferred_cloud, verbose).await
+}
 
-    // Fetch Kasa devices
-    let kasa_devices = fetch_devices_for_cloud(&mut auth, CloudType::Kasa, verbose).await?;
+/// `fetch_devices_with`, asking only the `only` cloud when given.
+async fn fetch_devices_from(
+    auth: AuthContext,
+    only: Option<CloudType>,
+    preferred_cloud: CloudType,
+    verbose: bool,
+) -> Result<(Vec<DeviceEntry>, AuthContext), AppError> {
+    let use_kasa = auth.has_kasa() && only != Some(CloudType::Tapo);
+    let use_tapo = auth.has_tapo() && only != Some(CloudType::Kasa);
+    if only.is_some() && !use_kasa && !use_tapo {
+        return Err(AppError::NotAuthenticated);
+    }
 
-    // Track Kasa device IDs for deduplication
-    let kasa_ids: HashSet<String> = kasa_devices
-        .iter()
-        .map(|(info, _, _)| info.id().to_string())
-        .collect();
+    let kasa = async {
+        if use_kasa {
+            fetch_cloud_devices(auth.clone(), CloudType::Kasa, verbose).await
+        } else {
+            (auth.clone(), Ok(Vec::new()))
+        }
+    };
+    let tapo = async {
+        // Tapo devices are best-effort
+        if use_tapo {
+            Some(fetch_cloud_devices(auth.clone(), CloudType::Tapo, verbose).await)
+        } else {
+            None
+        }
+    };
+    let ((kasa_auth, kasa_devices), tapo_result) = tokio::join!(kasa, tapo);
 
-    let mut devices = kasa_devices;
+    let mut merged_auth = kasa_auth;
+    let mut devices = kasa_devices?;
 
-    // Fetch Tapo devices (best-effort)
-    if auth.has_tapo() {
-        match fetch_devices_for_cloud(&mut auth, CloudType::Tapo, verbose).await {
+    if let Some((tapo_auth, tapo_devices)) = tapo_result {
+        let tapo_refreshed = tapo_auth.tapo_token != auth.tapo_token;
+        merged_auth.tapo_token = tapo_auth.tapo_token;
+        merged_auth.tapo_refresh_token = tapo_auth.tapo_refresh_token;
+        merged_auth.tapo_regional_url = tapo_auth.tapo_regional_url;
+
+        // Each refresh stored its own copy of the token set; if both clouds
+        // refreshed, store the merged set so neither overwrites the other.
+        if tapo_refreshed && merged_auth.token != auth.token {
+            keychain::store_tokens(&merged_auth.to_token_set())?;
+        }
+
+        match tapo_devices {
             Ok(tapo_devices) => {
-                for device in tapo_devices {
-                    // Deduplicate: skip if already in Kasa
-                    if !kasa_ids.contains(device.0.id()) {
-                        devices.push(device);
-                    }
-                }
+                devices = merge_cloud_devices(devices, tapo_devices, preferred_cloud);
             }
+            // Without Kasa, Tapo is the only source of devices
+            Err(e) if !use_kasa => return Err(e),
             Err(e) => {
                 if verbose {
                     eprintln!("Tapo device fetch failed (non-fatal): {}", e);
@@ -46,15 +255,59 @@ pub async fn fetch_all_devices(
         }
     }
 
-    Ok((devices, auth))
+    Ok((devices, merged_auth))
+}
+
+/// Combine both clouds' device lists, dropping devices the preferred cloud
+/// already lists (children share the parent's ID).
+fn merge_cloud_devices(
+    kasa: Vec<DeviceEntry>,
+    tapo: Vec<DeviceEntry>,
+    preferred_cloud: CloudType,
+) -> Vec<DeviceEntry> {
+    let (mut first, second) = match preferred_cloud {
+        CloudType::Kasa => (kasa, tapo),
+        CloudType::Tapo => (tapo, kasa),
+    };
+    let ids: HashSet<String> = first.iter().map(|d| d.info.id().to_string()).collect();
+    first.extend(second.into_iter().filter(|d| !ids.contains(d.info.id())));
+    first
 }
 
-/// Fetch devices from a single cloud.
-async fn fetch_devices_for_cloud(
+/// Parse one cloud's device list, skipping devices it lists more than once.
+fn unique_device_infos(
+    device_list: &[serde_json::Value],
+    cloud_type: CloudType,
+) -> Vec<DeviceInfo> {
+    let mut seen_ids: HashSet<String> = HashSet::new();
+    device_list
+        .iter()
+        .filter_map(DeviceInfo::from_json)
+        .filter(|info| seen_ids.insert(info.id().to_string()))
+        .map(|mut info| {
+            info.cloud_type = Some(cloud_type);
+            info
+        })
+        .collect()
+}
+
+/// Fetch the devices (and children) of one cloud, refreshing its token if it
+/// has expired. Returns the possibly-refreshed auth context alongside.
+async fn fetch_cloud_devices(
+    mut auth: AuthContext,
+    cloud_type: CloudType,
+    verbose: bool,
+) -> (AuthContext, Result<Vec<DeviceEntry>, AppError>) {
+    let result = collect_devices_for_cloud(&mut auth, cloud_type, verbose).await;
+    (auth, result)
+}
+
+/// Collect devices from one cloud.
+async fn collect_devices_for_cloud(
     auth: &mut AuthContext,
     cloud_type: CloudType,
     verbose: bool,
-) -> Result<Vec<(DeviceInfo, DeviceType, Option<String>)>, AppError> {
+) -> Result<Vec<DeviceEntry>, AppError> {
     let (token, regional_url) = match cloud_type {
         CloudType::Kasa => (auth.token.clone(), auth.regional_url.clone()),
         CloudType::Tapo => {
@@ -99,29 +352,39 @@ async fn fetch_devices_for_cloud(
         Err(e) => return Err(e),
     };
 
-    let mut devices = Vec::new();
-
-    for device_json in &device_list {
-        if let Some(mut info) = DeviceInfo::from_json(device_json) {
-            info.cloud_type = Some(cloud_type);
-            let dtype = DeviceType::from_model(info.model());
+    let mut listed = Vec::new();
+    for info in unique_device_infos(&device_list, cloud_type) {
+        let dtype = DeviceType::from_model(info.model());
+        let client = if dtype.has_children() {
+            Some(DeviceClient::new(
+                info.app_server_url.as_deref().unwrap_or(&api.host),
+                &token,
+                &auth.term_id,
+                verbose,
+                cloud_type,
+            )?)
+        } else {
+            None
+        };
+        listed.push((info, dtype, client));
+    }
 
-            if dtype.has_children() {
-                let client = DeviceClient::new(
-                    info.app_server_url.as_deref().unwrap_or(&api.host),
-                    &token,
-                    &auth.term_id,
-                    verbose,
-                    cloud_type,
-                )?;
+    // Enumerate children of multi-outlet devices concurrently (bounded),
+    // keeping each parent's children directly after it in list order.
+    let entries: Vec<Vec<DeviceEntry>> = stream::iter(listed)
+        .map(|(info, dtype, client)| async move {
+            // Parent (no child_id)
+            let mut entries = vec![DeviceEntry {
+                info: info.clone(),
+                device_type: dtype,
+                child_alias: None,
+                child_id: None,
+            }];
 
+            if let Some(client) = client {
                 let parent_device =
                     Device::new(client, info.id().to_string(), info.clone(), dtype, None);
 
-                // Add parent
-                devices.push((info.clone(), dtype, None));
-
-                // Add children
                 if let Ok(children) = parent_device.get_children().await {
                     for child in children {
                         let child_alias = if child.alias.is_empty() {
@@ -129,110 +392,264 @@ async fn fetch_devices_for_cloud(
                         } else {
                             Some(child.alias)
                         };
-                        devices.push((info.clone(), dtype.child_type(), child_alias));
+                        // Hub sensors name their own model
+                        let device_type = match &child.model {
+                            Some(model) => DeviceType::from_model(model),
+                            None => dtype.child_type(),
+                        };
+                        entries.push(DeviceEntry {
+                            info: info.clone(),
+                            device_type,
+                            child_alias,
+                            child_id: Some(child.id),
+                        });
                     }
                 }
-            } else {
-                devices.push((info, dtype, None));
             }
-        }
+            entries
+        })
+        .buffered(MAX_CONCURRENT_REQUESTS)
+        .collect()
+        .await;
+
+    Ok(entries.into_iter().flatten().collect())
+}
+
+/// Fetch live power state for each device concurrently (bounded).
+/// Offline devices and devices that fail to respond get an empty state.
+pub async fn fetch_device_states(
+    devices: &[DeviceEntry],
+    auth: &AuthContext,
+    verbose: bool,
+) -> Vec<DeviceState> {
+    fetch_device_states_with(devices, auth, verbose, || {}).await
+}
+
+/// `fetch_device_states`, calling `on_done` as each device's state arrives
+/// (e.g. to advance a progress count).
+pub async fn fetch_device_states_with(
+    devices: &[DeviceEntry],
+    auth: &AuthContext,
+    verbose: bool,
+    on_done: impl Fn() + Sync,
+) -> Vec<DeviceState> {
+    let on_done = &on_done;
+    stream::iter(devices)
+        .map(|entry| async move {
+            let state = fetch_device_state(entry, auth, verbose).await;
+            on_done();
+            state
+        })
+        .buffered(MAX_CONCURRENT_REQUESTS)
+        .collect()
+        .await
+}
+
+async fn fetch_device_state(entry: &DeviceEntry, auth: &AuthContext, verbose: bool) -> DeviceState {
+    if !entry.is_online() {
+        return DeviceState::default();
+    }
+    let device = match build_device(entry, auth, verbose) {
+        Ok(device) => device,
+        Err(_) => return DeviceState::default(),
+    };
+    let sys_info = device.get_sys_info().await.ok().flatten();
+    let is_on = sys_info.as_ref().and_then(|info| device.power_state(info));
+    let rssi = sys_info
+        .as_ref()
+        .and_then(|info| info.get("rssi"))
+        .and_then(|v| v.as_i64());
+    let power_w = match device.as_energy_monitor() {
+        Some(meter) => meter
+            .emeter_realtime()
+            .await
+            .ok()
+            .and_then(|reading| reading.power_mw)
+            .map(|mw| mw / 1000.0),
+        None => None,
+    };
+    DeviceState {
+        is_on,
+        power_w,
+        rssi,
     }
+}
+
+/// Fetch each device's schedule rules concurrently (bounded). Offline devices
+/// and devices that fail to respond get `None`.
+pub async fn fetch_schedules(
+    devices: &[DeviceEntry],
+    auth: &AuthContext,
+    verbose: bool,
+) -> Vec<Option<serde_json::Value>> {
+    stream::iter(devices)
+        .map(|entry| async move {
+            if !entry.is_online() {
+                return None;
+            }
+            let device = build_device(entry, auth, verbose).ok()?;
+            match device.get_schedule_rules().await {
+                Ok(rules) => rules,
+                Err(e) => {
+                    if verbose {
+                        eprintln!("Could not read schedules of {}: {}", entry.alias(), e);
+                    }
+                    None
+                }
+            }
+        })
+        .buffered(MAX_CONCURRENT_REQUESTS)
+        .collect()
+        .await
+}
 
-    Ok(devices)
+/// Whether a device argument looks like a TP-Link device ID: 40 hex
+/// characters, or 42 for a child outlet (parent ID plus a two-digit index).
+pub fn looks_like_device_id(value: &str) -> bool {
+    matches!(value.len(), 40 | 42) && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Resolve an optional device argument, falling back to `default_device`
+/// from the config file (or `TPLC_DEFAULT_DEVICE`).
+pub async fn resolve_device_or_default(
+    name_or_id: Option<&str>,
+    config: &RuntimeConfig,
+) -> Result<Device, AppError> {
+    match name_or_id.or(config.default_device.as_deref()) {
+        Some(name_or_id) => resolve_device(name_or_id, config).await,
+        None => Err(AppError::InvalidInput(
+            "No device given and no default_device is configured".into(),
+        )),
+    }
 }
 
 /// Resolve a device by name or ID, searching both Kasa and Tapo clouds.
-pub async fn resolve_device(name_or_id: &str, verbose: bool) -> Result<Device, AppError> {
-    let mut auth = get_auth_context(verbose).await?;
+///
+/// With `--regex` the argument is matched as a pattern against device names.
+/// Local nicknames (`tplc alias`) are consulted first, then the static
+/// manifest. Arguments that look like device IDs (or any argument with `--device-id`)
+/// are looked up directly with a single sysinfo request instead of listing
+/// every device first.
+///
+/// Requests to a device the cloud lists as offline fail right away with
+/// `DeviceOffline` unless `--force` is set. `--assume-type` replaces the
+/// device's model.
+pub async fn resolve_device(name_or_id: &str, config: &RuntimeConfig) -> Result<Device, AppError> {
+    let mut device = find_device(name_or_id, config)
+        .await?
+        .with_force(config.force);
+    if let Some(device_type) = config.assume_type {
+        device.device_type = device_type;
+    }
+    Ok(device)
+}
 
-    // Build flat list from both clouds
-    let mut all_devices: Vec<(DeviceInfo, DeviceType, Option<String>, Option<String>)> = Vec::new();
-    let mut seen_ids: HashSet<String> = HashSet::new();
+async fn find_device(name_or_id: &str, config: &RuntimeConfig) -> Result<Device, AppError> {
+    let verbose = config.verbose;
 
-    // Kasa devices
-    collect_devices_for_resolution(
-        &mut auth,
-        CloudType::Kasa,
-        verbose,
-        &mut all_devices,
-        &mut seen_ids,
-    )
-    .await?;
+    if config.regex {
+        let pattern = Regex::new(name_or_id).map_err(|e| {
+            AppError::InvalidInput(format!("Invalid regex '{}': {}", name_or_id, e))
+        })?;
+        let (all_devices, auth) = fetch_all_devices(config).await?;
+        let matches: Vec<&DeviceEntry> = all_devices
+            .iter()
+            .filter(|d| pattern.is_match(d.alias()))
+            .collect();
+        let entry = choose_one(name_or_id, &matches)?;
+        return build_device(entry, &auth, verbose);
+    }
 
-    // Tapo devices (best-effort)
-    if auth.has_tapo() {
-        if let Err(e) = collect_devices_for_resolution(
-            &mut auth,
-            CloudType::Tapo,
-            verbose,
-            &mut all_devices,
-            &mut seen_ids,
-        )
-        .await
-        {
-            if verbose {
-                eprintln!("Tapo device fetch failed (non-fatal): {}", e);
+    let local_alias = store::find_alias(name_or_id)?;
+    let name_or_id = local_alias
+        .as_ref()
+        .map(|alias| alias.device_id.as_str())
+        .unwrap_or(name_or_id);
+
+    if let Some(entry) = manifest::find(&config.manifest, name_or_id)
+        .filter(|entry| in_cloud(entry, config.only_cloud))
+    {
+        let auth = get_auth_context(verbose).await?;
+        return build_device(entry, &auth, verbose);
+    }
+
+    if config.device_id || looks_like_device_id(name_or_id) {
+        // A list already fetched for this run answers without a request
+        if let Some((devices, auth)) = cached_devices(config).await {
+            if let Some(entry) = devices.iter().find(|d| d.full_id() == name_or_id) {
+                return build_device(entry, &auth, verbose);
+            }
+        }
+        match resolve_device_by_id(name_or_id, config.only_cloud, verbose).await {
+            Ok(device) => return Ok(device),
+            Err(e) if config.device_id => return Err(e),
+            Err(e) => {
+                if verbose {
+                    eprintln!(
+                        "Direct lookup of '{}' failed, falling back to device list: {}",
+                        name_or_id, e
+                    );
+                }
             }
         }
     }
 
+    let (all_devices, auth) = fetch_all_devices(config).await?;
+
     // Resolution priority:
     // 1. Exact alias match
     // 2. Exact device_id match
     // 3. Case-insensitive alias match
-    // 4. Partial alias match (only if exactly one result)
+    // 4. Partial alias match (several matches: picker when interactive)
+    // 5. Fuzzy alias match for typos (picker when interactive, else suggestions)
 
     let name_lower = name_or_id.to_lowercase();
 
     // 1. Exact alias match
-    for (info, dtype, child_alias, child_id) in &all_devices {
-        let alias = child_alias.as_deref().unwrap_or(info.alias_or_name());
-        if alias == name_or_id {
-            return build_device(info, *dtype, child_id.clone(), &auth, verbose);
-        }
+    if let Some(entry) = all_devices.iter().find(|d| d.alias() == name_or_id) {
+        return build_device(entry, &auth, verbose);
     }
 
-    // 2. Exact device_id match
-    for (info, dtype, _, child_id) in &all_devices {
-        if info.id() == name_or_id {
-            return build_device(info, *dtype, child_id.clone(), &auth, verbose);
-        }
+    // 2. Exact device_id match (parent first, then full child ID)
+    if let Some(entry) = all_devices
+        .iter()
+        .find(|d| d.child_id.is_none() && d.info.id() == name_or_id)
+        .or_else(|| all_devices.iter().find(|d| d.full_id() == name_or_id))
+    {
+        return build_device(entry, &auth, verbose);
     }
 
     // 3. Case-insensitive alias match
-    for (info, dtype, child_alias, child_id) in &all_devices {
-        let alias = child_alias.as_deref().unwrap_or(info.alias_or_name());
-        if alias.to_lowercase() == name_lower {
-            return build_device(info, *dtype, child_id.clone(), &auth, verbose);
-        }
+    if let Some(entry) = all_devices
+        .iter()
+        .find(|d| d.alias().to_lowercase() == name_lower)
+    {
+        return build_device(entry, &auth, verbose);
     }
 
     // 4. Partial alias match
-    let partial_matches: Vec<_> = all_devices
+    let partial_matches: Vec<&DeviceEntry> = all_devices
         .iter()
-        .filter(|(info, _, child_alias, _)| {
-            let alias = child_alias.as_deref().unwrap_or(info.alias_or_name());
-            alias.to_lowercase().contains(&name_lower)
-        })
+        .filter(|d| d.alias().to_lowercase().contains(&name_lower))
         .collect();
 
-    if partial_matches.len() == 1 {
-        let (info, dtype, _, child_id) = partial_matches[0];
-        return build_device(info, *dtype, child_id.clone(), &auth, verbose);
+    if !partial_matches.is_empty() {
+        let entry = choose_one(name_or_id, &partial_matches)?;
+        return build_device(entry, &auth, verbose);
     }
 
-    if partial_matches.len() > 1 {
-        let names: Vec<String> = partial_matches
-            .iter()
-            .map(|(info, _, child_alias, _)| {
-                child_alias
-                    .as_deref()
-                    .unwrap_or(info.alias_or_name())
-                    .to_string()
-            })
-            .collect();
+    // 5. Fuzzy alias match
+    let suggestions = fuzzy_matches(&name_lower, &all_devices);
+    if !suggestions.is_empty() {
+        #[cfg(feature = "cli")]
+        if is_interactive() {
+            let prompt = format!("No device named '{}'. Did you mean", name_or_id);
+            let entry = pick_device(&prompt, &suggestions)?;
+            return build_device(entry, &auth, verbose);
+        }
+        let names: Vec<&str> = suggestions.iter().map(|d| d.alias()).collect();
         return Err(AppError::DeviceNotFound(format!(
-            "Multiple devices match '{}': {}",
+            "{} (did you mean: {}?)",
             name_or_id,
             names.join(", ")
         )));
@@ -241,114 +658,246 @@ pub async fn resolve_device(name_or_id: &str, verbose: bool) -> Result<Device, A
     Err(AppError::DeviceNotFound(name_or_id.to_string()))
 }
 
-/// Collect devices from one cloud into the all_devices list for resolution.
-async fn collect_devices_for_resolution(
-    auth: &mut AuthContext,
-    cloud_type: CloudType,
-    verbose: bool,
-    all_devices: &mut Vec<(DeviceInfo, DeviceType, Option<String>, Option<String>)>,
-    seen_ids: &mut HashSet<String>,
-) -> Result<(), AppError> {
-    let (token, regional_url) = match cloud_type {
-        CloudType::Kasa => (auth.token.clone(), auth.regional_url.clone()),
-        CloudType::Tapo => {
-            let token = auth
-                .tapo_token
-                .as_ref()
-                .ok_or(AppError::NotAuthenticated)?
-                .clone();
-            let url = auth
-                .tapo_regional_url
-                .as_ref()
-                .ok_or(AppError::NotAuthenticated)?
-                .clone();
-            (token, url)
+/// Devices carrying `tag` (`tplc tag add`), matched case-insensitively, in
+/// list order. A tag on a power strip covers its outlets rather than the
+/// strip itself. Hidden devices are included: tagging names them.
+pub async fn tagged_devices(
+    tag: &str,
+    config: &RuntimeConfig,
+) -> Result<Vec<DeviceEntry>, AppError> {
+    let tag = tag.to_lowercase();
+    let tagged: Vec<String> = store::load_tags()?
+        .into_iter()
+        .filter(|(_, device)| device.tags.contains(&tag))
+        .map(|(device_id, _)| device_id)
+        .collect();
+    if tagged.is_empty() {
+        return Err(AppError::DeviceNotFound(format!(
+            "no devices tagged '{}'",
+            tag
+        )));
+    }
+    let (devices, _auth) = fetch_all_devices(config).await?;
+    Ok(select_tagged(&tagged, &devices)
+        .into_iter()
+        .cloned()
+        .collect())
+}
+
+fn select_tagged<'a>(tagged_ids: &[String], devices: &'a [DeviceEntry]) -> Vec<&'a DeviceEntry> {
+    devices
+        .iter()
+        .filter(|d| !d.device_type.has_children() || d.child_id.is_some())
+        .filter(|d| {
+            tagged_ids
+                .iter()
+                .any(|id| d.full_id() == *id || d.info.id() == id)
+        })
+        .collect()
+}
+
+/// Listed devices that `query` names exactly: by alias (ignoring case), ID
+/// or full child ID. A parent's ID also names all of its outlets.
+pub fn exact_matches<'a>(query: &str, devices: &'a [DeviceEntry]) -> Vec<&'a DeviceEntry> {
+    let query_lower = query.to_lowercase();
+    devices
+        .iter()
+        .filter(|d| {
+            d.alias().to_lowercase() == query_lower || d.info.id() == query || d.full_id() == query
+        })
+        .collect()
+}
+
+#[cfg(feature = "cli")]
+pub(crate) fn is_interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// Pick the single matching device. Several matches open a numbered picker
+/// when running interactively (with the `cli` feature), and are an error
+/// otherwise.
+fn choose_one<'a>(query: &str, matches: &[&'a DeviceEntry]) -> Result<&'a DeviceEntry, AppError> {
+    match matches {
+        [] => Err(AppError::DeviceNotFound(query.to_string())),
+        [entry] => Ok(entry),
+        #[cfg(feature = "cli")]
+        _ if is_interactive() => {
+            pick_device(&format!("Multiple devices match '{}'", query), matches)
         }
-    };
+        _ => {
+            let names: Vec<&str> = matches.iter().map(|d| d.alias()).collect();
+            Err(AppError::DeviceNotFound(format!(
+                "Multiple devices match '{}': {}",
+                query,
+                names.join(", ")
+            )))
+        }
+    }
+}
 
-    let api = TPLinkApi::new(
-        Some(regional_url),
-        verbose,
-        Some(auth.term_id.clone()),
-        cloud_type,
-    )?;
+/// Prompt on stderr for one of the candidates (stdout stays clean for JSON).
+#[cfg(feature = "cli")]
+fn pick_device<'a>(
+    prompt: &str,
+    candidates: &[&'a DeviceEntry],
+) -> Result<&'a DeviceEntry, AppError> {
+    let labels: Vec<String> = candidates
+        .iter()
+        .map(|d| format!("{} ({}, {})", d.alias(), d.info.model(), d.full_id()))
+        .collect();
+    let selection = Select::new()
+        .with_prompt(prompt)
+        .items(&labels)
+        .default(0)
+        .interact_opt()
+        .map_err(|e| AppError::InvalidInput(e.to_string()))?;
+    selection
+        .map(|i| candidates[i])
+        .ok_or_else(|| AppError::DeviceNotFound("no device selected".into()))
+}
 
-    let device_list = match api.get_device_info_list(&token).await {
-        Ok(list) => list,
-        Err(AppError::TokenExpired { .. }) => {
-            match cloud_type {
-                CloudType::Kasa => refresh_auth(auth, verbose).await?,
-                CloudType::Tapo => refresh_tapo_auth(auth, verbose).await?,
-            }
-            let refreshed_token = match cloud_type {
-                CloudType::Kasa => auth.token.clone(),
-                CloudType::Tapo => auth
-                    .tapo_token
-                    .as_ref()
-                    .ok_or(AppError::NotAuthenticated)?
-                    .clone(),
-            };
-            api.get_device_info_list(&refreshed_token).await?
+/// Devices whose alias is within a small edit distance of the query,
+/// closest first.
+fn fuzzy_matches<'a>(query_lower: &str, devices: &'a [DeviceEntry]) -> Vec<&'a DeviceEntry> {
+    let max_distance = (query_lower.chars().count() / 3).max(1);
+    let mut scored: Vec<(usize, &DeviceEntry)> = devices
+        .iter()
+        .map(|d| (edit_distance(query_lower, &d.alias().to_lowercase()), d))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    scored.sort_by_key(|(distance, _)| *distance);
+    scored.into_iter().map(|(_, d)| d).collect()
+}
+
+/// Levenshtein distance between two strings, by character.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(prev[j + 1] + 1).min(current[j] + 1);
         }
-        Err(e) => return Err(e),
+        prev = current;
+    }
+    prev[b.len()]
+}
+
+/// Build a `Device` for a device ID from its own sysinfo, trying the Kasa
+/// cloud first and then Tapo (only `only` when given).
+async fn resolve_device_by_id(
+    device_id: &str,
+    only: Option<CloudType>,
+    verbose: bool,
+) -> Result<Device, AppError> {
+    let auth = get_auth_context(verbose).await?;
+
+    let (parent_id, child_id) = if device_id.len() == 42 {
+        (&device_id[..40], Some(device_id))
+    } else {
+        (device_id, None)
     };
 
-    for device_json in &device_list {
-        if let Some(mut info) = DeviceInfo::from_json(device_json) {
-            // Deduplicate: Kasa takes priority
-            if !seen_ids.insert(info.id().to_string()) {
+    let mut clouds = Vec::new();
+    if auth.has_kasa() {
+        clouds.push(CloudType::Kasa);
+    }
+    if auth.has_tapo() {
+        clouds.push(CloudType::Tapo);
+    }
+    clouds.retain(|cloud| only.is_none_or(|only| only == *cloud));
+
+    let mut last_err = AppError::DeviceNotFound(device_id.to_string());
+    for cloud_type in clouds {
+        let probe = DeviceEntry {
+            info: DeviceInfo {
+                device_id: Some(parent_id.to_string()),
+                cloud_type: Some(cloud_type),
+                ..Default::default()
+            },
+            device_type: DeviceType::Unknown,
+            child_alias: None,
+            child_id: None,
+        };
+
+        let probe_device = build_device(&probe, &auth, verbose)?;
+        let sys_info = match probe_device.get_sys_info().await {
+            Ok(Some(sys_info)) => sys_info,
+            Ok(None) => continue,
+            Err(e) => {
+                last_err = e;
                 continue;
             }
+        };
 
-            info.cloud_type = Some(cloud_type);
-            let dtype = DeviceType::from_model(info.model());
+        let entry = entry_from_sys_info(probe.info, &sys_info, probe_device.host(), child_id)?;
+        return build_device(&entry, &auth, verbose);
+    }
 
-            if dtype.has_children() {
-                let client = DeviceClient::new(
-                    info.app_server_url.as_deref().unwrap_or(&api.host),
-                    &token,
-                    &auth.term_id,
-                    verbose,
-                    cloud_type,
-                )?;
+    Err(last_err)
+}
 
-                let parent_device =
-                    Device::new(client, info.id().to_string(), info.clone(), dtype, None);
+/// Fill in a probed entry from the device's sysinfo, selecting a child outlet
+/// if requested. `app_server_url` is the endpoint that answered the probe.
+/// The cloud's online status is unknown, so `status` is left unset.
+fn entry_from_sys_info(
+    mut info: DeviceInfo,
+    sys_info: &serde_json::Value,
+    app_server_url: &str,
+    child_id: Option<&str>,
+) -> Result<DeviceEntry, AppError> {
+    let field = |key: &str| sys_info.get(key).and_then(|v| v.as_str()).map(String::from);
+    info.alias = field("alias");
+    info.device_model = field("model");
+    info.device_hw_ver = field("hw_ver");
+    info.fw_ver = field("sw_ver");
+    info.device_mac = field("mac").or_else(|| field("mic_mac"));
+    info.app_server_url = Some(app_server_url.to_string());
 
-                // Add parent (no child_id)
-                all_devices.push((info.clone(), dtype, None, None));
+    let device_type = DeviceType::from_model(info.model());
 
-                if let Ok(children) = parent_device.get_children().await {
-                    for child in children {
-                        let child_alias = if child.alias.is_empty() {
-                            None
-                        } else {
-                            Some(child.alias)
-                        };
-                        all_devices.push((
-                            info.clone(),
-                            dtype.child_type(),
-                            child_alias,
-                            Some(child.id),
-                        ));
-                    }
-                }
-            } else {
-                all_devices.push((info, dtype, None, None));
-            }
-        }
-    }
+    let Some(child_id) = child_id else {
+        return Ok(DeviceEntry {
+            info,
+            device_type,
+            child_alias: None,
+            child_id: None,
+        });
+    };
 
-    Ok(())
+    let parent_id = info.id().to_string();
+    let child = sys_info
+        .get("children")
+        .and_then(|v| v.as_array())
+        .and_then(|children| {
+            children.iter().find(|c| {
+                c.get("id")
+                    .and_then(|v| v.as_str())
+                    .is_some_and(|id| child_full_id(&parent_id, id) == child_id)
+            })
+        })
+        .ok_or_else(|| AppError::DeviceNotFound(child_id.to_string()))?;
+
+    Ok(DeviceEntry {
+        child_alias: child
+            .get("alias")
+            .and_then(|v| v.as_str())
+            .filter(|a| !a.is_empty())
+            .map(String::from),
+        child_id: child.get("id").and_then(|v| v.as_str()).map(String::from),
+        device_type: device_type.child_type(),
+        info,
+    })
 }
 
-fn build_device(
-    info: &DeviceInfo,
-    dtype: DeviceType,
-    child_id: Option<String>,
+/// Build a `Device` handle for a listed entry using the matching cloud's token.
+pub fn build_device(
+    entry: &DeviceEntry,
     auth: &AuthContext,
     verbose: bool,
 ) -> Result<Device, AppError> {
+    let info = &entry.info;
     let cloud_type = info.cloud_type.unwrap_or(CloudType::Kasa);
 
     let (token, regional_url) = match cloud_type {
@@ -380,7 +929,178 @@ fn build_device(
         client,
         info.id().to_string(),
         info.clone(),
-        dtype,
-        child_id,
+        entry.device_type,
+        entry.child_id.clone(),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("kitchen", "kitchen"), 0);
+        assert_eq!(edit_distance("kitchn", "kitchen"), 1);
+        assert_eq!(edit_distance("lamp", "lump"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_fuzzy_matches_closest_first() {
+        let devices = vec![
+            DeviceEntry::test("1", "Porch Light"),
+            DeviceEntry::test("2", "Kitchen"),
+            DeviceEntry::test("3", "Kitchen Plug"),
+        ];
+        let matches = fuzzy_matches("kitchn", &devices);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].alias(), "Kitchen");
+        assert!(fuzzy_matches("garage", &devices).is_empty());
+    }
+
+    #[test]
+    fn test_exact_matches() {
+        let strip = DeviceEntry::test(&"A".repeat(40), "Strip");
+        let mut outlet = strip.clone();
+        outlet.child_id = Some("01".into());
+        outlet.child_alias = Some("Fridge".into());
+        let devices = vec![
+            DeviceEntry::test("1", "Router"),
+            DeviceEntry::test("2", "Router Lamp"),
+            strip,
+            outlet,
+        ];
+
+        let aliases = |query: &str| -> Vec<String> {
+            exact_matches(query, &devices)
+                .iter()
+                .map(|d| d.alias().to_string())
+                .collect()
+        };
+        assert_eq!(aliases("router"), vec!["Router"]);
+        assert_eq!(aliases("fridge"), vec!["Fridge"]);
+        assert_eq!(aliases(&"A".repeat(40)), vec!["Strip", "Fridge"]);
+        assert_eq!(aliases(&format!("{}01", "A".repeat(40))), vec!["Fridge"]);
+        assert!(aliases("Rout").is_empty());
+    }
+
+    #[test]
+    fn test_select_tagged() {
+        let strip = DeviceEntry::test(&"A".repeat(40), "Strip").with_model("HS300(US)");
+        let mut outlet = strip.clone();
+        outlet.child_id = Some("01".into());
+        outlet.child_alias = Some("Fridge".into());
+        let lamp = DeviceEntry::test(&"B".repeat(40), "Lamp");
+        let devices = vec![DeviceEntry::test("1", "Router"), strip, outlet, lamp];
+
+        let aliases = |tagged: &[String]| -> Vec<String> {
+            select_tagged(tagged, &devices)
+                .iter()
+                .map(|d| d.alias().to_string())
+                .collect()
+        };
+        assert_eq!(
+            aliases(&["A".repeat(40), "B".repeat(40)]),
+            vec!["Fridge", "Lamp"]
+        );
+        assert_eq!(aliases(&[format!("{}01", "A".repeat(40))]), vec!["Fridge"]);
+        assert!(aliases(&["C".repeat(40)]).is_empty());
+    }
+
+    #[test]
+    fn test_in_cloud() {
+        let kasa = DeviceEntry::test("1", "Plug");
+        let mut tapo = DeviceEntry::test("2", "Bulb");
+        tapo.info.cloud_type = Some(CloudType::Tapo);
+        assert!(in_cloud(&kasa, None) && in_cloud(&tapo, None));
+        assert!(in_cloud(&kasa, Some(CloudType::Kasa)));
+        assert!(!in_cloud(&tapo, Some(CloudType::Kasa)));
+        assert!(in_cloud(&tapo, Some(CloudType::Tapo)));
+    }
+
+    #[test]
+    fn test_merge_cloud_devices() {
+        let plug = DeviceEntry::test(&"A".repeat(40), "Plug");
+        let mut tapo_plug = plug.clone();
+        tapo_plug.info.alias = Some("Tapo Plug".into());
+        let bulb = DeviceEntry::test(&"B".repeat(40), "Bulb");
+
+        let aliases = |preferred| -> Vec<String> {
+            merge_cloud_devices(
+                vec![plug.clone()],
+                vec![tapo_plug.clone(), bulb.clone()],
+                preferred,
+            )
+            .iter()
+            .map(|d| d.alias().to_string())
+            .collect()
+        };
+        assert_eq!(aliases(CloudType::Kasa), vec!["Plug", "Bulb"]);
+        assert_eq!(aliases(CloudType::Tapo), vec!["Tapo Plug", "Bulb"]);
+    }
+
+    #[test]
+    fn test_unique_device_infos_drops_same_cloud_duplicates() {
+        let list = vec![
+            serde_json::json!({"deviceId": "A", "alias": "Plug"}),
+            serde_json::json!({"deviceId": "B", "alias": "Bulb"}),
+            serde_json::json!({"deviceId": "A", "alias": "Plug (again)"}),
+        ];
+        let infos = unique_device_infos(&list, CloudType::Tapo);
+        let aliases: Vec<&str> = infos.iter().map(|i| i.alias_or_name()).collect();
+        assert_eq!(aliases, vec!["Plug", "Bulb"]);
+        assert!(infos.iter().all(|i| i.cloud_type == Some(CloudType::Tapo)));
+    }
+
+    #[test]
+    fn test_entry_from_sys_info() {
+        let parent_id = "A".repeat(40);
+        let info = DeviceInfo {
+            device_id: Some(parent_id.clone()),
+            cloud_type: Some(CloudType::Kasa),
+            ..Default::default()
+        };
+        let sys_info = serde_json::json!({
+            "alias": "Strip",
+            "model": "HS300(US)",
+            "hw_ver": "2.0",
+            "sw_ver": "1.0.12",
+            "mac": "AA:BB:CC:DD:EE:FF",
+            "children": [
+                {"id": "00", "alias": "Fridge"},
+                {"id": format!("{}01", parent_id), "alias": ""},
+            ],
+        });
+        let url = "https://use1-wap.tplinkcloud.com";
+
+        let parent = entry_from_sys_info(info.clone(), &sys_info, url, None).unwrap();
+        assert_eq!(parent.alias(), "Strip");
+        assert_eq!(parent.device_type, DeviceType::HS300);
+        assert_eq!(parent.info.device_hw_ver.as_deref(), Some("2.0"));
+        assert_eq!(parent.info.app_server_url.as_deref(), Some(url));
+        assert_eq!(parent.info.status, None);
+
+        let child_id = format!("{}00", parent_id);
+        let outlet = entry_from_sys_info(info.clone(), &sys_info, url, Some(&child_id)).unwrap();
+        assert_eq!(outlet.alias(), "Fridge");
+        assert_eq!(outlet.device_type, DeviceType::HS300Child);
+        assert_eq!(outlet.full_id(), child_id);
+
+        let unnamed = format!("{}01", parent_id);
+        let outlet = entry_from_sys_info(info.clone(), &sys_info, url, Some(&unnamed)).unwrap();
+        assert_eq!(outlet.alias(), "Strip");
+
+        let missing = format!("{}05", parent_id);
+        assert!(matches!(
+            entry_from_sys_info(info, &sys_info, url, Some(&missing)),
+            Err(AppError::DeviceNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_looks_like_device_id() {
+        assert!(looks_like_device_id(&"8006AB".repeat(7)[..40]));
+        assert!(!looks_like_device_id("Living Room"));
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+
+/// Directory for local tplc state. `TPLC_CONFIG_DIR` overrides the platform
+/// default; `XDG_CONFIG_HOME` is honoured on every platform.
+pub fn config_dir() -> Result<PathBuf, AppError> {
+    if let Some(dir) = std::env::var_os("TPLC_CONFIG_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        return Ok(PathBuf::from(dir).join("tplc"));
+    }
+    dirs::config_dir()
+        .map(|dir| dir.join("tplc"))
+        .ok_or_else(|| AppError::Io(std::io::Error::other("no config directory found")))
+}
+
+/// Load a JSON state file, or its default if it does not exist yet.
+pub fn load<T: DeserializeOwned + Default>(name: &str) -> Result<T, AppError> {
+    let path = config_dir()?.join(name);
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => Ok(serde_json::from_str(&contents)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn save<T: Serialize>(name: &str, value: &T) -> Result<(), AppError> {
+    let dir = config_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join(name), serde_json::to_string_pretty(value)?)?;
+    Ok(())
+}
+
+const ALIASES_FILE: &str = "aliases.json";
+
+/// A local nickname for a device.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalAlias {
+    pub device_id: String,
+    /// Cloud alias when the nickname was created (for display only).
+    pub device_name: String,
+}
+
+pub fn load_aliases() -> Result<BTreeMap<String, LocalAlias>, AppError> {
+    load(ALIASES_FILE)
+}
+
+pub fn save_aliases(aliases: &BTreeMap<String, LocalAlias>) -> Result<(), AppError> {
+    save(ALIASES_FILE, aliases)
+}
+
+/// Look up a nickname, exact match first, then case-insensitive.
+pub fn find_alias(name: &str) -> Result<Option<LocalAlias>, AppError> {
+    let aliases = load_aliases()?;
+    if let Some(alias) = aliases.get(name) {
+        return Ok(Some(alias.clone()));
+    }
+    Ok(aliases
+        .iter()
+        .find(|(nickname, _)| nickname.eq_ignore_ascii_case(name))
+        .map(|(_, alias)| alias.clone()))
+}
+
+const NOTES_FILE: &str = "notes.json";
+
+/// Freeform device notes, keyed by full device ID.
+pub fn load_notes() -> Result<BTreeMap<String, String>, AppError> {
+    load(NOTES_FILE)
+}
+
+pub fn save_notes(notes: &BTreeMap<String, String>) -> Result<(), AppError> {
+    save(NOTES_FILE, notes)
+}
+
+const HIDDEN_FILE: &str = "hidden.json";
+
+/// Devices hidden from listings (`devices hide`), full device ID to the
+/// alias at the time it was hidden.
+pub fn load_hidden() -> Result<BTreeMap<String, String>, AppError> {
+    load(HIDDEN_FILE)
+}
+
+pub fn save_hidden(hidden: &BTreeMap<String, String>) -> Result<(), AppError> {
+    save(HIDDEN_FILE, hidden)
+}
+
+const TAGS_FILE: &str = "tags.json";
+
+/// Local tags of one device (`tplc tag add`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceTags {
+    /// Cloud alias when the device was last tagged (for display only).
+    pub device_name: String,
+    /// Lowercase tag names
+    pub tags: BTreeSet<String>,
+}
+
+/// Device tags keyed by full device ID. A device can carry many tags and a
+/// tag many devices.
+pub fn load_tags() -> Result<BTreeMap<String, DeviceTags>, AppError> {
+    load(TAGS_FILE)
+}
+
+pub fn save_tags(tags: &BTreeMap<String, DeviceTags>) -> Result<(), AppError> {
+    save(TAGS_FILE, tags)
+}
+
+const FAVORITES_FILE: &str = "favorites.json";
+
+/// `tplc shell` favorites: name to the command line it runs.
+pub fn load_favorites() -> Result<BTreeMap<String, String>, AppError> {
+    load(FAVORITES_FILE)
+}
+
+pub fn save_favorites(favorites: &BTreeMap<String, String>) -> Result<(), AppError> {
+    save(FAVORITES_FILE, favorites)
+}
+
+const INSTALLED_JOBS_FILE: &str = "installed_jobs.json";
+
+/// Names of the `[[cron]]` jobs last installed as launchd agents or
+/// scheduled tasks, so `cron remove` knows what to clean up.
+pub fn load_installed_jobs() -> Result<Vec<String>, AppError> {
+    load(INSTALLED_JOBS_FILE)
+}
+
+pub fn save_installed_jobs(names: &[String]) -> Result<(), AppError> {
+    save(INSTALLED_JOBS_FILE, &names)
+}
+
+const LED_POLICY_FILE: &str = "led_policy.json";
+
+/// LED policies from `led policy`: device category to whether the LED
+/// should be on. `devices watch` applies them to devices that appear later.
+pub fn load_led_policy() -> Result<BTreeMap<String, bool>, AppError> {
+    load(LED_POLICY_FILE)
+}
+
+pub fn save_led_policy(policy: &BTreeMap<String, bool>) -> Result<(), AppError> {
+    save(LED_POLICY_FILE, policy)
+}
+
+const TRUSTED_TERMINALS_FILE: &str = "trusted_terminals.json";
+
+/// A terminal ID the cloud accepted an MFA code from. Logging in again with
+/// the same ID is not challenged, like the official apps on a known phone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustedTerminal {
+    pub term_id: String,
+    pub trusted_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Trusted terminals keyed by lowercase account email. Kept across
+/// `tplc logout`.
+pub fn load_trusted_terminals() -> Result<BTreeMap<String, TrustedTerminal>, AppError> {
+    load(TRUSTED_TERMINALS_FILE)
+}
+
+pub fn save_trusted_terminals(
+    terminals: &BTreeMap<String, TrustedTerminal>,
+) -> Result<(), AppError> {
+    save(TRUSTED_TERMINALS_FILE, terminals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Tests that point `TPLC_CONFIG_DIR` at a temp dir run one at a time.
+    static CONFIG_DIR_LOCK: Mutex<()> = Mutex::new(());
+
+    fn with_config_dir(test: impl FnOnce()) {
+        let _guard = CONFIG_DIR_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let previous = std::env::var_os("TPLC_CONFIG_DIR");
+        std::env::set_var("TPLC_CONFIG_DIR", dir.path());
+        test();
+        match previous {
+            Some(value) => std::env::set_var("TPLC_CONFIG_DIR", value),
+            None => std::env::remove_var("TPLC_CONFIG_DIR"),
+        }
+    }
+
+    #[test]
+    fn test_aliases_round_trip() {
+        with_config_dir(|| {
+            assert!(load_aliases().unwrap().is_empty());
+
+            let mut aliases = BTreeMap::new();
+            aliases.insert(
+                "fridge".to_string(),
+                LocalAlias {
+                    device_id: "AAA".to_string(),
+                    device_name: "Kitchen Plug".to_string(),
+                },
+            );
+            save_aliases(&aliases).unwrap();
+
+            let loaded = load_aliases().unwrap();
+            assert_eq!(loaded.len(), 1);
+            assert_eq!(loaded["fridge"].device_id, "AAA");
+            assert_eq!(loaded["fridge"].device_name, "Kitchen Plug");
+            assert_eq!(find_alias("Fridge").unwrap().unwrap().device_id, "AAA");
+            assert!(find_alias("oven").unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn test_notes_round_trip() {
+        with_config_dir(|| {
+            assert!(load_notes().unwrap().is_empty());
+
+            let mut notes = BTreeMap::new();
+            notes.insert("AAA".to_string(), "Behind the fridge".to_string());
+            notes.insert("BBB01".to_string(), "Left outlet".to_string());
+            save_notes(&notes).unwrap();
+            assert_eq!(load_notes().unwrap(), notes);
+
+            notes.remove("AAA");
+            save_notes(&notes).unwrap();
+            assert_eq!(load_notes().unwrap(), notes);
+        });
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::resolve::DeviceEntry;
+
+/// Bumped when the bundle layout changes incompatibly.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Lines kept from the end of each log file.
+pub const LOG_TAIL_LINES: usize = 200;
+
+const REDACTED: &str = "[redacted]";
+
+/// Everything `tplc support-bundle` collects. Tokens, passwords and account
+/// emails are never included, and device IDs are shortened.
+#[derive(Debug, Serialize)]
+pub struct SupportBundle {
+    pub version: u32,
+    pub created_at: String,
+    pub tplc_version: &'static str,
+    pub environment: Environment,
+    pub auth: AuthSummary,
+    /// config.toml with secret-looking values removed
+    pub config_toml: Option<String>,
+    pub inventory: Option<InventorySummary>,
+    /// Why the inventory is missing (not logged in, network error, ...)
+    pub inventory_error: Option<String>,
+    /// Log file name to its last `LOG_TAIL_LINES` lines
+    pub logs: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Environment {
+    pub os: &'static str,
+    pub arch: &'static str,
+    /// Crypto-relevant CPU features detected at run time; TLS and request
+    /// signing fall back to portable code without them
+    pub cpu_features: Vec<&'static str>,
+    pub terminal: bool,
+    pub config_dir: String,
+    /// Names (not values) of the TPLC_* variables that are set
+    pub tplc_env: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthSummary {
+    pub authenticated: bool,
+    pub tapo: bool,
+    pub storage: &'static str,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct InventorySummary {
+    pub total: usize,
+    pub online: usize,
+    pub by_model: BTreeMap<String, usize>,
+    pub by_cloud: BTreeMap<String, usize>,
+}
+
+impl InventorySummary {
+    pub fn from_devices(devices: &[DeviceEntry]) -> Self {
+        let mut summary = InventorySummary {
+            total: devices.len(),
+            online: devices.iter().filter(|d| d.is_online()).count(),
+            ..Default::default()
+        };
+        for entry in devices {
+            *summary
+                .by_model
+                .entry(entry.info.model().to_string())
+                .or_default() += 1;
+            let cloud = entry
+                .info
+                .cloud_type
+                .map(|c| c.to_string())
+                .unwrap_or_else(|| "unknown".to_string());
+            *summary.by_cloud.entry(cloud).or_default() += 1;
+        }
+        summary
+    }
+}
+
+fn secret_key() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?i)^(\s*[\w.-]*(token|password|passwd|secret|key|username|email)[\w.-]*\s*=).*$",
+        )
+        .expect("valid regex")
+    })
+}
+
+fn email() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"[\w.+-]+@[\w-]+(\.[\w-]+)+").expect("valid regex"))
+}
+
+/// Device IDs are 40 hex characters, 42 for a child outlet.
+fn device_id() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\b([0-9A-Fa-f]{6})[0-9A-Fa-f]{34,36}\b").expect("valid regex"))
+}
+
+/// Which of the CPU features that speed up TLS and hashing this machine has.
+/// Empty on architectures without run-time detection (e.g. 32-bit ARM).
+pub fn cpu_features() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut features = Vec::new();
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if std::arch::is_x86_feature_detected!("aes") {
+            features.push("aes");
+        }
+        if std::arch::is_x86_feature_detected!("pclmulqdq") {
+            features.push("pclmulqdq");
+        }
+        if std::arch::is_x86_feature_detected!("sha") {
+            features.push("sha");
+        }
+        if std::arch::is_x86_feature_detected!("avx2") {
+            features.push("avx2");
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            features.push("neon");
+        }
+        if std::arch::is_aarch64_feature_detected!("aes") {
+            features.push("aes");
+        }
+        if std::arch::is_aarch64_feature_detected!("sha2") {
+            features.push("sha2");
+        }
+    }
+    features
+}
+
+/// Blank out config.toml values whose key looks like a secret, then apply
+/// `redact_text`.
+pub fn redact_config(contents: &str, home: Option<&str>) -> String {
+    let lines: Vec<String> = contents
+        .lines()
+        .map(|line| {
+            secret_key()
+                .replace(line, format!("$1 \"{}\"", REDACTED))
+                .into_owned()
+        })
+        .collect();
+    redact_text(&lines.join("\n"), home)
+}
+
+/// Replace email addresses, shorten device IDs to their first six
+/// characters, and replace the home directory with `~`.
+pub fn redact_text(text: &str, home: Option<&str>) -> String {
+    let text = email().replace_all(text, REDACTED);
+    let mut text = device_id().replace_all(&text, "$1…").into_owned();
+    if let Some(home) = home.filter(|h| h.len() > 1) {
+        text = text.replace(home, "~");
+    }
+    text
+}
+
+/// The last `n` lines of `contents`.
+pub fn tail(contents: &str, n: usize) -> Vec<String> {
+    let lines: Vec<&str> = contents.lines().collect();
+    lines[lines.len().saturating_sub(n)..]
+        .iter()
+        .map(|l| l.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_config() {
+        let config = "output = \"table\"\napi_token = \"abc\"\n[energy]\nprice = 0.31\n\
+                      # owner me@example.com\ndefault_device = \"8006ABCDEF0123456789ABCDEF0123456789ABCD01\"";
+        let redacted = redact_config(config, None);
+        assert_eq!(
+            redacted,
+            "output = \"table\"\napi_token = \"[redacted]\"\n[energy]\nprice = 0.31\n\
+             # owner [redacted]\ndefault_device = \"8006AB…\""
+        );
+    }
+
+    #[test]
+    fn test_redact_text_home_and_tail() {
+        let log = "first\nwrote /home/me/.config/tplc/cron.log\nlast";
+        assert_eq!(
+            redact_text(log, Some("/home/me")),
+            "first\nwrote ~/.config/tplc/cron.log\nlast"
+        );
+        assert_eq!(tail(log, 2).len(), 2);
+        assert_eq!(tail(log, 2)[1], "last");
+        assert_eq!(tail(log, 10).len(), 3);
+    }
+}
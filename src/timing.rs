@@ -0,0 +1,80 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SPANS: Mutex<Vec<SpanRecord>> = Mutex::new(Vec::new());
+static ORIGIN: OnceLock<Instant> = OnceLock::new();
+
+struct SpanRecord {
+    label: String,
+    start: Duration,
+    end: Duration,
+}
+
+/// Guard that records a span when dropped.
+pub struct Span {
+    label: Option<String>,
+    start: Instant,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(label) = self.label.take() {
+            let origin = *ORIGIN.get_or_init(Instant::now);
+            let record = SpanRecord {
+                label,
+                start: self.start.saturating_duration_since(origin),
+                end: Instant::now().saturating_duration_since(origin),
+            };
+            if let Ok(mut spans) = SPANS.lock() {
+                spans.push(record);
+            }
+        }
+    }
+}
+
+pub fn enable() {
+    ORIGIN.get_or_init(Instant::now);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Start a span. Does nothing unless timing is enabled.
+pub fn span(label: impl Into<String>) -> Span {
+    Span {
+        label: is_enabled().then(|| label.into()),
+        start: Instant::now(),
+    }
+}
+
+/// Print the recorded spans to stderr, ordered by start time.
+/// Indentation shows which spans ran inside others.
+pub fn report() {
+    if !is_enabled() {
+        return;
+    }
+    let mut spans = match SPANS.lock() {
+        Ok(mut spans) => std::mem::take(&mut *spans),
+        Err(_) => return,
+    };
+    spans.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+
+    eprintln!("[timing]");
+    for (i, span) in spans.iter().enumerate() {
+        let depth = spans[..i]
+            .iter()
+            .filter(|outer| outer.start <= span.start && span.end <= outer.end)
+            .count();
+        eprintln!(
+            "{:>10.1}ms {:>10.1}ms  {}{}",
+            span.start.as_secs_f64() * 1000.0,
+            (span.end - span.start).as_secs_f64() * 1000.0,
+            "  ".repeat(depth),
+            span.label
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::resolve::{DeviceEntry, DeviceState};
+
+/// What `devices watch` remembers about a device between polls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Observed {
+    pub online: bool,
+    pub is_on: Option<bool>,
+}
+
+/// Observed state keyed by full device ID.
+pub type Snapshot = HashMap<String, Observed>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Event {
+    pub time: String,
+    pub device: String,
+    pub device_id: String,
+    /// "on", "off", "online" or "offline"; with `--messages` also a
+    /// message-center "overload" or "notice"
+    pub event: &'static str,
+    /// Text of a message-center notice
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+pub fn snapshot(devices: &[DeviceEntry], states: &[DeviceState]) -> Snapshot {
+    devices
+        .iter()
+        .zip(states)
+        .map(|(entry, state)| {
+            let observed = Observed {
+                online: entry.is_online(),
+                is_on: state.is_on,
+            };
+            (entry.full_id(), observed)
+        })
+        .collect()
+}
+
+/// Events for devices whose state changed since the previous poll. Devices
+/// not seen before produce no events; an unknown power state (device did not
+/// answer) is not reported as a change.
+pub fn diff(
+    previous: &Snapshot,
+    current: &Snapshot,
+    devices: &[DeviceEntry],
+    time: &str,
+) -> Vec<Event> {
+    let mut events = Vec::new();
+    for entry in devices {
+        let id = entry.full_id();
+        let (Some(before), Some(after)) = (previous.get(&id), current.get(&id)) else {
+            continue;
+        };
+        let mut push = |event| {
+            events.push(Event {
+                time: time.to_string(),
+                device: entry.alias().to_string(),
+                device_id: id.clone(),
+                event,
+                message: None,
+            })
+        };
+        if before.online != after.online {
+            push(if after.online { "online" } else { "offline" });
+        }
+        if let (Some(was_on), Some(is_on)) = (before.is_on, after.is_on) {
+            if was_on != is_on {
+                push(if is_on { "on" } else { "off" });
+            }
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observed(online: bool, is_on: Option<bool>) -> Observed {
+        Observed { online, is_on }
+    }
+
+    #[test]
+    fn test_diff_reports_changes() {
+        let devices = vec![
+            DeviceEntry::test("AAA", "Porch"),
+            DeviceEntry::test("BBB", "Hall"),
+            DeviceEntry::test("CCC", "New"),
+        ];
+        let previous: Snapshot = [
+            ("AAA".to_string(), observed(true, Some(false))),
+            ("BBB".to_string(), observed(true, Some(true))),
+        ]
+        .into();
+        let current: Snapshot = [
+            ("AAA".to_string(), observed(true, Some(true))),
+            ("BBB".to_string(), observed(false, None)),
+            ("CCC".to_string(), observed(true, Some(true))),
+        ]
+        .into();
+
+        let events = diff(&previous, &current, &devices, "12:00");
+        let summary: Vec<(&str, &str)> = events
+            .iter()
+            .map(|e| (e.device.as_str(), e.event))
+            .collect();
+        assert_eq!(summary, vec![("Porch", "on"), ("Hall", "offline")]);
+        assert!(diff(&current, &current, &devices, "12:01").is_empty());
+    }
+}